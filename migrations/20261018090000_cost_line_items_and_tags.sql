@@ -0,0 +1,64 @@
+-- Cost line items and tag normalization
+--
+-- Organization-scoped tables are keyed by the Clerk organization ID
+-- (e.g. "org_2abc..."), which is what the authenticated claims carry.
+
+-- Cost line items. `tags` are normalized by the organization's tag rules
+-- when a line item is inserted, and recomputed from `raw_tags`, the tags
+-- exactly as the provider reported them, whenever the rules change.
+CREATE TABLE cost_line_items (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    provider TEXT NOT NULL CHECK (provider IN ('aws', 'azure', 'gcp')),
+    account_id TEXT NOT NULL,
+    usage_start TIMESTAMPTZ NOT NULL,
+    usage_end TIMESTAMPTZ NOT NULL,
+    service TEXT NOT NULL,
+    region TEXT,
+    usage_type TEXT,
+    line_item_type TEXT NOT NULL DEFAULT 'Usage',
+    resource_id TEXT,
+    usage_amount DOUBLE PRECISION NOT NULL DEFAULT 0,
+    pricing_unit TEXT,
+    cost DOUBLE PRECISION NOT NULL,
+    currency TEXT NOT NULL DEFAULT 'USD',
+    raw_tags JSONB NOT NULL DEFAULT '{}'::jsonb,
+    tags JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK (usage_end >= usage_start)
+);
+
+CREATE INDEX idx_cost_line_items_org_usage_start
+    ON cost_line_items (organization_id, usage_start);
+CREATE INDEX idx_cost_line_items_tags ON cost_line_items USING GIN (tags);
+
+SELECT create_audit_trigger('cost_line_items');
+
+-- Per-organization tag normalization policy (key aliases, value mappings
+-- and case folding), stored as a single document edited as a whole.
+CREATE TABLE tag_policies (
+    organization_id TEXT PRIMARY KEY,
+    policy JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+SELECT create_audit_trigger('tag_policies');
+
+-- Virtual tags: a tag value assigned by rule to line items that do not
+-- carry the tag key themselves.
+CREATE TABLE virtual_tags (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    priority INTEGER NOT NULL DEFAULT 0,
+    conditions JSONB NOT NULL DEFAULT '[]'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_virtual_tags_org ON virtual_tags (organization_id);
+
+SELECT create_audit_trigger('virtual_tags');
@@ -0,0 +1,99 @@
+-- Showback/chargeback statements per cost center
+
+-- Internal cost centers. A line item belongs to the highest-priority cost
+-- center whose conditions (same shape as virtual tag conditions) all match.
+CREATE TABLE cost_centers (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    code TEXT,
+    priority INTEGER NOT NULL DEFAULT 0,
+    conditions JSONB NOT NULL DEFAULT '[]'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organization_id, name)
+);
+
+SELECT create_audit_trigger('cost_centers');
+
+-- Markup (positive percent) or discount (negative percent) applied to
+-- charges. The most specific matching rule wins: cost center + service,
+-- then service, then cost center, then organization-wide.
+CREATE TABLE chargeback_rate_rules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    cost_center_id UUID REFERENCES cost_centers (id) ON DELETE CASCADE,
+    service TEXT,
+    percent DOUBLE PRECISION NOT NULL CHECK (percent >= -100),
+    description TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_chargeback_rate_rules_org ON chargeback_rate_rules (organization_id);
+
+SELECT create_audit_trigger('chargeback_rate_rules');
+
+-- One statement per cost center and month. Closed statements are never
+-- regenerated; corrections become adjustments carried onto a later statement.
+CREATE TABLE chargeback_statements (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    cost_center_id UUID NOT NULL REFERENCES cost_centers (id) ON DELETE RESTRICT,
+    period DATE NOT NULL CHECK (EXTRACT(DAY FROM period) = 1),
+    status TEXT NOT NULL DEFAULT 'draft' CHECK (status IN ('draft', 'closed')),
+    currency TEXT NOT NULL DEFAULT 'USD',
+    generated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    closed_at TIMESTAMPTZ,
+    closed_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (cost_center_id, period)
+);
+
+CREATE INDEX idx_chargeback_statements_org_period
+    ON chargeback_statements (organization_id, period);
+
+SELECT create_audit_trigger('chargeback_statements');
+
+-- Corrections to closed statements, applied to the next statement generated
+-- for the same cost center.
+CREATE TABLE chargeback_adjustments (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    cost_center_id UUID NOT NULL REFERENCES cost_centers (id) ON DELETE CASCADE,
+    statement_id UUID NOT NULL REFERENCES chargeback_statements (id) ON DELETE CASCADE,
+    applied_statement_id UUID REFERENCES chargeback_statements (id) ON DELETE SET NULL,
+    service TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    amount DOUBLE PRECISION NOT NULL,
+    reason TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_chargeback_adjustments_pending
+    ON chargeback_adjustments (cost_center_id) WHERE applied_statement_id IS NULL;
+
+SELECT create_audit_trigger('chargeback_adjustments');
+
+CREATE TABLE chargeback_statement_lines (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    statement_id UUID NOT NULL REFERENCES chargeback_statements (id) ON DELETE CASCADE,
+    line_type TEXT NOT NULL CHECK (line_type IN ('charge', 'adjustment')),
+    service TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    description TEXT NOT NULL,
+    usage_cost DOUBLE PRECISION NOT NULL,
+    rate_percent DOUBLE PRECISION NOT NULL DEFAULT 0,
+    rate_amount DOUBLE PRECISION NOT NULL DEFAULT 0,
+    amount DOUBLE PRECISION NOT NULL,
+    adjustment_id UUID REFERENCES chargeback_adjustments (id) ON DELETE SET NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_chargeback_statement_lines_statement
+    ON chargeback_statement_lines (statement_id);
+
+SELECT create_audit_trigger('chargeback_statement_lines');
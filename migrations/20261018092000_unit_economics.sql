@@ -0,0 +1,48 @@
+-- Unit economics: business metrics and cost-per-unit metrics
+
+-- A business metric pushed by the customer (customers, requests, GB served...)
+CREATE TABLE business_metrics (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    unit TEXT NOT NULL,
+    description TEXT,
+    -- How daily values combine into coarser buckets: 'sum' for flows
+    -- (requests, GB), 'average' for levels (active customers)
+    aggregation TEXT NOT NULL DEFAULT 'sum' CHECK (aggregation IN ('sum', 'average')),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organization_id, name)
+);
+
+SELECT create_audit_trigger('business_metrics');
+
+-- Daily values; `dimension` is '' when the value is not broken down
+CREATE TABLE business_metric_values (
+    metric_id UUID NOT NULL REFERENCES business_metrics (id) ON DELETE CASCADE,
+    date DATE NOT NULL,
+    dimension TEXT NOT NULL DEFAULT '',
+    value DOUBLE PRECISION NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (metric_id, date, dimension)
+);
+
+SELECT create_audit_trigger('business_metric_values');
+
+-- Cost per unit: spend selected by a cost filter divided by a business metric
+CREATE TABLE unit_metrics (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    business_metric_id UUID NOT NULL REFERENCES business_metrics (id) ON DELETE CASCADE,
+    cost_filter JSONB NOT NULL DEFAULT '{}'::jsonb,
+    -- Optional cost dimension matched against business metric dimensions
+    dimension TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organization_id, name)
+);
+
+SELECT create_audit_trigger('unit_metrics');
@@ -0,0 +1,55 @@
+-- Reserved Instance and Savings Plan utilization, coverage and alerts
+
+-- Commitment-related CUR fields:
+--   instance_type          product/instanceType (coverage is grouped by family)
+--   commitment_arn         reservation/ReservationARN or savingsPlan/SavingsPlanARN
+--   commitment_end         reservation/EndTime or savingsPlan/EndTime
+--   effective_cost         reservation/EffectiveCost or savingsPlan/SavingsPlanEffectiveCost
+--   public_on_demand_cost  pricing/publicOnDemandCost
+ALTER TABLE cost_line_items
+    ADD COLUMN instance_type TEXT,
+    ADD COLUMN commitment_arn TEXT,
+    ADD COLUMN commitment_end TIMESTAMPTZ,
+    ADD COLUMN effective_cost DOUBLE PRECISION,
+    ADD COLUMN public_on_demand_cost DOUBLE PRECISION;
+
+CREATE INDEX idx_cost_line_items_commitment
+    ON cost_line_items (organization_id, commitment_arn, usage_start)
+    WHERE commitment_arn IS NOT NULL;
+
+CREATE TABLE commitment_alert_settings (
+    organization_id TEXT PRIMARY KEY,
+    -- Alert when utilization over the lookback window falls below this (0-1)
+    utilization_threshold DOUBLE PRECISION NOT NULL DEFAULT 0.8
+        CHECK (utilization_threshold >= 0 AND utilization_threshold <= 1),
+    utilization_lookback_days INTEGER NOT NULL DEFAULT 7
+        CHECK (utilization_lookback_days > 0),
+    -- Alert when a commitment ends within this many days
+    expiry_warning_days INTEGER NOT NULL DEFAULT 30 CHECK (expiry_warning_days >= 0),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+SELECT create_audit_trigger('commitment_alert_settings');
+
+-- Raised alerts. `dedupe_key` makes evaluation idempotent: an expiry alert
+-- is raised once per end date, a utilization alert once per day.
+CREATE TABLE commitment_alerts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    commitment_arn TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('expiring', 'low_utilization')),
+    dedupe_key TEXT NOT NULL,
+    message TEXT NOT NULL,
+    details JSONB NOT NULL DEFAULT '{}'::jsonb,
+    acknowledged_at TIMESTAMPTZ,
+    acknowledged_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organization_id, commitment_arn, kind, dedupe_key)
+);
+
+CREATE INDEX idx_commitment_alerts_org_created
+    ON commitment_alerts (organization_id, created_at DESC);
+
+SELECT create_audit_trigger('commitment_alerts');
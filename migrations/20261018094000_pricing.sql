@@ -0,0 +1,52 @@
+-- Offline cloud pricing catalogs
+--
+-- Catalogs are global (list prices are public) and immutable once loaded:
+-- a new price file becomes a new catalog version, so estimates that record
+-- the catalog they used stay reproducible.
+
+CREATE TABLE price_catalogs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    provider TEXT NOT NULL CHECK (provider IN ('aws', 'azure', 'gcp')),
+    -- AWS offer code, or the file name for Azure and GCP exports
+    name TEXT NOT NULL,
+    -- Offer file version for AWS, content checksum prefix otherwise
+    version TEXT NOT NULL,
+    source TEXT NOT NULL,
+    checksum TEXT NOT NULL,
+    publication_date TIMESTAMPTZ,
+    price_count INTEGER NOT NULL,
+    loaded_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (provider, name, version)
+);
+
+SELECT create_audit_trigger('price_catalogs');
+
+CREATE TABLE prices (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    catalog_id UUID NOT NULL REFERENCES price_catalogs(id) ON DELETE CASCADE,
+    service TEXT NOT NULL,
+    sku TEXT NOT NULL,
+    product_family TEXT,
+    region TEXT,
+    description TEXT,
+    -- e.g. OnDemand, Reserved (AWS), Consumption, Reservation (Azure), OnDemand, Preemptible (GCP)
+    price_type TEXT NOT NULL,
+    -- Commitment term for reserved prices, e.g. '1yr standard All Upfront'
+    term TEXT,
+    unit TEXT NOT NULL,
+    currency TEXT NOT NULL,
+    effective_from TIMESTAMPTZ,
+    -- [{"startUsage": 0, "endUsage": 10240, "unitPrice": 0.09}, ...]
+    tiers JSONB NOT NULL,
+    attributes JSONB NOT NULL DEFAULT '{}'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_prices_lookup ON prices (catalog_id, service, region, price_type);
+CREATE INDEX idx_prices_sku ON prices (catalog_id, sku);
+CREATE INDEX idx_prices_attributes ON prices USING GIN (attributes jsonb_path_ops);
+
+SELECT create_audit_trigger('prices');
@@ -0,0 +1,76 @@
+-- Connected cloud accounts and the resource inventory discovered in them
+
+CREATE TABLE cloud_accounts (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    provider TEXT NOT NULL CHECK (provider IN ('aws', 'azure', 'gcp')),
+    -- Provider account id, e.g. the 12-digit AWS account number
+    account_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    -- Role assumed for API access; the backend's own credentials are used
+    -- directly when absent
+    role_arn TEXT,
+    -- External id the role's trust policy must require
+    external_id TEXT NOT NULL DEFAULT gen_random_uuid()::text,
+    -- Regions to discover; every enabled region when empty
+    regions TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (organization_id, provider, account_id)
+);
+
+SELECT create_audit_trigger('cloud_accounts');
+
+CREATE TABLE discovery_runs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    cloud_account_id UUID NOT NULL REFERENCES cloud_accounts(id) ON DELETE CASCADE,
+    status TEXT NOT NULL DEFAULT 'running'
+        CHECK (status IN ('running', 'succeeded', 'partial', 'failed')),
+    started_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    finished_at TIMESTAMPTZ,
+    resource_count INTEGER NOT NULL DEFAULT 0,
+    -- Per region and resource type failures: [{region, resourceType, message}]
+    errors JSONB NOT NULL DEFAULT '[]'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+-- At most one run in progress per account
+CREATE UNIQUE INDEX idx_discovery_runs_running
+    ON discovery_runs (cloud_account_id)
+    WHERE status = 'running';
+
+CREATE INDEX idx_discovery_runs_account_started
+    ON discovery_runs (cloud_account_id, started_at DESC);
+
+SELECT create_audit_trigger('discovery_runs');
+
+CREATE TABLE resources (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    cloud_account_id UUID NOT NULL REFERENCES cloud_accounts(id) ON DELETE CASCADE,
+    provider TEXT NOT NULL,
+    account_id TEXT NOT NULL,
+    resource_type TEXT NOT NULL,
+    -- Provider id of the resource, e.g. `i-0abc...`, `vol-...` or a bucket name
+    provider_id TEXT NOT NULL,
+    arn TEXT,
+    name TEXT,
+    region TEXT NOT NULL,
+    state TEXT,
+    tags JSONB NOT NULL DEFAULT '{}'::jsonb,
+    -- Provider description of the resource as returned by its API
+    attributes JSONB NOT NULL DEFAULT '{}'::jsonb,
+    first_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    last_seen_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (cloud_account_id, resource_type, region, provider_id)
+);
+
+CREATE INDEX idx_resources_org_type ON resources (organization_id, resource_type, region);
+CREATE INDEX idx_resources_org_arn ON resources (organization_id, arn) WHERE arn IS NOT NULL;
+CREATE INDEX idx_resources_tags ON resources USING GIN (tags jsonb_path_ops);
+
+SELECT create_audit_trigger('resources');
@@ -0,0 +1,44 @@
+-- Versioned history of discovered resources
+
+ALTER TABLE resources
+    -- Current version, matching the latest row in resource_versions
+    ADD COLUMN version INTEGER NOT NULL DEFAULT 1,
+    -- Set when a discovery run no longer finds the resource
+    ADD COLUMN deleted_at TIMESTAMPTZ;
+
+ALTER TABLE discovery_runs
+    -- Resources created, changed or found again by the run
+    ADD COLUMN changed_count INTEGER NOT NULL DEFAULT 0,
+    ADD COLUMN deleted_count INTEGER NOT NULL DEFAULT 0;
+
+CREATE INDEX idx_resources_account_live
+    ON resources (cloud_account_id, resource_type, region)
+    WHERE deleted_at IS NULL;
+
+-- One row per observed change. `snapshot` is the resource as observed
+-- ({name, state, tags, attributes}); `changes` lists the fields that differ
+-- from the previous version as [{field, before, after}].
+CREATE TABLE resource_versions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    resource_id UUID NOT NULL REFERENCES resources(id) ON DELETE CASCADE,
+    version INTEGER NOT NULL,
+    discovery_run_id UUID REFERENCES discovery_runs(id) ON DELETE SET NULL,
+    observed_at TIMESTAMPTZ NOT NULL,
+    change_kind TEXT NOT NULL
+        CHECK (change_kind IN ('created', 'updated', 'deleted', 'restored')),
+    snapshot JSONB NOT NULL,
+    changes JSONB NOT NULL DEFAULT '[]'::jsonb,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (resource_id, version)
+);
+
+CREATE INDEX idx_resource_versions_run ON resource_versions (discovery_run_id);
+
+SELECT create_audit_trigger('resource_versions');
+
+-- Resources discovered before history existed start at version 1
+INSERT INTO resource_versions (resource_id, version, observed_at, change_kind, snapshot)
+SELECT id, 1, first_seen_at, 'created',
+       jsonb_build_object('name', name, 'state', state, 'tags', tags, 'attributes', attributes)
+FROM resources;
@@ -0,0 +1,48 @@
+-- Links from cost line items to discovered resources
+--
+-- Providers report resource ids in several forms (CUR uses instance and
+-- volume ids, full ARNs for RDS and load balancers, bucket names for S3).
+-- Both sides keep normalized keys so linking is a plain join; the backfills
+-- below mirror `resources::link`.
+
+ALTER TABLE resources
+    -- Ids a billing line item may use for the resource
+    ADD COLUMN link_keys TEXT[] NOT NULL DEFAULT '{}';
+
+ALTER TABLE cost_line_items
+    -- Normalized form of resource_id
+    ADD COLUMN resource_key TEXT,
+    ADD COLUMN linked_resource_id UUID REFERENCES resources(id) ON DELETE SET NULL;
+
+CREATE INDEX idx_resources_link_keys ON resources USING GIN (link_keys);
+CREATE INDEX idx_cost_line_items_linked_resource
+    ON cost_line_items (linked_resource_id, usage_start)
+    WHERE linked_resource_id IS NOT NULL;
+CREATE INDEX idx_cost_line_items_unlinked
+    ON cost_line_items (organization_id, account_id)
+    WHERE linked_resource_id IS NULL AND resource_key IS NOT NULL;
+
+UPDATE resources
+SET link_keys = ARRAY_REMOVE(ARRAY[
+        provider_id,
+        CASE WHEN resource_type IN ('rds_instance', 'load_balancer') THEN arn END,
+        CASE WHEN resource_type = 'elastic_ip' THEN NULLIF(attributes ->> 'publicIp', '') END
+    ], NULL);
+
+UPDATE cost_line_items
+SET resource_key = CASE
+        WHEN resource_id ~ '^arn:[^:]+:ec2:' THEN regexp_replace(resource_id, '^.*/', '')
+        WHEN resource_id ~ '^arn:[^:]+:s3:::' THEN
+            split_part(regexp_replace(resource_id, '^arn:[^:]+:s3:::', ''), '/', 1)
+        ELSE NULLIF(btrim(resource_id), '')
+    END
+WHERE resource_id IS NOT NULL;
+
+UPDATE cost_line_items c
+SET linked_resource_id = r.id
+FROM resources r
+WHERE c.resource_key IS NOT NULL
+  AND r.organization_id = c.organization_id
+  AND r.provider = c.provider
+  AND r.account_id = c.account_id
+  AND c.resource_key = ANY(r.link_keys);
@@ -0,0 +1,51 @@
+-- Idle and orphaned resource detection
+
+CREATE TABLE waste_settings (
+    organization_id TEXT PRIMARY KEY,
+    -- Resources must have been idle (unattached, unassociated, stopped or
+    -- without targets) for this long before they are reported
+    idle_days INTEGER NOT NULL DEFAULT 7 CHECK (idle_days >= 0),
+    -- Snapshots older than this are reported
+    snapshot_age_days INTEGER NOT NULL DEFAULT 90 CHECK (snapshot_age_days > 0),
+    -- Window of linked spend used to estimate savings
+    lookback_days INTEGER NOT NULL DEFAULT 30 CHECK (lookback_days > 0),
+    disabled_rules TEXT[] NOT NULL DEFAULT '{}',
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+SELECT create_audit_trigger('waste_settings');
+
+-- One finding per resource and rule, refreshed by every evaluation.
+-- Findings whose condition no longer holds are resolved; a snoozed finding
+-- reopens once `snoozed_until` passes; a dismissed one stays dismissed.
+CREATE TABLE waste_findings (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    resource_id UUID NOT NULL REFERENCES resources(id) ON DELETE CASCADE,
+    rule TEXT NOT NULL CHECK (rule IN (
+        'unattached_volume', 'idle_elastic_ip', 'stopped_instance',
+        'old_snapshot', 'idle_load_balancer'
+    )),
+    severity TEXT NOT NULL CHECK (severity IN ('low', 'medium', 'high')),
+    message TEXT NOT NULL,
+    estimated_monthly_savings DOUBLE PRECISION NOT NULL,
+    currency TEXT NOT NULL DEFAULT 'USD',
+    evidence JSONB NOT NULL DEFAULT '{}'::jsonb,
+    status TEXT NOT NULL DEFAULT 'open'
+        CHECK (status IN ('open', 'snoozed', 'dismissed', 'resolved')),
+    status_reason TEXT,
+    status_changed_by TEXT,
+    status_changed_at TIMESTAMPTZ,
+    snoozed_until TIMESTAMPTZ,
+    first_detected_at TIMESTAMPTZ NOT NULL,
+    last_detected_at TIMESTAMPTZ NOT NULL,
+    resolved_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    UNIQUE (resource_id, rule)
+);
+
+CREATE INDEX idx_waste_findings_org_status ON waste_findings (organization_id, status);
+
+SELECT create_audit_trigger('waste_findings');
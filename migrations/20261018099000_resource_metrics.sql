@@ -0,0 +1,16 @@
+-- Utilization metrics of discovered resources
+--
+-- Values are percentages (0-100) for cpu and memory and bytes per second
+-- for network_in and network_out.
+CREATE TABLE resource_metrics (
+    resource_id UUID NOT NULL REFERENCES resources(id) ON DELETE CASCADE,
+    metric TEXT NOT NULL CHECK (metric IN ('cpu', 'memory', 'network_in', 'network_out')),
+    observed_at TIMESTAMPTZ NOT NULL,
+    value DOUBLE PRECISION NOT NULL,
+    organization_id TEXT NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (resource_id, metric, observed_at)
+);
+
+SELECT create_audit_trigger('resource_metrics');
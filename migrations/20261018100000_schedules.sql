@@ -0,0 +1,85 @@
+-- Start/stop schedules for EC2 and RDS instances
+--
+-- A schedule keeps its resources running inside weekly windows of local
+-- time in its time zone and stopped outside them and on holidays. It
+-- applies to the resources listed by id and to those carrying every tag of
+-- its tag selector.
+CREATE TABLE schedules (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    description TEXT,
+    timezone TEXT NOT NULL,
+    -- [{"days": ["Mon", ...], "start": "08:00:00", "end": "18:00:00"}]
+    windows JSONB NOT NULL DEFAULT '[]',
+    -- [{"date": "2026-12-25", "name": "Christmas"}]
+    holidays JSONB NOT NULL DEFAULT '[]',
+    resource_ids UUID[] NOT NULL DEFAULT '{}',
+    tag_selector JSONB NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    -- Log the actions the schedule would take without taking them
+    dry_run BOOLEAN NOT NULL DEFAULT FALSE,
+    created_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_schedules_enabled ON schedules(organization_id) WHERE enabled;
+
+SELECT create_audit_trigger('schedules');
+
+-- Temporary exceptions, e.g. keep a resource running until 22:00. Without a
+-- resource the override applies to every resource of the schedule.
+CREATE TABLE schedule_overrides (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    schedule_id UUID NOT NULL REFERENCES schedules(id) ON DELETE CASCADE,
+    organization_id TEXT NOT NULL,
+    resource_id UUID REFERENCES resources(id) ON DELETE CASCADE,
+    action TEXT NOT NULL CHECK (action IN ('keep_running', 'keep_stopped')),
+    until TIMESTAMPTZ NOT NULL,
+    reason TEXT,
+    created_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_schedule_overrides_schedule ON schedule_overrides(schedule_id, until);
+
+SELECT create_audit_trigger('schedule_overrides');
+
+-- State the scheduler last brought each resource to. Resources are only
+-- acted on when the wanted state changes, so manual starts and stops
+-- between transitions are left alone.
+CREATE TABLE schedule_resource_states (
+    schedule_id UUID NOT NULL REFERENCES schedules(id) ON DELETE CASCADE,
+    resource_id UUID NOT NULL REFERENCES resources(id) ON DELETE CASCADE,
+    desired_state TEXT NOT NULL CHECK (desired_state IN ('running', 'stopped')),
+    applied_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (schedule_id, resource_id)
+);
+
+SELECT create_audit_trigger('schedule_resource_states');
+
+CREATE TABLE schedule_executions (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    schedule_id UUID NOT NULL REFERENCES schedules(id) ON DELETE CASCADE,
+    organization_id TEXT NOT NULL,
+    resource_id UUID REFERENCES resources(id) ON DELETE SET NULL,
+    resource_type TEXT NOT NULL,
+    provider_id TEXT NOT NULL,
+    region TEXT NOT NULL,
+    action TEXT NOT NULL CHECK (action IN ('start', 'stop')),
+    -- What made the resource's wanted state change
+    trigger TEXT NOT NULL CHECK (trigger IN ('schedule', 'override')),
+    status TEXT NOT NULL CHECK (status IN ('succeeded', 'failed', 'dry_run')),
+    message TEXT,
+    executed_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_schedule_executions_schedule ON schedule_executions(schedule_id, executed_at DESC);
+
+SELECT create_audit_trigger('schedule_executions');
@@ -0,0 +1,37 @@
+-- Durable background jobs
+--
+-- Workers claim queued jobs whose run_at has passed with
+-- FOR UPDATE SKIP LOCKED, so any number of them can share the table. A
+-- failed job is queued again with exponential backoff until it runs out of
+-- attempts and is left dead for an admin to inspect or retry.
+CREATE TABLE jobs (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    -- NULL for jobs not run on behalf of an organization
+    organization_id TEXT,
+    kind TEXT NOT NULL,
+    -- The typed job, including its kind
+    payload JSONB NOT NULL,
+    status TEXT NOT NULL DEFAULT 'queued'
+        CHECK (status IN ('queued', 'running', 'succeeded', 'dead')),
+    -- Only one queued or running job may carry the same key
+    unique_key TEXT,
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL DEFAULT 5 CHECK (max_attempts > 0),
+    run_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    locked_at TIMESTAMPTZ,
+    locked_by TEXT,
+    last_error TEXT,
+    finished_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE UNIQUE INDEX idx_jobs_unique_key
+    ON jobs(unique_key)
+    WHERE status IN ('queued', 'running');
+
+CREATE INDEX idx_jobs_queued ON jobs(run_at) WHERE status = 'queued';
+
+CREATE INDEX idx_jobs_organization ON jobs(organization_id, created_at DESC);
+
+SELECT create_audit_trigger('jobs');
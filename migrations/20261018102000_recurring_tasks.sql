@@ -0,0 +1,19 @@
+-- Recurring tasks fired by the cron scheduler
+--
+-- Tasks are defined in code and synced here by the replica holding the
+-- scheduler's advisory lock, which enqueues a job whenever a task is due.
+-- Missed runs after downtime are caught up according to catch_up.
+CREATE TABLE recurring_tasks (
+    name TEXT PRIMARY KEY,
+    -- Cron expression with seconds, in UTC
+    schedule TEXT NOT NULL,
+    catch_up TEXT NOT NULL CHECK (catch_up IN ('skip', 'once', 'all')),
+    -- Scheduled time of the latest run enqueued
+    last_run_at TIMESTAMPTZ,
+    last_job_id UUID REFERENCES jobs(id) ON DELETE SET NULL,
+    next_run_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+SELECT create_audit_trigger('recurring_tasks');
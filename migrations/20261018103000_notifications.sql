@@ -0,0 +1,55 @@
+-- Notification channels and the log of messages delivered through them
+--
+-- A channel is an email distribution list, a Slack or Microsoft Teams
+-- incoming webhook, or a generic webhook whose requests are signed with
+-- HMAC-SHA256 using the channel's signing secret.
+CREATE TABLE notification_channels (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    organization_id TEXT NOT NULL,
+    name TEXT NOT NULL,
+    kind TEXT NOT NULL CHECK (kind IN ('email', 'slack', 'teams', 'webhook')),
+    -- Email addresses of email channels
+    recipients TEXT[] NOT NULL DEFAULT '{}',
+    -- Webhook URL of the other kinds
+    url TEXT,
+    signing_secret TEXT NOT NULL,
+    -- Events sent to the channel; every event when empty
+    events TEXT[] NOT NULL DEFAULT '{}',
+    enabled BOOLEAN NOT NULL DEFAULT TRUE,
+    created_by TEXT,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    CHECK ((kind = 'email') = (url IS NULL))
+);
+
+CREATE INDEX idx_notification_channels_org ON notification_channels(organization_id);
+
+SELECT create_audit_trigger('notification_channels');
+
+-- Messages are rendered when queued and sent by a job, which retries
+-- failed attempts
+CREATE TABLE notification_deliveries (
+    id UUID PRIMARY KEY DEFAULT gen_random_uuid(),
+    channel_id UUID NOT NULL REFERENCES notification_channels(id) ON DELETE CASCADE,
+    organization_id TEXT NOT NULL,
+    event TEXT NOT NULL,
+    subject TEXT NOT NULL,
+    body TEXT NOT NULL,
+    -- Template variables, sent along by generic webhooks
+    data JSONB NOT NULL DEFAULT '{}',
+    status TEXT NOT NULL DEFAULT 'pending'
+        CHECK (status IN ('pending', 'delivered', 'failed')),
+    attempts INTEGER NOT NULL DEFAULT 0,
+    max_attempts INTEGER NOT NULL,
+    -- HTTP status of the last webhook attempt
+    response_status INTEGER,
+    last_error TEXT,
+    delivered_at TIMESTAMPTZ,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW()
+);
+
+CREATE INDEX idx_notification_deliveries_channel
+    ON notification_deliveries(channel_id, created_at DESC);
+
+SELECT create_audit_trigger('notification_deliveries');
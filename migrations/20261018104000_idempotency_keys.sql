@@ -0,0 +1,27 @@
+-- Idempotency keys of mutating requests
+--
+-- The first request carrying a key claims it and, once handled, stores its
+-- response, which retries with the same key get back instead of running
+-- the request again. Keys are kept for 24 hours.
+CREATE TABLE idempotency_keys (
+    -- Organization of the caller, or `user:<id>` without an active one
+    scope TEXT NOT NULL,
+    key TEXT NOT NULL,
+    -- SHA-256 of the method, path and body of the request
+    fingerprint TEXT NOT NULL,
+    -- Id of the current claim, so a request whose claim was taken over
+    -- cannot store or release the key of the one that took it
+    claim_id UUID NOT NULL,
+    -- Response, NULL while the first request is in flight
+    status SMALLINT,
+    response_headers JSONB,
+    response_body BYTEA,
+    expires_at TIMESTAMPTZ NOT NULL,
+    created_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    updated_at TIMESTAMPTZ NOT NULL DEFAULT NOW(),
+    PRIMARY KEY (scope, key)
+);
+
+SELECT create_audit_trigger('idempotency_keys');
+
+CREATE INDEX idx_idempotency_keys_expires ON idempotency_keys(expires_at);
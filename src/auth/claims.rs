@@ -2,6 +2,8 @@
 
 use serde::{Deserialize, Serialize};
 
+use crate::error::AppError;
+
 /// JWT claims extracted from a validated Clerk token
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Claims {
@@ -50,6 +52,36 @@ impl Claims {
         self.org_id.as_deref()
     }
 
+    /// Get the organization ID, failing if no organization is active
+    ///
+    /// Organization-scoped data is keyed by the Clerk organization ID, so
+    /// handlers touching it should call this rather than `organization_id`.
+    pub fn require_organization_id(&self) -> Result<&str, AppError> {
+        self.organization_id()
+            .ok_or_else(|| AppError::Forbidden("An active organization is required".to_string()))
+    }
+
+    /// Fail unless the user is an administrator of the active organization
+    pub fn require_organization_admin(&self) -> Result<&str, AppError> {
+        let org_id = self.require_organization_id()?;
+        if self.organization_role() != Some("org:admin") {
+            return Err(AppError::Forbidden(
+                "Organization admin role is required".to_string(),
+            ));
+        }
+        Ok(org_id)
+    }
+
+    /// Fail unless the user is one of the configured platform admins
+    pub fn require_platform_admin(&self, admins: &[String]) -> Result<(), AppError> {
+        if !admins.iter().any(|admin| admin == self.user_id()) {
+            return Err(AppError::Forbidden(
+                "Platform admin access is required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
     /// Check if the user belongs to an organization
     pub fn has_organization(&self) -> bool {
         self.org_id.is_some()
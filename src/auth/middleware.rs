@@ -5,14 +5,13 @@ use axum::{
     http::{header::AUTHORIZATION, StatusCode},
     middleware::Next,
     response::{IntoResponse, Response},
-    Json,
 };
 use jsonwebtoken::{decode, decode_header, Validation};
-use serde_json::json;
 
 use super::claims::Claims;
 use super::jwks::SharedJwksCache;
 use crate::config::ClerkConfig;
+use crate::error::Problem;
 
 /// Extension key for storing authenticated claims
 #[derive(Clone)]
@@ -30,30 +29,34 @@ pub enum AuthError {
 
 impl IntoResponse for AuthError {
     fn into_response(self) -> Response {
-        let (status, message) = match self {
-            AuthError::MissingToken => (
+        let problem = match self {
+            AuthError::MissingToken => Problem::new(
                 StatusCode::UNAUTHORIZED,
-                "Missing authorization header".to_string(),
+                "auth.token_missing",
+                "Missing authorization header",
             ),
-            AuthError::InvalidToken(msg) => {
-                (StatusCode::UNAUTHORIZED, format!("Invalid token: {}", msg))
-            }
-            AuthError::ExpiredToken => (StatusCode::UNAUTHORIZED, "Token has expired".to_string()),
-            AuthError::InvalidIssuer => {
-                (StatusCode::UNAUTHORIZED, "Invalid token issuer".to_string())
-            }
-            AuthError::JwksError(msg) => (
+            AuthError::InvalidToken(msg) => Problem::new(
+                StatusCode::UNAUTHORIZED,
+                "auth.token_invalid",
+                format!("Invalid token: {}", msg),
+            ),
+            AuthError::ExpiredToken => Problem::new(
+                StatusCode::UNAUTHORIZED,
+                "auth.token_expired",
+                "Token has expired",
+            ),
+            AuthError::InvalidIssuer => Problem::new(
+                StatusCode::UNAUTHORIZED,
+                "auth.issuer_invalid",
+                "Invalid token issuer",
+            ),
+            AuthError::JwksError(msg) => Problem::new(
                 StatusCode::INTERNAL_SERVER_ERROR,
+                "auth.keys_unavailable",
                 format!("Authentication service error: {}", msg),
             ),
         };
-
-        let body = Json(json!({
-            "error": message,
-            "status": status.as_u16()
-        }));
-
-        (status, body).into_response()
+        problem.into_response()
     }
 }
 
@@ -134,6 +137,8 @@ pub async fn require_auth(
             _ => AuthError::InvalidToken(e.to_string()),
         })?;
 
+    crate::request_id::record_caller(&token_data.claims);
+
     // Store claims in request extensions
     request
         .extensions_mut()
@@ -0,0 +1,383 @@
+//! Minimal AWS API client
+//!
+//! Signs requests with SigV4 and parses XML responses for the handful of
+//! Query API services (EC2, RDS, ELBv2, STS) and S3 calls the backend
+//! needs. Endpoints can be overridden, globally or per service, to run
+//! against LocalStack or another emulator.
+
+pub mod sigv4;
+pub mod xml;
+
+use std::collections::HashMap;
+use std::fmt;
+
+use chrono::Utc;
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE};
+use reqwest::Method;
+
+pub use xml::Element;
+
+/// Region used for global endpoints (S3 `ListBuckets`, region discovery)
+pub const DEFAULT_REGION: &str = "us-east-1";
+
+#[derive(Clone)]
+pub struct Credentials {
+    pub access_key_id: String,
+    pub secret_access_key: String,
+    pub session_token: Option<String>,
+}
+
+impl fmt::Debug for Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Credentials")
+            .field("access_key_id", &self.access_key_id)
+            .field("secret_access_key", &"<redacted>")
+            .field(
+                "session_token",
+                &self.session_token.as_ref().map(|_| "<redacted>"),
+            )
+            .finish()
+    }
+}
+
+/// Endpoint overrides, keyed by lowercase signing name (`ec2`, `s3`, ...)
+#[derive(Debug, Clone, Default)]
+pub struct Endpoints {
+    pub default: Option<String>,
+    pub services: HashMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Service {
+    Ec2,
+    Rds,
+    ElasticLoadBalancingV2,
+    S3,
+    Sts,
+}
+
+impl Service {
+    /// Name used in endpoints and the signing scope
+    pub fn signing_name(self) -> &'static str {
+        match self {
+            Service::Ec2 => "ec2",
+            Service::Rds => "rds",
+            Service::ElasticLoadBalancingV2 => "elasticloadbalancing",
+            Service::S3 => "s3",
+            Service::Sts => "sts",
+        }
+    }
+
+    /// Query API version (S3 is a REST API and has none)
+    fn api_version(self) -> &'static str {
+        match self {
+            Service::Ec2 => "2016-11-15",
+            Service::Rds => "2014-10-31",
+            Service::ElasticLoadBalancingV2 => "2015-12-01",
+            Service::S3 => "",
+            Service::Sts => "2011-06-15",
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+pub enum AwsError {
+    #[error("request failed: {0}")]
+    Http(#[from] reqwest::Error),
+    #[error("{code}: {message}")]
+    Api {
+        status: u16,
+        code: String,
+        message: String,
+    },
+    #[error("invalid response: {0}")]
+    InvalidResponse(String),
+}
+
+impl AwsError {
+    pub fn code(&self) -> Option<&str> {
+        match self {
+            AwsError::Api { code, .. } => Some(code),
+            _ => None,
+        }
+    }
+}
+
+/// Token parameter and response field of a paginated Query API action
+#[derive(Debug, Clone, Copy)]
+pub struct Pagination {
+    pub request: &'static str,
+    pub response: &'static str,
+}
+
+impl Pagination {
+    pub const EC2: Pagination = Pagination {
+        request: "NextToken",
+        response: "nextToken",
+    };
+    pub const MARKER: Pagination = Pagination {
+        request: "Marker",
+        response: "Marker",
+    };
+    pub const NEXT_MARKER: Pagination = Pagination {
+        request: "Marker",
+        response: "NextMarker",
+    };
+}
+
+#[derive(Clone)]
+pub struct AwsClient {
+    http: reqwest::Client,
+    credentials: Credentials,
+    endpoints: Endpoints,
+}
+
+impl AwsClient {
+    pub fn new(credentials: Credentials, endpoints: Endpoints) -> Self {
+        Self {
+            http: reqwest::Client::new(),
+            credentials,
+            endpoints,
+        }
+    }
+
+    /// Base URL of a service in a region, without a trailing slash
+    fn endpoint(&self, service: Service, region: &str) -> String {
+        let name = service.signing_name();
+        self.endpoints
+            .services
+            .get(name)
+            .or(self.endpoints.default.as_ref())
+            .map(|url| url.trim_end_matches('/').to_string())
+            .unwrap_or_else(|| {
+                let suffix = if region.starts_with("cn-") {
+                    "amazonaws.com.cn"
+                } else {
+                    "amazonaws.com"
+                };
+                format!("https://{}.{}.{}", name, region, suffix)
+            })
+    }
+
+    async fn send(
+        &self,
+        service: Service,
+        region: &str,
+        method: Method,
+        path: &str,
+        query: &[(String, String)],
+        body: Vec<u8>,
+    ) -> Result<(u16, Vec<u8>), AwsError> {
+        let base = self.endpoint(service, region);
+        let url = reqwest::Url::parse(&format!("{}{}", base, path))
+            .map_err(|e| AwsError::InvalidResponse(format!("invalid endpoint {}: {}", base, e)))?;
+        let host = match url.port() {
+            Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+            None => url.host_str().unwrap_or_default().to_string(),
+        };
+
+        let content_type = (!body.is_empty())
+            .then(|| "application/x-www-form-urlencoded; charset=utf-8".to_string());
+        let extra_headers: Vec<(&str, String)> = content_type
+            .iter()
+            .map(|value| ("content-type", value.clone()))
+            .collect();
+        let signed = sigv4::sign(
+            &sigv4::SignableRequest {
+                method: method.as_str(),
+                path: url.path(),
+                query,
+                headers: &extra_headers,
+                host: &host,
+                body: &body,
+            },
+            &self.credentials,
+            region,
+            service.signing_name(),
+            Utc::now(),
+        );
+
+        let mut headers = HeaderMap::new();
+        let header = |value: &str| {
+            HeaderValue::from_str(value).map_err(|e| AwsError::InvalidResponse(e.to_string()))
+        };
+        headers.insert("authorization", header(&signed.authorization)?);
+        headers.insert("x-amz-date", header(&signed.amz_date)?);
+        headers.insert("x-amz-content-sha256", header(&signed.content_sha256)?);
+        if let Some(token) = &signed.security_token {
+            headers.insert("x-amz-security-token", header(token)?);
+        }
+        if let Some(content_type) = &content_type {
+            headers.insert(CONTENT_TYPE, header(content_type)?);
+        }
+
+        let mut url = url;
+        if !query.is_empty() {
+            url.set_query(Some(&sigv4::canonical_query(query)));
+        }
+        let response = self
+            .http
+            .request(method, url)
+            .headers(headers)
+            .body(body)
+            .send()
+            .await?;
+        let status = response.status().as_u16();
+        let bytes = response.bytes().await?.to_vec();
+        Ok((status, bytes))
+    }
+
+    /// Call a Query API action and return the response document
+    pub async fn query(
+        &self,
+        service: Service,
+        region: &str,
+        action: &str,
+        params: &[(String, String)],
+    ) -> Result<Element, AwsError> {
+        let mut form = vec![
+            ("Action".to_string(), action.to_string()),
+            ("Version".to_string(), service.api_version().to_string()),
+        ];
+        form.extend(params.iter().cloned());
+        let body = sigv4::canonical_query(&form).into_bytes();
+
+        let (status, bytes) = self
+            .send(service, region, Method::POST, "/", &[], body)
+            .await?;
+        parse_response(status, &bytes)
+    }
+
+    /// Call a paginated Query API action, returning every page. For actions
+    /// other than EC2's the token sits in the `<Action>Result` element.
+    pub async fn query_all(
+        &self,
+        service: Service,
+        region: &str,
+        action: &str,
+        params: &[(String, String)],
+        pagination: Pagination,
+    ) -> Result<Vec<Element>, AwsError> {
+        let mut pages = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut page_params = params.to_vec();
+            if let Some(token) = token.take() {
+                page_params.push((pagination.request.to_string(), token));
+            }
+            let page = self.query(service, region, action, &page_params).await?;
+            token = page
+                .text_at(&[pagination.response])
+                .or_else(|| page.text_at(&[&format!("{}Result", action), pagination.response]))
+                .map(str::to_string);
+            pages.push(page);
+            if token.is_none() {
+                return Ok(pages);
+            }
+        }
+    }
+
+    /// GET an S3 path (path-style addressing, which emulators also accept).
+    /// Returns `None` for 404 responses.
+    pub async fn s3_get(
+        &self,
+        region: &str,
+        path: &str,
+        query: &[(String, String)],
+    ) -> Result<Option<Element>, AwsError> {
+        let (status, bytes) = self
+            .send(Service::S3, region, Method::GET, path, query, Vec::new())
+            .await?;
+        if status == 404 {
+            return Ok(None);
+        }
+        parse_response(status, &bytes).map(Some)
+    }
+
+    /// Temporary credentials for a role, via STS `AssumeRole`
+    pub async fn assume_role(
+        &self,
+        role_arn: &str,
+        external_id: &str,
+        session_name: &str,
+    ) -> Result<Credentials, AwsError> {
+        let response = self
+            .query(
+                Service::Sts,
+                DEFAULT_REGION,
+                "AssumeRole",
+                &[
+                    ("RoleArn".to_string(), role_arn.to_string()),
+                    ("RoleSessionName".to_string(), session_name.to_string()),
+                    ("ExternalId".to_string(), external_id.to_string()),
+                    ("DurationSeconds".to_string(), "3600".to_string()),
+                ],
+            )
+            .await?;
+
+        let credentials = response
+            .path(&["AssumeRoleResult", "Credentials"])
+            .ok_or_else(|| AwsError::InvalidResponse("missing credentials".to_string()))?;
+        let field = |name: &str| {
+            credentials
+                .text_at(&[name])
+                .map(str::to_string)
+                .ok_or_else(|| AwsError::InvalidResponse(format!("missing {}", name)))
+        };
+        Ok(Credentials {
+            access_key_id: field("AccessKeyId")?,
+            secret_access_key: field("SecretAccessKey")?,
+            session_token: Some(field("SessionToken")?),
+        })
+    }
+
+    /// Client using other credentials against the same endpoints
+    pub fn with_credentials(&self, credentials: Credentials) -> Self {
+        Self {
+            http: self.http.clone(),
+            credentials,
+            endpoints: self.endpoints.clone(),
+        }
+    }
+
+    /// Account the client's credentials belong to, via STS `GetCallerIdentity`
+    pub async fn caller_account(&self) -> Result<String, AwsError> {
+        let response = self
+            .query(Service::Sts, DEFAULT_REGION, "GetCallerIdentity", &[])
+            .await?;
+        response
+            .text_at(&["GetCallerIdentityResult", "Account"])
+            .map(str::to_string)
+            .ok_or_else(|| AwsError::InvalidResponse("missing account".to_string()))
+    }
+}
+
+fn parse_response(status: u16, bytes: &[u8]) -> Result<Element, AwsError> {
+    let document = Element::parse(bytes);
+    if (200..300).contains(&status) {
+        return document.map_err(AwsError::InvalidResponse);
+    }
+
+    // EC2 nests errors in Response/Errors/Error, other Query APIs in
+    // ErrorResponse/Error and S3 returns a bare Error element
+    let error = document.ok().and_then(|root| {
+        [&["Errors", "Error"][..], &["Error"][..], &[][..]]
+            .iter()
+            .filter_map(|path| root.path(path))
+            .find(|e| e.child("Code").is_some())
+            .cloned()
+    });
+    Err(AwsError::Api {
+        status,
+        code: error
+            .as_ref()
+            .and_then(|e| e.text_at(&["Code"]))
+            .unwrap_or("UnknownError")
+            .to_string(),
+        message: error
+            .as_ref()
+            .and_then(|e| e.text_at(&["Message"]))
+            .map(str::to_string)
+            .unwrap_or_else(|| format!("HTTP {}", status)),
+    })
+}
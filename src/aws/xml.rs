@@ -0,0 +1,187 @@
+//! Minimal XML tree for AWS Query and S3 responses
+
+use quick_xml::events::Event;
+use quick_xml::Reader;
+use serde_json::{Map, Value};
+
+/// An element with its text and child elements. Namespace prefixes are
+/// dropped from names; attributes are ignored since AWS responses carry
+/// their data in elements.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Element {
+    pub name: String,
+    pub text: String,
+    pub children: Vec<Element>,
+}
+
+impl Element {
+    pub fn parse(input: &[u8]) -> Result<Element, String> {
+        let mut reader = Reader::from_reader(input);
+        let mut stack: Vec<Element> = Vec::new();
+        let mut buf = Vec::new();
+
+        loop {
+            match reader.read_event_into(&mut buf) {
+                Ok(Event::Start(start)) => stack.push(Element {
+                    name: local_name(start.name().as_ref()),
+                    ..Element::default()
+                }),
+                Ok(Event::Empty(empty)) => {
+                    let element = Element {
+                        name: local_name(empty.name().as_ref()),
+                        ..Element::default()
+                    };
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                Ok(Event::Text(text)) => {
+                    if let Some(current) = stack.last_mut() {
+                        current
+                            .text
+                            .push_str(&text.unescape().map_err(|e| e.to_string())?);
+                    }
+                }
+                Ok(Event::CData(data)) => {
+                    if let Some(current) = stack.last_mut() {
+                        current
+                            .text
+                            .push_str(&String::from_utf8_lossy(&data.into_inner()));
+                    }
+                }
+                Ok(Event::End(_)) => {
+                    let mut element = stack.pop().ok_or("unbalanced end tag")?;
+                    element.text = element.text.trim().to_string();
+                    match stack.last_mut() {
+                        Some(parent) => parent.children.push(element),
+                        None => return Ok(element),
+                    }
+                }
+                Ok(Event::Eof) => return Err("missing root element".to_string()),
+                Ok(_) => {}
+                Err(e) => return Err(format!("at position {}: {}", reader.error_position(), e)),
+            }
+            buf.clear();
+        }
+    }
+
+    pub fn child(&self, name: &str) -> Option<&Element> {
+        self.children.iter().find(|c| c.name == name)
+    }
+
+    /// Follow a path of child names
+    pub fn path(&self, names: &[&str]) -> Option<&Element> {
+        names
+            .iter()
+            .try_fold(self, |element, name| element.child(name))
+    }
+
+    /// Text of the child at `names`, `None` when missing or empty
+    pub fn text_at(&self, names: &[&str]) -> Option<&str> {
+        self.path(names)
+            .map(|e| e.text.as_str())
+            .filter(|text| !text.is_empty())
+    }
+
+    /// Entries of the list element at `names`, whatever their element name
+    /// (`item` for EC2, `member` for most Query APIs, a type name for RDS)
+    pub fn list(&self, names: &[&str]) -> &[Element] {
+        self.path(names)
+            .map(|e| e.children.as_slice())
+            .unwrap_or_default()
+    }
+
+    /// Convert to JSON for storing raw provider attributes.
+    ///
+    /// Leaves become strings (null when empty). Elements whose children all
+    /// share a name become arrays if that name is `item`/`member` or repeats,
+    /// or if the element is a `List`/`Set` or the plural of its children
+    /// (`DBInstances` of `DBInstance`); everything else becomes an object.
+    pub fn to_json(&self) -> Value {
+        if self.children.is_empty() {
+            return if self.text.is_empty() {
+                Value::Null
+            } else {
+                Value::String(self.text.clone())
+            };
+        }
+
+        let first = &self.children[0].name;
+        let same_names = self.children.iter().all(|c| &c.name == first);
+        let is_list = same_names
+            && (first == "item"
+                || first == "member"
+                || self.children.len() > 1
+                || self.name.ends_with("List")
+                || self.name.ends_with("Set")
+                || self
+                    .name
+                    .strip_suffix('s')
+                    .is_some_and(|singular| first.starts_with(singular)));
+        if is_list {
+            return Value::Array(self.children.iter().map(Element::to_json).collect());
+        }
+
+        let mut object = Map::new();
+        for child in &self.children {
+            let value = child.to_json();
+            match object.get_mut(&child.name) {
+                Some(Value::Array(values)) => values.push(value),
+                Some(existing) => *existing = Value::Array(vec![existing.take(), value]),
+                None => {
+                    object.insert(child.name.clone(), value);
+                }
+            }
+        }
+        Value::Object(object)
+    }
+}
+
+fn local_name(name: &[u8]) -> String {
+    let name = String::from_utf8_lossy(name);
+    match name.rsplit_once(':') {
+        Some((_, local)) => local.to_string(),
+        None => name.into_owned(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_parse_and_convert() {
+        let xml = br#"<?xml version="1.0" encoding="UTF-8"?>
+            <DescribeVolumesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+                <volumeSet>
+                    <item>
+                        <volumeId>vol-1</volumeId>
+                        <size>8</size>
+                        <tagSet><item><key>Name</key><value>a &amp; b</value></item></tagSet>
+                        <attachmentSet/>
+                        <Groups><GroupMembership><Id>sg-1</Id></GroupMembership></Groups>
+                    </item>
+                </volumeSet>
+                <nextToken/>
+            </DescribeVolumesResponse>"#;
+        let root = Element::parse(xml).unwrap();
+        assert_eq!(root.name, "DescribeVolumesResponse");
+        assert_eq!(root.list(&["volumeSet"]).len(), 1);
+        assert_eq!(root.text_at(&["nextToken"]), None);
+
+        let volume = &root.list(&["volumeSet"])[0];
+        assert_eq!(volume.text_at(&["volumeId"]), Some("vol-1"));
+        assert_eq!(
+            volume.to_json(),
+            json!({
+                "volumeId": "vol-1",
+                "size": "8",
+                "tagSet": [{"key": "Name", "value": "a & b"}],
+                "attachmentSet": null,
+                "Groups": [{"Id": "sg-1"}]
+            })
+        );
+    }
+}
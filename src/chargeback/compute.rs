@@ -0,0 +1,187 @@
+//! Statement line computation: rate rules and post-close corrections
+
+use std::collections::BTreeMap;
+
+use uuid::Uuid;
+
+use super::model::RateRule;
+
+/// Differences smaller than this are rounding noise, not corrections
+const CORRECTION_EPSILON: f64 = 0.005;
+
+/// Usage cost of one cost center for a service in an account
+#[derive(Debug, Clone, PartialEq)]
+pub struct UsageCost {
+    pub service: String,
+    pub account_id: String,
+    pub cost: f64,
+}
+
+/// A computed charge line, before it is stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct ChargeLine {
+    pub service: String,
+    pub account_id: String,
+    pub usage_cost: f64,
+    pub rate_percent: f64,
+    pub rate_amount: f64,
+    pub amount: f64,
+}
+
+/// Pick the most specific rule for a cost center and service.
+///
+/// Specificity: cost center + service, then service only, then cost center
+/// only, then organization-wide. Ties go to the first rule in `rules`.
+pub fn select_rate<'a>(
+    rules: &'a [RateRule],
+    cost_center_id: Uuid,
+    service: &str,
+) -> Option<&'a RateRule> {
+    rules
+        .iter()
+        .filter_map(|rule| {
+            let specificity = match (rule.cost_center_id, rule.service.as_deref()) {
+                (Some(cc), Some(s)) if cc == cost_center_id && s == service => 3,
+                (None, Some(s)) if s == service => 2,
+                (Some(cc), None) if cc == cost_center_id => 1,
+                (None, None) => 0,
+                _ => return None,
+            };
+            Some((specificity, rule))
+        })
+        .fold(
+            None,
+            |best: Option<(u8, &RateRule)>, (specificity, rule)| match best {
+                Some((best_specificity, _)) if best_specificity >= specificity => best,
+                _ => Some((specificity, rule)),
+            },
+        )
+        .map(|(_, rule)| rule)
+}
+
+/// Apply rate rules to a cost center's usage
+pub fn charge_lines(
+    usage: &[UsageCost],
+    rules: &[RateRule],
+    cost_center_id: Uuid,
+) -> Vec<ChargeLine> {
+    usage
+        .iter()
+        .map(|u| {
+            let rate_percent = select_rate(rules, cost_center_id, &u.service)
+                .map(|rule| rule.percent)
+                .unwrap_or(0.0);
+            let rate_amount = u.cost * rate_percent / 100.0;
+            ChargeLine {
+                service: u.service.clone(),
+                account_id: u.account_id.clone(),
+                usage_cost: u.cost,
+                rate_percent,
+                rate_amount,
+                amount: u.cost + rate_amount,
+            }
+        })
+        .collect()
+}
+
+/// Compare what a closed statement billed against freshly computed charges.
+///
+/// `billed` holds the amount already billed per (service, account), i.e. the
+/// closed charge lines plus earlier adjustments. Returns the corrections
+/// still owed, sorted by service and account.
+pub fn corrections(
+    billed: &BTreeMap<(String, String), f64>,
+    computed: &[ChargeLine],
+) -> Vec<(String, String, f64)> {
+    let mut deltas: BTreeMap<(String, String), f64> = billed
+        .iter()
+        .map(|(key, amount)| (key.clone(), -amount))
+        .collect();
+
+    for line in computed {
+        *deltas
+            .entry((line.service.clone(), line.account_id.clone()))
+            .or_default() += line.amount;
+    }
+
+    deltas
+        .into_iter()
+        .filter(|(_, delta)| delta.abs() >= CORRECTION_EPSILON)
+        .map(|((service, account_id), delta)| (service, account_id, delta))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(cost_center_id: Option<Uuid>, service: Option<&str>, percent: f64) -> RateRule {
+        RateRule {
+            id: Uuid::new_v4(),
+            cost_center_id,
+            service: service.map(str::to_string),
+            percent,
+            description: None,
+            updated_at: chrono::Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_select_rate_prefers_most_specific() {
+        let cc = Uuid::new_v4();
+        let other = Uuid::new_v4();
+        let rules = vec![
+            rule(None, None, 5.0),
+            rule(Some(cc), None, 10.0),
+            rule(None, Some("AmazonS3"), -20.0),
+            rule(Some(cc), Some("AmazonEC2"), 15.0),
+            rule(Some(other), Some("AmazonRDS"), 50.0),
+        ];
+
+        let percent = |service| select_rate(&rules, cc, service).map(|r| r.percent);
+        assert_eq!(percent("AmazonEC2"), Some(15.0));
+        assert_eq!(percent("AmazonS3"), Some(-20.0));
+        assert_eq!(percent("AmazonRDS"), Some(10.0));
+        assert_eq!(
+            select_rate(&rules, other, "AWSLambda").map(|r| r.percent),
+            Some(5.0)
+        );
+    }
+
+    #[test]
+    fn test_charge_lines_apply_markup() {
+        let cc = Uuid::new_v4();
+        let usage = vec![UsageCost {
+            service: "AmazonEC2".to_string(),
+            account_id: "111122223333".to_string(),
+            cost: 200.0,
+        }];
+        let lines = charge_lines(&usage, &[rule(Some(cc), None, 10.0)], cc);
+
+        assert_eq!(lines[0].rate_amount, 20.0);
+        assert_eq!(lines[0].amount, 220.0);
+    }
+
+    #[test]
+    fn test_corrections_only_report_changes() {
+        let key = |s: &str| (s.to_string(), "1".to_string());
+        let billed = BTreeMap::from([(key("AmazonEC2"), 100.0), (key("AmazonS3"), 10.0)]);
+        let line = |service: &str, amount: f64| ChargeLine {
+            service: service.to_string(),
+            account_id: "1".to_string(),
+            usage_cost: amount,
+            rate_percent: 0.0,
+            rate_amount: 0.0,
+            amount,
+        };
+        let computed = vec![line("AmazonEC2", 100.001), line("AWSLambda", 3.0)];
+
+        assert_eq!(
+            corrections(&billed, &computed),
+            vec![
+                ("AWSLambda".to_string(), "1".to_string(), 3.0),
+                ("AmazonS3".to_string(), "1".to_string(), -10.0),
+            ]
+        );
+    }
+}
@@ -0,0 +1,277 @@
+//! CSV and PDF rendering of chargeback statements
+
+use std::borrow::Cow;
+
+use super::model::StatementDetail;
+
+pub fn to_csv(detail: &StatementDetail) -> Result<Vec<u8>, csv::Error> {
+    let statement = &detail.statement;
+    let mut writer = csv::Writer::from_writer(Vec::new());
+
+    writer.write_record([
+        "cost_center",
+        "period",
+        "status",
+        "line_type",
+        "service",
+        "account_id",
+        "description",
+        "usage_cost",
+        "rate_percent",
+        "rate_amount",
+        "amount",
+        "currency",
+    ])?;
+
+    for line in &detail.lines {
+        writer.write_record([
+            &*csv_text(&statement.cost_center_name),
+            &statement.period.to_string(),
+            statement.status.as_str(),
+            &line.line_type,
+            &csv_text(&line.service),
+            &csv_text(&line.account_id),
+            &csv_text(&line.description),
+            &format!("{:.2}", line.usage_cost),
+            &format!("{:.2}", line.rate_percent),
+            &format!("{:.2}", line.rate_amount),
+            &format!("{:.2}", line.amount),
+            &statement.currency,
+        ])?;
+    }
+
+    writer
+        .into_inner()
+        .map_err(|e| csv::Error::from(e.into_error()))
+}
+
+/// Keep spreadsheets from evaluating a text cell as a formula by prefixing
+/// cells that start with a formula character with `'`.
+fn csv_text(s: &str) -> Cow<'_, str> {
+    if s.starts_with(['=', '+', '-', '@', '\t', '\r']) {
+        Cow::Owned(format!("'{}", s))
+    } else {
+        Cow::Borrowed(s)
+    }
+}
+
+pub fn to_pdf(detail: &StatementDetail) -> Vec<u8> {
+    let statement = &detail.statement;
+    let mut text = vec![
+        format!("Chargeback statement - {}", statement.cost_center_name),
+        format!(
+            "Period: {}    Status: {}    Currency: {}",
+            statement.period,
+            statement.status.as_str(),
+            statement.currency
+        ),
+        String::new(),
+        format!(
+            "{:<10} {:<28} {:<14} {:>12} {:>8} {:>12}",
+            "Type", "Service", "Account", "Usage", "Rate %", "Amount"
+        ),
+        "-".repeat(89),
+    ];
+
+    for line in &detail.lines {
+        text.push(format!(
+            "{:<10} {:<28} {:<14} {:>12.2} {:>8.2} {:>12.2}",
+            line.line_type,
+            truncate(&line.service, 28),
+            truncate(&line.account_id, 14),
+            line.usage_cost,
+            line.rate_percent,
+            line.amount
+        ));
+    }
+
+    text.push("-".repeat(89));
+    text.push(format!("{:<77}{:>12.2}", "Subtotal", statement.subtotal));
+    text.push(format!(
+        "{:<77}{:>12.2}",
+        "Adjustments", statement.adjustments_total
+    ));
+    text.push(format!("{:<77}{:>12.2}", "Total", statement.total));
+
+    render_text_pdf(&text)
+}
+
+fn truncate(s: &str, max: usize) -> String {
+    s.chars().take(max).collect()
+}
+
+/// Render monospaced lines of text onto A4 landscape pages
+fn render_text_pdf(lines: &[String]) -> Vec<u8> {
+    const LINES_PER_PAGE: usize = 44;
+    const FONT_SIZE: u32 = 9;
+    const LEADING: u32 = 12;
+    const PAGE_WIDTH: u32 = 842;
+    const PAGE_HEIGHT: u32 = 595;
+    const MARGIN: u32 = 36;
+
+    let pages: Vec<&[String]> = if lines.is_empty() {
+        vec![&[]]
+    } else {
+        lines.chunks(LINES_PER_PAGE).collect()
+    };
+
+    // Object layout: 1 catalog, 2 page tree, 3 font, then a page and a
+    // content stream object for each page.
+    let page_ids: Vec<usize> = (0..pages.len()).map(|i| 4 + i * 2).collect();
+    let mut objects: Vec<Vec<u8>> = vec![
+        b"<< /Type /Catalog /Pages 2 0 R >>".to_vec(),
+        format!(
+            "<< /Type /Pages /Kids [{}] /Count {} >>",
+            page_ids
+                .iter()
+                .map(|id| format!("{} 0 R", id))
+                .collect::<Vec<_>>()
+                .join(" "),
+            pages.len()
+        )
+        .into_bytes(),
+        b"<< /Type /Font /Subtype /Type1 /BaseFont /Courier >>".to_vec(),
+    ];
+
+    for (page, page_id) in pages.iter().zip(&page_ids) {
+        let mut content = format!(
+            "BT /F1 {} Tf {} TL {} {} Td\n",
+            FONT_SIZE,
+            LEADING,
+            MARGIN,
+            PAGE_HEIGHT - MARGIN
+        );
+        for line in page.iter() {
+            content.push_str(&format!("({}) Tj T*\n", escape_pdf_text(line)));
+        }
+        content.push_str("ET");
+
+        objects.push(
+            format!(
+                "<< /Type /Page /Parent 2 0 R /MediaBox [0 0 {} {}] \
+                 /Resources << /Font << /F1 3 0 R >> >> /Contents {} 0 R >>",
+                PAGE_WIDTH,
+                PAGE_HEIGHT,
+                page_id + 1
+            )
+            .into_bytes(),
+        );
+        objects.push(
+            format!(
+                "<< /Length {} >>\nstream\n{}\nendstream",
+                content.len(),
+                content
+            )
+            .into_bytes(),
+        );
+    }
+
+    let mut pdf = b"%PDF-1.4\n".to_vec();
+    let mut offsets = Vec::with_capacity(objects.len());
+    for (i, object) in objects.iter().enumerate() {
+        offsets.push(pdf.len());
+        pdf.extend_from_slice(format!("{} 0 obj\n", i + 1).as_bytes());
+        pdf.extend_from_slice(object);
+        pdf.extend_from_slice(b"\nendobj\n");
+    }
+
+    let xref_offset = pdf.len();
+    pdf.extend_from_slice(format!("xref\n0 {}\n", objects.len() + 1).as_bytes());
+    pdf.extend_from_slice(b"0000000000 65535 f \n");
+    for offset in offsets {
+        pdf.extend_from_slice(format!("{:010} 00000 n \n", offset).as_bytes());
+    }
+    pdf.extend_from_slice(
+        format!(
+            "trailer\n<< /Size {} /Root 1 0 R >>\nstartxref\n{}\n%%EOF\n",
+            objects.len() + 1,
+            xref_offset
+        )
+        .as_bytes(),
+    );
+
+    pdf
+}
+
+/// Escape PDF string delimiters; non-ASCII characters are replaced since the
+/// standard Courier font only covers Latin-1 reliably.
+fn escape_pdf_text(s: &str) -> String {
+    let mut escaped = String::with_capacity(s.len());
+    for c in s.chars() {
+        match c {
+            '(' | ')' | '\\' => {
+                escaped.push('\\');
+                escaped.push(c);
+            }
+            c if c.is_ascii() && !c.is_ascii_control() => escaped.push(c),
+            _ => escaped.push('?'),
+        }
+    }
+    escaped
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chargeback::model::{Statement, StatementLine, StatementStatus};
+    use chrono::Utc;
+    use uuid::Uuid;
+
+    #[test]
+    fn test_csv_escapes_formulas() {
+        let line = |description: &str, amount: f64| StatementLine {
+            id: Uuid::new_v4(),
+            line_type: "adjustment".to_string(),
+            service: "AmazonEC2".to_string(),
+            account_id: "123456789012".to_string(),
+            description: description.to_string(),
+            usage_cost: amount,
+            rate_percent: 0.0,
+            rate_amount: 0.0,
+            amount,
+        };
+        let detail = StatementDetail {
+            statement: Statement {
+                id: Uuid::new_v4(),
+                cost_center_id: Uuid::new_v4(),
+                cost_center_name: "=HYPERLINK(\"http://evil\")".to_string(),
+                period: "2026-09".parse().unwrap(),
+                status: StatementStatus::Closed,
+                currency: "USD".to_string(),
+                subtotal: 0.0,
+                adjustments_total: -5.0,
+                total: -5.0,
+                generated_at: Utc::now(),
+                closed_at: None,
+                closed_by: None,
+                pending_adjustments: 0.0,
+            },
+            lines: vec![line("@SUM(A1:A2)", -5.0), line("Credit", 1.0)],
+        };
+
+        let csv = String::from_utf8(to_csv(&detail).unwrap()).unwrap();
+        let rows: Vec<&str> = csv.lines().skip(1).collect();
+        assert_eq!(
+            rows[0],
+            "\"'=HYPERLINK(\"\"http://evil\"\")\",2026-09,closed,adjustment,AmazonEC2,\
+             123456789012,'@SUM(A1:A2),-5.00,0.00,0.00,-5.00,USD"
+        );
+        assert!(rows[1].contains(",Credit,1.00,"));
+    }
+
+    #[test]
+    fn test_render_text_pdf_structure() {
+        let lines: Vec<String> = (0..50).map(|i| format!("line (#{})", i)).collect();
+        let pdf = render_text_pdf(&lines);
+        let text = String::from_utf8(pdf.clone()).unwrap();
+
+        assert!(text.starts_with("%PDF-1.4"));
+        assert!(text.contains("/Count 2"));
+        assert!(text.contains("(line \\(#49\\)) Tj"));
+
+        // startxref must point at the xref table
+        let start = text.rfind("startxref\n").unwrap() + "startxref\n".len();
+        let offset: usize = text[start..].lines().next().unwrap().parse().unwrap();
+        assert!(text[offset..].starts_with("xref\n0 8\n"));
+    }
+}
@@ -0,0 +1,14 @@
+//! Showback/chargeback statements per cost center
+//!
+//! Spend is attributed to cost centers by condition rules, priced with
+//! markup/discount rate rules and collected into one statement per cost
+//! center and month. Once finance closes a statement it is never rewritten:
+//! later data corrections are recorded as adjustments and carried onto the
+//! next statement generated for that cost center.
+
+pub mod compute;
+pub mod export;
+pub mod model;
+pub mod store;
+
+pub use model::{CostCenter, RateRule, Statement, StatementDetail, StatementLine, StatementStatus};
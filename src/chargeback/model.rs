@@ -0,0 +1,177 @@
+//! Chargeback API and storage types
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::conditional::Versioned;
+use crate::costs::Month;
+use crate::tags::normalize::TagCondition;
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CostCenter {
+    pub id: Uuid,
+    pub name: String,
+    pub code: Option<String>,
+    /// Higher priorities are matched first; a line item belongs to one center
+    pub priority: i32,
+    pub conditions: Vec<TagCondition>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for CostCenter {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct CostCenterRow {
+    pub id: Uuid,
+    pub name: String,
+    pub code: Option<String>,
+    pub priority: i32,
+    pub conditions: Json<Vec<TagCondition>>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<CostCenterRow> for CostCenter {
+    fn from(row: CostCenterRow) -> Self {
+        CostCenter {
+            id: row.id,
+            name: row.name,
+            code: row.code,
+            priority: row.priority,
+            conditions: row.conditions.0,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// Markup (positive) or discount (negative) percentage applied to charges
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RateRule {
+    pub id: Uuid,
+    /// Restrict the rule to one cost center (all cost centers when absent)
+    pub cost_center_id: Option<Uuid>,
+    /// Restrict the rule to one service (all services when absent)
+    pub service: Option<String>,
+    pub percent: f64,
+    pub description: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for RateRule {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum StatementStatus {
+    Draft,
+    Closed,
+}
+
+impl StatementStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            StatementStatus::Draft => "draft",
+            StatementStatus::Closed => "closed",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        if s == "closed" {
+            StatementStatus::Closed
+        } else {
+            StatementStatus::Draft
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Statement {
+    pub id: Uuid,
+    pub cost_center_id: Uuid,
+    pub cost_center_name: String,
+    pub period: Month,
+    pub status: StatementStatus,
+    pub currency: String,
+    /// Sum of charge lines
+    pub subtotal: f64,
+    /// Sum of adjustment lines carried over from closed statements
+    pub adjustments_total: f64,
+    pub total: f64,
+    pub generated_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub closed_by: Option<String>,
+    /// Corrections to this statement found after it was closed
+    pub pending_adjustments: f64,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct StatementRow {
+    pub id: Uuid,
+    pub cost_center_id: Uuid,
+    pub cost_center_name: String,
+    pub period: NaiveDate,
+    pub status: String,
+    pub currency: String,
+    pub subtotal: f64,
+    pub adjustments_total: f64,
+    pub total: f64,
+    pub generated_at: DateTime<Utc>,
+    pub closed_at: Option<DateTime<Utc>>,
+    pub closed_by: Option<String>,
+    pub pending_adjustments: f64,
+}
+
+impl From<StatementRow> for Statement {
+    fn from(row: StatementRow) -> Self {
+        Statement {
+            id: row.id,
+            cost_center_id: row.cost_center_id,
+            cost_center_name: row.cost_center_name,
+            period: Month::containing(row.period),
+            status: StatementStatus::parse(&row.status),
+            currency: row.currency,
+            subtotal: row.subtotal,
+            adjustments_total: row.adjustments_total,
+            total: row.total,
+            generated_at: row.generated_at,
+            closed_at: row.closed_at,
+            closed_by: row.closed_by,
+            pending_adjustments: row.pending_adjustments,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementLine {
+    pub id: Uuid,
+    /// `charge` or `adjustment`
+    pub line_type: String,
+    pub service: String,
+    pub account_id: String,
+    pub description: String,
+    pub usage_cost: f64,
+    pub rate_percent: f64,
+    pub rate_amount: f64,
+    pub amount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct StatementDetail {
+    #[serde(flatten)]
+    pub statement: Statement,
+    pub lines: Vec<StatementLine>,
+}
@@ -0,0 +1,559 @@
+//! Persistence and generation of chargeback statements
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use super::compute::{self, ChargeLine, UsageCost};
+use super::model::{
+    CostCenter, CostCenterRow, RateRule, Statement, StatementDetail, StatementLine, StatementRow,
+    StatementStatus,
+};
+use crate::costs::Month;
+use crate::db::DbPool;
+use crate::tags::normalize::TagCondition;
+use crate::tags::sql::push_conditions;
+
+/// Fields of a cost center supplied when creating or replacing it
+#[derive(Debug, Clone)]
+pub struct CostCenterInput {
+    pub name: String,
+    pub code: Option<String>,
+    pub priority: i32,
+    pub conditions: Vec<TagCondition>,
+}
+
+const COST_CENTER_COLUMNS: &str = "id, name, code, priority, conditions, updated_at";
+
+const RATE_RULE_COLUMNS: &str = "id, cost_center_id, service, percent, description, updated_at";
+
+pub async fn list_cost_centers(db: &DbPool, org_id: &str) -> Result<Vec<CostCenter>, sqlx::Error> {
+    let rows: Vec<CostCenterRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM cost_centers
+         WHERE organization_id = $1
+         ORDER BY priority DESC, created_at",
+        COST_CENTER_COLUMNS
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(CostCenter::from).collect())
+}
+
+pub async fn get_cost_center(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<CostCenter>, sqlx::Error> {
+    let row: Option<CostCenterRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM cost_centers WHERE organization_id = $1 AND id = $2",
+        COST_CENTER_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(CostCenter::from))
+}
+
+pub async fn create_cost_center(
+    db: &DbPool,
+    org_id: &str,
+    input: CostCenterInput,
+) -> Result<CostCenter, sqlx::Error> {
+    let row: CostCenterRow = sqlx::query_as(&format!(
+        "INSERT INTO cost_centers (organization_id, name, code, priority, conditions)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING {}",
+        COST_CENTER_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(&input.name)
+    .bind(&input.code)
+    .bind(input.priority)
+    .bind(Json(&input.conditions))
+    .fetch_one(db)
+    .await?;
+
+    Ok(row.into())
+}
+
+/// Returns `None` if no such cost center exists, or it no longer has
+/// `version` when one is given
+pub async fn update_cost_center(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    input: CostCenterInput,
+    version: Option<DateTime<Utc>>,
+) -> Result<Option<CostCenter>, sqlx::Error> {
+    let row: Option<CostCenterRow> = sqlx::query_as(&format!(
+        "UPDATE cost_centers SET name = $3, code = $4, priority = $5, conditions = $6
+         WHERE organization_id = $1 AND id = $2
+           AND ($7::timestamptz IS NULL OR updated_at = $7)
+         RETURNING {}",
+        COST_CENTER_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .bind(&input.name)
+    .bind(&input.code)
+    .bind(input.priority)
+    .bind(Json(&input.conditions))
+    .bind(version)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(CostCenter::from))
+}
+
+/// Whether any statement was generated for the organization's cost center
+pub async fn cost_center_has_statements(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (
+             SELECT 1 FROM chargeback_statements s
+             JOIN cost_centers c ON c.id = s.cost_center_id
+             WHERE c.organization_id = $1 AND c.id = $2
+         )",
+    )
+    .bind(org_id)
+    .bind(id)
+    .fetch_one(db)
+    .await
+}
+
+/// Returns `false` if no such cost center exists, or it no longer has
+/// `version` when one is given
+pub async fn delete_cost_center(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM cost_centers WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+pub async fn list_rate_rules(db: &DbPool, org_id: &str) -> Result<Vec<RateRule>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM chargeback_rate_rules
+         WHERE organization_id = $1
+         ORDER BY created_at",
+        RATE_RULE_COLUMNS
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await
+}
+
+pub async fn get_rate_rule(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<RateRule>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM chargeback_rate_rules WHERE organization_id = $1 AND id = $2",
+        RATE_RULE_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Returns `None` if `cost_center_id` does not belong to the organization
+pub async fn create_rate_rule(
+    db: &DbPool,
+    org_id: &str,
+    cost_center_id: Option<Uuid>,
+    service: Option<String>,
+    percent: f64,
+    description: Option<String>,
+) -> Result<Option<RateRule>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "INSERT INTO chargeback_rate_rules
+             (organization_id, cost_center_id, service, percent, description)
+         SELECT $1, $2, $3, $4, $5
+         WHERE $2::uuid IS NULL
+            OR EXISTS (SELECT 1 FROM cost_centers WHERE organization_id = $1 AND id = $2)
+         RETURNING {}",
+        RATE_RULE_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(cost_center_id)
+    .bind(service)
+    .bind(percent)
+    .bind(description)
+    .fetch_optional(db)
+    .await
+}
+
+/// Returns `false` if no such rule exists, or it no longer has `version`
+/// when one is given
+pub async fn delete_rate_rule(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM chargeback_rate_rules WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Usage cost per cost center, service and account for a month.
+///
+/// Each line item is attributed to the first cost center (in priority order)
+/// whose conditions match; unmatched spend is not returned.
+async fn usage_by_cost_center(
+    db: &DbPool,
+    org_id: &str,
+    month: Month,
+    centers: &[CostCenter],
+) -> Result<BTreeMap<Uuid, Vec<UsageCost>>, sqlx::Error> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new("SELECT CASE");
+    for center in centers {
+        builder.push(" WHEN ");
+        push_conditions(&mut builder, &center.conditions, "c");
+        builder.push(" THEN ").push_bind(center.id);
+    }
+    builder
+        .push(" END AS cost_center_id, c.service, c.account_id, SUM(c.cost)")
+        .push(" FROM cost_line_items c WHERE c.organization_id = ")
+        .push_bind(org_id.to_string())
+        .push(" AND c.usage_start >= ")
+        .push_bind(month.start())
+        .push(" AND c.usage_start < ")
+        .push_bind(month.end())
+        .push(" GROUP BY 1, 2, 3 ORDER BY 2, 3");
+
+    let rows: Vec<(Option<Uuid>, String, String, f64)> =
+        builder.build_query_as().fetch_all(db).await?;
+
+    let mut usage: BTreeMap<Uuid, Vec<UsageCost>> = BTreeMap::new();
+    for (cost_center_id, service, account_id, cost) in rows {
+        if let Some(id) = cost_center_id {
+            usage.entry(id).or_default().push(UsageCost {
+                service,
+                account_id,
+                cost,
+            });
+        }
+    }
+
+    Ok(usage)
+}
+
+/// Generate (or regenerate) statements for every cost center for a month.
+///
+/// Draft statements are rebuilt from current data. Closed statements are left
+/// untouched; any difference against what they billed is recorded as an
+/// adjustment that lands on the next statement generated for that cost center.
+pub async fn generate_statements(
+    db: &DbPool,
+    org_id: &str,
+    month: Month,
+) -> Result<Vec<Statement>, sqlx::Error> {
+    let centers = list_cost_centers(db, org_id).await?;
+    if centers.is_empty() {
+        return Ok(Vec::new());
+    }
+    let rules = list_rate_rules(db, org_id).await?;
+    let mut usage = usage_by_cost_center(db, org_id, month, &centers).await?;
+
+    let mut tx = db.begin().await?;
+    for center in &centers {
+        let lines = compute::charge_lines(
+            &usage.remove(&center.id).unwrap_or_default(),
+            &rules,
+            center.id,
+        );
+        generate_for_center(&mut tx, org_id, center.id, month, &lines).await?;
+    }
+    tx.commit().await?;
+
+    list_statements(db, org_id, Some(month), None).await
+}
+
+async fn generate_for_center(
+    tx: &mut Transaction<'_, Postgres>,
+    org_id: &str,
+    cost_center_id: Uuid,
+    month: Month,
+    lines: &[ChargeLine],
+) -> Result<(), sqlx::Error> {
+    let existing: Option<(Uuid, String)> = sqlx::query_as(
+        "SELECT id, status FROM chargeback_statements
+         WHERE cost_center_id = $1 AND period = $2
+         FOR UPDATE",
+    )
+    .bind(cost_center_id)
+    .bind(month.first_day())
+    .fetch_optional(&mut **tx)
+    .await?;
+
+    let statement_id = match existing {
+        Some((id, status)) if StatementStatus::parse(&status) == StatementStatus::Closed => {
+            return record_corrections(tx, org_id, cost_center_id, id, lines).await;
+        }
+        Some((id, _)) => {
+            sqlx::query(
+                "UPDATE chargeback_adjustments SET applied_statement_id = NULL
+                 WHERE applied_statement_id = $1",
+            )
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+            sqlx::query("DELETE FROM chargeback_statement_lines WHERE statement_id = $1")
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+            sqlx::query("UPDATE chargeback_statements SET generated_at = NOW() WHERE id = $1")
+                .bind(id)
+                .execute(&mut **tx)
+                .await?;
+            id
+        }
+        None => {
+            let has_pending: bool = sqlx::query_scalar(
+                "SELECT EXISTS (
+                     SELECT 1 FROM chargeback_adjustments a
+                     JOIN chargeback_statements s ON s.id = a.statement_id
+                     WHERE a.cost_center_id = $1 AND a.applied_statement_id IS NULL
+                       AND s.period < $2
+                 )",
+            )
+            .bind(cost_center_id)
+            .bind(month.first_day())
+            .fetch_one(&mut **tx)
+            .await?;
+
+            if lines.is_empty() && !has_pending {
+                return Ok(());
+            }
+
+            sqlx::query_scalar(
+                "INSERT INTO chargeback_statements (organization_id, cost_center_id, period)
+                 VALUES ($1, $2, $3)
+                 RETURNING id",
+            )
+            .bind(org_id)
+            .bind(cost_center_id)
+            .bind(month.first_day())
+            .fetch_one(&mut **tx)
+            .await?
+        }
+    };
+
+    if !lines.is_empty() {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO chargeback_statement_lines (statement_id, line_type, service, \
+             account_id, description, usage_cost, rate_percent, rate_amount, amount) ",
+        );
+        builder.push_values(lines, |mut row, line| {
+            row.push_bind(statement_id)
+                .push_bind("charge")
+                .push_bind(&line.service)
+                .push_bind(&line.account_id)
+                .push_bind(format!(
+                    "{} usage in account {}",
+                    line.service, line.account_id
+                ))
+                .push_bind(line.usage_cost)
+                .push_bind(line.rate_percent)
+                .push_bind(line.rate_amount)
+                .push_bind(line.amount);
+        });
+        builder.build().execute(&mut **tx).await?;
+    }
+
+    // Carry pending corrections of earlier closed months onto this statement
+    sqlx::query(
+        "WITH pending AS (
+             UPDATE chargeback_adjustments a SET applied_statement_id = $1
+             FROM chargeback_statements s
+             WHERE s.id = a.statement_id AND a.cost_center_id = $2
+               AND a.applied_statement_id IS NULL AND s.period < $3
+             RETURNING a.id, a.service, a.account_id, a.amount, a.reason, s.period
+         )
+         INSERT INTO chargeback_statement_lines (statement_id, line_type, service, account_id,
+             description, usage_cost, amount, adjustment_id)
+         SELECT $1, 'adjustment', service, account_id,
+                reason || ' (' || to_char(period, 'YYYY-MM') || ')', amount, amount, id
+         FROM pending",
+    )
+    .bind(statement_id)
+    .bind(cost_center_id)
+    .bind(month.first_day())
+    .execute(&mut **tx)
+    .await?;
+
+    Ok(())
+}
+
+async fn record_corrections(
+    tx: &mut Transaction<'_, Postgres>,
+    org_id: &str,
+    cost_center_id: Uuid,
+    statement_id: Uuid,
+    lines: &[ChargeLine],
+) -> Result<(), sqlx::Error> {
+    let billed_rows: Vec<(String, String, f64)> = sqlx::query_as(
+        "SELECT service, account_id, SUM(amount) FROM (
+             SELECT service, account_id, amount FROM chargeback_statement_lines
+             WHERE statement_id = $1 AND line_type = 'charge'
+             UNION ALL
+             SELECT service, account_id, amount FROM chargeback_adjustments
+             WHERE statement_id = $1
+         ) billed
+         GROUP BY service, account_id",
+    )
+    .bind(statement_id)
+    .fetch_all(&mut **tx)
+    .await?;
+
+    let billed: BTreeMap<(String, String), f64> = billed_rows
+        .into_iter()
+        .map(|(service, account_id, amount)| ((service, account_id), amount))
+        .collect();
+
+    for (service, account_id, amount) in compute::corrections(&billed, lines) {
+        sqlx::query(
+            "INSERT INTO chargeback_adjustments
+                 (organization_id, cost_center_id, statement_id, service, account_id, amount, reason)
+             VALUES ($1, $2, $3, $4, $5, $6, 'Correction to closed statement')",
+        )
+        .bind(org_id)
+        .bind(cost_center_id)
+        .bind(statement_id)
+        .bind(&service)
+        .bind(&account_id)
+        .bind(amount)
+        .execute(&mut **tx)
+        .await?;
+    }
+
+    Ok(())
+}
+
+fn statement_select() -> QueryBuilder<'static, Postgres> {
+    QueryBuilder::new(
+        "SELECT s.id, s.cost_center_id, cc.name AS cost_center_name, s.period, s.status,
+             s.currency,
+             COALESCE((SELECT SUM(amount) FROM chargeback_statement_lines
+                       WHERE statement_id = s.id AND line_type = 'charge'), 0) AS subtotal,
+             COALESCE((SELECT SUM(amount) FROM chargeback_statement_lines
+                       WHERE statement_id = s.id AND line_type = 'adjustment'), 0)
+                 AS adjustments_total,
+             COALESCE((SELECT SUM(amount) FROM chargeback_statement_lines
+                       WHERE statement_id = s.id), 0) AS total,
+             s.generated_at, s.closed_at, s.closed_by,
+             COALESCE((SELECT SUM(amount) FROM chargeback_adjustments
+                       WHERE statement_id = s.id AND applied_statement_id IS NULL), 0)
+                 AS pending_adjustments
+         FROM chargeback_statements s
+         JOIN cost_centers cc ON cc.id = s.cost_center_id
+         WHERE s.organization_id = ",
+    )
+}
+
+pub async fn list_statements(
+    db: &DbPool,
+    org_id: &str,
+    month: Option<Month>,
+    cost_center_id: Option<Uuid>,
+) -> Result<Vec<Statement>, sqlx::Error> {
+    let mut builder = statement_select();
+    builder.push_bind(org_id.to_string());
+    if let Some(month) = month {
+        builder
+            .push(" AND s.period = ")
+            .push_bind(month.first_day());
+    }
+    if let Some(id) = cost_center_id {
+        builder.push(" AND s.cost_center_id = ").push_bind(id);
+    }
+    builder.push(" ORDER BY s.period DESC, cc.name");
+
+    let rows: Vec<StatementRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(rows.into_iter().map(Statement::from).collect())
+}
+
+pub async fn get_statement(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<StatementDetail>, sqlx::Error> {
+    let mut builder = statement_select();
+    builder
+        .push_bind(org_id.to_string())
+        .push(" AND s.id = ")
+        .push_bind(id);
+
+    let Some(row): Option<StatementRow> = builder.build_query_as().fetch_optional(db).await? else {
+        return Ok(None);
+    };
+
+    let lines: Vec<StatementLine> = sqlx::query_as(
+        "SELECT id, line_type, service, account_id, description, usage_cost, rate_percent,
+             rate_amount, amount
+         FROM chargeback_statement_lines
+         WHERE statement_id = $1
+         ORDER BY line_type, service, account_id",
+    )
+    .bind(id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(Some(StatementDetail {
+        statement: row.into(),
+        lines,
+    }))
+}
+
+/// Close a draft statement. Returns `false` if it is not an open draft.
+pub async fn close_statement(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    closed_by: &str,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE chargeback_statements
+         SET status = 'closed', closed_at = NOW(), closed_by = $3
+         WHERE organization_id = $1 AND id = $2 AND status = 'draft'",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(closed_by)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
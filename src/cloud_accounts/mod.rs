@@ -0,0 +1,10 @@
+//! Connected cloud accounts
+//!
+//! An account records how the backend reaches a provider account: for AWS
+//! an optional IAM role (assumed with the backend's credentials and the
+//! account's external id) and the regions to discover resources in.
+
+pub mod model;
+pub mod store;
+
+pub use model::{CloudAccount, CloudAccountUpdate, NewCloudAccount};
@@ -0,0 +1,139 @@
+//! Cloud account API and storage types
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::cloud_ids::{self, Arn, Region};
+use crate::conditional::Versioned;
+use crate::costs::Provider;
+use crate::validation::FIELD_PARAM;
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudAccount {
+    pub id: Uuid,
+    pub provider: String,
+    /// Provider account id, e.g. the 12-digit AWS account number
+    pub account_id: String,
+    pub name: String,
+    /// Role assumed for API access; the backend's own credentials are used
+    /// when absent
+    pub role_arn: Option<String>,
+    /// External id the role's trust policy must require
+    pub external_id: String,
+    /// Regions to discover; every enabled region when empty
+    pub regions: Vec<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for CloudAccount {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_account_id"))]
+pub struct NewCloudAccount {
+    pub provider: Provider,
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub account_id: String,
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub name: String,
+    #[validate(custom(function = "validate_role_arn"))]
+    pub role_arn: Option<Arn>,
+    #[serde(default)]
+    #[validate(custom(function = "validate_regions"))]
+    pub regions: Vec<Region>,
+}
+
+/// Fields that can change after an account is connected
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CloudAccountUpdate {
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub name: String,
+    #[validate(custom(function = "validate_role_arn"))]
+    pub role_arn: Option<Arn>,
+    #[serde(default)]
+    #[validate(custom(function = "validate_regions"))]
+    pub regions: Vec<Region>,
+}
+
+fn invalid(code: &'static str, message: String) -> ValidationError {
+    let mut err = ValidationError::new(code);
+    err.message = Some(message.into());
+    err
+}
+
+fn validate_account_id(account: &NewCloudAccount) -> Result<(), ValidationError> {
+    cloud_ids::validate_account_id(account.provider, &account.account_id).map_err(|mut err| {
+        err.add_param(FIELD_PARAM.into(), &"account_id");
+        err
+    })
+}
+
+fn validate_role_arn(arn: &Arn) -> Result<(), ValidationError> {
+    if arn.service() != "iam" || arn.resource_type() != Some("role") {
+        return Err(invalid(
+            "role_arn",
+            format!("'{}' is not an IAM role ARN", arn),
+        ));
+    }
+    Ok(())
+}
+
+/// Discovery runs in AWS regions, named like `us-east-1` or `us-gov-west-1`
+fn validate_regions(regions: &[Region]) -> Result<(), ValidationError> {
+    match regions.iter().find(|r| !r.is_aws()) {
+        Some(region) => Err(invalid(
+            "regions",
+            format!("'{}' is not an AWS region", region),
+        )),
+        None => Ok(()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn account(account_id: &str, role_arn: Option<&str>, regions: &[&str]) -> NewCloudAccount {
+        NewCloudAccount {
+            provider: Provider::Aws,
+            account_id: account_id.to_string(),
+            name: "prod".to_string(),
+            role_arn: role_arn.map(|arn| arn.parse().unwrap()),
+            regions: regions.iter().map(|r| r.parse().unwrap()).collect(),
+        }
+    }
+
+    #[test]
+    fn test_validate_new_cloud_account() {
+        let role = "arn:aws:iam::123456789012:role/scho1ar/Discovery";
+        assert!(
+            account("123456789012", Some(role), &["us-east-1", "us-gov-west-1"])
+                .validate()
+                .is_ok()
+        );
+        assert!(account("1234", None, &[]).validate().is_err());
+        assert!(account(
+            "123456789012",
+            Some("arn:aws:iam::123456789012:user/x"),
+            &[]
+        )
+        .validate()
+        .is_err());
+        assert!(account("123456789012", None, &["us-east"])
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,137 @@
+//! Persistence of connected cloud accounts
+
+use chrono::{DateTime, Utc};
+use sqlx::QueryBuilder;
+use uuid::Uuid;
+
+use super::model::{CloudAccount, CloudAccountUpdate, NewCloudAccount};
+use crate::cloud_ids::{Arn, Region};
+use crate::db::DbPool;
+use crate::pagination::{Field, FieldKind, Listing, Page, PageQuery};
+
+const COLUMNS: &str =
+    "id, provider, account_id, name, role_arn, external_id, regions, created_at, updated_at";
+
+fn region_names(regions: &[Region]) -> Vec<&str> {
+    regions.iter().map(Region::as_str).collect()
+}
+
+/// Sort and filter fields of the account list
+pub const LISTING: Listing = Listing {
+    fields: &[
+        Field::new("name", "name", FieldKind::Text),
+        Field::new("provider", "provider", FieldKind::Text),
+        Field::new("accountId", "account_id", FieldKind::Text),
+        Field::new("createdAt", "created_at", FieldKind::Timestamp),
+        Field::new("roleArn", "role_arn", FieldKind::Text).filter_only(),
+    ],
+    default_sort: "name",
+};
+
+pub async fn list_accounts(
+    db: &DbPool,
+    org_id: &str,
+    page: &PageQuery,
+) -> Result<Page<CloudAccount>, sqlx::Error> {
+    let mut builder = QueryBuilder::new(format!(
+        "SELECT {} FROM cloud_accounts WHERE organization_id = ",
+        COLUMNS
+    ));
+    builder.push_bind(org_id);
+    page.push_conditions(&mut builder);
+    page.push_order_and_limit(&mut builder);
+
+    let accounts = builder.build_query_as().fetch_all(db).await?;
+    Ok(page.page(accounts))
+}
+
+/// Organization and id of every account discovery supports, across
+/// organizations
+pub async fn discoverable_accounts(db: &DbPool) -> Result<Vec<(String, Uuid)>, sqlx::Error> {
+    sqlx::query_as("SELECT organization_id, id FROM cloud_accounts WHERE provider = 'aws'")
+        .fetch_all(db)
+        .await
+}
+
+pub async fn get_account(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<CloudAccount>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM cloud_accounts WHERE organization_id = $1 AND id = $2",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await
+}
+
+/// Returns `None` if the account is already connected to the organization
+pub async fn create_account(
+    db: &DbPool,
+    org_id: &str,
+    account: &NewCloudAccount,
+) -> Result<Option<CloudAccount>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "INSERT INTO cloud_accounts (organization_id, provider, account_id, name, role_arn, regions)
+         VALUES ($1, $2, $3, $4, $5, $6)
+         ON CONFLICT (organization_id, provider, account_id) DO NOTHING
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(account.provider.as_str())
+    .bind(&account.account_id)
+    .bind(&account.name)
+    .bind(account.role_arn.as_ref().map(Arn::as_str))
+    .bind(region_names(&account.regions))
+    .fetch_optional(db)
+    .await
+}
+
+pub async fn update_account(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    update: &CloudAccountUpdate,
+    version: Option<DateTime<Utc>>,
+) -> Result<Option<CloudAccount>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "UPDATE cloud_accounts SET name = $3, role_arn = $4, regions = $5
+         WHERE organization_id = $1 AND id = $2
+           AND ($6::timestamptz IS NULL OR updated_at = $6)
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .bind(&update.name)
+    .bind(update.role_arn.as_ref().map(Arn::as_str))
+    .bind(region_names(&update.regions))
+    .bind(version)
+    .fetch_optional(db)
+    .await
+}
+
+/// Deletes the account with its discovered resources. Returns `false` if no
+/// such account exists, or it is no longer at `version`.
+pub async fn delete_account(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM cloud_accounts WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
@@ -0,0 +1,469 @@
+//! Parsed cloud provider identifiers
+//!
+//! Each type only holds a well-formed value, so a payload field of one of
+//! these types is rejected during deserialization with a message saying
+//! what is wrong, e.g. `/roleArn: 'arn:aws:iam' is not an ARN: expected 6
+//! ':'-separated parts`. Fields kept as strings can use the `validate_*`
+//! functions with `#[validate(custom(function = ...))]` instead.
+
+use std::fmt;
+use std::str::FromStr;
+
+use serde::{de, Deserialize, Deserializer, Serialize, Serializer};
+use utoipa::openapi::schema::{KnownFormat, ObjectBuilder, SchemaFormat, Type};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{PartialSchema, ToSchema};
+use uuid::Uuid;
+use validator::ValidationError;
+
+use crate::costs::Provider;
+use crate::openapi;
+
+/// Why a string is not a valid identifier
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ParseIdError {
+    /// What was expected, e.g. "an AWS account id"
+    pub expected: &'static str,
+    pub value: String,
+    pub reason: String,
+}
+
+impl ParseIdError {
+    fn new(expected: &'static str, value: &str, reason: impl Into<String>) -> Self {
+        ParseIdError {
+            expected,
+            value: value.to_string(),
+            reason: reason.into(),
+        }
+    }
+}
+
+impl fmt::Display for ParseIdError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "'{}' is not {}: {}",
+            self.value, self.expected, self.reason
+        )
+    }
+}
+
+impl std::error::Error for ParseIdError {}
+
+/// Implement string conversions and serde for a `FromStr` identifier
+macro_rules! string_id {
+    ($name:ident, $pattern:expr, $example:expr) => {
+        impl fmt::Display for $name {
+            fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+                f.write_str(self.as_str())
+            }
+        }
+
+        impl AsRef<str> for $name {
+            fn as_ref(&self) -> &str {
+                self.as_str()
+            }
+        }
+
+        impl Serialize for $name {
+            fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+                serializer.serialize_str(self.as_str())
+            }
+        }
+
+        impl<'de> Deserialize<'de> for $name {
+            fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+                let s = String::deserialize(deserializer)?;
+                s.parse().map_err(de::Error::custom)
+            }
+        }
+
+        impl PartialSchema for $name {
+            fn schema() -> RefOr<Schema> {
+                openapi::string_schema($pattern, $example)
+            }
+        }
+
+        impl ToSchema for $name {}
+    };
+}
+
+/// A 12-digit AWS account id
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AwsAccountId(String);
+
+impl AwsAccountId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for AwsAccountId {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() != 12 || !s.bytes().all(|b| b.is_ascii_digit()) {
+            return Err(ParseIdError::new(
+                "an AWS account id",
+                s,
+                "expected 12 digits",
+            ));
+        }
+        Ok(AwsAccountId(s.to_string()))
+    }
+}
+
+string_id!(AwsAccountId, r"^[0-9]{12}$", "123456789012");
+
+/// A region code such as AWS `us-gov-west-1`, GCP `europe-west4` or Azure
+/// `eastus2`
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct Region(String);
+
+impl Region {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+
+    /// Whether this is an AWS region code: lowercase words and a number,
+    /// separated by hyphens
+    pub fn is_aws(&self) -> bool {
+        let parts: Vec<&str> = self.0.split('-').collect();
+        parts.len() >= 3
+            && parts[..parts.len() - 1]
+                .iter()
+                .all(|p| p.bytes().all(|b| b.is_ascii_lowercase()))
+            && parts[parts.len() - 1].parse::<u8>().is_ok()
+    }
+}
+
+impl FromStr for Region {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| Err(ParseIdError::new("a region code", s, reason));
+        if s.is_empty() || s.len() > 32 {
+            return invalid("expected 1 to 32 characters");
+        }
+        if !s
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        {
+            return invalid("expected lowercase letters, digits and hyphens");
+        }
+        if !s.starts_with(|c: char| c.is_ascii_lowercase()) || s.ends_with('-') || s.contains("--")
+        {
+            return invalid("expected hyphen-separated words starting with a letter");
+        }
+        Ok(Region(s.to_string()))
+    }
+}
+
+string_id!(Region, r"^[a-z][a-z0-9]*(-[a-z0-9]+)*$", "us-east-1");
+
+/// An Azure subscription id, a GUID
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct AzureSubscriptionId(Uuid);
+
+impl AzureSubscriptionId {
+    pub fn as_uuid(&self) -> Uuid {
+        self.0
+    }
+}
+
+impl fmt::Display for AzureSubscriptionId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.hyphenated().fmt(f)
+    }
+}
+
+impl FromStr for AzureSubscriptionId {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        // Only the hyphenated form Azure itself uses
+        match Uuid::try_parse(s) {
+            Ok(id) if s.len() == 36 => Ok(AzureSubscriptionId(id)),
+            _ => Err(ParseIdError::new(
+                "an Azure subscription id",
+                s,
+                "expected a GUID like 00000000-0000-0000-0000-000000000000",
+            )),
+        }
+    }
+}
+
+impl Serialize for AzureSubscriptionId {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for AzureSubscriptionId {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(de::Error::custom)
+    }
+}
+
+impl PartialSchema for AzureSubscriptionId {
+    fn schema() -> RefOr<Schema> {
+        ObjectBuilder::new()
+            .schema_type(Type::String)
+            .format(Some(SchemaFormat::KnownFormat(KnownFormat::Uuid)))
+            .into()
+    }
+}
+
+impl ToSchema for AzureSubscriptionId {}
+
+/// A GCP project id: 6 to 30 lowercase letters, digits and hyphens,
+/// starting with a letter and not ending with a hyphen
+#[derive(Debug, Clone, PartialEq, Eq, Hash, PartialOrd, Ord)]
+pub struct GcpProjectId(String);
+
+impl GcpProjectId {
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl FromStr for GcpProjectId {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason| Err(ParseIdError::new("a GCP project id", s, reason));
+        if !(6..=30).contains(&s.len()) {
+            return invalid("expected 6 to 30 characters");
+        }
+        if !s
+            .bytes()
+            .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        {
+            return invalid("expected lowercase letters, digits and hyphens");
+        }
+        if !s.starts_with(|c: char| c.is_ascii_lowercase()) {
+            return invalid("expected to start with a letter");
+        }
+        if s.ends_with('-') {
+            return invalid("expected not to end with a hyphen");
+        }
+        Ok(GcpProjectId(s.to_string()))
+    }
+}
+
+string_id!(
+    GcpProjectId,
+    r"^[a-z][a-z0-9-]{4,28}[a-z0-9]$",
+    "my-project-123"
+);
+
+/// An Amazon Resource Name,
+/// `arn:<partition>:<service>:<region>:<account>:<resource>`
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct Arn {
+    raw: String,
+    partition: String,
+    service: String,
+    region: Option<Region>,
+    account_id: Option<String>,
+    resource: String,
+}
+
+impl Arn {
+    pub fn as_str(&self) -> &str {
+        &self.raw
+    }
+
+    /// `aws`, `aws-cn`, `aws-us-gov`, ...
+    pub fn partition(&self) -> &str {
+        &self.partition
+    }
+
+    pub fn service(&self) -> &str {
+        &self.service
+    }
+
+    /// Absent for global resources such as IAM roles
+    pub fn region(&self) -> Option<&Region> {
+        self.region.as_ref()
+    }
+
+    /// The owning account's 12-digit id, or `aws` for AWS-managed
+    /// resources; absent for resources such as S3 buckets
+    pub fn account_id(&self) -> Option<&str> {
+        self.account_id.as_deref()
+    }
+
+    /// Everything after the account, e.g. `role/scho1ar/Discovery`
+    pub fn resource(&self) -> &str {
+        &self.resource
+    }
+
+    /// The resource's type when it has one, e.g. `role` of
+    /// `role/scho1ar/Discovery` or `function` of `function:billing`
+    pub fn resource_type(&self) -> Option<&str> {
+        self.resource
+            .split_once(['/', ':'])
+            .map(|(resource_type, _)| resource_type)
+    }
+}
+
+impl FromStr for Arn {
+    type Err = ParseIdError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: String| ParseIdError::new("an ARN", s, reason);
+        let parts: Vec<&str> = s.splitn(6, ':').collect();
+        let [prefix, partition, service, region, account, resource] = parts[..] else {
+            return Err(invalid("expected 6 ':'-separated parts".to_string()));
+        };
+        if prefix != "arn" {
+            return Err(invalid("expected to start with 'arn:'".to_string()));
+        }
+        if partition != "aws" && !partition.starts_with("aws-") {
+            return Err(invalid(format!("unknown partition '{}'", partition)));
+        }
+        if service.is_empty()
+            || !service
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-')
+        {
+            return Err(invalid(format!("invalid service '{}'", service)));
+        }
+        let region = match region {
+            "" => None,
+            region => Some(region.parse::<Region>().map_err(|e| invalid(e.reason))?),
+        };
+        let account_id = match account {
+            "" => None,
+            "aws" => Some(account.to_string()),
+            account => Some(
+                account
+                    .parse::<AwsAccountId>()
+                    .map_err(|_| invalid(format!("invalid account '{}'", account)))?
+                    .0,
+            ),
+        };
+        if resource.is_empty() {
+            return Err(invalid("expected a resource".to_string()));
+        }
+        Ok(Arn {
+            raw: s.to_string(),
+            partition: partition.to_string(),
+            service: service.to_string(),
+            region,
+            account_id,
+            resource: resource.to_string(),
+        })
+    }
+}
+
+string_id!(
+    Arn,
+    r"^arn:[^:]+:[^:]+:[^:]*:[^:]*:.+$",
+    "arn:aws:iam::123456789012:role/Scho1arReadOnly"
+);
+
+fn invalid(code: &'static str, error: ParseIdError) -> ValidationError {
+    let mut err = ValidationError::new(code);
+    err.message = Some(error.to_string().into());
+    err
+}
+
+pub fn validate_aws_account_id(value: &str) -> Result<(), ValidationError> {
+    value
+        .parse::<AwsAccountId>()
+        .map(|_| ())
+        .map_err(|e| invalid("aws_account_id", e))
+}
+
+pub fn validate_arn(value: &str) -> Result<(), ValidationError> {
+    value
+        .parse::<Arn>()
+        .map(|_| ())
+        .map_err(|e| invalid("arn", e))
+}
+
+pub fn validate_azure_subscription_id(value: &str) -> Result<(), ValidationError> {
+    value
+        .parse::<AzureSubscriptionId>()
+        .map(|_| ())
+        .map_err(|e| invalid("azure_subscription_id", e))
+}
+
+pub fn validate_gcp_project_id(value: &str) -> Result<(), ValidationError> {
+    value
+        .parse::<GcpProjectId>()
+        .map(|_| ())
+        .map_err(|e| invalid("gcp_project_id", e))
+}
+
+pub fn validate_region(value: &str) -> Result<(), ValidationError> {
+    value
+        .parse::<Region>()
+        .map(|_| ())
+        .map_err(|e| invalid("region", e))
+}
+
+/// Check an account id in the form `provider` uses: an AWS account id, an
+/// Azure subscription id or a GCP project id
+pub fn validate_account_id(provider: Provider, value: &str) -> Result<(), ValidationError> {
+    match provider {
+        Provider::Aws => validate_aws_account_id(value),
+        Provider::Azure => validate_azure_subscription_id(value),
+        Provider::Gcp => validate_gcp_project_id(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_ids() {
+        assert!("123456789012".parse::<AwsAccountId>().is_ok());
+        assert!("12345678901a".parse::<AwsAccountId>().is_err());
+
+        for region in ["us-east-1", "us-gov-west-1", "europe-west4", "eastus2"] {
+            assert!(region.parse::<Region>().is_ok(), "{}", region);
+        }
+        assert!("us-gov-west-1".parse::<Region>().unwrap().is_aws());
+        assert!(!"europe-west4".parse::<Region>().unwrap().is_aws());
+        assert!("US-East-1".parse::<Region>().is_err());
+
+        let subscription = "6F1A2B3C-0000-4000-8000-00000000ABCD";
+        assert_eq!(
+            subscription
+                .parse::<AzureSubscriptionId>()
+                .unwrap()
+                .to_string(),
+            subscription.to_lowercase()
+        );
+        assert!("6f1a2b3c00004000800000000000abcd"
+            .parse::<AzureSubscriptionId>()
+            .is_err());
+
+        assert!("billing-prod-42".parse::<GcpProjectId>().is_ok());
+        assert!("42-billing".parse::<GcpProjectId>().is_err());
+        assert!("prod".parse::<GcpProjectId>().is_err());
+
+        let role: Arn = "arn:aws:iam::123456789012:role/scho1ar/Discovery"
+            .parse()
+            .unwrap();
+        assert_eq!(role.service(), "iam");
+        assert_eq!(role.region(), None);
+        assert_eq!(role.account_id(), Some("123456789012"));
+        assert_eq!(role.resource_type(), Some("role"));
+        let function: Arn = "arn:aws-us-gov:lambda:us-gov-west-1:123456789012:function:billing"
+            .parse()
+            .unwrap();
+        assert_eq!(function.region().map(Region::as_str), Some("us-gov-west-1"));
+        assert_eq!(function.resource_type(), Some("function"));
+        assert_eq!(
+            "arn:aws:iam".parse::<Arn>().unwrap_err().to_string(),
+            "'arn:aws:iam' is not an ARN: expected 6 ':'-separated parts"
+        );
+        assert!("arn:aws:s3:::".parse::<Arn>().is_err());
+    }
+}
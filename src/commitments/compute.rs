@@ -0,0 +1,337 @@
+//! Utilization math and alert evaluation
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+use serde_json::json;
+
+use super::model::{
+    AlertKind, AlertSettings, CommitmentDayRow, CommitmentSummary, CommitmentType, CoveragePoint,
+    FeeSpanRow, NewAlert, UsedDayRow, UtilizationPoint,
+};
+
+/// Instance family of an instance type: `m5.large` is `m5`, `db.r6g.xlarge`
+/// is `db.r6g`
+pub fn instance_family(instance_type: &str) -> &str {
+    instance_type
+        .rsplit_once('.')
+        .map_or(instance_type, |(family, _)| family)
+}
+
+/// Share of `purchased` that was used, capped at 1
+fn ratio(used: f64, purchased: f64) -> Option<f64> {
+    (purchased > 0.0).then(|| (used / purchased).min(1.0))
+}
+
+/// Purchased and used amounts of a commitment in its own unit: reserved
+/// hours for Reserved Instances, committed spend for Savings Plans.
+fn amounts(commitment_type: CommitmentType, row: &CommitmentDayRow) -> (f64, f64) {
+    match commitment_type {
+        CommitmentType::ReservedInstance => (row.fee_hours, row.used_hours),
+        CommitmentType::SavingsPlan => (row.fee_cost, row.used_cost),
+    }
+}
+
+/// Daily rows of the days from `start` until `end`: fees spread evenly over
+/// the days their lines cover, so a monthly RI fee counts towards every day
+/// of the month, joined with the usage they covered
+pub fn commitment_days(
+    fees: &[FeeSpanRow],
+    used: &[UsedDayRow],
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Vec<CommitmentDayRow> {
+    let mut days: BTreeMap<(NaiveDate, &str), CommitmentDayRow> = BTreeMap::new();
+    for fee in fees {
+        let span_days = (fee.last_day - fee.first_day).num_days() + 1;
+        let covered = fee
+            .first_day
+            .iter_days()
+            .take_while(|date| *date <= fee.last_day)
+            .filter(|date| *date >= start && *date < end);
+        for date in covered {
+            let day = days
+                .entry((date, fee.commitment_arn.as_str()))
+                .or_insert_with(|| CommitmentDayRow {
+                    date,
+                    commitment_arn: fee.commitment_arn.clone(),
+                    fee_line_item_type: fee.fee_line_item_type.clone(),
+                    account_id: fee.account_id.clone(),
+                    region: fee.region.clone(),
+                    instance_type: fee.instance_type.clone(),
+                    commitment_end: fee.commitment_end,
+                    fee_hours: 0.0,
+                    fee_cost: 0.0,
+                    used_hours: 0.0,
+                    used_cost: 0.0,
+                });
+            day.fee_hours += fee.fee_hours / span_days as f64;
+            day.fee_cost += fee.fee_cost / span_days as f64;
+            day.commitment_end = day.commitment_end.max(fee.commitment_end);
+        }
+    }
+    for row in used {
+        if let Some(day) = days.get_mut(&(row.date, row.commitment_arn.as_str())) {
+            day.used_hours += row.used_hours;
+            day.used_cost += row.used_cost;
+        }
+    }
+    days.into_values().collect()
+}
+
+pub fn utilization_point(row: &CommitmentDayRow) -> UtilizationPoint {
+    let commitment_type = CommitmentType::from_fee_line_item_type(&row.fee_line_item_type);
+    let (purchased, used) = amounts(commitment_type, row);
+    let utilization = ratio(used, purchased);
+
+    UtilizationPoint {
+        date: row.date,
+        commitment_arn: row.commitment_arn.clone(),
+        commitment_type,
+        account_id: row.account_id.clone(),
+        instance_family: row
+            .instance_type
+            .as_deref()
+            .map(|t| instance_family(t).to_string()),
+        purchased,
+        used,
+        utilization,
+        unused_cost: row.fee_cost * (1.0 - utilization.unwrap_or(0.0)),
+    }
+}
+
+/// Roll daily rows up into one summary per commitment
+pub fn summarize(rows: &[CommitmentDayRow]) -> Vec<CommitmentSummary> {
+    let mut summaries: BTreeMap<&str, CommitmentSummary> = BTreeMap::new();
+    for row in rows {
+        let point = utilization_point(row);
+        let summary = summaries
+            .entry(&row.commitment_arn)
+            .or_insert_with(|| CommitmentSummary {
+                commitment_arn: row.commitment_arn.clone(),
+                commitment_type: point.commitment_type,
+                account_id: row.account_id.clone(),
+                region: row.region.clone(),
+                instance_type: row.instance_type.clone(),
+                instance_family: point.instance_family.clone(),
+                end: row.commitment_end,
+                purchased: 0.0,
+                used: 0.0,
+                utilization: None,
+                unused_cost: 0.0,
+            });
+        summary.purchased += point.purchased;
+        summary.used += point.used;
+        summary.unused_cost += point.unused_cost;
+        summary.end = summary.end.max(row.commitment_end);
+    }
+
+    summaries
+        .into_values()
+        .map(|mut summary| {
+            summary.utilization = ratio(summary.used, summary.purchased);
+            summary
+        })
+        .collect()
+}
+
+/// Coverage of one account and instance family on one day from
+/// `(covered, on-demand)` instance hours and on-demand equivalent cost
+pub fn coverage_point(
+    date: NaiveDate,
+    account_id: String,
+    instance_family: String,
+    (covered_hours, on_demand_hours): (f64, f64),
+    (covered_cost, on_demand_cost): (f64, f64),
+) -> CoveragePoint {
+    CoveragePoint {
+        date,
+        account_id,
+        instance_family,
+        covered_hours,
+        on_demand_hours,
+        coverage: ratio(covered_hours, covered_hours + on_demand_hours),
+        covered_cost,
+        on_demand_cost,
+        cost_coverage: ratio(covered_cost, covered_cost + on_demand_cost),
+    }
+}
+
+/// Alerts due for commitments summarized over the lookback window ending
+/// `today`
+pub fn evaluate_alerts(
+    summaries: &[CommitmentSummary],
+    settings: &AlertSettings,
+    today: NaiveDate,
+) -> Vec<NewAlert> {
+    let mut alerts = Vec::new();
+    for summary in summaries {
+        if let Some(end) = summary.end {
+            let days_left = (end.date_naive() - today).num_days();
+            if (0..=i64::from(settings.expiry_warning_days)).contains(&days_left) {
+                alerts.push(NewAlert {
+                    commitment_arn: summary.commitment_arn.clone(),
+                    kind: AlertKind::Expiring,
+                    dedupe_key: end.date_naive().to_string(),
+                    message: format!(
+                        "Commitment {} expires in {} day(s) on {}",
+                        summary.commitment_arn,
+                        days_left,
+                        end.date_naive()
+                    ),
+                    details: json!({ "end": end, "daysLeft": days_left }),
+                });
+            }
+        }
+
+        if let Some(utilization) = summary.utilization {
+            if utilization < settings.utilization_threshold {
+                alerts.push(NewAlert {
+                    commitment_arn: summary.commitment_arn.clone(),
+                    kind: AlertKind::LowUtilization,
+                    dedupe_key: today.to_string(),
+                    message: format!(
+                        "Commitment {} was {:.1}% utilized over the last {} day(s), below {:.1}%",
+                        summary.commitment_arn,
+                        utilization * 100.0,
+                        settings.utilization_lookback_days,
+                        settings.utilization_threshold * 100.0
+                    ),
+                    details: json!({
+                        "utilization": utilization,
+                        "threshold": settings.utilization_threshold,
+                        "lookbackDays": settings.utilization_lookback_days,
+                        "unusedCost": summary.unused_cost,
+                    }),
+                });
+            }
+        }
+    }
+    alerts
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::{DateTime, Utc};
+
+    fn row(
+        arn: &str,
+        fee_type: &str,
+        date: &str,
+        fee: (f64, f64),
+        used: (f64, f64),
+    ) -> CommitmentDayRow {
+        CommitmentDayRow {
+            date: date.parse().unwrap(),
+            commitment_arn: arn.to_string(),
+            fee_line_item_type: fee_type.to_string(),
+            account_id: "111111111111".to_string(),
+            region: Some("us-east-1".to_string()),
+            instance_type: Some("m5.large".to_string()),
+            commitment_end: Some("2026-11-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()),
+            fee_hours: fee.0,
+            fee_cost: fee.1,
+            used_hours: used.0,
+            used_cost: used.1,
+        }
+    }
+
+    #[test]
+    fn test_instance_family() {
+        assert_eq!(instance_family("m5.large"), "m5");
+        assert_eq!(instance_family("db.r6g.xlarge"), "db.r6g");
+        assert_eq!(instance_family("custom"), "custom");
+    }
+
+    #[test]
+    fn test_utilization_by_commitment_type() {
+        // RIs are measured in hours, Savings Plans in committed spend
+        let ri = utilization_point(&row("ri", "RIFee", "2026-10-01", (24.0, 48.0), (18.0, 0.0)));
+        assert_eq!(ri.commitment_type, CommitmentType::ReservedInstance);
+        assert_eq!(ri.utilization, Some(0.75));
+        assert_eq!(ri.unused_cost, 12.0);
+        assert_eq!(ri.instance_family.as_deref(), Some("m5"));
+
+        let sp = utilization_point(&row(
+            "sp",
+            "SavingsPlanRecurringFee",
+            "2026-10-01",
+            (24.0, 10.0),
+            (30.0, 12.0),
+        ));
+        assert_eq!(sp.commitment_type, CommitmentType::SavingsPlan);
+        assert_eq!(sp.utilization, Some(1.0));
+        assert_eq!(sp.unused_cost, 0.0);
+
+        let summaries = summarize(&[
+            row("ri", "RIFee", "2026-10-01", (24.0, 48.0), (24.0, 0.0)),
+            row("ri", "RIFee", "2026-10-02", (24.0, 48.0), (0.0, 0.0)),
+        ]);
+        assert_eq!(summaries.len(), 1);
+        assert_eq!(summaries[0].utilization, Some(0.5));
+        assert_eq!(summaries[0].unused_cost, 48.0);
+    }
+
+    #[test]
+    fn test_monthly_fee_spread_over_days() {
+        // One RIFee line for October, reserving one instance for 744 hours
+        let fee = FeeSpanRow {
+            commitment_arn: "ri".to_string(),
+            first_day: "2026-10-01".parse().unwrap(),
+            last_day: "2026-10-31".parse().unwrap(),
+            fee_line_item_type: "RIFee".to_string(),
+            account_id: "111111111111".to_string(),
+            region: Some("us-east-1".to_string()),
+            instance_type: Some("m5.large".to_string()),
+            commitment_end: None,
+            fee_hours: 744.0,
+            fee_cost: 62.0,
+        };
+        let used = |date: &str, hours: f64| UsedDayRow {
+            date: date.parse().unwrap(),
+            commitment_arn: "ri".to_string(),
+            used_hours: hours,
+            used_cost: 0.0,
+        };
+        let days = commitment_days(
+            &[fee],
+            &[used("2026-10-01", 24.0), used("2026-10-02", 12.0)],
+            "2026-09-30".parse().unwrap(),
+            "2026-10-08".parse().unwrap(),
+        );
+
+        assert_eq!(days.len(), 7);
+        assert_eq!(days[0].date, "2026-10-01".parse::<NaiveDate>().unwrap());
+        assert!(days
+            .iter()
+            .all(|day| day.fee_hours == 24.0 && day.fee_cost == 2.0));
+        let points: Vec<_> = days.iter().map(utilization_point).collect();
+        assert_eq!(points[0].utilization, Some(1.0));
+        assert_eq!(points[1].utilization, Some(0.5));
+        assert_eq!(points[2].utilization, Some(0.0));
+    }
+
+    #[test]
+    fn test_evaluate_alerts() {
+        let settings = AlertSettings::default();
+        let summaries = summarize(&[row("ri", "RIFee", "2026-10-01", (24.0, 48.0), (12.0, 0.0))]);
+
+        let alerts = evaluate_alerts(&summaries, &settings, "2026-10-18".parse().unwrap());
+        let kinds: Vec<_> = alerts
+            .iter()
+            .map(|a| (a.kind, a.dedupe_key.as_str()))
+            .collect();
+        assert_eq!(
+            kinds,
+            vec![
+                (AlertKind::Expiring, "2026-11-01"),
+                (AlertKind::LowUtilization, "2026-10-18")
+            ]
+        );
+
+        // Far from expiry and above the threshold
+        let summaries = summarize(&[row("ri", "RIFee", "2026-10-01", (24.0, 48.0), (23.0, 0.0))]);
+        assert!(evaluate_alerts(&summaries, &settings, "2026-09-01".parse().unwrap()).is_empty());
+    }
+}
@@ -0,0 +1,17 @@
+//! Reserved Instance and Savings Plan utilization, coverage and alerts
+//!
+//! Everything is derived from ingested CUR lines: commitment fees (`RIFee`,
+//! `SavingsPlanRecurringFee`) give what was purchased, covered usage
+//! (`DiscountedUsage`, `SavingsPlanCoveredUsage`) what was used, and plain
+//! `Usage` lines of instance types what ran on demand. The same on-demand
+//! usage sizes new purchases against the bundled commitment rate catalog.
+
+pub mod compute;
+pub mod model;
+pub mod recommend;
+pub mod store;
+
+pub use model::{
+    AlertKind, AlertSettings, CommitmentAlert, CommitmentSummary, CommitmentType, CoveragePoint,
+    UtilizationPoint,
+};
@@ -0,0 +1,245 @@
+//! Commitment API and storage types
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+/// Kind of commitment, derived from the CUR fee line that reports it
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum CommitmentType {
+    ReservedInstance,
+    SavingsPlan,
+}
+
+impl CommitmentType {
+    /// CUR line item type carrying the commitment fee
+    pub fn fee_line_item_type(self) -> &'static str {
+        match self {
+            CommitmentType::ReservedInstance => "RIFee",
+            CommitmentType::SavingsPlan => "SavingsPlanRecurringFee",
+        }
+    }
+
+    /// CUR line item type of usage covered by the commitment
+    pub fn covered_line_item_type(self) -> &'static str {
+        match self {
+            CommitmentType::ReservedInstance => "DiscountedUsage",
+            CommitmentType::SavingsPlan => "SavingsPlanCoveredUsage",
+        }
+    }
+
+    pub(super) fn from_fee_line_item_type(s: &str) -> Self {
+        if s == "SavingsPlanRecurringFee" {
+            CommitmentType::SavingsPlan
+        } else {
+            CommitmentType::ReservedInstance
+        }
+    }
+}
+
+/// Fee lines of one commitment covering the same days. CUR reports RI fees
+/// as one line per month and Savings Plan fees as hourly lines.
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct FeeSpanRow {
+    pub commitment_arn: String,
+    pub first_day: NaiveDate,
+    /// Last day the lines cover, inclusive
+    pub last_day: NaiveDate,
+    pub fee_line_item_type: String,
+    pub account_id: String,
+    pub region: Option<String>,
+    pub instance_type: Option<String>,
+    pub commitment_end: Option<DateTime<Utc>>,
+    pub fee_hours: f64,
+    pub fee_cost: f64,
+}
+
+/// Usage covered by one commitment on one day
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct UsedDayRow {
+    pub date: NaiveDate,
+    pub commitment_arn: String,
+    pub used_hours: f64,
+    pub used_cost: f64,
+}
+
+/// Fee and covered usage of one commitment on one day, as read from CUR lines
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CommitmentDayRow {
+    pub date: NaiveDate,
+    pub commitment_arn: String,
+    pub fee_line_item_type: String,
+    pub account_id: String,
+    pub region: Option<String>,
+    pub instance_type: Option<String>,
+    pub commitment_end: Option<DateTime<Utc>>,
+    /// Reserved hours (RI fee usage amount)
+    pub fee_hours: f64,
+    /// Amortized commitment for the day
+    pub fee_cost: f64,
+    /// Hours of usage covered by the commitment
+    pub used_hours: f64,
+    /// Effective cost of the covered usage
+    pub used_cost: f64,
+}
+
+/// Utilization of one commitment on one day.
+///
+/// Reserved Instances are measured in reserved hours, Savings Plans in
+/// committed spend.
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UtilizationPoint {
+    pub date: NaiveDate,
+    pub commitment_arn: String,
+    pub commitment_type: CommitmentType,
+    pub account_id: String,
+    pub instance_family: Option<String>,
+    pub purchased: f64,
+    pub used: f64,
+    /// `null` when nothing was purchased on the day
+    pub utilization: Option<f64>,
+    /// Commitment spend not absorbed by usage
+    pub unused_cost: f64,
+}
+
+/// A commitment with its utilization over the requested period
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentSummary {
+    pub commitment_arn: String,
+    pub commitment_type: CommitmentType,
+    pub account_id: String,
+    pub region: Option<String>,
+    pub instance_type: Option<String>,
+    pub instance_family: Option<String>,
+    pub end: Option<DateTime<Utc>>,
+    pub purchased: f64,
+    pub used: f64,
+    pub utilization: Option<f64>,
+    pub unused_cost: f64,
+}
+
+/// On-demand versus commitment-covered instance usage for one account and
+/// instance family on one day
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CoveragePoint {
+    pub date: NaiveDate,
+    pub account_id: String,
+    pub instance_family: String,
+    pub covered_hours: f64,
+    pub on_demand_hours: f64,
+    /// Share of instance hours covered, `null` without usage
+    pub coverage: Option<f64>,
+    /// Public on-demand equivalent of the covered usage
+    pub covered_cost: f64,
+    pub on_demand_cost: f64,
+    /// Share of on-demand equivalent spend covered, `null` without pricing
+    pub cost_coverage: Option<f64>,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate, sqlx::FromRow, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct AlertSettings {
+    /// Alert when utilization over the lookback window falls below this share
+    #[validate(range(min = 0.0, max = 1.0))]
+    #[schema(minimum = 0.0, maximum = 1.0)]
+    pub utilization_threshold: f64,
+    #[validate(range(min = 1, max = 90))]
+    #[schema(minimum = 1, maximum = 90)]
+    pub utilization_lookback_days: i32,
+    /// Alert when a commitment ends within this many days
+    #[validate(range(min = 0, max = 365))]
+    #[schema(minimum = 0, maximum = 365)]
+    pub expiry_warning_days: i32,
+}
+
+impl Default for AlertSettings {
+    fn default() -> Self {
+        AlertSettings {
+            utilization_threshold: 0.8,
+            utilization_lookback_days: 7,
+            expiry_warning_days: 30,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum AlertKind {
+    Expiring,
+    LowUtilization,
+}
+
+impl AlertKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertKind::Expiring => "expiring",
+            AlertKind::LowUtilization => "low_utilization",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        if s == "expiring" {
+            AlertKind::Expiring
+        } else {
+            AlertKind::LowUtilization
+        }
+    }
+}
+
+/// An alert about to be raised, before deduplication against stored alerts
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewAlert {
+    pub commitment_arn: String,
+    pub kind: AlertKind,
+    /// Identifies the condition so re-evaluation does not raise it twice
+    pub dedupe_key: String,
+    pub message: String,
+    pub details: serde_json::Value,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentAlert {
+    pub id: Uuid,
+    pub commitment_arn: String,
+    pub kind: AlertKind,
+    pub message: String,
+    pub details: serde_json::Value,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub acknowledged_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct CommitmentAlertRow {
+    pub id: Uuid,
+    pub commitment_arn: String,
+    pub kind: String,
+    pub message: String,
+    pub details: Json<serde_json::Value>,
+    pub acknowledged_at: Option<DateTime<Utc>>,
+    pub acknowledged_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<CommitmentAlertRow> for CommitmentAlert {
+    fn from(row: CommitmentAlertRow) -> Self {
+        CommitmentAlert {
+            id: row.id,
+            commitment_arn: row.commitment_arn,
+            kind: AlertKind::parse(&row.kind),
+            message: row.message,
+            details: row.details.0,
+            acknowledged_at: row.acknowledged_at,
+            acknowledged_by: row.acknowledged_by,
+            created_at: row.created_at,
+        }
+    }
+}
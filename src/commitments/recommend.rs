@@ -0,0 +1,501 @@
+//! Savings Plan and Reserved Instance purchase recommendations
+//!
+//! Each candidate purchase is sized against the hourly on-demand usage of the
+//! lookback window. A commitment of level `k` (on-demand dollars per hour for
+//! Savings Plans, instances for Reserved Instances) saves
+//! `Σ min(usage, k) - hours · k · (1 - discount)` over the window; the level
+//! maximizing that is kept, capped so that at most `risk` of the past hours
+//! would have left the commitment partly unused.
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use validator::Validate;
+
+use super::compute::instance_family;
+use crate::pricing::{CommitmentRateCatalog, OfferingKind, PaymentOption, Term};
+
+/// Hours in an average month, as used by AWS pricing
+const HOURS_PER_MONTH: f64 = 730.0;
+
+/// Which usage a commitment can apply to
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum Scope {
+    /// Purchased in the payer account and shared across all accounts
+    #[default]
+    Shared,
+    /// Purchased in, and applying to, one account
+    Linked,
+}
+
+fn default_lookback_days() -> u32 {
+    30
+}
+
+fn default_risk() -> f64 {
+    0.1
+}
+
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationRequest {
+    #[serde(default = "default_lookback_days")]
+    #[validate(range(min = 7, max = 90))]
+    #[schema(minimum = 7, maximum = 90)]
+    pub lookback_days: u32,
+    /// Share of past hours in which the commitment may go partly unused;
+    /// `0` only commits to the usage floor
+    #[serde(default = "default_risk")]
+    #[validate(range(min = 0.0, max = 0.5))]
+    #[schema(minimum = 0.0, maximum = 0.5)]
+    pub risk: f64,
+    #[serde(default)]
+    pub scope: Scope,
+    /// Offerings to consider (all when empty)
+    #[serde(default)]
+    pub offerings: Vec<OfferingKind>,
+    /// Terms to consider (all when empty)
+    #[serde(default)]
+    pub terms: Vec<Term>,
+    /// Payment options to consider (all when empty)
+    #[serde(default)]
+    pub payment_options: Vec<PaymentOption>,
+}
+
+/// On-demand instance usage of one hour
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct HourlyUsage {
+    pub hour: DateTime<Utc>,
+    pub account_id: String,
+    pub region: Option<String>,
+    pub instance_type: String,
+    pub usage_hours: f64,
+    pub on_demand_cost: f64,
+}
+
+/// On-demand equivalent of usage already covered by commitments
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct CoveredUsage {
+    pub account_id: String,
+    pub region: Option<String>,
+    pub instance_type: String,
+    pub on_demand_cost: f64,
+}
+
+/// Usage a commitment would apply to; fields narrower than the offering
+/// are left empty
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Target {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub account_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub region: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_family: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub instance_type: Option<String>,
+}
+
+/// How finely an offering splits usage
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+enum Granularity {
+    Compute,
+    Family,
+    InstanceType,
+}
+
+impl Granularity {
+    fn of(offering: OfferingKind) -> Self {
+        match offering {
+            OfferingKind::ComputeSavingsPlan => Granularity::Compute,
+            OfferingKind::Ec2InstanceSavingsPlan => Granularity::Family,
+            OfferingKind::StandardReservedInstance | OfferingKind::ConvertibleReservedInstance => {
+                Granularity::InstanceType
+            }
+        }
+    }
+
+    fn target(
+        self,
+        scope: Scope,
+        account_id: &str,
+        region: &Option<String>,
+        instance_type: &str,
+    ) -> Target {
+        let mut target = Target {
+            account_id: (scope == Scope::Linked).then(|| account_id.to_string()),
+            ..Target::default()
+        };
+        if self != Granularity::Compute {
+            target.region = region.clone();
+            target.instance_family = Some(instance_family(instance_type).to_string());
+        }
+        if self == Granularity::InstanceType {
+            target.instance_type = Some(instance_type.to_string());
+        }
+        target
+    }
+
+    /// Savings Plans are sized in on-demand dollars, Reserved Instances in
+    /// instances
+    fn measure(self, usage: &HourlyUsage) -> f64 {
+        match self {
+            Granularity::InstanceType => usage.usage_hours,
+            _ => usage.on_demand_cost,
+        }
+    }
+}
+
+/// Hourly usage series of one target
+#[derive(Debug, Clone)]
+struct Demand {
+    series: Vec<f64>,
+    on_demand_cost: f64,
+    covered_cost: f64,
+}
+
+/// Best commitment level for a usage series
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Optimum {
+    level: f64,
+    /// Usage absorbed by the commitment over the window
+    covered: f64,
+}
+
+fn optimize(series: &[f64], discount: f64, risk: f64, integral: bool) -> Option<Optimum> {
+    let mut sorted = series.to_vec();
+    sorted.sort_by(f64::total_cmp);
+    let n = sorted.len();
+    if n == 0 {
+        return None;
+    }
+    let prefix: Vec<f64> = std::iter::once(0.0)
+        .chain(sorted.iter().scan(0.0, |sum, x| {
+            *sum += x;
+            Some(*sum)
+        }))
+        .collect();
+    let covered = |level: f64| {
+        let below = sorted.partition_point(|x| *x < level);
+        prefix[below] + (n - below) as f64 * level
+    };
+    let savings = |level: f64| covered(level) - n as f64 * level * (1.0 - discount);
+
+    let cap_index = ((risk * n as f64).floor() as usize).min(n - 1);
+    let cap = sorted[cap_index];
+    let candidates: Vec<f64> = if integral {
+        (1..=cap.floor() as u64).map(|k| k as f64).collect()
+    } else {
+        sorted[..=cap_index]
+            .iter()
+            .copied()
+            .filter(|level| *level > 0.0)
+            .collect()
+    };
+
+    candidates
+        .into_iter()
+        .map(|level| (level, savings(level)))
+        .filter(|(_, savings)| *savings > 0.0)
+        .max_by(|a, b| a.1.total_cmp(&b.1))
+        .map(|(level, _)| Optimum {
+            level,
+            covered: covered(level),
+        })
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentRecommendation {
+    pub offering: OfferingKind,
+    pub term: Term,
+    pub payment_option: PaymentOption,
+    pub scope: Scope,
+    #[serde(flatten)]
+    pub target: Target,
+    /// Savings Plan commitment in dollars per hour
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub hourly_commitment: Option<f64>,
+    /// Reserved Instances to buy
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub quantity: Option<u32>,
+    pub discount: f64,
+    pub upfront_cost: f64,
+    pub recurring_monthly_cost: f64,
+    /// On-demand spend of the target before the purchase
+    pub on_demand_monthly_cost: f64,
+    /// Net of upfront payments amortized over the term
+    pub estimated_monthly_savings: f64,
+    pub estimated_savings_percent: f64,
+    /// Month in which cumulative savings exceed the upfront payment; `null`
+    /// if that does not happen within the term
+    pub break_even_month: Option<u32>,
+    /// Expected share of the commitment used
+    pub expected_utilization: f64,
+    /// Share of the target's on-demand equivalent spend covered by
+    /// commitments before and after the purchase
+    pub coverage_before: f64,
+    pub coverage_after: f64,
+}
+
+/// One purchasable offering with its catalog discount
+#[derive(Debug, Clone, Copy)]
+struct Offer {
+    offering: OfferingKind,
+    term: Term,
+    payment_option: PaymentOption,
+    discount: f64,
+}
+
+fn evaluate(
+    offer: Offer,
+    scope: Scope,
+    target: &Target,
+    demand: &Demand,
+    risk: f64,
+) -> Option<CommitmentRecommendation> {
+    let Offer {
+        offering,
+        term,
+        payment_option,
+        discount,
+    } = offer;
+    let granularity = Granularity::of(offering);
+    let integral = granularity == Granularity::InstanceType;
+    let optimum = optimize(&demand.series, discount, risk, integral)?;
+
+    let hours = demand.series.len() as f64;
+    let usage: f64 = demand.series.iter().sum();
+    // On-demand price of one unit of level for one hour
+    let unit_price = if integral {
+        demand.on_demand_cost / usage
+    } else {
+        1.0
+    };
+
+    let to_monthly = HOURS_PER_MONTH / hours;
+    let on_demand_monthly_cost = demand.on_demand_cost * to_monthly;
+    let covered_monthly = optimum.covered * unit_price * to_monthly;
+    let commitment_hourly = optimum.level * unit_price * (1.0 - discount);
+    let commitment_monthly = commitment_hourly * HOURS_PER_MONTH;
+
+    let upfront_cost =
+        commitment_monthly * f64::from(term.months()) * payment_option.upfront_fraction();
+    let recurring_monthly_cost = commitment_monthly * (1.0 - payment_option.upfront_fraction());
+    let estimated_monthly_savings = covered_monthly - commitment_monthly;
+
+    let monthly_benefit = covered_monthly - recurring_monthly_cost;
+    let break_even_month = if upfront_cost <= 0.0 {
+        Some(1)
+    } else if monthly_benefit > 0.0 {
+        let month = (upfront_cost / monthly_benefit).ceil() as u32;
+        (month <= term.months()).then_some(month.max(1))
+    } else {
+        None
+    };
+
+    let total = demand.covered_cost + demand.on_demand_cost;
+    let coverage = |covered: f64| if total > 0.0 { covered / total } else { 0.0 };
+
+    Some(CommitmentRecommendation {
+        offering,
+        term,
+        payment_option,
+        scope,
+        target: target.clone(),
+        hourly_commitment: (!integral).then_some(commitment_hourly),
+        quantity: integral.then_some(optimum.level as u32),
+        discount,
+        upfront_cost,
+        recurring_monthly_cost,
+        on_demand_monthly_cost,
+        estimated_monthly_savings,
+        estimated_savings_percent: if on_demand_monthly_cost > 0.0 {
+            estimated_monthly_savings / on_demand_monthly_cost * 100.0
+        } else {
+            0.0
+        },
+        break_even_month,
+        expected_utilization: optimum.covered / (optimum.level * hours),
+        coverage_before: coverage(demand.covered_cost),
+        coverage_after: coverage(demand.covered_cost + optimum.covered * unit_price),
+    })
+}
+
+fn or_all<T: Copy>(selected: &[T], all: &[T]) -> Vec<T> {
+    if selected.is_empty() {
+        all.to_vec()
+    } else {
+        selected.to_vec()
+    }
+}
+
+/// Best purchase per offering kind and target, highest savings first.
+///
+/// Recommendations for different offering kinds are alternatives for the
+/// same usage; their savings do not add up.
+pub fn recommend(
+    request: &RecommendationRequest,
+    catalog: &CommitmentRateCatalog,
+    window_start: DateTime<Utc>,
+    hours: usize,
+    usage: &[HourlyUsage],
+    covered: &[CoveredUsage],
+) -> Vec<CommitmentRecommendation> {
+    let offerings = or_all(&request.offerings, &OfferingKind::ALL);
+    let terms = or_all(&request.terms, &Term::ALL);
+    let payment_options = or_all(&request.payment_options, &PaymentOption::ALL);
+
+    let mut granularities: Vec<Granularity> =
+        offerings.iter().map(|o| Granularity::of(*o)).collect();
+    granularities.sort();
+    granularities.dedup();
+
+    let mut demands: BTreeMap<(Granularity, Target), Demand> = BTreeMap::new();
+    for granularity in granularities {
+        for item in usage {
+            let Ok(index) = usize::try_from((item.hour - window_start).num_hours()) else {
+                continue;
+            };
+            if index >= hours {
+                continue;
+            }
+            let target = granularity.target(
+                request.scope,
+                &item.account_id,
+                &item.region,
+                &item.instance_type,
+            );
+            let demand = demands
+                .entry((granularity, target))
+                .or_insert_with(|| Demand {
+                    series: vec![0.0; hours],
+                    on_demand_cost: 0.0,
+                    covered_cost: 0.0,
+                });
+            demand.series[index] += granularity.measure(item);
+            demand.on_demand_cost += item.on_demand_cost;
+        }
+        for item in covered {
+            let target = granularity.target(
+                request.scope,
+                &item.account_id,
+                &item.region,
+                &item.instance_type,
+            );
+            if let Some(demand) = demands.get_mut(&(granularity, target)) {
+                demand.covered_cost += item.on_demand_cost;
+            }
+        }
+    }
+
+    let mut recommendations = Vec::new();
+    for ((granularity, target), demand) in &demands {
+        for offering in offerings
+            .iter()
+            .filter(|o| Granularity::of(**o) == *granularity)
+        {
+            let best = terms
+                .iter()
+                .flat_map(|term| payment_options.iter().map(move |payment| (*term, *payment)))
+                .filter_map(|(term, payment)| {
+                    let discount = catalog.discount(
+                        *offering,
+                        term,
+                        payment,
+                        target.instance_family.as_deref(),
+                    )?;
+                    let offer = Offer {
+                        offering: *offering,
+                        term,
+                        payment_option: payment,
+                        discount,
+                    };
+                    evaluate(offer, request.scope, target, demand, request.risk)
+                })
+                .max_by(|a, b| {
+                    a.estimated_monthly_savings
+                        .total_cmp(&b.estimated_monthly_savings)
+                });
+            recommendations.extend(best);
+        }
+    }
+
+    recommendations.sort_by(|a, b| {
+        b.estimated_monthly_savings
+            .total_cmp(&a.estimated_monthly_savings)
+    });
+    recommendations
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::Duration;
+
+    #[test]
+    fn test_optimize_respects_risk() {
+        // 10 hours at 2 instances, 10 hours at 4
+        let series: Vec<f64> = [vec![2.0; 10], vec![4.0; 10]].concat();
+
+        // A peak held half the time pays off above 50% off: at 60% off,
+        // covering 4 saves 60 - 80 · 0.4 = 28 against 40 - 40 · 0.4 = 24
+        let aggressive = optimize(&series, 0.6, 0.5, true).unwrap();
+        assert_eq!(aggressive.level, 4.0);
+        // Without risk only the floor is committed to
+        let conservative = optimize(&series, 0.6, 0.0, true).unwrap();
+        assert_eq!(conservative.level, 2.0);
+        assert_eq!(conservative.covered, 40.0);
+
+        // At 10% off only the floor pays for itself
+        assert_eq!(optimize(&series, 0.1, 0.5, false).unwrap().level, 2.0);
+        assert!(optimize(&[0.0; 5], 0.4, 0.5, false).is_none());
+    }
+
+    #[test]
+    fn test_recommend_reserved_instances() {
+        let start: DateTime<Utc> = "2026-09-01T00:00:00Z".parse().unwrap();
+        let usage: Vec<HourlyUsage> = (0..24)
+            .map(|h| HourlyUsage {
+                hour: start + Duration::hours(h),
+                account_id: "111".to_string(),
+                region: Some("us-east-1".to_string()),
+                instance_type: "m5.large".to_string(),
+                usage_hours: 2.0,
+                on_demand_cost: 0.2,
+            })
+            .collect();
+        let request = RecommendationRequest {
+            lookback_days: 1,
+            risk: 0.0,
+            scope: Scope::Shared,
+            offerings: vec![OfferingKind::StandardReservedInstance],
+            terms: vec![Term::OneYear],
+            payment_options: vec![PaymentOption::NoUpfront, PaymentOption::AllUpfront],
+        };
+
+        let recs = recommend(
+            &request,
+            CommitmentRateCatalog::bundled(),
+            start,
+            24,
+            &usage,
+            &[],
+        );
+        assert_eq!(recs.len(), 1);
+        let rec = &recs[0];
+        assert_eq!(rec.quantity, Some(2));
+        assert_eq!(rec.payment_option, PaymentOption::AllUpfront);
+        assert_eq!(rec.target.instance_type.as_deref(), Some("m5.large"));
+        assert!((rec.on_demand_monthly_cost - 146.0).abs() < 1e-9);
+        assert!((rec.estimated_monthly_savings - 146.0 * rec.discount).abs() < 1e-9);
+        assert_eq!(rec.break_even_month, Some(8));
+        assert_eq!(rec.expected_utilization, 1.0);
+        assert_eq!(rec.coverage_before, 0.0);
+        assert!((rec.coverage_after - 1.0).abs() < 1e-9);
+    }
+}
@@ -0,0 +1,319 @@
+//! Commitment utilization and coverage queries, alert settings and alerts
+
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use sqlx::types::Json;
+
+use super::compute;
+use super::model::{
+    AlertSettings, CommitmentAlert, CommitmentAlertRow, CommitmentDayRow, CommitmentSummary,
+    CommitmentType::{ReservedInstance, SavingsPlan},
+    CoveragePoint, FeeSpanRow, UsedDayRow, UtilizationPoint,
+};
+use super::recommend::{CoveredUsage, HourlyUsage};
+use crate::costs::period::day_start;
+use crate::db::DbPool;
+
+/// Fee lines joined with the usage they covered, per commitment and day.
+///
+/// Fee lines overlapping the period are grouped by the days they cover, so
+/// hourly Savings Plan fees add up per day while a monthly RI fee stays one
+/// row to spread over its month. Savings Plan fees are taken from the
+/// effective (amortized) cost so upfront payments count towards the
+/// commitment.
+async fn commitment_days(
+    db: &DbPool,
+    org_id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+    commitment_arn: Option<&str>,
+) -> Result<Vec<CommitmentDayRow>, sqlx::Error> {
+    let fees: Vec<FeeSpanRow> = sqlx::query_as(
+        "SELECT commitment_arn,
+                (usage_start AT TIME ZONE 'UTC')::date AS first_day,
+                GREATEST(((usage_end - INTERVAL '1 microsecond') AT TIME ZONE 'UTC')::date,
+                         (usage_start AT TIME ZONE 'UTC')::date) AS last_day,
+                MAX(line_item_type) AS fee_line_item_type,
+                MAX(account_id) AS account_id,
+                MAX(region) AS region,
+                MAX(instance_type) AS instance_type,
+                MAX(commitment_end) AS commitment_end,
+                SUM(usage_amount) AS fee_hours,
+                SUM(COALESCE(effective_cost, cost)) AS fee_cost
+         FROM cost_line_items
+         WHERE organization_id = $1 AND usage_start < $3
+           AND (usage_end > $2 OR usage_start >= $2)
+           AND commitment_arn IS NOT NULL AND line_item_type IN ($5, $6)
+           AND ($4::text IS NULL OR commitment_arn = $4)
+         GROUP BY 1, 2, 3",
+    )
+    .bind(org_id)
+    .bind(day_start(start))
+    .bind(day_start(end))
+    .bind(commitment_arn)
+    .bind(ReservedInstance.fee_line_item_type())
+    .bind(SavingsPlan.fee_line_item_type())
+    .fetch_all(db)
+    .await?;
+
+    let used: Vec<UsedDayRow> = sqlx::query_as(
+        "SELECT (usage_start AT TIME ZONE 'UTC')::date AS date, commitment_arn,
+                SUM(usage_amount) AS used_hours,
+                SUM(COALESCE(effective_cost, 0)) AS used_cost
+         FROM cost_line_items
+         WHERE organization_id = $1 AND usage_start >= $2 AND usage_start < $3
+           AND commitment_arn IS NOT NULL AND line_item_type IN ($5, $6)
+           AND ($4::text IS NULL OR commitment_arn = $4)
+         GROUP BY 1, 2",
+    )
+    .bind(org_id)
+    .bind(day_start(start))
+    .bind(day_start(end))
+    .bind(commitment_arn)
+    .bind(ReservedInstance.covered_line_item_type())
+    .bind(SavingsPlan.covered_line_item_type())
+    .fetch_all(db)
+    .await?;
+
+    Ok(compute::commitment_days(&fees, &used, start, end))
+}
+
+/// Daily utilization of every commitment (or one) with fees in the period
+pub async fn daily_utilization(
+    db: &DbPool,
+    org_id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+    commitment_arn: Option<&str>,
+) -> Result<Vec<UtilizationPoint>, sqlx::Error> {
+    let rows = commitment_days(db, org_id, start, end, commitment_arn).await?;
+    Ok(rows.iter().map(compute::utilization_point).collect())
+}
+
+/// Commitments with fees in the period and their utilization over it
+pub async fn commitment_summaries(
+    db: &DbPool,
+    org_id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<CommitmentSummary>, sqlx::Error> {
+    let rows = commitment_days(db, org_id, start, end, None).await?;
+    Ok(compute::summarize(&rows))
+}
+
+/// Daily instance-hour coverage per account and instance family
+pub async fn daily_coverage(
+    db: &DbPool,
+    org_id: &str,
+    start: NaiveDate,
+    end: NaiveDate,
+    account_id: Option<&str>,
+) -> Result<Vec<CoveragePoint>, sqlx::Error> {
+    let rows: Vec<(NaiveDate, String, String, f64, f64, f64, f64)> = sqlx::query_as(
+        "SELECT (usage_start AT TIME ZONE 'UTC')::date AS date, account_id,
+                regexp_replace(instance_type, '\\.[^.]*$', '') AS instance_family,
+                COALESCE(SUM(usage_amount) FILTER (WHERE line_item_type IN ($5, $6)), 0),
+                COALESCE(SUM(usage_amount) FILTER (WHERE line_item_type = 'Usage'), 0),
+                COALESCE(SUM(public_on_demand_cost) FILTER (WHERE line_item_type IN ($5, $6)), 0),
+                COALESCE(SUM(COALESCE(public_on_demand_cost, cost))
+                    FILTER (WHERE line_item_type = 'Usage'), 0)
+         FROM cost_line_items
+         WHERE organization_id = $1 AND usage_start >= $2 AND usage_start < $3
+           AND instance_type IS NOT NULL AND line_item_type IN ('Usage', $5, $6)
+           AND ($4::text IS NULL OR account_id = $4)
+         GROUP BY 1, 2, 3
+         ORDER BY 1, 2, 3",
+    )
+    .bind(org_id)
+    .bind(day_start(start))
+    .bind(day_start(end))
+    .bind(account_id)
+    .bind(ReservedInstance.covered_line_item_type())
+    .bind(SavingsPlan.covered_line_item_type())
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(
+            |(
+                date,
+                account_id,
+                instance_family,
+                covered_hours,
+                on_demand_hours,
+                covered_cost,
+                on_demand_cost,
+            )| {
+                compute::coverage_point(
+                    date,
+                    account_id,
+                    instance_family,
+                    (covered_hours, on_demand_hours),
+                    (covered_cost, on_demand_cost),
+                )
+            },
+        )
+        .collect())
+}
+
+/// Hourly on-demand AWS instance usage, and the on-demand equivalent of
+/// usage already covered by commitments, between `start` and `end`
+pub async fn recommendation_usage(
+    db: &DbPool,
+    org_id: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<(Vec<HourlyUsage>, Vec<CoveredUsage>), sqlx::Error> {
+    let usage: Vec<HourlyUsage> = sqlx::query_as(
+        "SELECT date_trunc('hour', usage_start) AS hour, account_id, region, instance_type,
+                SUM(usage_amount) AS usage_hours,
+                SUM(COALESCE(public_on_demand_cost, cost)) AS on_demand_cost
+         FROM cost_line_items
+         WHERE organization_id = $1 AND usage_start >= $2 AND usage_start < $3
+           AND provider = 'aws' AND line_item_type = 'Usage' AND instance_type IS NOT NULL
+         GROUP BY 1, 2, 3, 4",
+    )
+    .bind(org_id)
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await?;
+
+    let covered: Vec<CoveredUsage> = sqlx::query_as(
+        "SELECT account_id, region, instance_type,
+                COALESCE(SUM(public_on_demand_cost), 0) AS on_demand_cost
+         FROM cost_line_items
+         WHERE organization_id = $1 AND usage_start >= $2 AND usage_start < $3
+           AND provider = 'aws' AND line_item_type IN ($4, $5) AND instance_type IS NOT NULL
+         GROUP BY 1, 2, 3",
+    )
+    .bind(org_id)
+    .bind(start)
+    .bind(end)
+    .bind(ReservedInstance.covered_line_item_type())
+    .bind(SavingsPlan.covered_line_item_type())
+    .fetch_all(db)
+    .await?;
+
+    Ok((usage, covered))
+}
+
+/// The organization's alert settings, or the defaults if never saved
+pub async fn get_alert_settings(db: &DbPool, org_id: &str) -> Result<AlertSettings, sqlx::Error> {
+    let settings: Option<AlertSettings> = sqlx::query_as(
+        "SELECT utilization_threshold, utilization_lookback_days, expiry_warning_days
+         FROM commitment_alert_settings WHERE organization_id = $1",
+    )
+    .bind(org_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(settings.unwrap_or_default())
+}
+
+pub async fn put_alert_settings(
+    db: &DbPool,
+    org_id: &str,
+    settings: &AlertSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO commitment_alert_settings
+             (organization_id, utilization_threshold, utilization_lookback_days, expiry_warning_days)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (organization_id) DO UPDATE
+         SET utilization_threshold = EXCLUDED.utilization_threshold,
+             utilization_lookback_days = EXCLUDED.utilization_lookback_days,
+             expiry_warning_days = EXCLUDED.expiry_warning_days",
+    )
+    .bind(org_id)
+    .bind(settings.utilization_threshold)
+    .bind(settings.utilization_lookback_days)
+    .bind(settings.expiry_warning_days)
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+const ALERT_COLUMNS: &str = "id, commitment_arn, kind, message, details, acknowledged_at, \
+                             acknowledged_by, created_at";
+
+pub async fn list_alerts(
+    db: &DbPool,
+    org_id: &str,
+    include_acknowledged: bool,
+) -> Result<Vec<CommitmentAlert>, sqlx::Error> {
+    let rows: Vec<CommitmentAlertRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM commitment_alerts
+         WHERE organization_id = $1 AND ($2 OR acknowledged_at IS NULL)
+         ORDER BY created_at DESC
+         LIMIT 500",
+        ALERT_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(include_acknowledged)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(CommitmentAlert::from).collect())
+}
+
+/// Returns `None` if no such alert exists. Acknowledging twice keeps the
+/// first acknowledgement.
+pub async fn acknowledge_alert(
+    db: &DbPool,
+    org_id: &str,
+    id: uuid::Uuid,
+    user_id: &str,
+) -> Result<Option<CommitmentAlert>, sqlx::Error> {
+    let row: Option<CommitmentAlertRow> = sqlx::query_as(&format!(
+        "UPDATE commitment_alerts
+         SET acknowledged_at = COALESCE(acknowledged_at, NOW()),
+             acknowledged_by = COALESCE(acknowledged_by, $3)
+         WHERE organization_id = $1 AND id = $2
+         RETURNING {}",
+        ALERT_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .bind(user_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(CommitmentAlert::from))
+}
+
+/// Check active commitments against the alert settings as of `today` and
+/// store alerts not raised before. Returns the newly raised alerts.
+pub async fn evaluate_alerts(
+    db: &DbPool,
+    org_id: &str,
+    today: NaiveDate,
+) -> Result<Vec<CommitmentAlert>, sqlx::Error> {
+    let settings = get_alert_settings(db, org_id).await?;
+    let start = today - Duration::days(i64::from(settings.utilization_lookback_days));
+    let summaries = commitment_summaries(db, org_id, start, today).await?;
+
+    let mut raised = Vec::new();
+    for alert in compute::evaluate_alerts(&summaries, &settings, today) {
+        let row: Option<CommitmentAlertRow> = sqlx::query_as(&format!(
+            "INSERT INTO commitment_alerts
+                 (organization_id, commitment_arn, kind, dedupe_key, message, details)
+             VALUES ($1, $2, $3, $4, $5, $6)
+             ON CONFLICT (organization_id, commitment_arn, kind, dedupe_key) DO NOTHING
+             RETURNING {}",
+            ALERT_COLUMNS
+        ))
+        .bind(org_id)
+        .bind(&alert.commitment_arn)
+        .bind(alert.kind.as_str())
+        .bind(&alert.dedupe_key)
+        .bind(&alert.message)
+        .bind(Json(&alert.details))
+        .fetch_optional(db)
+        .await?;
+        raised.extend(row.map(CommitmentAlert::from));
+    }
+
+    Ok(raised)
+}
@@ -0,0 +1,328 @@
+//! Conditional requests
+//!
+//! Resources that can be edited carry an `ETag` derived from their
+//! `updated_at` version. Sending it back in `If-Match` with a `PUT` or
+//! `DELETE` makes the write fail with `412 Precondition Failed` if someone
+//! changed the resource meanwhile, instead of silently overwriting their
+//! edit.
+//!
+//! Successful `GET` responses carry an `ETag` as well: a version one set by
+//! the handler, or else one hashed from JSON bodies of up to 4 MiB. A `GET`
+//! whose `If-None-Match` lists it gets `304 Not Modified` without the body.
+//! Exports and other non-JSON or streamed bodies are passed through as is.
+
+use axum::{
+    async_trait,
+    body::{to_bytes, Body, HttpBody},
+    extract::{FromRequestParts, Request},
+    http::{header, request::Parts, HeaderMap, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use utoipa::openapi::path::{Parameter, ParameterBuilder, ParameterIn};
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::openapi::Required;
+use utoipa::IntoParams;
+
+use crate::error::{AppError, AppResult};
+
+/// A resource whose version changes with every write
+pub trait Versioned {
+    /// The `updated_at` of the resource's row
+    fn version(&self) -> DateTime<Utc>;
+
+    fn etag(&self) -> String {
+        version_etag(self.version())
+    }
+}
+
+fn version_etag(version: DateTime<Utc>) -> String {
+    format!("\"{:x}\"", version.timestamp_micros())
+}
+
+/// Tags listed in an `If-Match` or `If-None-Match` header
+#[derive(Debug, Clone, PartialEq, Eq)]
+enum TagList {
+    Any,
+    Tags(Vec<String>),
+}
+
+impl TagList {
+    fn parse(headers: &HeaderMap, name: header::HeaderName) -> Option<Self> {
+        let values: Vec<&str> = headers
+            .get_all(name)
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .collect();
+        if values.is_empty() {
+            return None;
+        }
+        if values.iter().any(|value| value.trim() == "*") {
+            return Some(TagList::Any);
+        }
+        let tags = values
+            .iter()
+            .flat_map(|value| value.split(','))
+            .map(|tag| tag.trim().to_string())
+            .filter(|tag| !tag.is_empty())
+            .collect();
+        Some(TagList::Tags(tags))
+    }
+
+    /// Strong comparison, as `If-Match` requires: weak tags match nothing
+    fn matches_strong(&self, etag: &str) -> bool {
+        match self {
+            TagList::Any => true,
+            TagList::Tags(tags) => tags.iter().any(|tag| tag == etag),
+        }
+    }
+
+    /// Weak comparison, as `If-None-Match` requires
+    fn matches_weak(&self, etag: &str) -> bool {
+        let opaque = |tag: &str| tag.strip_prefix("W/").unwrap_or(tag).to_string();
+        match self {
+            TagList::Any => true,
+            TagList::Tags(tags) => tags.iter().any(|tag| opaque(tag) == opaque(etag)),
+        }
+    }
+}
+
+/// The `If-Match` header of a write
+#[derive(Debug, Clone, Default)]
+pub struct IfMatch(Option<TagList>);
+
+impl IfMatch {
+    /// Check the header against the resource's current version. Returns
+    /// the version to make the write conditional on, if the request has a
+    /// precondition.
+    pub fn check(&self, current: &impl Versioned) -> AppResult<Option<DateTime<Utc>>> {
+        match &self.0 {
+            None => Ok(None),
+            Some(tags) if tags.matches_strong(&current.etag()) => Ok(Some(current.version())),
+            Some(_) => Err(precondition_failed()),
+        }
+    }
+}
+
+/// The error for a write whose `If-Match` no longer matches
+pub fn precondition_failed() -> AppError {
+    AppError::PreconditionFailed(
+        "The resource was changed since the version in If-Match".to_string(),
+    )
+}
+
+/// The error for a conditional write that matched no row: the resource
+/// changed after its precondition was checked, or it is gone
+pub fn changed_or(version: Option<DateTime<Utc>>, not_found: AppError) -> AppError {
+    match version {
+        Some(_) => precondition_failed(),
+        None => not_found,
+    }
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for IfMatch
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(IfMatch(TagList::parse(&parts.headers, header::IF_MATCH)))
+    }
+}
+
+impl IntoParams for IfMatch {
+    fn into_params(_parameter_in_provider: impl Fn() -> Option<ParameterIn>) -> Vec<Parameter> {
+        vec![ParameterBuilder::new()
+            .name("If-Match")
+            .parameter_in(ParameterIn::Header)
+            .required(Required::False)
+            .schema(Some(ObjectBuilder::new().schema_type(Type::String)))
+            .description(Some(
+                "`ETag` the resource must still have, failing with 412 otherwise",
+            ))
+            .build()]
+    }
+}
+
+/// A JSON response with the `ETag` of the resource's version
+pub struct Tagged<T>(pub T);
+
+impl<T: Serialize + Versioned> IntoResponse for Tagged<T> {
+    fn into_response(self) -> Response {
+        let etag = self.0.etag();
+        let mut response = Json(self.0).into_response();
+        if let Ok(value) = HeaderValue::from_str(&etag) {
+            response.headers_mut().insert(header::ETAG, value);
+        }
+        response
+    }
+}
+
+/// Largest response body hashed for an `ETag`
+const MAX_HASHED_BODY: u64 = 4 * 1024 * 1024;
+
+/// Whether a response without a version `ETag` gets one hashed from its
+/// body: only JSON bodies held in memory, whose size is known upfront
+fn hashable(headers: &HeaderMap, body: &Body) -> bool {
+    let json = headers
+        .get(header::CONTENT_TYPE)
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| value.starts_with("application/json"));
+    json && body
+        .size_hint()
+        .exact()
+        .is_some_and(|len| len <= MAX_HASHED_BODY)
+}
+
+/// Hex SHA-256 of a response body, as a strong `ETag`
+fn body_etag(body: &[u8]) -> String {
+    format!("\"{}\"", hex::encode(Sha256::digest(body)))
+}
+
+/// Middleware giving successful `GET` responses an `ETag` and answering
+/// ones the client already has, per `If-None-Match`, with `304`
+pub async fn not_modified(request: Request, next: Next) -> Response {
+    if request.method() != Method::GET {
+        return next.run(request).await;
+    }
+    let if_none_match = TagList::parse(request.headers(), header::IF_NONE_MATCH);
+    let response = next.run(request).await;
+    if response.status() != StatusCode::OK {
+        return response;
+    }
+
+    let (mut parts, body) = response.into_parts();
+    let body = if parts.headers.contains_key(header::ETAG) || !hashable(&parts.headers, &body) {
+        body
+    } else {
+        let body = match to_bytes(body, MAX_HASHED_BODY as usize).await {
+            Ok(body) => body,
+            Err(e) => {
+                let error = AppError::Internal(format!("Failed to read response body: {}", e));
+                return error.into_response();
+            }
+        };
+        let etag = HeaderValue::from_str(&body_etag(&body)).expect("hex is a valid header");
+        parts.headers.insert(header::ETAG, etag);
+        Body::from(body)
+    };
+
+    let unchanged = if_none_match
+        .zip(parts.headers.get(header::ETAG))
+        .and_then(|(tags, etag)| Some(tags.matches_weak(etag.to_str().ok()?)))
+        .unwrap_or(false);
+    if !unchanged {
+        return Response::from_parts(parts, body);
+    }
+    parts.status = StatusCode::NOT_MODIFIED;
+    parts.headers.remove(header::CONTENT_TYPE);
+    parts.headers.remove(header::CONTENT_LENGTH);
+    Response::from_parts(parts, Body::empty())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{middleware, routing::get, Router};
+    use chrono::TimeZone;
+    use tower::Service;
+
+    struct Row(DateTime<Utc>);
+
+    impl Versioned for Row {
+        fn version(&self) -> DateTime<Utc> {
+            self.0
+        }
+    }
+
+    fn headers(name: header::HeaderName, values: &[&str]) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        for value in values {
+            headers.append(name.clone(), HeaderValue::from_str(value).unwrap());
+        }
+        headers
+    }
+
+    fn if_match(values: &[&str]) -> IfMatch {
+        IfMatch(TagList::parse(
+            &headers(header::IF_MATCH, values),
+            header::IF_MATCH,
+        ))
+    }
+
+    #[test]
+    fn test_preconditions() {
+        let row = Row(Utc.with_ymd_and_hms(2026, 10, 18, 12, 0, 0).unwrap());
+        let etag = row.etag();
+        let etag = etag.as_str();
+        let newer = Row(row.0 + chrono::Duration::microseconds(1));
+        assert_ne!(row.etag(), newer.etag());
+
+        assert_eq!(if_match(&[]).check(&row).unwrap(), None);
+        assert_eq!(if_match(&[etag]).check(&row).unwrap(), Some(row.0));
+        assert_eq!(if_match(&["\"x\"", etag]).check(&row).unwrap(), Some(row.0));
+        assert_eq!(if_match(&["*"]).check(&row).unwrap(), Some(row.0));
+        assert!(matches!(
+            if_match(&[etag]).check(&newer),
+            Err(AppError::PreconditionFailed(_))
+        ));
+        assert!(if_match(&[&format!("W/{}", etag)]).check(&row).is_err());
+
+        let none_match = |values: &[&str]| {
+            TagList::parse(
+                &headers(header::IF_NONE_MATCH, values),
+                header::IF_NONE_MATCH,
+            )
+            .unwrap()
+        };
+        assert!(none_match(&["\"a\", W/\"b\""]).matches_weak("\"b\""));
+        assert!(!none_match(&["\"a\""]).matches_weak("\"b\""));
+        assert_eq!(body_etag(b"{}"), body_etag(b"{}"));
+        assert_ne!(body_etag(b"{}"), body_etag(b"[]"));
+    }
+
+    #[tokio::test]
+    async fn test_not_modified_hashes_json_only() {
+        let mut app = Router::new()
+            .route(
+                "/json",
+                get(|| async { Json(serde_json::json!({ "a": 1 })) }),
+            )
+            .route(
+                "/csv",
+                get(|| async { ([(header::CONTENT_TYPE, "text/csv")], "a,b\n") }),
+            )
+            .route(
+                "/large",
+                get(|| async { Json("x".repeat(MAX_HASHED_BODY as usize)) }),
+            )
+            .layer(middleware::from_fn(not_modified));
+        let mut get = |uri: &str, if_none_match: Option<&str>| {
+            let mut request = Request::get(uri);
+            if let Some(tag) = if_none_match {
+                request = request.header(header::IF_NONE_MATCH, tag);
+            }
+            app.call(request.body(Body::empty()).unwrap())
+        };
+
+        let response = get("/json", None).await.unwrap();
+        let etag = response.headers()[header::ETAG]
+            .to_str()
+            .unwrap()
+            .to_string();
+        let response = get("/json", Some(&etag)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_MODIFIED);
+
+        for uri in ["/csv", "/large"] {
+            let response = get(uri, Some("*")).await.unwrap();
+            assert_eq!(response.status(), StatusCode::OK, "{}", uri);
+            assert!(!response.headers().contains_key(header::ETAG), "{}", uri);
+        }
+    }
+}
@@ -1,4 +1,10 @@
 use std::env;
+use std::fmt;
+use std::path::PathBuf;
+
+use uuid::Uuid;
+
+use crate::aws::{Credentials, Endpoints, Service};
 
 #[derive(Debug, Clone)]
 pub struct Config {
@@ -8,6 +14,18 @@ pub struct Config {
     pub cors_origins: Vec<String>,
     pub environment: String,
     pub clerk: ClerkConfig,
+    /// Clerk user IDs allowed to manage system jobs, which belong to no
+    /// organization
+    pub platform_admins: Vec<String>,
+    /// Directory of provider price files (`<dir>/<provider>/<file>`)
+    pub pricing_data_dir: Option<PathBuf>,
+    pub aws: AwsConfig,
+    /// Job workers started alongside the server; 0 leaves jobs to separate
+    /// `worker` processes
+    pub worker_concurrency: usize,
+    pub notifications: NotificationConfig,
+    pub pagination: PaginationConfig,
+    pub log_format: LogFormat,
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +38,200 @@ pub struct ClerkConfig {
     pub audience: Option<String>,
 }
 
+#[derive(Debug, Clone, Default)]
+pub struct AwsConfig {
+    /// Backend credentials, used directly or to assume account roles
+    pub credentials: Option<Credentials>,
+    /// Endpoint overrides, e.g. `http://localhost:4566` for LocalStack
+    pub endpoints: Endpoints,
+}
+
+#[derive(Debug, Clone, Default)]
+pub struct NotificationConfig {
+    /// Server email notifications are sent through; email channels fail
+    /// without one
+    pub smtp: Option<SmtpConfig>,
+    /// Allow plain HTTP webhook URLs, e.g. for local stand-ins in tests
+    pub allow_http_webhooks: bool,
+    /// Lowercase hosts webhooks may reach on loopback, private or
+    /// link-local addresses, e.g. local stand-ins in tests
+    pub private_webhook_hosts: Vec<String>,
+}
+
+#[derive(Debug, Clone)]
+pub struct SmtpConfig {
+    pub host: String,
+    pub port: u16,
+    pub username: Option<String>,
+    pub password: Option<String>,
+    /// Sender address, e.g. `Scho1ar <alerts@example.com>`
+    pub from: String,
+    pub tls: SmtpTls,
+}
+
+#[derive(Debug, Clone)]
+pub struct PaginationConfig {
+    /// Page size of list endpoints when the request gives no `limit`
+    pub default_page_size: i64,
+    /// Largest `limit` a request may ask for
+    pub max_page_size: i64,
+    /// Key list cursors are signed with
+    pub cursor_key: CursorKey,
+}
+
+/// HMAC key of list cursors, kept out of `Debug` output
+#[derive(Clone)]
+pub struct CursorKey(pub Vec<u8>);
+
+impl fmt::Debug for CursorKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("CursorKey(..)")
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LogFormat {
+    /// Human-readable lines, for development
+    Text,
+    /// One JSON object per line, for log aggregation
+    Json,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SmtpTls {
+    /// Plain connection, for local test servers
+    None,
+    StartTls,
+    /// TLS from the start, usually on port 465
+    Tls,
+}
+
+impl NotificationConfig {
+    fn from_env() -> Result<Self, ConfigError> {
+        let smtp = match env::var("SMTP_HOST") {
+            Ok(host) => {
+                let tls = match env::var("SMTP_TLS").as_deref() {
+                    Ok("none") => SmtpTls::None,
+                    Ok("starttls") | Err(_) => SmtpTls::StartTls,
+                    Ok("tls") => SmtpTls::Tls,
+                    Ok(_) => {
+                        return Err(ConfigError::Invalid(
+                            "SMTP_TLS must be none, starttls or tls".to_string(),
+                        ))
+                    }
+                };
+                let default_port = if tls == SmtpTls::Tls { "465" } else { "587" };
+                let port = env::var("SMTP_PORT")
+                    .unwrap_or_else(|_| default_port.to_string())
+                    .parse::<u16>()
+                    .map_err(|_| {
+                        ConfigError::Invalid("SMTP_PORT must be a valid number".to_string())
+                    })?;
+                Some(SmtpConfig {
+                    host,
+                    port,
+                    username: env::var("SMTP_USERNAME").ok(),
+                    password: env::var("SMTP_PASSWORD").ok(),
+                    from: env::var("SMTP_FROM")
+                        .map_err(|_| ConfigError::Missing("SMTP_FROM".to_string()))?,
+                    tls,
+                })
+            }
+            Err(_) => None,
+        };
+
+        Ok(NotificationConfig {
+            smtp,
+            allow_http_webhooks: env::var("NOTIFICATION_ALLOW_HTTP").as_deref() == Ok("true"),
+            private_webhook_hosts: env::var("NOTIFICATION_PRIVATE_HOSTS")
+                .unwrap_or_default()
+                .split(',')
+                .map(|s| s.trim().to_lowercase())
+                .filter(|s| !s.is_empty())
+                .collect(),
+        })
+    }
+}
+
+impl PaginationConfig {
+    fn from_env(is_production: bool) -> Result<Self, ConfigError> {
+        let size = |name: &str, default: &str| {
+            env::var(name)
+                .unwrap_or_else(|_| default.to_string())
+                .parse::<i64>()
+                .ok()
+                .filter(|size| *size > 0)
+                .ok_or_else(|| ConfigError::Invalid(format!("{} must be a positive number", name)))
+        };
+        let default_page_size = size("PAGE_SIZE_DEFAULT", "50")?;
+        let max_page_size = size("PAGE_SIZE_MAX", "200")?;
+        if default_page_size > max_page_size {
+            return Err(ConfigError::Invalid(
+                "PAGE_SIZE_DEFAULT may not exceed PAGE_SIZE_MAX".to_string(),
+            ));
+        }
+
+        // Without a configured key, cursors only work within this process,
+        // which is fine for development but breaks behind a load balancer
+        let cursor_key = match env::var("CURSOR_SECRET") {
+            Ok(secret) => secret.into_bytes(),
+            Err(_) if is_production => {
+                return Err(ConfigError::Missing("CURSOR_SECRET".to_string()))
+            }
+            Err(_) => [Uuid::new_v4(), Uuid::new_v4()]
+                .iter()
+                .flat_map(|id| id.into_bytes())
+                .collect(),
+        };
+
+        Ok(PaginationConfig {
+            default_page_size,
+            max_page_size,
+            cursor_key: CursorKey(cursor_key),
+        })
+    }
+}
+
+impl AwsConfig {
+    fn from_env() -> Self {
+        let credentials = match (
+            env::var("AWS_ACCESS_KEY_ID"),
+            env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            (Ok(access_key_id), Ok(secret_access_key)) => Some(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: env::var("AWS_SESSION_TOKEN").ok(),
+            }),
+            _ => None,
+        };
+
+        let services = [
+            Service::Ec2,
+            Service::Rds,
+            Service::ElasticLoadBalancingV2,
+            Service::S3,
+            Service::Sts,
+        ]
+        .into_iter()
+        .filter_map(|service| {
+            let name = service.signing_name();
+            env::var(format!("AWS_ENDPOINT_URL_{}", name.to_uppercase()))
+                .ok()
+                .map(|url| (name.to_string(), url))
+        })
+        .collect();
+
+        AwsConfig {
+            credentials,
+            endpoints: Endpoints {
+                default: env::var("AWS_ENDPOINT_URL").ok(),
+                services,
+            },
+        }
+    }
+}
+
 impl Config {
     pub fn from_env() -> Result<Self, ConfigError> {
         dotenvy::dotenv().ok();
@@ -57,6 +269,36 @@ impl Config {
             audience: clerk_audience,
         };
 
+        let platform_admins = env::var("PLATFORM_ADMINS")
+            .unwrap_or_default()
+            .split(',')
+            .map(|s| s.trim().to_string())
+            .filter(|s| !s.is_empty())
+            .collect();
+
+        let pricing_data_dir = env::var("PRICING_DATA_DIR").ok().map(PathBuf::from);
+
+        let worker_concurrency = env::var("WORKER_CONCURRENCY")
+            .unwrap_or_else(|_| "4".to_string())
+            .parse::<usize>()
+            .map_err(|_| {
+                ConfigError::Invalid("WORKER_CONCURRENCY must be a valid number".to_string())
+            })?;
+
+        let pagination = PaginationConfig::from_env(environment == "production")?;
+
+        let log_format = match env::var("LOG_FORMAT").as_deref() {
+            Ok("json") => LogFormat::Json,
+            Ok("text") => LogFormat::Text,
+            Err(_) if environment == "production" => LogFormat::Json,
+            Err(_) => LogFormat::Text,
+            Ok(_) => {
+                return Err(ConfigError::Invalid(
+                    "LOG_FORMAT must be json or text".to_string(),
+                ))
+            }
+        };
+
         Ok(Config {
             database_url,
             host,
@@ -64,6 +306,13 @@ impl Config {
             cors_origins,
             environment,
             clerk,
+            platform_admins,
+            pricing_data_dir,
+            aws: AwsConfig::from_env(),
+            worker_concurrency,
+            notifications: NotificationConfig::from_env()?,
+            pagination,
+            log_format,
         })
     }
 
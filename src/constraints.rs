@@ -0,0 +1,142 @@
+//! Database constraints as API errors
+//!
+//! A request violating a unique, foreign key or check constraint is turned
+//! into a response naming the payload field at fault. Constraints missing
+//! from [`CONSTRAINTS`] still map to the right status, with a generic
+//! message and no field.
+
+/// A constraint and the request field it guards
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Constraint {
+    /// Postgres constraint or unique index name
+    pub name: &'static str,
+    /// camelCase payload field, as in the JSON pointer of the error
+    pub field: &'static str,
+    pub message: &'static str,
+}
+
+const fn constraint(name: &'static str, field: &'static str, message: &'static str) -> Constraint {
+    Constraint {
+        name,
+        field,
+        message,
+    }
+}
+
+pub const CONSTRAINTS: &[Constraint] = &[
+    // Unique
+    constraint(
+        "cost_centers_organization_id_name_key",
+        "name",
+        "A cost center with this name already exists",
+    ),
+    constraint(
+        "business_metrics_organization_id_name_key",
+        "name",
+        "A business metric with this name already exists",
+    ),
+    constraint(
+        "unit_metrics_organization_id_name_key",
+        "name",
+        "A unit metric with this name already exists",
+    ),
+    constraint(
+        "cloud_accounts_organization_id_provider_account_id_key",
+        "accountId",
+        "This cloud account is already connected",
+    ),
+    constraint(
+        "chargeback_statements_cost_center_id_period_key",
+        "month",
+        "The cost center already has a statement for this month",
+    ),
+    // Foreign key
+    constraint(
+        "chargeback_rate_rules_cost_center_id_fkey",
+        "costCenterId",
+        "Cost center does not exist",
+    ),
+    constraint(
+        "chargeback_adjustments_cost_center_id_fkey",
+        "costCenterId",
+        "Cost center does not exist",
+    ),
+    constraint(
+        "chargeback_statements_cost_center_id_fkey",
+        "costCenterId",
+        "Cost center does not exist",
+    ),
+    constraint(
+        "unit_metrics_business_metric_id_fkey",
+        "businessMetricId",
+        "Business metric does not exist",
+    ),
+    constraint(
+        "schedule_overrides_resource_id_fkey",
+        "resourceId",
+        "Resource does not exist",
+    ),
+    // Check
+    constraint(
+        "chargeback_rate_rules_percent_check",
+        "percent",
+        "percent must be at least -100",
+    ),
+    constraint(
+        "notification_channels_check",
+        "url",
+        "Email channels take no URL and other channels need one",
+    ),
+    constraint(
+        "commitment_alert_settings_utilization_threshold_check",
+        "utilizationThreshold",
+        "utilizationThreshold must be between 0 and 1",
+    ),
+    constraint(
+        "commitment_alert_settings_utilization_lookback_days_check",
+        "utilizationLookbackDays",
+        "utilizationLookbackDays must be positive",
+    ),
+    constraint(
+        "commitment_alert_settings_expiry_warning_days_check",
+        "expiryWarningDays",
+        "expiryWarningDays may not be negative",
+    ),
+    constraint(
+        "waste_settings_idle_days_check",
+        "idleDays",
+        "idleDays may not be negative",
+    ),
+    constraint(
+        "waste_settings_snapshot_age_days_check",
+        "snapshotAgeDays",
+        "snapshotAgeDays must be positive",
+    ),
+    constraint(
+        "waste_settings_lookback_days_check",
+        "lookbackDays",
+        "lookbackDays must be positive",
+    ),
+];
+
+/// The registered constraint called `name`
+pub fn lookup(name: &str) -> Option<&'static Constraint> {
+    CONSTRAINTS.iter().find(|c| c.name == name)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::HashSet;
+
+    #[test]
+    fn test_constraints() {
+        let names: HashSet<&str> = CONSTRAINTS.iter().map(|c| c.name).collect();
+        assert_eq!(names.len(), CONSTRAINTS.len(), "duplicate constraint");
+        assert_eq!(
+            lookup("cost_centers_organization_id_name_key").map(|c| c.field),
+            Some("name")
+        );
+        assert!(lookup("no_such_constraint").is_none());
+    }
+}
@@ -0,0 +1,109 @@
+//! Writing line items into `cost_line_items`
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{Postgres, QueryBuilder};
+
+use super::Provider;
+use crate::db::DbPool;
+use crate::resources::{link, store as resources};
+use crate::tags::{LineItemContext, TagNormalizer, Tags};
+
+/// A line item as produced by a billing export parser, before normalization
+#[derive(Debug, Clone)]
+pub struct NewLineItem {
+    pub provider: Provider,
+    pub account_id: String,
+    pub usage_start: DateTime<Utc>,
+    pub usage_end: DateTime<Utc>,
+    pub service: String,
+    pub region: Option<String>,
+    pub usage_type: Option<String>,
+    /// Provider line item type, e.g. CUR `Usage`, `RIFee`, `SavingsPlanRecurringFee`
+    pub line_item_type: String,
+    pub resource_id: Option<String>,
+    pub usage_amount: f64,
+    pub pricing_unit: Option<String>,
+    pub cost: f64,
+    pub currency: String,
+    pub raw_tags: Tags,
+    /// Instance type for compute usage, e.g. `m5.large`
+    pub instance_type: Option<String>,
+    /// Reservation or Savings Plan ARN on commitment fees and covered usage
+    pub commitment_arn: Option<String>,
+    /// End of the commitment term, reported on commitment fee lines
+    pub commitment_end: Option<DateTime<Utc>>,
+    /// Amortized cost after commitment discounts
+    pub effective_cost: Option<f64>,
+    /// What the usage would have cost at public on-demand rates
+    pub public_on_demand_cost: Option<f64>,
+}
+
+impl NewLineItem {
+    pub fn context(&self) -> LineItemContext<'_> {
+        LineItemContext {
+            account_id: &self.account_id,
+            service: &self.service,
+            region: self.region.as_deref(),
+            resource_id: self.resource_id.as_deref(),
+            usage_type: self.usage_type.as_deref(),
+        }
+    }
+}
+
+/// Insert line items for an organization, normalizing their tags on the way
+/// and linking them to inventory resources.
+///
+/// Returns the number of rows inserted.
+pub async fn insert_line_items(
+    db: &DbPool,
+    org_id: &str,
+    normalizer: &TagNormalizer,
+    items: &[NewLineItem],
+) -> Result<u64, sqlx::Error> {
+    // Stay well below the 65535 bind parameter limit
+    const CHUNK_SIZE: usize = 1000;
+
+    let mut inserted = 0;
+    for chunk in items.chunks(CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO cost_line_items (organization_id, provider, account_id, usage_start, \
+             usage_end, service, region, usage_type, line_item_type, resource_id, usage_amount, \
+             pricing_unit, cost, currency, raw_tags, tags, instance_type, commitment_arn, \
+             commitment_end, effective_cost, public_on_demand_cost, resource_key) ",
+        );
+        builder.push_values(chunk, |mut row, item| {
+            let tags = normalizer.normalize(&item.raw_tags, &item.context());
+            row.push_bind(org_id)
+                .push_bind(item.provider.as_str())
+                .push_bind(&item.account_id)
+                .push_bind(item.usage_start)
+                .push_bind(item.usage_end)
+                .push_bind(&item.service)
+                .push_bind(&item.region)
+                .push_bind(&item.usage_type)
+                .push_bind(&item.line_item_type)
+                .push_bind(&item.resource_id)
+                .push_bind(item.usage_amount)
+                .push_bind(&item.pricing_unit)
+                .push_bind(item.cost)
+                .push_bind(&item.currency)
+                .push_bind(Json(&item.raw_tags))
+                .push_bind(Json(tags))
+                .push_bind(&item.instance_type)
+                .push_bind(&item.commitment_arn)
+                .push_bind(item.commitment_end)
+                .push_bind(item.effective_cost)
+                .push_bind(item.public_on_demand_cost)
+                .push_bind(
+                    item.resource_id
+                        .as_deref()
+                        .and_then(link::normalize_resource_id),
+                );
+        });
+        inserted += builder.build().execute(db).await?.rows_affected();
+    }
+    resources::link_line_items(db, org_id).await?;
+
+    Ok(inserted)
+}
@@ -0,0 +1,17 @@
+//! Cost line items
+//!
+//! Line items are stored per organization in `cost_line_items` with both the
+//! provider-reported tags and the normalized tags computed from the
+//! organization's tag rules.
+
+pub mod ingest;
+pub mod period;
+pub mod provider;
+pub mod query;
+
+pub use ingest::{insert_line_items, NewLineItem};
+pub use period::Month;
+pub use provider::Provider;
+pub use query::{
+    CostFilter, CostQuery, CostQueryParams, CostQueryResult, Granularity, GroupBy, LinkCoverage,
+};
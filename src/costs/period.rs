@@ -0,0 +1,101 @@
+//! Calendar periods used by cost reports
+
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Datelike, Months, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{PartialSchema, ToSchema};
+
+use crate::openapi;
+
+/// Midnight UTC at the start of `date`
+pub fn day_start(date: NaiveDate) -> DateTime<Utc> {
+    date.and_hms_opt(0, 0, 0).unwrap_or_default().and_utc()
+}
+
+/// A calendar month, written as `YYYY-MM`
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct Month(NaiveDate);
+
+impl Month {
+    /// The month containing `date`
+    pub fn containing(date: NaiveDate) -> Self {
+        Month(date.with_day(1).unwrap_or(date))
+    }
+
+    /// First day of the month
+    pub fn first_day(self) -> NaiveDate {
+        self.0
+    }
+
+    pub fn next(self) -> Self {
+        Month(self.0 + Months::new(1))
+    }
+
+    /// Inclusive start instant (midnight UTC on the first day)
+    pub fn start(self) -> DateTime<Utc> {
+        day_start(self.0)
+    }
+
+    /// Exclusive end instant (start of the following month)
+    pub fn end(self) -> DateTime<Utc> {
+        self.next().start()
+    }
+}
+
+impl fmt::Display for Month {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:04}-{:02}", self.0.year(), self.0.month())
+    }
+}
+
+impl FromStr for Month {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        NaiveDate::parse_from_str(&format!("{}-01", s), "%Y-%m-%d")
+            .map(Month)
+            .map_err(|_| format!("invalid month '{}', expected YYYY-MM", s))
+    }
+}
+
+impl Serialize for Month {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_str(self)
+    }
+}
+
+impl<'de> Deserialize<'de> for Month {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        s.parse().map_err(serde::de::Error::custom)
+    }
+}
+
+impl PartialSchema for Month {
+    fn schema() -> RefOr<Schema> {
+        openapi::string_schema(r"^[0-9]{4}-(0[1-9]|1[0-2])$", "2026-01")
+    }
+}
+
+impl ToSchema for Month {}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_month_parse_and_bounds() {
+        let month: Month = "2026-12".parse().unwrap();
+        assert_eq!(month.to_string(), "2026-12");
+        assert_eq!(month.next().to_string(), "2027-01");
+        assert_eq!(
+            month.end(),
+            "2027-01-01T00:00:00Z".parse::<DateTime<Utc>>().unwrap()
+        );
+        assert!("2026-13".parse::<Month>().is_err());
+        assert!("2026-1x".parse::<Month>().is_err());
+    }
+}
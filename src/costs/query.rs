@@ -0,0 +1,456 @@
+//! Cost query: filtered, grouped spend over time
+//!
+//! The filter and grouping model here is shared by every report built on
+//! line items (cost explorer, unit metrics, ...).
+
+use std::collections::{BTreeMap, BTreeSet};
+
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder, Row};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{IntoParams, PartialSchema, ToSchema};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use super::period::day_start;
+use super::Provider;
+use crate::db::DbPool;
+use crate::openapi;
+use crate::resources::{store as resources, ResourceSummary};
+use crate::validation::comma_separated;
+
+/// Longest period a single query may cover
+const MAX_QUERY_DAYS: i64 = 731;
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Granularity {
+    Daily,
+    #[default]
+    Monthly,
+}
+
+impl Granularity {
+    /// `date_trunc` unit of a period
+    pub(crate) fn trunc_unit(self) -> &'static str {
+        match self {
+            Granularity::Daily => "day",
+            Granularity::Monthly => "month",
+        }
+    }
+}
+
+/// Line item dimension results can be grouped by
+///
+/// Serialized as `provider`, `account`, `service`, `region`, `usageType`,
+/// `lineItemType`, `resource` (the linked inventory resource id) or
+/// `tag:<key>`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum GroupBy {
+    Provider,
+    Account,
+    Service,
+    Region,
+    UsageType,
+    LineItemType,
+    Resource,
+    Tag(String),
+}
+
+impl GroupBy {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "provider" => Some(Self::Provider),
+            "account" => Some(Self::Account),
+            "service" => Some(Self::Service),
+            "region" => Some(Self::Region),
+            "usageType" => Some(Self::UsageType),
+            "lineItemType" => Some(Self::LineItemType),
+            "resource" => Some(Self::Resource),
+            _ => s
+                .strip_prefix("tag:")
+                .filter(|key| !key.is_empty())
+                .map(|key| Self::Tag(key.to_string())),
+        }
+    }
+
+    pub fn as_string(&self) -> String {
+        match self {
+            Self::Provider => "provider".to_string(),
+            Self::Account => "account".to_string(),
+            Self::Service => "service".to_string(),
+            Self::Region => "region".to_string(),
+            Self::UsageType => "usageType".to_string(),
+            Self::LineItemType => "lineItemType".to_string(),
+            Self::Resource => "resource".to_string(),
+            Self::Tag(key) => format!("tag:{}", key),
+        }
+    }
+
+    /// Push the SQL expression for this dimension of `cost_line_items`
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>, alias: &str) {
+        match self {
+            Self::Provider => builder.push(format!("{}.provider", alias)),
+            Self::Account => builder.push(format!("{}.account_id", alias)),
+            Self::Service => builder.push(format!("{}.service", alias)),
+            Self::Region => builder.push(format!("{}.region", alias)),
+            Self::UsageType => builder.push(format!("{}.usage_type", alias)),
+            Self::LineItemType => builder.push(format!("{}.line_item_type", alias)),
+            Self::Resource => builder.push(format!("{}.linked_resource_id::text", alias)),
+            Self::Tag(key) => builder
+                .push(format!("{}.tags ->> ", alias))
+                .push_bind(key.clone()),
+        };
+    }
+}
+
+impl Serialize for GroupBy {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for GroupBy {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "unknown group '{}', expected provider, account, service, region, \
+                 usageType, lineItemType, resource or tag:<key>",
+                s
+            ))
+        })
+    }
+}
+
+impl PartialSchema for GroupBy {
+    fn schema() -> RefOr<Schema> {
+        openapi::string_schema(
+            r"^(provider|account|service|region|usageType|lineItemType|resource|tag:.+)$",
+            "service",
+        )
+    }
+}
+
+impl ToSchema for GroupBy {}
+
+/// Restricts which line items a query covers; empty lists match everything
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct CostFilter {
+    pub providers: Vec<Provider>,
+    pub accounts: Vec<String>,
+    pub services: Vec<String>,
+    pub regions: Vec<String>,
+    pub line_item_types: Vec<String>,
+    /// Inventory resources the line items are linked to
+    pub resource_ids: Vec<Uuid>,
+    /// Normalized tag key -> accepted values
+    pub tags: BTreeMap<String, Vec<String>>,
+}
+
+impl CostFilter {
+    /// Push ` AND ...` clauses for every non-empty filter
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>, alias: &str) {
+        if !self.providers.is_empty() {
+            let providers: Vec<&str> = self.providers.iter().map(|p| p.as_str()).collect();
+            builder
+                .push(format!(" AND {}.provider = ANY(", alias))
+                .push_bind(providers)
+                .push(")");
+        }
+        for (column, values) in [
+            ("account_id", &self.accounts),
+            ("service", &self.services),
+            ("region", &self.regions),
+            ("line_item_type", &self.line_item_types),
+        ] {
+            if !values.is_empty() {
+                builder
+                    .push(format!(" AND {}.{} = ANY(", alias, column))
+                    .push_bind(values.clone())
+                    .push(")");
+            }
+        }
+        if !self.resource_ids.is_empty() {
+            builder
+                .push(format!(" AND {}.linked_resource_id = ANY(", alias))
+                .push_bind(self.resource_ids.clone())
+                .push(")");
+        }
+        for (key, values) in &self.tags {
+            builder
+                .push(format!(" AND {}.tags ->> ", alias))
+                .push_bind(key.clone())
+                .push(" = ANY(")
+                .push_bind(values.clone())
+                .push(")");
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_period"))]
+pub struct CostQuery {
+    /// First day included
+    pub start: NaiveDate,
+    /// Day after the last day included
+    pub end: NaiveDate,
+    #[serde(default)]
+    pub granularity: Granularity,
+    #[serde(default)]
+    pub filter: CostFilter,
+    #[serde(default)]
+    #[validate(length(max = 3, message = "at most 3 group by dimensions are supported"))]
+    #[schema(max_items = 3)]
+    pub group_by: Vec<GroupBy>,
+}
+
+fn validate_period(query: &CostQuery) -> Result<(), ValidationError> {
+    check_period(query.start, query.end)
+}
+
+/// Check that a cost query period is non-empty and at most
+/// [`MAX_QUERY_DAYS`] long
+pub(crate) fn check_period(start: NaiveDate, end: NaiveDate) -> Result<(), ValidationError> {
+    if end <= start {
+        let mut err = ValidationError::new("period");
+        err.message = Some("end must be after start".into());
+        return Err(err);
+    }
+    if (end - start).num_days() > MAX_QUERY_DAYS {
+        let mut err = ValidationError::new("period");
+        err.message = Some(format!("period may not exceed {} days", MAX_QUERY_DAYS).into());
+        return Err(err);
+    }
+    Ok(())
+}
+
+/// A cost query given as query string parameters. List parameters may be
+/// repeated or comma-separated, e.g. `?region=us-east-1,eu-west-1`.
+#[derive(Debug, Clone, Deserialize, Validate, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_params_period"))]
+#[into_params(parameter_in = Query)]
+pub struct CostQueryParams {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    #[serde(default)]
+    pub granularity: Granularity,
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub provider: Vec<Provider>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub account: Vec<String>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub service: Vec<String>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub region: Vec<String>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub line_item_type: Vec<String>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    pub resource_id: Vec<Uuid>,
+    #[serde(default, deserialize_with = "comma_separated")]
+    #[validate(length(max = 3, message = "at most 3 group by dimensions are supported"))]
+    #[param(max_items = 3)]
+    pub group_by: Vec<GroupBy>,
+}
+
+fn validate_params_period(params: &CostQueryParams) -> Result<(), ValidationError> {
+    check_period(params.start, params.end)
+}
+
+impl From<CostQueryParams> for CostQuery {
+    fn from(params: CostQueryParams) -> Self {
+        CostQuery {
+            start: params.start,
+            end: params.end,
+            granularity: params.granularity,
+            filter: CostFilter {
+                providers: params.provider,
+                accounts: params.account,
+                services: params.service,
+                regions: params.region,
+                line_item_types: params.line_item_type,
+                resource_ids: params.resource_id,
+                tags: BTreeMap::new(),
+            },
+            group_by: params.group_by,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CostRow {
+    /// First day of the day or month bucket
+    pub period: NaiveDate,
+    /// Group values, in `groupBy` order (`null` when the dimension is unset)
+    pub group: Vec<Option<String>>,
+    pub cost: f64,
+    pub usage_amount: f64,
+}
+
+/// How much of the queried spend is linked to inventory resources
+#[derive(Debug, Clone, Default, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct LinkCoverage {
+    pub linked_cost: f64,
+    /// Spend with a resource id that matches no discovered resource
+    pub unmatched_cost: f64,
+    /// Spend without a resource id, such as support, tax or data transfer
+    pub no_resource_id_cost: f64,
+    /// Share of the total spend not linked to a resource
+    pub unlinked_percent: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CostQueryResult {
+    pub granularity: Granularity,
+    pub group_by: Vec<GroupBy>,
+    pub total: f64,
+    pub rows: Vec<CostRow>,
+    pub coverage: LinkCoverage,
+    /// Inventory details of the resources in `resource` groups, by id
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub resources: BTreeMap<Uuid, ResourceSummary>,
+}
+
+impl CostQuery {
+    fn build_sql<'a>(&'a self, org_id: &'a str) -> QueryBuilder<'a, Postgres> {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+            "SELECT date_trunc('{}', c.usage_start AT TIME ZONE 'UTC')::date AS period",
+            self.granularity.trunc_unit()
+        ));
+        for group in &self.group_by {
+            builder.push(", ");
+            group.push_sql(&mut builder, "c");
+        }
+        builder
+            .push(
+                ", SUM(c.cost), SUM(c.usage_amount), \
+                 COALESCE(SUM(c.cost) FILTER (WHERE c.linked_resource_id IS NOT NULL), 0), \
+                 COALESCE(SUM(c.cost) FILTER (WHERE c.linked_resource_id IS NULL \
+                 AND c.resource_key IS NOT NULL), 0) FROM cost_line_items c",
+            )
+            .push(" WHERE c.organization_id = ")
+            .push_bind(org_id)
+            .push(" AND c.usage_start >= ")
+            .push_bind(day_start(self.start))
+            .push(" AND c.usage_start < ")
+            .push_bind(day_start(self.end));
+        self.filter.push_sql(&mut builder, "c");
+
+        builder.push(" GROUP BY ");
+        for i in 1..=self.group_by.len() + 1 {
+            if i > 1 {
+                builder.push(", ");
+            }
+            builder.push(i.to_string());
+        }
+        builder.push(" ORDER BY 1");
+        builder
+    }
+
+    pub async fn execute(&self, db: &DbPool, org_id: &str) -> Result<CostQueryResult, sqlx::Error> {
+        let rows = self.build_sql(org_id).build().fetch_all(db).await?;
+        let groups = self.group_by.len();
+
+        let mut coverage = LinkCoverage::default();
+        let rows = rows
+            .iter()
+            .map(|row| {
+                let group = (1..=groups)
+                    .map(|i| row.try_get::<Option<String>, _>(i))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let cost: f64 = row.try_get(groups + 1)?;
+                let linked: f64 = row.try_get(groups + 3)?;
+                let unmatched: f64 = row.try_get(groups + 4)?;
+                coverage.linked_cost += linked;
+                coverage.unmatched_cost += unmatched;
+                coverage.no_resource_id_cost += cost - linked - unmatched;
+                Ok(CostRow {
+                    period: row.try_get(0)?,
+                    group,
+                    cost,
+                    usage_amount: row.try_get(groups + 2)?,
+                })
+            })
+            .collect::<Result<Vec<_>, sqlx::Error>>()?;
+
+        let total: f64 = rows.iter().map(|r| r.cost).sum();
+        if total != 0.0 {
+            coverage.unlinked_percent = (total - coverage.linked_cost) / total * 100.0;
+        }
+
+        let resources = match self.group_by.iter().position(|g| *g == GroupBy::Resource) {
+            Some(i) => {
+                let ids: BTreeSet<Uuid> = rows
+                    .iter()
+                    .filter_map(|row| row.group[i].as_deref()?.parse().ok())
+                    .collect();
+                let ids: Vec<Uuid> = ids.into_iter().collect();
+                resources::resource_summaries(db, org_id, &ids)
+                    .await?
+                    .into_iter()
+                    .collect()
+            }
+            None => BTreeMap::new(),
+        };
+
+        Ok(CostQueryResult {
+            granularity: self.granularity,
+            group_by: self.group_by.clone(),
+            total,
+            rows,
+            coverage,
+            resources,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_cost_query_sql() {
+        let query: CostQuery = serde_json::from_value(serde_json::json!({
+            "start": "2026-09-01",
+            "end": "2026-10-01",
+            "granularity": "daily",
+            "filter": { "providers": ["aws"], "tags": { "team": ["data"] } },
+            "groupBy": ["service", "tag:env"]
+        }))
+        .unwrap();
+
+        assert_eq!(
+            query.build_sql("org_1").sql(),
+            "SELECT date_trunc('day', c.usage_start AT TIME ZONE 'UTC')::date AS period, \
+             c.service, c.tags ->> $1, SUM(c.cost), SUM(c.usage_amount), \
+             COALESCE(SUM(c.cost) FILTER (WHERE c.linked_resource_id IS NOT NULL), 0), \
+             COALESCE(SUM(c.cost) FILTER (WHERE c.linked_resource_id IS NULL \
+             AND c.resource_key IS NOT NULL), 0) FROM cost_line_items c WHERE c.organization_id = $2 \
+             AND c.usage_start >= $3 AND c.usage_start < $4 \
+             AND c.provider = ANY($5) AND c.tags ->> $6 = ANY($7) \
+             GROUP BY 1, 2, 3 ORDER BY 1"
+        );
+    }
+
+    #[test]
+    fn test_cost_query_validation() {
+        let query = |start: &str, end: &str| CostQuery {
+            start: start.parse().unwrap(),
+            end: end.parse().unwrap(),
+            granularity: Granularity::Monthly,
+            filter: CostFilter::default(),
+            group_by: Vec::new(),
+        };
+        assert!(query("2026-01-01", "2026-02-01").validate().is_ok());
+        assert!(query("2026-02-01", "2026-02-01").validate().is_err());
+        assert!(query("2020-01-01", "2026-02-01").validate().is_err());
+        assert!(GroupBy::parse("tag:").is_none());
+    }
+}
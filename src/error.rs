@@ -1,14 +1,178 @@
+//! API errors
+//!
+//! Every error response is an RFC 7807 `application/problem+json` document:
+//!
+//! ```json
+//! {
+//!   "type": "urn:scho1ar:problem:validation.failed",
+//!   "title": "Unprocessable Entity",
+//!   "status": 422,
+//!   "detail": "/name: length must be between 1 and 128",
+//!   "code": "validation.failed",
+//!   "requestId": "5f0c...",
+//!   "errors": [{
+//!     "pointer": "/name",
+//!     "code": "length",
+//!     "message": "length must be between 1 and 128",
+//!     "params": { "min": 1, "max": 128 }
+//!   }]
+//! }
+//! ```
+//!
+//! Clients should branch on `code`, which is stable; `detail` is for people
+//! and its wording may change.
+
+use std::collections::BTreeMap;
+
 use axum::{
-    http::StatusCode,
+    http::{header, HeaderValue, StatusCode},
     response::{IntoResponse, Response},
     Json,
 };
-use serde_json::json;
+use serde::Serialize;
+use sqlx::error::ErrorKind;
+use sqlx::postgres::PgDatabaseError;
+use utoipa::ToSchema;
+
+use crate::{constraints, request_id};
+
+pub const PROBLEM_CONTENT_TYPE: &str = "application/problem+json";
+
+/// Prefix of the `type` URI of every problem, followed by its code
+const PROBLEM_TYPE_PREFIX: &str = "urn:scho1ar:problem:";
+
+/// A field of the request that failed validation
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldError {
+    /// JSON pointer to the field in the request body, e.g. `/name`
+    pub pointer: String,
+    /// Validator code, e.g. `length` or `email`
+    pub code: String,
+    pub message: String,
+    /// Arguments of the check, e.g. `min` and `max` of a length
+    #[serde(skip_serializing_if = "BTreeMap::is_empty")]
+    pub params: BTreeMap<String, serde_json::Value>,
+}
+
+/// An RFC 7807 problem details response body
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Problem {
+    /// `urn:scho1ar:problem:` followed by the code
+    #[serde(rename = "type")]
+    pub type_uri: String,
+    pub title: String,
+    pub status: u16,
+    pub detail: String,
+    /// Stable machine-readable code, e.g. `auth.token_expired`
+    pub code: &'static str,
+    /// The `X-Request-Id` of the request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub request_id: Option<String>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub errors: Vec<FieldError>,
+    /// Seconds after which the request may be retried, sent as `Retry-After`
+    #[serde(skip)]
+    pub retry_after: Option<u64>,
+}
+
+impl Problem {
+    pub fn new(status: StatusCode, code: &'static str, detail: impl Into<String>) -> Self {
+        Problem {
+            type_uri: format!("{}{}", PROBLEM_TYPE_PREFIX, code),
+            title: status.canonical_reason().unwrap_or("Error").to_string(),
+            status: status.as_u16(),
+            detail: detail.into(),
+            code,
+            request_id: request_id::current(),
+            errors: Vec::new(),
+            retry_after: None,
+        }
+    }
+
+    pub fn with_errors(mut self, errors: Vec<FieldError>) -> Self {
+        self.errors = errors;
+        self
+    }
+}
+
+impl IntoResponse for Problem {
+    fn into_response(self) -> Response {
+        let status = StatusCode::from_u16(self.status).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+        let retry_after = self.retry_after;
+        let mut response = (status, Json(self)).into_response();
+        let headers = response.headers_mut();
+        headers.insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static(PROBLEM_CONTENT_TYPE),
+        );
+        if let Some(seconds) = retry_after {
+            headers.insert(header::RETRY_AFTER, HeaderValue::from(seconds));
+        }
+        response
+    }
+}
+
+/// A request that violates a database constraint
+#[derive(Debug, Clone, PartialEq)]
+pub struct ConstraintViolation {
+    pub constraint: String,
+    /// Payload field the constraint guards, when registered
+    pub field: Option<&'static str>,
+    pub message: String,
+}
+
+impl ConstraintViolation {
+    fn new(constraint: Option<&str>, fallback: &str) -> Self {
+        let constraint = constraint.unwrap_or_default();
+        let registered = constraints::lookup(constraint);
+        ConstraintViolation {
+            constraint: constraint.to_string(),
+            field: registered.map(|c| c.field),
+            message: registered.map_or(fallback, |c| c.message).to_string(),
+        }
+    }
+
+    fn field_errors(&self, code: &str) -> Vec<FieldError> {
+        self.field
+            .map(|field| FieldError {
+                pointer: format!("/{}", field),
+                code: code.to_string(),
+                message: self.message.clone(),
+                params: BTreeMap::new(),
+            })
+            .into_iter()
+            .collect()
+    }
+}
+
+impl std::fmt::Display for ConstraintViolation {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} ({})", self.message, self.constraint)
+    }
+}
 
 #[derive(Debug, thiserror::Error)]
 pub enum AppError {
     #[error("Database error: {0}")]
-    Database(#[from] sqlx::Error),
+    Database(sqlx::Error),
+
+    /// A unique constraint violation: the resource already exists
+    #[error("Conflict: {0}")]
+    UniqueViolation(ConstraintViolation),
+
+    /// A foreign key violation: a referenced resource does not exist
+    #[error("Invalid reference: {0}")]
+    ForeignKeyViolation(ConstraintViolation),
+
+    /// A transient failure the request can be retried after
+    #[error("Unavailable: {detail}")]
+    Unavailable {
+        code: &'static str,
+        detail: String,
+        retry_after: u64,
+    },
 
     #[error("Not found: {0}")]
     NotFound(String),
@@ -16,46 +180,288 @@ pub enum AppError {
     #[error("Bad request: {0}")]
     BadRequest(String),
 
+    /// A request body that is not well-formed JSON
+    #[error("Invalid JSON: {}", .0.message)]
+    InvalidJson(FieldError),
+
     #[error("Validation error: {0}")]
     Validation(String),
 
+    /// Validation failures of individual request fields
+    #[error("Validation error: {0}")]
+    InvalidFields(String, Vec<FieldError>),
+
     #[error("Unauthorized")]
     Unauthorized,
 
+    #[error("Forbidden: {0}")]
+    Forbidden(String),
+
+    #[error("Conflict: {0}")]
+    Conflict(String),
+
+    /// An `If-Match` precondition that no longer holds
+    #[error("Precondition failed: {0}")]
+    PreconditionFailed(String),
+
     #[error("Internal error: {0}")]
     Internal(String),
 }
 
-impl IntoResponse for AppError {
-    fn into_response(self) -> Response {
-        let (status, message) = match &self {
+impl AppError {
+    pub fn status(&self) -> StatusCode {
+        match self {
+            AppError::Database(_) | AppError::Internal(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            AppError::UniqueViolation(_) => StatusCode::CONFLICT,
+            AppError::ForeignKeyViolation(_) => StatusCode::UNPROCESSABLE_ENTITY,
+            AppError::Unavailable { .. } => StatusCode::SERVICE_UNAVAILABLE,
+            AppError::NotFound(_) => StatusCode::NOT_FOUND,
+            AppError::BadRequest(_) | AppError::InvalidJson(_) => StatusCode::BAD_REQUEST,
+            AppError::Validation(_) | AppError::InvalidFields(..) => {
+                StatusCode::UNPROCESSABLE_ENTITY
+            }
+            AppError::Unauthorized => StatusCode::UNAUTHORIZED,
+            AppError::Forbidden(_) => StatusCode::FORBIDDEN,
+            AppError::Conflict(_) => StatusCode::CONFLICT,
+            AppError::PreconditionFailed(_) => StatusCode::PRECONDITION_FAILED,
+        }
+    }
+
+    /// Stable code of the error for clients to branch on
+    pub fn code(&self) -> &'static str {
+        match self {
+            AppError::Database(_) => "database.error",
+            AppError::UniqueViolation(_) => "resource.already_exists",
+            AppError::ForeignKeyViolation(_) => "resource.reference_not_found",
+            AppError::Unavailable { code, .. } => code,
+            AppError::NotFound(_) => "resource.not_found",
+            AppError::BadRequest(_) => "request.invalid",
+            AppError::InvalidJson(_) => "request.invalid_json",
+            AppError::Validation(_) | AppError::InvalidFields(..) => "validation.failed",
+            AppError::Unauthorized => "auth.unauthorized",
+            AppError::Forbidden(_) => "auth.forbidden",
+            AppError::Conflict(_) => "resource.conflict",
+            AppError::PreconditionFailed(_) => "resource.modified",
+            AppError::Internal(_) => "internal.error",
+        }
+    }
+}
+
+impl AppError {
+    /// Convert an error of a statement deleting rows of `table`.
+    ///
+    /// Postgres reports a foreign key violation on the referencing table
+    /// whichever side caused it, so one reported on another table means a
+    /// row there still references a row being deleted.
+    pub fn from_delete(table: &str, error: sqlx::Error) -> Self {
+        if let sqlx::Error::Database(db) = &error {
+            if matches!(db.kind(), ErrorKind::ForeignKeyViolation) && db.table() != Some(table) {
+                return AppError::Conflict("The resource is still in use".to_string());
+            }
+        }
+        AppError::from(error)
+    }
+}
+
+impl From<AppError> for Problem {
+    fn from(error: AppError) -> Self {
+        let (status, code) = (error.status(), error.code());
+        match error {
             AppError::Database(e) => {
                 tracing::error!("Database error: {:?}", e);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Database error".to_string(),
-                )
-            }
-            AppError::NotFound(msg) => (StatusCode::NOT_FOUND, msg.clone()),
-            AppError::BadRequest(msg) => (StatusCode::BAD_REQUEST, msg.clone()),
-            AppError::Validation(msg) => (StatusCode::UNPROCESSABLE_ENTITY, msg.clone()),
-            AppError::Unauthorized => (StatusCode::UNAUTHORIZED, "Unauthorized".to_string()),
+                Problem::new(status, code, "Database error")
+            }
             AppError::Internal(msg) => {
                 tracing::error!("Internal error: {}", msg);
-                (
-                    StatusCode::INTERNAL_SERVER_ERROR,
-                    "Internal server error".to_string(),
-                )
+                Problem::new(status, code, "Internal server error")
+            }
+            AppError::UniqueViolation(violation) => {
+                let errors = violation.field_errors("unique");
+                Problem::new(status, code, violation.message).with_errors(errors)
+            }
+            AppError::ForeignKeyViolation(violation) => {
+                let errors = violation.field_errors("reference");
+                Problem::new(status, code, violation.message).with_errors(errors)
+            }
+            AppError::Unavailable {
+                detail,
+                retry_after,
+                ..
+            } => {
+                tracing::warn!("Unavailable: {}", detail);
+                let mut problem = Problem::new(status, code, detail);
+                problem.retry_after = Some(retry_after);
+                problem
+            }
+            AppError::InvalidJson(error) => {
+                Problem::new(status, code, format!("Invalid JSON: {}", error.message))
+                    .with_errors(vec![error])
+            }
+            AppError::InvalidFields(msg, errors) => {
+                Problem::new(status, code, msg).with_errors(errors)
+            }
+            AppError::Unauthorized => Problem::new(status, code, "Unauthorized"),
+            AppError::NotFound(msg)
+            | AppError::BadRequest(msg)
+            | AppError::Validation(msg)
+            | AppError::Forbidden(msg)
+            | AppError::Conflict(msg)
+            | AppError::PreconditionFailed(msg) => Problem::new(status, code, msg),
+        }
+    }
+}
+
+/// Seconds to wait before retrying after a serialization failure or deadlock
+const RETRY_CONFLICT_SECS: u64 = 1;
+
+/// Seconds to wait before retrying when no connection was available
+const RETRY_BUSY_SECS: u64 = 5;
+
+impl From<sqlx::Error> for AppError {
+    fn from(error: sqlx::Error) -> Self {
+        let db = match &error {
+            sqlx::Error::RowNotFound => {
+                return AppError::NotFound("Resource not found".to_string())
             }
+            sqlx::Error::PoolTimedOut | sqlx::Error::PoolClosed => {
+                return AppError::Unavailable {
+                    code: "database.unavailable",
+                    detail: "The database is busy, try again shortly".to_string(),
+                    retry_after: RETRY_BUSY_SECS,
+                }
+            }
+            sqlx::Error::Database(db) => db,
+            _ => return AppError::Database(error),
         };
 
-        let body = Json(json!({
-            "error": message,
-            "status": status.as_u16()
-        }));
+        // serialization_failure and deadlock_detected
+        if matches!(db.code().as_deref(), Some("40001" | "40P01")) {
+            return AppError::Unavailable {
+                code: "database.retry",
+                detail: "The request conflicted with a concurrent one, retry it".to_string(),
+                retry_after: RETRY_CONFLICT_SECS,
+            };
+        }
+        match db.kind() {
+            ErrorKind::UniqueViolation => AppError::UniqueViolation(ConstraintViolation::new(
+                db.constraint(),
+                "The resource already exists",
+            )),
+            ErrorKind::ForeignKeyViolation => AppError::ForeignKeyViolation(
+                ConstraintViolation::new(db.constraint(), "A referenced resource does not exist"),
+            ),
+            ErrorKind::CheckViolation => {
+                let violation =
+                    ConstraintViolation::new(db.constraint(), "A value is out of range");
+                let mut errors = violation.field_errors("check");
+                if errors.is_empty() {
+                    errors.push(FieldError {
+                        pointer: String::new(),
+                        code: "check".to_string(),
+                        message: violation.message.clone(),
+                        params: BTreeMap::new(),
+                    });
+                }
+                AppError::InvalidFields(violation.message, errors)
+            }
+            ErrorKind::NotNullViolation => {
+                let column = db
+                    .try_downcast_ref::<PgDatabaseError>()
+                    .and_then(|e| e.column())
+                    .unwrap_or_default();
+                let error = FieldError {
+                    pointer: format!("/{}", camel_case(column)),
+                    code: "required".to_string(),
+                    message: format!("{} is required", camel_case(column)),
+                    params: BTreeMap::new(),
+                };
+                AppError::InvalidFields(error.message.clone(), vec![error])
+            }
+            _ => AppError::Database(error),
+        }
+    }
+}
+
+/// Payload name of a snake_case column
+fn camel_case(column: &str) -> String {
+    let mut parts = column.split('_');
+    let mut out = parts.next().unwrap_or_default().to_string();
+    for part in parts {
+        let mut chars = part.chars();
+        if let Some(first) = chars.next() {
+            out.extend(first.to_uppercase());
+            out.push_str(chars.as_str());
+        }
+    }
+    out
+}
 
-        (status, body).into_response()
+impl IntoResponse for AppError {
+    fn into_response(self) -> Response {
+        Problem::from(self).into_response()
     }
 }
 
 pub type AppResult<T> = Result<T, AppError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_invalid_fields_problem() {
+        let error = AppError::InvalidFields(
+            "name: too long".to_string(),
+            vec![FieldError {
+                pointer: "/name".to_string(),
+                code: "length".to_string(),
+                message: "too long".to_string(),
+                params: BTreeMap::from([("max".to_string(), json!(128))]),
+            }],
+        );
+        assert_eq!(
+            serde_json::to_value(Problem::from(error)).unwrap(),
+            json!({
+                "type": "urn:scho1ar:problem:validation.failed",
+                "title": "Unprocessable Entity",
+                "status": 422,
+                "detail": "name: too long",
+                "code": "validation.failed",
+                "errors": [{
+                    "pointer": "/name",
+                    "code": "length",
+                    "message": "too long",
+                    "params": { "max": 128 },
+                }],
+            })
+        );
+    }
+
+    #[test]
+    fn test_unique_violation_problem() {
+        let conflict = Problem::from(AppError::UniqueViolation(ConstraintViolation::new(
+            Some("cost_centers_organization_id_name_key"),
+            "exists",
+        )));
+        assert_eq!(
+            (conflict.status, conflict.code),
+            (409, "resource.already_exists")
+        );
+        assert_eq!(conflict.errors[0].pointer, "/name");
+    }
+
+    #[test]
+    fn test_pool_timeout_problem() {
+        let busy = Problem::from(AppError::from(sqlx::Error::PoolTimedOut));
+        assert_eq!((busy.status, busy.code), (503, "database.unavailable"));
+        assert_eq!(busy.retry_after, Some(RETRY_BUSY_SECS));
+    }
+
+    #[test]
+    fn test_internal_problem() {
+        let internal = Problem::from(AppError::Internal("connection string leaked".to_string()));
+        assert_eq!(internal.detail, "Internal server error");
+        assert_eq!(internal.code, "internal.error");
+    }
+}
@@ -0,0 +1,323 @@
+//! Idempotent retries of mutating requests
+//!
+//! A `POST`, `PUT`, `PATCH` or `DELETE` carrying an `Idempotency-Key` header
+//! runs once per key within an organization. The response is stored for 24
+//! hours and retries with the same key get it back, marked with an
+//! `Idempotent-Replayed: true` header, without running the request again.
+//!
+//! Reusing a key for a different request (method, path or body) is a `409`
+//! with code `idempotency.key_reused`; a retry arriving while the first
+//! request is still being handled is a `409` with code
+//! `idempotency.in_progress` and a `Retry-After` header. Server errors are
+//! not stored, so a retry after one runs the request again.
+
+pub mod store;
+
+use axum::{
+    body::{to_bytes, Body, Bytes},
+    extract::{Request, State},
+    http::{request::Parts, HeaderName, HeaderValue, Method, StatusCode},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use sha2::{Digest, Sha256};
+use uuid::Uuid;
+
+use crate::auth::AuthenticatedUser;
+use crate::db::DbPool;
+use crate::error::{AppError, Problem};
+use crate::AppState;
+use store::{ClaimOutcome, StoredResponse};
+
+pub const IDEMPOTENCY_KEY_HEADER: HeaderName = HeaderName::from_static("idempotency-key");
+
+/// Header marking a replayed response
+pub const REPLAYED_HEADER: HeaderName = HeaderName::from_static("idempotent-replayed");
+
+/// Longest key accepted
+pub const MAX_KEY_LEN: usize = 255;
+
+/// Largest request body of a request with a key, axum's default body limit
+const MAX_BODY_BYTES: usize = 2 * 1024 * 1024;
+
+/// Seconds a retry of a request in flight is asked to wait
+const IN_PROGRESS_RETRY_SECS: u64 = 1;
+
+/// Idempotency error responses
+#[derive(Debug)]
+pub enum IdempotencyError {
+    InvalidKey,
+    BodyTooLarge,
+    KeyReused,
+    InProgress,
+    Database(sqlx::Error),
+}
+
+impl From<sqlx::Error> for IdempotencyError {
+    fn from(error: sqlx::Error) -> Self {
+        IdempotencyError::Database(error)
+    }
+}
+
+impl IntoResponse for IdempotencyError {
+    fn into_response(self) -> Response {
+        let problem = match self {
+            IdempotencyError::InvalidKey => Problem::new(
+                StatusCode::BAD_REQUEST,
+                "idempotency.key_invalid",
+                format!(
+                    "Idempotency-Key must be 1 to {} printable ASCII characters",
+                    MAX_KEY_LEN
+                ),
+            ),
+            IdempotencyError::BodyTooLarge => Problem::new(
+                StatusCode::PAYLOAD_TOO_LARGE,
+                "idempotency.body_too_large",
+                format!(
+                    "Requests with an Idempotency-Key may have at most {} bytes of body",
+                    MAX_BODY_BYTES
+                ),
+            ),
+            IdempotencyError::KeyReused => Problem::new(
+                StatusCode::CONFLICT,
+                "idempotency.key_reused",
+                "The Idempotency-Key was already used for a different request",
+            ),
+            IdempotencyError::InProgress => {
+                let mut problem = Problem::new(
+                    StatusCode::CONFLICT,
+                    "idempotency.in_progress",
+                    "A request with this Idempotency-Key is still being handled",
+                );
+                problem.retry_after = Some(IN_PROGRESS_RETRY_SECS);
+                problem
+            }
+            IdempotencyError::Database(e) => Problem::from(AppError::from(e)),
+        };
+        problem.into_response()
+    }
+}
+
+/// The key of a request, if it has a usable one
+fn idempotency_key(value: &HeaderValue) -> Result<&str, IdempotencyError> {
+    value
+        .to_str()
+        .ok()
+        .filter(|key| {
+            !key.is_empty() && key.len() <= MAX_KEY_LEN && key.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .ok_or(IdempotencyError::InvalidKey)
+}
+
+/// Hex SHA-256 of what identifies a request beyond its key
+fn fingerprint(parts: &Parts, body: &[u8]) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(parts.method.as_str());
+    hasher.update(b"\n");
+    hasher.update(parts.uri.path_and_query().map_or("", |p| p.as_str()));
+    hasher.update(b"\n");
+    hasher.update(body);
+    hex::encode(hasher.finalize())
+}
+
+fn replayed(stored: StoredResponse) -> Response {
+    let mut response = Response::new(Body::from(stored.body));
+    *response.status_mut() =
+        StatusCode::from_u16(stored.status as u16).unwrap_or(StatusCode::INTERNAL_SERVER_ERROR);
+    let headers = response.headers_mut();
+    for (name, value) in stored.headers.0 {
+        if let (Ok(name), Ok(value)) = (HeaderName::try_from(name), HeaderValue::try_from(value)) {
+            headers.append(name, value);
+        }
+    }
+    headers.insert(REPLAYED_HEADER, HeaderValue::from_static("true"));
+    response
+}
+
+/// A claimed key, released when dropped before a response was stored, e.g.
+/// when the client disconnects mid-request
+struct Claim {
+    db: DbPool,
+    scope: String,
+    key: String,
+    id: Uuid,
+    settled: bool,
+}
+
+impl Claim {
+    async fn complete(mut self, response: &StoredResponse) {
+        self.settled = true;
+        if let Err(e) = store::complete(&self.db, &self.scope, &self.key, self.id, response).await {
+            tracing::error!("Failed to store response of idempotency key: {:?}", e);
+            self.release_now().await;
+        }
+    }
+
+    async fn release(mut self) {
+        self.settled = true;
+        self.release_now().await;
+    }
+
+    async fn release_now(&self) {
+        if let Err(e) = store::release(&self.db, &self.scope, &self.key, self.id).await {
+            tracing::error!("Failed to release idempotency key: {:?}", e);
+        }
+    }
+}
+
+impl Drop for Claim {
+    fn drop(&mut self) {
+        if self.settled {
+            return;
+        }
+        let (db, scope, key, id) = (
+            self.db.clone(),
+            self.scope.clone(),
+            self.key.clone(),
+            self.id,
+        );
+        tokio::spawn(async move {
+            if let Err(e) = store::release(&db, &scope, &key, id).await {
+                tracing::error!("Failed to release idempotency key: {:?}", e);
+            }
+        });
+    }
+}
+
+/// Middleware running each mutating request with an `Idempotency-Key` once
+/// per key. It needs the caller's claims, so goes inside
+/// [`crate::auth::require_auth`].
+pub async fn deduplicate(
+    State(state): State<AppState>,
+    request: Request,
+    next: Next,
+) -> Result<Response, IdempotencyError> {
+    let mutating = matches!(
+        *request.method(),
+        Method::POST | Method::PUT | Method::PATCH | Method::DELETE
+    );
+    let (Some(value), Some(AuthenticatedUser(claims)), true) = (
+        request.headers().get(&IDEMPOTENCY_KEY_HEADER),
+        request.extensions().get::<AuthenticatedUser>(),
+        mutating,
+    ) else {
+        return Ok(next.run(request).await);
+    };
+    let key = idempotency_key(value)?.to_string();
+    let scope = match claims.organization_id() {
+        Some(org_id) => org_id.to_string(),
+        None => format!("user:{}", claims.user_id()),
+    };
+
+    let (parts, body) = request.into_parts();
+    let body: Bytes = to_bytes(body, MAX_BODY_BYTES)
+        .await
+        .map_err(|_| IdempotencyError::BodyTooLarge)?;
+    let fingerprint = fingerprint(&parts, &body);
+
+    let id = match store::claim(&state.db, &scope, &key, &fingerprint).await? {
+        ClaimOutcome::Claimed(id) => id,
+        ClaimOutcome::InUse(existing) if existing.fingerprint != fingerprint => {
+            return Err(IdempotencyError::KeyReused);
+        }
+        ClaimOutcome::InUse(existing) => {
+            return existing
+                .response
+                .map(replayed)
+                .ok_or(IdempotencyError::InProgress);
+        }
+    };
+
+    let claim = Claim {
+        db: state.db.clone(),
+        scope,
+        key,
+        id,
+        settled: false,
+    };
+    let response = next.run(Request::from_parts(parts, Body::from(body))).await;
+    if response.status().is_server_error() {
+        claim.release().await;
+        return Ok(response);
+    }
+
+    let (parts, body) = response.into_parts();
+    let body = match to_bytes(body, usize::MAX).await {
+        Ok(body) => body,
+        Err(e) => {
+            claim.release().await;
+            let error = AppError::Internal(format!("Failed to read response body: {}", e));
+            return Ok(error.into_response());
+        }
+    };
+    let stored = StoredResponse {
+        status: parts.status.as_u16() as i16,
+        headers: sqlx::types::Json(
+            parts
+                .headers
+                .iter()
+                .filter_map(|(name, value)| {
+                    Some((name.to_string(), value.to_str().ok()?.to_string()))
+                })
+                .collect(),
+        ),
+        body: body.to_vec(),
+    };
+    claim.complete(&stored).await;
+    Ok(Response::from_parts(parts, Body::from(body)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::http::header;
+
+    fn parts(method: &str, uri: &str) -> Parts {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .body(())
+            .unwrap()
+            .into_parts()
+            .0
+    }
+
+    #[tokio::test]
+    async fn test_idempotency() {
+        assert_eq!(
+            idempotency_key(&HeaderValue::from_static("a1b2-c3")).unwrap(),
+            "a1b2-c3"
+        );
+        assert!(idempotency_key(&HeaderValue::from_static("two words")).is_err());
+        assert!(idempotency_key(&HeaderValue::from_static("")).is_err());
+
+        let post = fingerprint(&parts("POST", "/api/cloud-accounts"), b"{}");
+        assert_eq!(
+            post,
+            fingerprint(&parts("POST", "/api/cloud-accounts"), b"{}")
+        );
+        assert_ne!(
+            post,
+            fingerprint(&parts("POST", "/api/cloud-accounts"), b"{ }")
+        );
+        assert_ne!(
+            post,
+            fingerprint(&parts("PUT", "/api/cloud-accounts"), b"{}")
+        );
+        assert_ne!(post, fingerprint(&parts("POST", "/api/schedules"), b"{}"));
+
+        let response = replayed(StoredResponse {
+            status: 201,
+            headers: sqlx::types::Json(vec![(
+                "content-type".to_string(),
+                "application/json".to_string(),
+            )]),
+            body: b"{\"id\":1}".to_vec(),
+        });
+        assert_eq!(response.status(), StatusCode::CREATED);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(response.headers()[REPLAYED_HEADER], "true");
+        let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+        assert_eq!(&body[..], b"{\"id\":1}");
+    }
+}
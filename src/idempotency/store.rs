@@ -0,0 +1,158 @@
+//! Persistence of idempotency keys and their responses
+
+use sqlx::types::Json;
+use uuid::Uuid;
+
+use crate::db::DbPool;
+
+/// Hours a key and its response are kept
+const TTL_HOURS: i32 = 24;
+
+/// Seconds after which a request still in flight is assumed to have died
+/// with the process handling it, and its key may be claimed again
+const IN_FLIGHT_TIMEOUT_SECS: f64 = 300.0;
+
+/// A response stored for replay
+#[derive(Debug, Clone, PartialEq, sqlx::FromRow)]
+pub struct StoredResponse {
+    pub status: i16,
+    pub headers: Json<Vec<(String, String)>>,
+    pub body: Vec<u8>,
+}
+
+/// Outcome of claiming a key
+#[derive(Debug, Clone, PartialEq)]
+pub enum ClaimOutcome {
+    /// The key was free; the id identifies this claim
+    Claimed(Uuid),
+    /// An earlier request holds the key
+    InUse(ExistingKey),
+}
+
+/// A key claimed by an earlier request
+#[derive(Debug, Clone, PartialEq)]
+pub struct ExistingKey {
+    pub fingerprint: String,
+    /// `None` while the earlier request is in flight
+    pub response: Option<StoredResponse>,
+}
+
+#[derive(sqlx::FromRow)]
+struct ExistingKeyRow {
+    fingerprint: String,
+    status: Option<i16>,
+    response_headers: Option<Json<Vec<(String, String)>>>,
+    response_body: Option<Vec<u8>>,
+}
+
+/// Claim `key` for a request, or get the earlier claim if the key is in use
+pub async fn claim(
+    db: &DbPool,
+    scope: &str,
+    key: &str,
+    fingerprint: &str,
+) -> Result<ClaimOutcome, sqlx::Error> {
+    let claim_id = Uuid::new_v4();
+    let claimed = sqlx::query(
+        "INSERT INTO idempotency_keys (scope, key, fingerprint, claim_id, expires_at)
+         VALUES ($1, $2, $3, $6, NOW() + make_interval(hours => $4))
+         ON CONFLICT (scope, key) DO UPDATE
+         SET fingerprint = EXCLUDED.fingerprint, claim_id = EXCLUDED.claim_id, status = NULL,
+             response_headers = NULL, response_body = NULL, created_at = NOW(),
+             expires_at = EXCLUDED.expires_at
+         WHERE idempotency_keys.expires_at <= NOW()
+            OR (idempotency_keys.status IS NULL
+                AND idempotency_keys.created_at < NOW() - make_interval(secs => $5))",
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(fingerprint)
+    .bind(TTL_HOURS)
+    .bind(IN_FLIGHT_TIMEOUT_SECS)
+    .bind(claim_id)
+    .execute(db)
+    .await?;
+    if claimed.rows_affected() > 0 {
+        return Ok(ClaimOutcome::Claimed(claim_id));
+    }
+
+    let row: Option<ExistingKeyRow> = sqlx::query_as(
+        "SELECT fingerprint, status, response_headers, response_body
+         FROM idempotency_keys WHERE scope = $1 AND key = $2",
+    )
+    .bind(scope)
+    .bind(key)
+    .fetch_optional(db)
+    .await?;
+
+    // A key released between the two statements is treated as in flight;
+    // the retry it leads to can claim it
+    let row = row.unwrap_or(ExistingKeyRow {
+        fingerprint: fingerprint.to_string(),
+        status: None,
+        response_headers: None,
+        response_body: None,
+    });
+    let response = match (row.status, row.response_headers, row.response_body) {
+        (Some(status), Some(headers), Some(body)) => Some(StoredResponse {
+            status,
+            headers,
+            body,
+        }),
+        _ => None,
+    };
+    Ok(ClaimOutcome::InUse(ExistingKey {
+        fingerprint: row.fingerprint,
+        response,
+    }))
+}
+
+/// Store the response of the request holding `key` under `claim_id`
+pub async fn complete(
+    db: &DbPool,
+    scope: &str,
+    key: &str,
+    claim_id: Uuid,
+    response: &StoredResponse,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE idempotency_keys SET status = $4, response_headers = $5, response_body = $6
+         WHERE scope = $1 AND key = $2 AND claim_id = $3",
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(claim_id)
+    .bind(response.status)
+    .bind(&response.headers)
+    .bind(&response.body)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Give up a claim without a response, so the request can be retried
+pub async fn release(
+    db: &DbPool,
+    scope: &str,
+    key: &str,
+    claim_id: Uuid,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "DELETE FROM idempotency_keys
+         WHERE scope = $1 AND key = $2 AND claim_id = $3 AND status IS NULL",
+    )
+    .bind(scope)
+    .bind(key)
+    .bind(claim_id)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Delete expired keys. Returns the number deleted.
+pub async fn purge_expired(db: &DbPool) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query("DELETE FROM idempotency_keys WHERE expires_at <= NOW()")
+        .execute(db)
+        .await?;
+    Ok(result.rows_affected())
+}
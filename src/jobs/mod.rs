@@ -0,0 +1,13 @@
+//! Durable background jobs
+//!
+//! Jobs are rows of the `jobs` table carrying a typed payload. Workers,
+//! started with the server or on their own with the binary's `worker` mode,
+//! claim them with `FOR UPDATE SKIP LOCKED`, retry failures with exponential
+//! backoff and leave jobs that run out of attempts dead. A unique key keeps
+//! the same work from being queued twice.
+
+pub mod model;
+pub mod store;
+pub mod worker;
+
+pub use model::{Job, JobPayload, JobStatus, NewJob, DEFAULT_MAX_ATTEMPTS};
@@ -0,0 +1,203 @@
+//! Job API and storage types
+
+use std::path::PathBuf;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+/// Attempts a job gets unless enqueued with a different limit
+pub const DEFAULT_MAX_ATTEMPTS: i32 = 5;
+
+/// Work a job carries out. Stored in the job's payload, tagged with its kind.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(
+    tag = "kind",
+    rename_all = "snake_case",
+    rename_all_fields = "camelCase"
+)]
+pub enum JobPayload {
+    /// Discover a cloud account's resources into a started discovery run,
+    /// then evaluate the organization's waste rules
+    Discovery {
+        cloud_account_id: Uuid,
+        run_id: Uuid,
+    },
+    /// Evaluate the organization's waste rules
+    WasteEvaluation,
+    /// Load new price files from a directory
+    PricingSync { directory: PathBuf },
+    /// Start discovery of every cloud account
+    DiscoverAccounts,
+    /// Bring the resources of every enabled start/stop schedule to the state
+    /// it wants them in
+    RunSchedules,
+    /// Send a queued notification delivery
+    Notification { delivery_id: Uuid },
+    /// Delete expired idempotency keys
+    PurgeIdempotencyKeys,
+    /// Apply the organization's current tag rules to its stored line items
+    RenormalizeTags,
+}
+
+impl JobPayload {
+    pub fn kind(&self) -> &'static str {
+        match self {
+            JobPayload::Discovery { .. } => "discovery",
+            JobPayload::WasteEvaluation => "waste_evaluation",
+            JobPayload::PricingSync { .. } => "pricing_sync",
+            JobPayload::DiscoverAccounts => "discover_accounts",
+            JobPayload::RunSchedules => "run_schedules",
+            JobPayload::Notification { .. } => "notification",
+            JobPayload::PurgeIdempotencyKeys => "purge_idempotency_keys",
+            JobPayload::RenormalizeTags => "renormalize_tags",
+        }
+    }
+}
+
+/// A job to enqueue
+#[derive(Debug, Clone)]
+pub struct NewJob {
+    pub organization_id: Option<String>,
+    pub payload: JobPayload,
+    /// Skip enqueuing while a queued or running job carries the same key
+    pub unique_key: Option<String>,
+    pub run_at: Option<DateTime<Utc>>,
+    pub max_attempts: i32,
+}
+
+impl NewJob {
+    pub fn new(organization_id: Option<&str>, payload: JobPayload) -> Self {
+        NewJob {
+            organization_id: organization_id.map(str::to_string),
+            payload,
+            unique_key: None,
+            run_at: None,
+            max_attempts: DEFAULT_MAX_ATTEMPTS,
+        }
+    }
+
+    pub fn unique(mut self, key: impl Into<String>) -> Self {
+        self.unique_key = Some(key.into());
+        self
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum JobStatus {
+    /// Waiting for `runAt`, including failed jobs waiting to be retried
+    Queued,
+    Running,
+    Succeeded,
+    /// Out of attempts
+    Dead,
+}
+
+impl JobStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            JobStatus::Queued => "queued",
+            JobStatus::Running => "running",
+            JobStatus::Succeeded => "succeeded",
+            JobStatus::Dead => "dead",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "running" => JobStatus::Running,
+            "succeeded" => JobStatus::Succeeded,
+            "dead" => JobStatus::Dead,
+            _ => JobStatus::Queued,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Job {
+    pub id: Uuid,
+    pub kind: String,
+    /// The job as stored; kept as JSON so jobs of kinds this version does
+    /// not know can still be listed
+    pub payload: serde_json::Value,
+    pub status: JobStatus,
+    pub unique_key: Option<String>,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub locked_by: Option<String>,
+    pub last_error: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct JobRow {
+    pub id: Uuid,
+    pub organization_id: Option<String>,
+    pub kind: String,
+    pub payload: Json<serde_json::Value>,
+    pub status: String,
+    pub unique_key: Option<String>,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub run_at: DateTime<Utc>,
+    pub locked_at: Option<DateTime<Utc>>,
+    pub locked_by: Option<String>,
+    pub last_error: Option<String>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<JobRow> for Job {
+    fn from(row: JobRow) -> Self {
+        Job {
+            id: row.id,
+            kind: row.kind,
+            payload: row.payload.0,
+            status: JobStatus::parse(&row.status),
+            unique_key: row.unique_key,
+            attempts: row.attempts,
+            max_attempts: row.max_attempts,
+            run_at: row.run_at,
+            locked_at: row.locked_at,
+            locked_by: row.locked_by,
+            last_error: row.last_error,
+            finished_at: row.finished_at,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// A job claimed by a worker
+#[derive(Debug, Clone)]
+pub struct ClaimedJob {
+    pub id: Uuid,
+    pub organization_id: Option<String>,
+    pub kind: String,
+    pub payload: serde_json::Value,
+    /// Including the current one
+    pub attempts: i32,
+    pub max_attempts: i32,
+}
+
+impl From<JobRow> for ClaimedJob {
+    fn from(row: JobRow) -> Self {
+        ClaimedJob {
+            id: row.id,
+            organization_id: row.organization_id,
+            kind: row.kind,
+            payload: row.payload.0,
+            attempts: row.attempts,
+            max_attempts: row.max_attempts,
+        }
+    }
+}
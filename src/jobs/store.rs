@@ -0,0 +1,214 @@
+//! Enqueuing, claiming and finishing jobs
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::types::Json;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use super::model::{ClaimedJob, Job, JobRow, NewJob};
+use crate::db::DbPool;
+use crate::pagination::{Field, FieldKind, Listing, Page, PageQuery};
+
+const COLUMNS: &str = "id, organization_id, kind, payload, status, unique_key, attempts, \
+     max_attempts, run_at, locked_at, locked_by, last_error, finished_at, created_at, updated_at";
+
+/// Add a job to the queue. Returns `None` if a queued or running job
+/// already carries its unique key.
+pub async fn enqueue(db: &DbPool, job: &NewJob) -> Result<Option<Job>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let job = enqueue_in(&mut tx, job).await?;
+    tx.commit().await?;
+    Ok(job)
+}
+
+/// [`enqueue`] as part of a larger transaction
+pub async fn enqueue_in(
+    tx: &mut Transaction<'_, Postgres>,
+    job: &NewJob,
+) -> Result<Option<Job>, sqlx::Error> {
+    let row: Option<JobRow> = sqlx::query_as(&format!(
+        "INSERT INTO jobs (organization_id, kind, payload, unique_key, run_at, max_attempts)
+         VALUES ($1, $2, $3, $4, COALESCE($5, NOW()), $6)
+         ON CONFLICT (unique_key) WHERE status IN ('queued', 'running') DO NOTHING
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(&job.organization_id)
+    .bind(job.payload.kind())
+    .bind(Json(&job.payload))
+    .bind(&job.unique_key)
+    .bind(job.run_at)
+    .bind(job.max_attempts)
+    .fetch_optional(&mut **tx)
+    .await?;
+    Ok(row.map(Job::from))
+}
+
+/// Claim the queued job that has been due the longest, skipping jobs other
+/// workers are claiming
+pub async fn claim(db: &DbPool, worker: &str) -> Result<Option<ClaimedJob>, sqlx::Error> {
+    let row: Option<JobRow> = sqlx::query_as(&format!(
+        "UPDATE jobs
+         SET status = 'running', attempts = attempts + 1, locked_at = NOW(), locked_by = $1
+         WHERE id = (
+             SELECT id FROM jobs
+             WHERE status = 'queued' AND run_at <= NOW()
+             ORDER BY run_at, created_at
+             LIMIT 1
+             FOR UPDATE SKIP LOCKED
+         )
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(worker)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(ClaimedJob::from))
+}
+
+/// Keep `worker`'s lock on a running job fresh. Returns whether the worker
+/// still holds the job.
+pub async fn heartbeat(db: &DbPool, id: Uuid, worker: &str) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE jobs SET locked_at = NOW()
+         WHERE id = $1 AND status = 'running' AND locked_by = $2",
+    )
+    .bind(id)
+    .bind(worker)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record that `worker` finished a job, unless the job was released from it
+pub async fn complete(db: &DbPool, id: Uuid, worker: &str) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE jobs
+         SET status = 'succeeded', finished_at = NOW(), locked_at = NULL, locked_by = NULL
+         WHERE id = $1 AND status = 'running' AND locked_by = $2",
+    )
+    .bind(id)
+    .bind(worker)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Record a failed attempt of `worker`. The job is queued again to run at
+/// `retry_at`, or left dead without one.
+pub async fn fail(
+    db: &DbPool,
+    id: Uuid,
+    worker: &str,
+    error: &str,
+    retry_at: Option<DateTime<Utc>>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE jobs
+         SET status = CASE WHEN $3::timestamptz IS NULL THEN 'dead' ELSE 'queued' END,
+             run_at = COALESCE($3, run_at),
+             finished_at = CASE WHEN $3::timestamptz IS NULL THEN NOW() END,
+             last_error = $2, locked_at = NULL, locked_by = NULL
+         WHERE id = $1 AND status = 'running' AND locked_by = $4",
+    )
+    .bind(id)
+    .bind(error)
+    .bind(retry_at)
+    .bind(worker)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+/// Queue again jobs whose worker has not refreshed its lock for longer than
+/// `timeout`, presumably because it died. Jobs out of attempts are left dead. Returns
+/// the number of jobs released.
+pub async fn release_stale(db: &DbPool, timeout: Duration) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE jobs
+         SET status = CASE WHEN attempts >= max_attempts THEN 'dead' ELSE 'queued' END,
+             finished_at = CASE WHEN attempts >= max_attempts THEN NOW() END,
+             run_at = NOW(),
+             last_error = 'worker ' || COALESCE(locked_by, '') || ' did not finish the job',
+             locked_at = NULL, locked_by = NULL
+         WHERE status = 'running' AND locked_at < NOW() - make_interval(secs => $1)",
+    )
+    .bind(timeout.num_seconds() as f64)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+pub const LISTING: Listing = Listing {
+    fields: &[
+        Field::new("createdAt", "created_at", FieldKind::Timestamp),
+        Field::new("runAt", "run_at", FieldKind::Timestamp),
+        Field::new("status", "status", FieldKind::Text),
+        Field::new("kind", "kind", FieldKind::Text),
+        Field::new("attempts", "attempts", FieldKind::Integer),
+        Field::new("finishedAt", "finished_at", FieldKind::Timestamp).filter_only(),
+    ],
+    default_sort: "-createdAt",
+};
+
+/// A page of the organization's jobs, or of system jobs without `org_id`
+pub async fn list_jobs(
+    db: &DbPool,
+    org_id: Option<&str>,
+    page: &PageQuery,
+) -> Result<Page<Job>, sqlx::Error> {
+    let mut builder = sqlx::QueryBuilder::new(format!("SELECT {} FROM jobs", COLUMNS));
+    // Spelled out rather than `IS NOT DISTINCT FROM` so that the
+    // organization index applies
+    match org_id {
+        Some(org_id) => builder.push(" WHERE organization_id = ").push_bind(org_id),
+        None => builder.push(" WHERE organization_id IS NULL"),
+    };
+    page.push_conditions(&mut builder);
+    page.push_order_and_limit(&mut builder);
+
+    let rows: Vec<JobRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(page.page(rows.into_iter().map(Job::from).collect()))
+}
+
+/// The organization's job, or a system job without `org_id`
+pub async fn get_job(
+    db: &DbPool,
+    org_id: Option<&str>,
+    id: Uuid,
+) -> Result<Option<Job>, sqlx::Error> {
+    let row: Option<JobRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM jobs WHERE organization_id IS NOT DISTINCT FROM $1 AND id = $2",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(Job::from))
+}
+
+/// Queue a dead job again with fresh attempts. Returns `None` if the job is
+/// not dead or another queued or running job carries its unique key.
+pub async fn retry_job(
+    db: &DbPool,
+    org_id: Option<&str>,
+    id: Uuid,
+) -> Result<Option<Job>, sqlx::Error> {
+    let row: Option<JobRow> = sqlx::query_as(&format!(
+        "UPDATE jobs j
+         SET status = 'queued', attempts = 0, run_at = NOW(), finished_at = NULL
+         WHERE j.organization_id IS NOT DISTINCT FROM $1 AND j.id = $2 AND j.status = 'dead'
+           AND NOT EXISTS (
+               SELECT 1 FROM jobs o
+               WHERE o.unique_key = j.unique_key AND o.status IN ('queued', 'running')
+           )
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(Job::from))
+}
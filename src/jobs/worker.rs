@@ -0,0 +1,261 @@
+//! The job worker pool
+//!
+//! Each worker claims one job at a time and polls when the queue is empty.
+//! Jobs run in their own task so a panic fails the attempt instead of the
+//! worker. Failed attempts are retried with exponential backoff; jobs whose
+//! payload cannot be read are left dead straight away.
+
+use std::time::Duration;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::model::{ClaimedJob, JobPayload, NewJob};
+use super::store;
+use crate::cloud_accounts;
+use crate::config::Config;
+use crate::db::DbPool;
+use crate::idempotency;
+use crate::notifications;
+use crate::pricing;
+use crate::recommendations;
+use crate::resources::{self, RunStatus};
+use crate::schedules;
+use crate::tags;
+
+/// How long a worker waits before polling an empty queue again
+const POLL_INTERVAL: Duration = Duration::from_secs(2);
+
+/// How often running jobs are checked for workers that died
+const RELEASE_INTERVAL: Duration = Duration::from_secs(60);
+
+/// How often a worker refreshes the lock of the job it is running
+const HEARTBEAT_INTERVAL: Duration = Duration::from_secs(30);
+
+/// How long a job's lock may go without a refresh before the job is
+/// presumed abandoned by a worker that died
+const LOCK_TIMEOUT_SECS: i64 = 5 * 60;
+
+const BASE_BACKOFF_SECS: i64 = 30;
+const MAX_BACKOFF_SECS: i64 = 60 * 60;
+
+/// Delay before retrying a job that failed its `attempts`th attempt: 30
+/// seconds doubling with every attempt, at most an hour
+pub fn backoff(attempts: i32) -> chrono::Duration {
+    let doublings = (attempts - 1).clamp(0, 16) as u32;
+    chrono::Duration::seconds((BASE_BACKOFF_SECS << doublings).min(MAX_BACKOFF_SECS))
+}
+
+/// Run `concurrency` workers and the release of abandoned jobs, forever
+pub async fn run(db: DbPool, config: Config, concurrency: usize) {
+    let prefix = format!("{}-{}", std::process::id(), Uuid::new_v4().simple());
+    tracing::info!("Starting {} job workers", concurrency);
+    for index in 0..concurrency {
+        let name = format!("{}-{}", prefix, index);
+        tokio::spawn(work(db.clone(), config.clone(), name));
+    }
+
+    let mut interval = tokio::time::interval(RELEASE_INTERVAL);
+    loop {
+        interval.tick().await;
+        match store::release_stale(&db, chrono::Duration::seconds(LOCK_TIMEOUT_SECS)).await {
+            Ok(0) => {}
+            Ok(released) => tracing::warn!("Released {} abandoned jobs", released),
+            Err(e) => tracing::error!("Failed to release abandoned jobs: {}", e),
+        }
+    }
+}
+
+async fn work(db: DbPool, config: Config, name: String) {
+    loop {
+        match store::claim(&db, &name).await {
+            Ok(Some(job)) => {
+                if let Err(e) = process(&db, &config, &name, job).await {
+                    tracing::error!("Failed to record job outcome: {}", e);
+                }
+            }
+            Ok(None) => tokio::time::sleep(POLL_INTERVAL).await,
+            Err(e) => {
+                tracing::error!("Failed to claim a job: {}", e);
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        }
+    }
+}
+
+async fn process(
+    db: &DbPool,
+    config: &Config,
+    worker: &str,
+    job: ClaimedJob,
+) -> Result<(), sqlx::Error> {
+    let payload: JobPayload = match serde_json::from_value(job.payload.clone()) {
+        Ok(payload) => payload,
+        Err(e) => {
+            let error = format!("unreadable {} job: {}", job.kind, e);
+            tracing::error!("Job {} failed permanently: {}", job.id, error);
+            return store::fail(db, job.id, worker, &error, None).await;
+        }
+    };
+
+    tracing::debug!("Running {} job {}", job.kind, job.id);
+    let mut task = tokio::spawn(perform(
+        db.clone(),
+        config.clone(),
+        job.organization_id.clone(),
+        payload,
+    ));
+    // Refresh the lock while the job runs so it is not released to another
+    // worker however long it takes
+    let mut heartbeat = tokio::time::interval(HEARTBEAT_INTERVAL);
+    heartbeat.tick().await;
+    let joined = loop {
+        tokio::select! {
+            joined = &mut task => break joined,
+            _ = heartbeat.tick() => match store::heartbeat(db, job.id, worker).await {
+                Ok(true) => {}
+                Ok(false) => tracing::warn!("Job {} was released from worker {}", job.id, worker),
+                Err(e) => tracing::error!("Failed to refresh the lock of job {}: {}", job.id, e),
+            },
+        }
+    };
+    let result = match joined {
+        Ok(result) => result,
+        Err(e) => Err(format!("job panicked: {}", e)),
+    };
+
+    match result {
+        Ok(()) => store::complete(db, job.id, worker).await,
+        Err(error) => {
+            let retry_at =
+                (job.attempts < job.max_attempts).then(|| Utc::now() + backoff(job.attempts));
+            match retry_at {
+                Some(at) => tracing::warn!("Job {} failed, retrying at {}: {}", job.id, at, error),
+                None => tracing::error!("Job {} failed permanently: {}", job.id, error),
+            }
+            store::fail(db, job.id, worker, &error, retry_at).await
+        }
+    }
+}
+
+async fn perform(
+    db: DbPool,
+    config: Config,
+    org_id: Option<String>,
+    payload: JobPayload,
+) -> Result<(), String> {
+    let organization = || org_id.as_deref().ok_or("job has no organization");
+    match payload {
+        JobPayload::Discovery {
+            cloud_account_id,
+            run_id,
+        } => {
+            let org_id = organization()?;
+            let run = resources::store::get_run(&db, run_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            let account = cloud_accounts::store::get_account(&db, org_id, cloud_account_id)
+                .await
+                .map_err(|e| e.to_string())?;
+            // Runs given up on as stale and accounts deleted meanwhile are
+            // left alone
+            let (Some(run), Some(account)) = (run, account) else {
+                return Ok(());
+            };
+            if run.status != RunStatus::Running {
+                return Ok(());
+            }
+            resources::discovery::run(&db, &config.aws, org_id, &account, &run)
+                .await
+                .map_err(|e| e.to_string())?;
+            enqueue_waste_evaluation(&db, org_id)
+                .await
+                .map_err(|e| e.to_string())
+        }
+        JobPayload::WasteEvaluation => {
+            recommendations::store::evaluate(&db, organization()?, Utc::now())
+                .await
+                .map(|_| ())
+                .map_err(|e| e.to_string())
+        }
+        JobPayload::PricingSync { directory } => pricing::loader::sync_directory(&db, &directory)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        JobPayload::DiscoverAccounts => discover_accounts(&db).await.map_err(|e| e.to_string()),
+        JobPayload::RunSchedules => schedules::worker::run_all(&db, &config.aws)
+            .await
+            .map_err(|e| e.to_string()),
+        JobPayload::Notification { delivery_id } => {
+            notifications::send::deliver(&db, &config.notifications, delivery_id).await
+        }
+        JobPayload::PurgeIdempotencyKeys => idempotency::store::purge_expired(&db)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+        JobPayload::RenormalizeTags => tags::store::renormalize(&db, organization()?)
+            .await
+            .map(|_| ())
+            .map_err(|e| e.to_string()),
+    }
+}
+
+/// Start a discovery run for every account without one in progress
+async fn discover_accounts(db: &DbPool) -> Result<(), sqlx::Error> {
+    for (org_id, cloud_account_id) in cloud_accounts::store::discoverable_accounts(db).await? {
+        let Some(run) = resources::store::start_run(db, &org_id, cloud_account_id).await? else {
+            continue;
+        };
+        let job = NewJob::new(
+            Some(&org_id),
+            JobPayload::Discovery {
+                cloud_account_id,
+                run_id: run.id,
+            },
+        );
+        store::enqueue(db, &job).await?;
+    }
+    Ok(())
+}
+
+/// Queue an evaluation of the organization's waste rules unless one is
+/// already waiting
+pub async fn enqueue_waste_evaluation(db: &DbPool, org_id: &str) -> Result<(), sqlx::Error> {
+    let job = NewJob::new(Some(org_id), JobPayload::WasteEvaluation)
+        .unique(format!("waste_evaluation:{}", org_id));
+    store::enqueue(db, &job).await?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_backoff() {
+        assert_eq!(backoff(1), chrono::Duration::seconds(30));
+        assert_eq!(backoff(2), chrono::Duration::seconds(60));
+        assert_eq!(backoff(4), chrono::Duration::seconds(240));
+        assert_eq!(backoff(8), chrono::Duration::hours(1));
+        assert_eq!(backoff(100), chrono::Duration::hours(1));
+    }
+
+    #[test]
+    fn test_payload_round_trip() {
+        let payload = JobPayload::Discovery {
+            cloud_account_id: Uuid::nil(),
+            run_id: Uuid::nil(),
+        };
+        let value = serde_json::to_value(&payload).unwrap();
+        assert_eq!(value["kind"], "discovery");
+        assert_eq!(value["cloudAccountId"], Uuid::nil().to_string());
+        assert_eq!(
+            serde_json::from_value::<JobPayload>(value).unwrap(),
+            payload
+        );
+        assert_eq!(
+            serde_json::to_value(JobPayload::WasteEvaluation).unwrap()["kind"],
+            JobPayload::WasteEvaluation.kind()
+        );
+    }
+}
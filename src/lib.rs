@@ -1,8 +1,30 @@
 pub mod auth;
+pub mod aws;
+pub mod chargeback;
+pub mod cloud_accounts;
+pub mod cloud_ids;
+pub mod commitments;
+pub mod conditional;
 pub mod config;
+pub mod constraints;
+pub mod costs;
 pub mod db;
 pub mod error;
+pub mod idempotency;
+pub mod jobs;
+pub mod notifications;
+pub mod openapi;
+pub mod pagination;
+pub mod pricing;
+pub mod recommendations;
+pub mod request_id;
+pub mod resources;
 pub mod routes;
+pub mod schedules;
+pub mod tags;
+pub mod tasks;
+pub mod unit_metrics;
+pub mod utilization;
 pub mod validation;
 
 use auth::jwks::{create_jwks_cache, SharedJwksCache};
@@ -1,23 +1,36 @@
 use std::net::SocketAddr;
 
 use axum::http::{header, HeaderValue, Method};
-use scho1ar_backend::{config::Config, db, routes, AppState};
+use scho1ar_backend::config::{Config, LogFormat};
+use scho1ar_backend::jobs::{self, JobPayload, NewJob};
+use scho1ar_backend::{db, idempotency, request_id, routes, tasks, AppState};
 use tower_http::cors::CorsLayer;
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
-    // Initialize tracing
-    tracing_subscriber::registry()
-        .with(
-            tracing_subscriber::EnvFilter::try_from_default_env()
-                .unwrap_or_else(|_| "scho1ar_backend=debug,tower_http=debug".into()),
-        )
-        .with(tracing_subscriber::fmt::layer())
-        .init();
-
     // Load configuration
     let config = Config::from_env()?;
+
+    // Initialize tracing
+    let logs = tracing_subscriber::registry().with(
+        tracing_subscriber::EnvFilter::try_from_default_env()
+            .unwrap_or_else(|_| "scho1ar_backend=debug,tower_http=debug".into()),
+    );
+    match config.log_format {
+        LogFormat::Text => logs.with(tracing_subscriber::fmt::layer()).init(),
+        // Events carry their fields at the top level and the fields of the
+        // request span they were logged in under `span`
+        LogFormat::Json => logs
+            .with(
+                tracing_subscriber::fmt::layer()
+                    .json()
+                    .flatten_event(true)
+                    .with_current_span(true)
+                    .with_span_list(false),
+            )
+            .init(),
+    }
     tracing::info!("Starting Scho1ar Backend in {} mode", config.environment);
 
     // Connect to database
@@ -25,6 +38,35 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     let pool = db::create_pool(&config.database_url).await?;
     tracing::info!("Database connected successfully");
 
+    // Load new price files in the background
+    if let Some(dir) = config.pricing_data_dir.clone() {
+        let job =
+            NewJob::new(None, JobPayload::PricingSync { directory: dir }).unique("pricing_sync");
+        jobs::store::enqueue(&pool, &job).await?;
+    }
+
+    // Enqueue recurring tasks from whichever replica is the leader
+    tokio::spawn(tasks::scheduler::run(
+        pool.clone(),
+        tasks::registry::recurring_tasks(&config),
+    ));
+
+    // `worker` runs only the background work, for deployments that scale it
+    // separately from the API
+    if std::env::args().nth(1).as_deref() == Some("worker") {
+        tracing::info!("Running in worker mode");
+        jobs::worker::run(pool, config.clone(), config.worker_concurrency.max(1)).await;
+        return Ok(());
+    }
+
+    if config.worker_concurrency > 0 {
+        tokio::spawn(jobs::worker::run(
+            pool.clone(),
+            config.clone(),
+            config.worker_concurrency,
+        ));
+    }
+
     // Create application state
     let state = AppState::new(pool, config.clone());
 
@@ -50,6 +92,15 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
             header::CONTENT_TYPE,
             header::ACCEPT,
             header::ORIGIN,
+            request_id::REQUEST_ID_HEADER,
+            idempotency::IDEMPOTENCY_KEY_HEADER,
+            header::IF_MATCH,
+            header::IF_NONE_MATCH,
+        ])
+        .expose_headers([
+            request_id::REQUEST_ID_HEADER,
+            idempotency::REPLAYED_HEADER,
+            header::ETAG,
         ])
         .allow_credentials(true);
 
@@ -0,0 +1,17 @@
+//! Notifications
+//!
+//! Organizations configure channels: email lists sent through the
+//! configured SMTP server, Slack and Microsoft Teams incoming webhooks, and
+//! generic webhooks with HMAC-SHA256 signed payloads. Events are rendered
+//! from templates into a delivery per interested channel, which a job sends
+//! and retries; every delivery is kept as the channel's log.
+
+pub mod model;
+pub mod send;
+pub mod store;
+pub mod template;
+
+pub use model::{
+    ChannelInput, ChannelKind, Delivery, DeliveryStatus, Message, NotificationChannel,
+    NotificationEvent,
+};
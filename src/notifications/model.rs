@@ -0,0 +1,328 @@
+//! Notification API and storage types
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidateEmail, ValidateUrl, ValidationError};
+
+use crate::conditional::Versioned;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ChannelKind {
+    Email,
+    Slack,
+    Teams,
+    /// Generic HTTPS webhook with HMAC-SHA256 signed requests
+    Webhook,
+}
+
+impl ChannelKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChannelKind::Email => "email",
+            ChannelKind::Slack => "slack",
+            ChannelKind::Teams => "teams",
+            ChannelKind::Webhook => "webhook",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "slack" => ChannelKind::Slack,
+            "teams" => ChannelKind::Teams,
+            "webhook" => ChannelKind::Webhook,
+            _ => ChannelKind::Email,
+        }
+    }
+}
+
+/// Something a channel can be told about
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum NotificationEvent {
+    /// Sent from the channel's test action
+    Test,
+    /// A start/stop schedule failed to start or stop a resource
+    ScheduleActionFailed,
+    /// Discovery of a cloud account failed
+    DiscoveryFailed,
+}
+
+impl NotificationEvent {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            NotificationEvent::Test => "test",
+            NotificationEvent::ScheduleActionFailed => "schedule_action_failed",
+            NotificationEvent::DiscoveryFailed => "discovery_failed",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "schedule_action_failed" => NotificationEvent::ScheduleActionFailed,
+            "discovery_failed" => NotificationEvent::DiscoveryFailed,
+            _ => NotificationEvent::Test,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_target"))]
+pub struct ChannelInput {
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub name: String,
+    pub kind: ChannelKind,
+    /// Addresses of email channels
+    #[serde(default)]
+    #[validate(length(max = 50), custom(function = "validate_recipients"))]
+    #[schema(max_items = 50)]
+    pub recipients: Vec<String>,
+    /// Webhook URL of Slack, Teams and generic webhook channels
+    #[validate(length(max = 2048))]
+    #[schema(max_length = 2048)]
+    pub url: Option<String>,
+    /// Events to send; every event when empty
+    #[serde(default)]
+    pub events: Vec<NotificationEvent>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn invalid(code: &'static str, message: String) -> ValidationError {
+    let mut err = ValidationError::new(code);
+    err.message = Some(message.into());
+    err
+}
+
+fn validate_recipients(recipients: &[String]) -> Result<(), ValidationError> {
+    match recipients.iter().find(|r| !r.validate_email()) {
+        Some(recipient) => Err(invalid(
+            "recipients",
+            format!("'{}' is not an email address", recipient),
+        )),
+        None => Ok(()),
+    }
+}
+
+fn validate_target(input: &ChannelInput) -> Result<(), ValidationError> {
+    match (input.kind, &input.url) {
+        (ChannelKind::Email, None) if !input.recipients.is_empty() => Ok(()),
+        (ChannelKind::Email, None) => Err(invalid(
+            "recipients",
+            "Email channels need at least one recipient".to_string(),
+        )),
+        (ChannelKind::Email, Some(_)) => Err(invalid(
+            "url",
+            "Email channels do not take a URL".to_string(),
+        )),
+        (_, Some(url)) if url.validate_url() && input.recipients.is_empty() => Ok(()),
+        (_, Some(url)) if input.recipients.is_empty() => {
+            Err(invalid("url", format!("'{}' is not a URL", url)))
+        }
+        (_, Some(_)) => Err(invalid(
+            "recipients",
+            "Only email channels take recipients".to_string(),
+        )),
+        (_, None) => Err(invalid("url", "Webhook channels need a URL".to_string())),
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NotificationChannel {
+    pub id: Uuid,
+    pub name: String,
+    pub kind: ChannelKind,
+    pub recipients: Vec<String>,
+    pub url: Option<String>,
+    /// Key of the `X-Scho1ar-Signature` HMAC of generic webhook requests
+    pub signing_secret: String,
+    pub events: Vec<NotificationEvent>,
+    pub enabled: bool,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for NotificationChannel {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+impl NotificationChannel {
+    /// Whether the channel wants to be told about `event`
+    pub fn accepts(&self, event: NotificationEvent) -> bool {
+        event == NotificationEvent::Test || self.events.is_empty() || self.events.contains(&event)
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct NotificationChannelRow {
+    pub id: Uuid,
+    pub name: String,
+    pub kind: String,
+    pub recipients: Vec<String>,
+    pub url: Option<String>,
+    pub signing_secret: String,
+    pub events: Vec<String>,
+    pub enabled: bool,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<NotificationChannelRow> for NotificationChannel {
+    fn from(row: NotificationChannelRow) -> Self {
+        NotificationChannel {
+            id: row.id,
+            name: row.name,
+            kind: ChannelKind::parse(&row.kind),
+            recipients: row.recipients,
+            url: row.url,
+            signing_secret: row.signing_secret,
+            events: row
+                .events
+                .iter()
+                .map(|e| NotificationEvent::parse(e))
+                .collect(),
+            enabled: row.enabled,
+            created_by: row.created_by,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// A rendered message about an event
+#[derive(Debug, Clone, PartialEq)]
+pub struct Message {
+    pub event: NotificationEvent,
+    pub subject: String,
+    pub body: String,
+    /// Variables the message was rendered from
+    pub data: BTreeMap<String, String>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum DeliveryStatus {
+    /// Not sent yet, or waiting to be retried
+    Pending,
+    Delivered,
+    /// Out of attempts
+    Failed,
+}
+
+impl DeliveryStatus {
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "delivered" => DeliveryStatus::Delivered,
+            "failed" => DeliveryStatus::Failed,
+            _ => DeliveryStatus::Pending,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Delivery {
+    pub id: Uuid,
+    pub channel_id: Uuid,
+    pub event: NotificationEvent,
+    pub subject: String,
+    pub body: String,
+    pub data: BTreeMap<String, String>,
+    pub status: DeliveryStatus,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    /// HTTP status of the last webhook attempt
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct DeliveryRow {
+    pub id: Uuid,
+    pub channel_id: Uuid,
+    pub event: String,
+    pub subject: String,
+    pub body: String,
+    pub data: Json<BTreeMap<String, String>>,
+    pub status: String,
+    pub attempts: i32,
+    pub max_attempts: i32,
+    pub response_status: Option<i32>,
+    pub last_error: Option<String>,
+    pub delivered_at: Option<DateTime<Utc>>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<DeliveryRow> for Delivery {
+    fn from(row: DeliveryRow) -> Self {
+        Delivery {
+            id: row.id,
+            channel_id: row.channel_id,
+            event: NotificationEvent::parse(&row.event),
+            subject: row.subject,
+            body: row.body,
+            data: row.data.0,
+            status: DeliveryStatus::parse(&row.status),
+            attempts: row.attempts,
+            max_attempts: row.max_attempts,
+            response_status: row.response_status,
+            last_error: row.last_error,
+            delivered_at: row.delivered_at,
+            created_at: row.created_at,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn channel(kind: ChannelKind, recipients: &[&str], url: Option<&str>) -> ChannelInput {
+        ChannelInput {
+            name: "ops".to_string(),
+            kind,
+            recipients: recipients.iter().map(|r| r.to_string()).collect(),
+            url: url.map(str::to_string),
+            events: Vec::new(),
+            enabled: true,
+        }
+    }
+
+    #[test]
+    fn test_validate_channel_input() {
+        let hook = Some("https://hooks.slack.com/services/T0/B0/x");
+        assert!(channel(ChannelKind::Email, &["ops@example.com"], None)
+            .validate()
+            .is_ok());
+        assert!(channel(ChannelKind::Slack, &[], hook).validate().is_ok());
+        assert!(channel(ChannelKind::Email, &["not an address"], None)
+            .validate()
+            .is_err());
+        assert!(channel(ChannelKind::Email, &[], None).validate().is_err());
+        assert!(channel(ChannelKind::Webhook, &[], None).validate().is_err());
+        assert!(channel(ChannelKind::Teams, &[], Some("teams"))
+            .validate()
+            .is_err());
+        assert!(channel(ChannelKind::Teams, &["ops@example.com"], hook)
+            .validate()
+            .is_err());
+    }
+}
@@ -0,0 +1,391 @@
+//! Sending deliveries through their channel
+//!
+//! Generic webhooks receive the delivery as JSON with an
+//! `X-Scho1ar-Signature: sha256=<hex>` header, the HMAC-SHA256 of
+//! `<X-Scho1ar-Timestamp>.<body>` keyed with the channel's signing secret.
+//!
+//! Webhooks are only sent to public addresses, so a channel cannot make the
+//! worker call the metadata service or other hosts inside the network.
+//! Hosts listed in `NOTIFICATION_PRIVATE_HOSTS` are exempt.
+
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Duration;
+
+use chrono::Utc;
+use hmac::{Hmac, Mac};
+use lettre::message::header::ContentType;
+use lettre::message::Mailbox;
+use lettre::transport::smtp::authentication::Credentials;
+use lettre::{AsyncSmtpTransport, AsyncTransport, Tokio1Executor};
+use once_cell::sync::OnceCell;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use serde_json::json;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use super::model::{ChannelKind, Delivery, DeliveryStatus, NotificationChannel};
+use super::store;
+use crate::config::{NotificationConfig, SmtpConfig, SmtpTls};
+use crate::db::DbPool;
+
+const TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Client for webhooks, see [`client`]
+static HTTP: OnceCell<reqwest::Client> = OnceCell::new();
+
+/// The client for webhooks. Redirects are not followed: [`allowed_url`]
+/// only checks the URL the channel was set up with, and a redirect could
+/// lead to a plain HTTP address instead. Host names are resolved by
+/// [`PublicResolver`], so they cannot lead to internal addresses either.
+fn client(config: &NotificationConfig) -> &'static reqwest::Client {
+    HTTP.get_or_init(|| {
+        reqwest::Client::builder()
+            .redirect(reqwest::redirect::Policy::none())
+            .dns_resolver(Arc::new(PublicResolver {
+                private_hosts: config.private_webhook_hosts.clone(),
+            }))
+            .build()
+            .expect("webhook HTTP client configuration is valid")
+    })
+}
+
+/// Resolves host names that only have public addresses, or are listed in
+/// `private_hosts`. The connection is made to the addresses checked here,
+/// so a host cannot resolve to another address by the time it is used.
+struct PublicResolver {
+    private_hosts: Vec<String>,
+}
+
+impl Resolve for PublicResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        let host = name.as_str().to_lowercase();
+        let allow_private = self.private_hosts.contains(&host);
+        Box::pin(async move {
+            let addrs: Vec<SocketAddr> =
+                tokio::net::lookup_host((host.as_str(), 0)).await?.collect();
+            if let Some(addr) = addrs.iter().find(|a| !allow_private && !is_public(a.ip())) {
+                return Err(
+                    format!("{} resolves to non-public address {}", host, addr.ip()).into(),
+                );
+            }
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Whether `ip` is reachable on the internet, rather than a loopback,
+/// private, link-local or otherwise special address
+fn is_public(ip: IpAddr) -> bool {
+    match ip {
+        IpAddr::V4(ip) => {
+            let [a, b, ..] = ip.octets();
+            !(ip.is_private()
+                || ip.is_loopback()
+                || ip.is_link_local()
+                || ip.is_unspecified()
+                || ip.is_broadcast()
+                || ip.is_documentation()
+                || ip.is_multicast()
+                // "This network", shared address space, benchmarking, reserved
+                || a == 0
+                || (a == 100 && (64..128).contains(&b))
+                || (a == 198 && (18..20).contains(&b))
+                || a >= 240)
+        }
+        IpAddr::V6(ip) => match ip.to_ipv4_mapped() {
+            Some(ip) => is_public(IpAddr::V4(ip)),
+            None => {
+                !(ip.is_loopback()
+                    || ip.is_unspecified()
+                    || ip.is_unique_local()
+                    || ip.is_unicast_link_local()
+                    || ip.is_multicast()
+                    // Documentation
+                    || ip.segments()[..2] == [0x2001, 0xdb8])
+            }
+        },
+    }
+}
+
+/// Characters of a failed webhook response kept in the delivery log
+const MAX_ERROR_BODY: usize = 500;
+
+#[derive(Debug)]
+pub struct SendError {
+    /// HTTP status of a webhook that answered
+    pub status: Option<u16>,
+    pub message: String,
+}
+
+impl SendError {
+    fn new(message: impl Into<String>) -> Self {
+        SendError {
+            status: None,
+            message: message.into(),
+        }
+    }
+}
+
+/// `sha256=<hex>` signature of a webhook body sent at `timestamp`
+pub fn signature(secret: &str, timestamp: i64, body: &[u8]) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts keys of any length");
+    mac.update(timestamp.to_string().as_bytes());
+    mac.update(b".");
+    mac.update(body);
+    format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+}
+
+/// Whether webhooks may be sent to `url` under `config`
+pub fn allowed_url(config: &NotificationConfig, url: &str) -> bool {
+    let Ok(url) = reqwest::Url::parse(url) else {
+        return false;
+    };
+    let scheme = url.scheme() == "https" || (config.allow_http_webhooks && url.scheme() == "http");
+    let Some(host) = url.host_str() else {
+        return false;
+    };
+    let public = match host.trim_start_matches('[').trim_end_matches(']').parse() {
+        Ok(ip) => is_public(ip),
+        // Host names are checked by the resolver when sending
+        Err(_) => true,
+    };
+    scheme && (public || config.private_webhook_hosts.iter().any(|h| h == host))
+}
+
+/// Make an attempt at sending a queued delivery and record its outcome. An
+/// error means the attempt failed and should be retried.
+pub async fn deliver(db: &DbPool, config: &NotificationConfig, id: Uuid) -> Result<(), String> {
+    let Some((delivery, channel)) = store::get_delivery(db, id)
+        .await
+        .map_err(|e| e.to_string())?
+    else {
+        // Deleted along with its channel
+        return Ok(());
+    };
+    if delivery.status == DeliveryStatus::Delivered {
+        return Ok(());
+    }
+
+    let (status, error) = match send(config, client(config), &channel, &delivery).await {
+        Ok(status) => (status, None),
+        Err(e) => (e.status, Some(e.message)),
+    };
+    store::record_attempt(db, id, status, error.as_deref())
+        .await
+        .map_err(|e| e.to_string())?;
+    error.map_or(Ok(()), Err)
+}
+
+/// Send `delivery` through `channel`. Returns the HTTP status of webhooks.
+pub async fn send(
+    config: &NotificationConfig,
+    http: &reqwest::Client,
+    channel: &NotificationChannel,
+    delivery: &Delivery,
+) -> Result<Option<u16>, SendError> {
+    let url = match (&channel.kind, &channel.url) {
+        (ChannelKind::Email, _) => {
+            let smtp = config
+                .smtp
+                .as_ref()
+                .ok_or_else(|| SendError::new("email is not configured"))?;
+            return send_email(smtp, &channel.recipients, delivery)
+                .await
+                .map(|()| None);
+        }
+        (_, Some(url)) if allowed_url(config, url) => url,
+        (_, _) => {
+            return Err(SendError::new(
+                "webhook URL is missing, not HTTPS or not a public address",
+            ))
+        }
+    };
+
+    let request = match channel.kind {
+        ChannelKind::Slack => http.post(url).json(&json!({
+            "text": format!("*{}*\n{}", delivery.subject, delivery.body),
+        })),
+        ChannelKind::Teams => http.post(url).json(&json!({
+            "@type": "MessageCard",
+            "@context": "https://schema.org/extensions",
+            "summary": delivery.subject,
+            "title": delivery.subject,
+            "text": delivery.body,
+        })),
+        _ => {
+            let body = serde_json::to_vec(&json!({
+                "id": delivery.id,
+                "event": delivery.event,
+                "subject": delivery.subject,
+                "body": delivery.body,
+                "data": delivery.data,
+                "createdAt": delivery.created_at,
+            }))
+            .map_err(|e| SendError::new(e.to_string()))?;
+            let timestamp = Utc::now().timestamp();
+            http.post(url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .header("X-Scho1ar-Event", delivery.event.as_str())
+                .header("X-Scho1ar-Delivery", delivery.id.to_string())
+                .header("X-Scho1ar-Timestamp", timestamp.to_string())
+                .header(
+                    "X-Scho1ar-Signature",
+                    signature(&channel.signing_secret, timestamp, &body),
+                )
+                .body(body)
+        }
+    };
+
+    let response = request
+        .timeout(TIMEOUT)
+        .send()
+        .await
+        .map_err(|e| SendError::new(e.to_string()))?;
+    let status = response.status();
+    if status.is_success() {
+        return Ok(Some(status.as_u16()));
+    }
+    if status.is_redirection() {
+        let location = response
+            .headers()
+            .get(reqwest::header::LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .unwrap_or_default();
+        return Err(SendError {
+            status: Some(status.as_u16()),
+            message: format!(
+                "HTTP {}: webhook redirects are not followed (to '{}')",
+                status.as_u16(),
+                location.chars().take(MAX_ERROR_BODY).collect::<String>()
+            ),
+        });
+    }
+    let text = response.text().await.unwrap_or_default();
+    Err(SendError {
+        status: Some(status.as_u16()),
+        message: format!(
+            "HTTP {}: {}",
+            status.as_u16(),
+            text.chars().take(MAX_ERROR_BODY).collect::<String>()
+        ),
+    })
+}
+
+async fn send_email(
+    smtp: &SmtpConfig,
+    recipients: &[String],
+    delivery: &Delivery,
+) -> Result<(), SendError> {
+    let parse = |address: &str| {
+        address
+            .parse::<Mailbox>()
+            .map_err(|e| SendError::new(format!("invalid address '{}': {}", address, e)))
+    };
+    let mut email = lettre::Message::builder()
+        .from(parse(&smtp.from)?)
+        .subject(&delivery.subject)
+        .header(ContentType::TEXT_PLAIN);
+    for recipient in recipients {
+        email = email.to(parse(recipient)?);
+    }
+    let email = email
+        .body(delivery.body.clone())
+        .map_err(|e| SendError::new(e.to_string()))?;
+
+    let transport = match smtp.tls {
+        SmtpTls::None => Ok(AsyncSmtpTransport::<Tokio1Executor>::builder_dangerous(
+            &smtp.host,
+        )),
+        SmtpTls::StartTls => AsyncSmtpTransport::<Tokio1Executor>::starttls_relay(&smtp.host),
+        SmtpTls::Tls => AsyncSmtpTransport::<Tokio1Executor>::relay(&smtp.host),
+    }
+    .map_err(|e| SendError::new(e.to_string()))?
+    .port(smtp.port)
+    .timeout(Some(TIMEOUT));
+    let transport = match (&smtp.username, &smtp.password) {
+        (Some(username), Some(password)) => {
+            transport.credentials(Credentials::new(username.clone(), password.clone()))
+        }
+        _ => transport,
+    };
+
+    transport
+        .build()
+        .send(email)
+        .await
+        .map_err(|e| SendError::new(e.to_string()))?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("secret", 1_700_000_000, br#"{"a":1}"#),
+            "sha256=49f24e537407743fa4a0242bb63b94b9a47ee99cbbe071ccd8a22550ae411686"
+        );
+    }
+
+    #[test]
+    fn test_allowed_url() {
+        let mut config = NotificationConfig {
+            smtp: None,
+            allow_http_webhooks: false,
+            private_webhook_hosts: Vec::new(),
+        };
+        for url in [
+            "https://hooks.example.com/x",
+            "https://93.184.215.14/x",
+            "https://[2606:4700::1111]/x",
+        ] {
+            assert!(allowed_url(&config, url), "{}", url);
+        }
+        for url in [
+            "http://hooks.example.com/x",
+            "https://169.254.169.254/latest/meta-data",
+            "https://10.0.0.5/x",
+            "https://127.0.0.1:8080/x",
+            "https://0x7f.1/x",
+            "https://100.64.0.1/x",
+            "https://[::1]/x",
+            "https://[fd00::1]/x",
+            "https://[::ffff:192.168.0.1]/x",
+            "not a url",
+        ] {
+            assert!(!allowed_url(&config, url), "{}", url);
+        }
+
+        config.allow_http_webhooks = true;
+        config.private_webhook_hosts = vec!["127.0.0.1".to_string()];
+        assert!(allowed_url(&config, "http://127.0.0.1:8080/x"));
+        assert!(!allowed_url(&config, "http://10.0.0.5/x"));
+    }
+
+    #[tokio::test]
+    async fn test_resolver_rejects_private_addresses() {
+        let resolver = PublicResolver {
+            private_hosts: Vec::new(),
+        };
+        let error = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("non-public address"));
+
+        let resolver = PublicResolver {
+            private_hosts: vec!["localhost".to_string()],
+        };
+        let addrs: Vec<_> = resolver
+            .resolve("localhost".parse().unwrap())
+            .await
+            .unwrap()
+            .collect();
+        assert!(addrs.iter().all(|addr| addr.ip().is_loopback()));
+    }
+}
@@ -0,0 +1,293 @@
+//! Notification channels and deliveries
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, Utc};
+use sqlx::types::Json;
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use super::model::{
+    ChannelInput, Delivery, DeliveryRow, Message, NotificationChannel, NotificationChannelRow,
+    NotificationEvent,
+};
+use super::template;
+use crate::db::DbPool;
+use crate::jobs::{self, JobPayload, NewJob, DEFAULT_MAX_ATTEMPTS};
+use crate::pagination::{Field, FieldKind, Listing, Page, PageQuery};
+
+const COLUMNS: &str = "id, name, kind, recipients, url, signing_secret, events, enabled, \
+     created_by, created_at, updated_at";
+
+const DELIVERY_COLUMNS: &str = "id, channel_id, event, subject, body, data, status, attempts, \
+     max_attempts, response_status, last_error, delivered_at, created_at";
+
+fn event_names(events: &[NotificationEvent]) -> Vec<&'static str> {
+    events.iter().map(|e| e.as_str()).collect()
+}
+
+pub async fn list_channels(
+    db: &DbPool,
+    org_id: &str,
+) -> Result<Vec<NotificationChannel>, sqlx::Error> {
+    let rows: Vec<NotificationChannelRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM notification_channels WHERE organization_id = $1 ORDER BY name, id",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(NotificationChannel::from).collect())
+}
+
+pub async fn get_channel(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<NotificationChannel>, sqlx::Error> {
+    let row: Option<NotificationChannelRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM notification_channels WHERE organization_id = $1 AND id = $2",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(NotificationChannel::from))
+}
+
+pub async fn create_channel(
+    db: &DbPool,
+    org_id: &str,
+    input: &ChannelInput,
+    user_id: &str,
+) -> Result<NotificationChannel, sqlx::Error> {
+    let signing_secret = format!("{}{}", Uuid::new_v4().simple(), Uuid::new_v4().simple());
+    let row: NotificationChannelRow = sqlx::query_as(&format!(
+        "INSERT INTO notification_channels
+             (organization_id, name, kind, recipients, url, signing_secret, events, enabled,
+              created_by)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9)
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(&input.name)
+    .bind(input.kind.as_str())
+    .bind(&input.recipients)
+    .bind(&input.url)
+    .bind(signing_secret)
+    .bind(event_names(&input.events))
+    .bind(input.enabled)
+    .bind(user_id)
+    .fetch_one(db)
+    .await?;
+    Ok(row.into())
+}
+
+/// Replace a channel's settings; its signing secret is kept
+pub async fn update_channel(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    input: &ChannelInput,
+    version: Option<DateTime<Utc>>,
+) -> Result<Option<NotificationChannel>, sqlx::Error> {
+    let row: Option<NotificationChannelRow> = sqlx::query_as(&format!(
+        "UPDATE notification_channels
+         SET name = $3, kind = $4, recipients = $5, url = $6, events = $7, enabled = $8
+         WHERE organization_id = $1 AND id = $2
+           AND ($9::timestamptz IS NULL OR updated_at = $9)
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .bind(&input.name)
+    .bind(input.kind.as_str())
+    .bind(&input.recipients)
+    .bind(&input.url)
+    .bind(event_names(&input.events))
+    .bind(input.enabled)
+    .bind(version)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(NotificationChannel::from))
+}
+
+/// Returns `false` if no such channel exists, or it is no longer at
+/// `version`
+pub async fn delete_channel(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM notification_channels WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected() > 0)
+}
+
+/// Record a delivery of `message` through a channel and queue the job
+/// sending it
+async fn queue_delivery(
+    tx: &mut Transaction<'_, Postgres>,
+    org_id: &str,
+    channel_id: Uuid,
+    message: &Message,
+) -> Result<Delivery, sqlx::Error> {
+    let row: DeliveryRow = sqlx::query_as(&format!(
+        "INSERT INTO notification_deliveries
+             (channel_id, organization_id, event, subject, body, data, max_attempts)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING {}",
+        DELIVERY_COLUMNS
+    ))
+    .bind(channel_id)
+    .bind(org_id)
+    .bind(message.event.as_str())
+    .bind(&message.subject)
+    .bind(&message.body)
+    .bind(Json(&message.data))
+    .bind(DEFAULT_MAX_ATTEMPTS)
+    .fetch_one(&mut **tx)
+    .await?;
+
+    let job = NewJob::new(
+        Some(org_id),
+        JobPayload::Notification {
+            delivery_id: row.id,
+        },
+    );
+    jobs::store::enqueue_in(tx, &job).await?;
+    Ok(row.into())
+}
+
+/// Tell every enabled channel of the organization that wants to hear about
+/// `event`. Returns the deliveries queued.
+pub async fn notify(
+    db: &DbPool,
+    org_id: &str,
+    event: NotificationEvent,
+    vars: BTreeMap<String, String>,
+) -> Result<Vec<Delivery>, sqlx::Error> {
+    let message = template::message(event, vars);
+    let channels = list_channels(db, org_id).await?;
+
+    let mut tx = db.begin().await?;
+    let mut deliveries = Vec::new();
+    for channel in channels
+        .iter()
+        .filter(|c| c.enabled && event != NotificationEvent::Test && c.accepts(event))
+    {
+        deliveries.push(queue_delivery(&mut tx, org_id, channel.id, &message).await?);
+    }
+    tx.commit().await?;
+    Ok(deliveries)
+}
+
+/// Queue a test message to a channel, enabled or not
+pub async fn send_test(
+    db: &DbPool,
+    org_id: &str,
+    channel: &NotificationChannel,
+) -> Result<Delivery, sqlx::Error> {
+    let message = template::message(
+        NotificationEvent::Test,
+        BTreeMap::from([("channel".to_string(), channel.name.clone())]),
+    );
+    let mut tx = db.begin().await?;
+    let delivery = queue_delivery(&mut tx, org_id, channel.id, &message).await?;
+    tx.commit().await?;
+    Ok(delivery)
+}
+
+/// A delivery with its channel, for sending
+pub(super) async fn get_delivery(
+    db: &DbPool,
+    id: Uuid,
+) -> Result<Option<(Delivery, NotificationChannel)>, sqlx::Error> {
+    let Some(row): Option<DeliveryRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM notification_deliveries WHERE id = $1",
+        DELIVERY_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?
+    else {
+        return Ok(None);
+    };
+    let channel: Option<NotificationChannelRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM notification_channels WHERE id = $1",
+        COLUMNS
+    ))
+    .bind(row.channel_id)
+    .fetch_optional(db)
+    .await?;
+    Ok(channel.map(|channel| (row.into(), channel.into())))
+}
+
+/// Record an attempt to send a delivery. A failure leaves the delivery
+/// failed once it is out of attempts.
+pub(super) async fn record_attempt(
+    db: &DbPool,
+    id: Uuid,
+    response_status: Option<u16>,
+    error: Option<&str>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE notification_deliveries
+         SET attempts = attempts + 1,
+             response_status = $2,
+             last_error = $3,
+             status = CASE WHEN $3 IS NULL THEN 'delivered'
+                           WHEN attempts + 1 >= max_attempts THEN 'failed'
+                           ELSE 'pending' END,
+             delivered_at = CASE WHEN $3 IS NULL THEN NOW() END
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(response_status.map(i32::from))
+    .bind(error)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub const DELIVERY_LISTING: Listing = Listing {
+    fields: &[
+        Field::new("createdAt", "created_at", FieldKind::Timestamp),
+        Field::new("event", "event", FieldKind::Text),
+        Field::new("status", "status", FieldKind::Text),
+        Field::new("attempts", "attempts", FieldKind::Integer),
+        Field::new("deliveredAt", "delivered_at", FieldKind::Timestamp).filter_only(),
+    ],
+    default_sort: "-createdAt",
+};
+
+/// A page of the deliveries through a channel
+pub async fn list_deliveries(
+    db: &DbPool,
+    org_id: &str,
+    channel_id: Uuid,
+    page: &PageQuery,
+) -> Result<Page<Delivery>, sqlx::Error> {
+    let mut builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM notification_deliveries WHERE organization_id = ",
+        DELIVERY_COLUMNS
+    ));
+    builder.push_bind(org_id);
+    builder.push(" AND channel_id = ").push_bind(channel_id);
+    page.push_conditions(&mut builder);
+    page.push_order_and_limit(&mut builder);
+
+    let rows: Vec<DeliveryRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(page.page(rows.into_iter().map(Delivery::from).collect()))
+}
@@ -0,0 +1,89 @@
+//! Message templates
+//!
+//! Every event has a subject and body template whose `{{name}}`
+//! placeholders are filled from the event's variables. Placeholders without
+//! a variable are left as they are so a missing value is visible.
+
+use std::collections::BTreeMap;
+
+use super::model::{Message, NotificationEvent};
+
+struct Template {
+    subject: &'static str,
+    body: &'static str,
+}
+
+fn template(event: NotificationEvent) -> Template {
+    match event {
+        NotificationEvent::Test => Template {
+            subject: "Test notification for {{channel}}",
+            body: "This is a test message sent to the {{channel}} notification channel.",
+        },
+        NotificationEvent::ScheduleActionFailed => Template {
+            subject: "Schedule {{schedule}} failed to {{action}} {{resource}}",
+            body: "The start/stop schedule {{schedule}} could not {{action}} {{resource_type}} \
+                   {{resource}} in {{region}}: {{error}}",
+        },
+        NotificationEvent::DiscoveryFailed => Template {
+            subject: "Discovery of {{account}} failed",
+            body: "Resource discovery of cloud account {{account}} ({{account_id}}) failed: \
+                   {{error}}",
+        },
+    }
+}
+
+/// Replace the `{{name}}` placeholders of `template` with `vars`
+pub fn render(template: &str, vars: &BTreeMap<String, String>) -> String {
+    let mut out = String::with_capacity(template.len());
+    let mut rest = template;
+    while let Some(start) = rest.find("{{") {
+        out.push_str(&rest[..start]);
+        let after = &rest[start + 2..];
+        let Some(end) = after.find("}}") else {
+            rest = &rest[start..];
+            break;
+        };
+        match vars.get(after[..end].trim()) {
+            Some(value) => out.push_str(value),
+            None => out.push_str(&rest[start..start + 2 + end + 2]),
+        }
+        rest = &after[end + 2..];
+    }
+    out.push_str(rest);
+    out
+}
+
+/// Render the message for `event`
+pub fn message(event: NotificationEvent, vars: BTreeMap<String, String>) -> Message {
+    let template = template(event);
+    Message {
+        event,
+        subject: render(template.subject, &vars),
+        body: render(template.body, &vars),
+        data: vars,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render() {
+        let vars = BTreeMap::from([
+            ("schedule".to_string(), "office hours".to_string()),
+            ("action".to_string(), "stop".to_string()),
+        ]);
+        assert_eq!(
+            render("{{ schedule }} will {{action}} {{resource}}", &vars),
+            "office hours will stop {{resource}}"
+        );
+        assert_eq!(render("braces {{ left open", &vars), "braces {{ left open");
+
+        let test = message(
+            NotificationEvent::Test,
+            BTreeMap::from([("channel".to_string(), "ops".to_string())]),
+        );
+        assert_eq!(test.subject, "Test notification for ops");
+    }
+}
@@ -0,0 +1,416 @@
+//! OpenAPI document for the API
+//!
+//! Built from the `#[utoipa::path]` attributes on the handlers and the
+//! `ToSchema` types they take and return, and served at `/api/openapi.json`
+//! with a browsable version at `/api/docs`. Payload constraints mirror the
+//! `#[validate]` rules of each type.
+//!
+//! The document is also committed as `openapi.json`; after changing the API,
+//! regenerate it with `UPDATE_OPENAPI=1 cargo test openapi`.
+
+use axum::{
+    http::header,
+    response::{Html, IntoResponse},
+};
+use once_cell::sync::Lazy;
+use utoipa::openapi::path::{Operation, ParameterBuilder, ParameterIn};
+use utoipa::openapi::schema::{ObjectBuilder, Type};
+use utoipa::openapi::security::{HttpAuthScheme, HttpBuilder, SecurityScheme};
+use utoipa::openapi::{
+    Components, ContentBuilder, HeaderBuilder, RefOr, Required, ResponseBuilder, Schema,
+};
+use utoipa::{Modify, OpenApi};
+
+use crate::cloud_ids::AwsAccountId;
+use crate::error::{FieldError, Problem, PROBLEM_CONTENT_TYPE};
+use crate::idempotency;
+use crate::routes;
+use crate::routes::chargeback::ExportFormat;
+
+/// Name of the bearer token security scheme, as in `security(...)` above
+const BEARER_AUTH: &str = "bearerAuth";
+
+#[derive(OpenApi)]
+#[openapi(
+    info(
+        title = "Scho1ar API",
+        description = "Cloud cost management API. Errors are RFC 7807 problem documents \
+                       with a stable `code`; every response carries an `X-Request-Id` header. \
+                       `GET` responses carry an `ETag`; sending it back in `If-None-Match` \
+                       gets `304 Not Modified` while the response is unchanged."
+    ),
+    paths(
+        routes::health::health_check,
+        routes::health::ready_check,
+        routes::api_root,
+        routes::get_current_user,
+        routes::costs::get_costs,
+        routes::costs::query_costs,
+        routes::tags::tag_coverage,
+        routes::tags::get_policy,
+        routes::tags::put_policy,
+        routes::tags::list_virtual_tags,
+        routes::tags::get_virtual_tag,
+        routes::tags::create_virtual_tag,
+        routes::tags::update_virtual_tag,
+        routes::tags::delete_virtual_tag,
+        routes::tags::renormalize,
+        routes::chargeback::list_cost_centers,
+        routes::chargeback::get_cost_center,
+        routes::chargeback::create_cost_center,
+        routes::chargeback::update_cost_center,
+        routes::chargeback::delete_cost_center,
+        routes::chargeback::list_rate_rules,
+        routes::chargeback::get_rate_rule,
+        routes::chargeback::create_rate_rule,
+        routes::chargeback::delete_rate_rule,
+        routes::chargeback::list_statements,
+        routes::chargeback::generate_statements,
+        routes::chargeback::get_statement,
+        routes::chargeback::close_statement,
+        routes::chargeback::export_statement,
+        routes::unit_metrics::list_business_metrics,
+        routes::unit_metrics::get_business_metric,
+        routes::unit_metrics::create_business_metric,
+        routes::unit_metrics::delete_business_metric,
+        routes::unit_metrics::list_metric_values,
+        routes::unit_metrics::push_metric_values,
+        routes::unit_metrics::upload_metric_values_csv,
+        routes::unit_metrics::list_unit_metrics,
+        routes::unit_metrics::get_unit_metric,
+        routes::unit_metrics::create_unit_metric,
+        routes::unit_metrics::update_unit_metric,
+        routes::unit_metrics::delete_unit_metric,
+        routes::unit_metrics::unit_metric_series,
+        routes::commitments::list_commitments,
+        routes::commitments::utilization,
+        routes::commitments::coverage,
+        routes::commitments::recommendations,
+        routes::commitments::get_alert_settings,
+        routes::commitments::put_alert_settings,
+        routes::commitments::list_alerts,
+        routes::commitments::evaluate_alerts,
+        routes::commitments::acknowledge_alert,
+        routes::pricing::lookup_prices,
+        routes::pricing::list_catalogs,
+        routes::pricing::sync_catalogs,
+        routes::cloud_accounts::list_accounts,
+        routes::cloud_accounts::create_account,
+        routes::cloud_accounts::get_account,
+        routes::cloud_accounts::update_account,
+        routes::cloud_accounts::delete_account,
+        routes::cloud_accounts::start_discovery,
+        routes::cloud_accounts::list_discovery_runs,
+        routes::resources::list_resources,
+        routes::resources::get_resource,
+        routes::resources::resource_history,
+        routes::resources::resource_costs,
+        routes::resources::resource_metrics,
+        routes::resources::push_resource_metrics,
+        routes::resources::import_resource_metrics,
+        routes::recommendations::list_waste,
+        routes::recommendations::waste_summary,
+        routes::recommendations::evaluate_waste,
+        routes::recommendations::get_waste_settings,
+        routes::recommendations::put_waste_settings,
+        routes::recommendations::get_waste_finding,
+        routes::recommendations::snooze_waste_finding,
+        routes::recommendations::dismiss_waste_finding,
+        routes::recommendations::reopen_waste_finding,
+        routes::recommendations::rightsizing,
+        routes::schedules::list_schedules,
+        routes::schedules::create_schedule,
+        routes::schedules::get_schedule,
+        routes::schedules::update_schedule,
+        routes::schedules::delete_schedule,
+        routes::schedules::schedule_resources,
+        routes::schedules::schedule_savings,
+        routes::schedules::list_executions,
+        routes::schedules::run_schedule,
+        routes::schedules::list_overrides,
+        routes::schedules::create_override,
+        routes::schedules::delete_override,
+        routes::notifications::list_channels,
+        routes::notifications::create_channel,
+        routes::notifications::get_channel,
+        routes::notifications::update_channel,
+        routes::notifications::delete_channel,
+        routes::notifications::test_channel,
+        routes::notifications::list_deliveries,
+        routes::jobs::list_jobs,
+        routes::jobs::get_job,
+        routes::jobs::retry_job,
+        routes::jobs::list_system_jobs,
+        routes::jobs::get_system_job,
+        routes::jobs::retry_system_job,
+    ),
+    // Types only referenced from query parameters are not collected
+    // automatically
+    components(schemas(Problem, FieldError, AwsAccountId, ExportFormat)),
+    modifiers(&BearerAuth, &ProblemResponses, &IdempotencyKey),
+    security(("bearerAuth" = []))
+)]
+pub struct ApiDoc;
+
+/// Clerk session tokens, checked by [`crate::auth::require_auth`]
+struct BearerAuth;
+
+impl Modify for BearerAuth {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Components::new);
+        components.add_security_scheme(
+            BEARER_AUTH,
+            SecurityScheme::Http(
+                HttpBuilder::new()
+                    .scheme(HttpAuthScheme::Bearer)
+                    .bearer_format("JWT")
+                    .description(Some(
+                        "Clerk session token; organization routes need an active organization",
+                    ))
+                    .build(),
+            ),
+        );
+    }
+}
+
+/// Error responses shared by the authenticated operations, which every
+/// handler can return without listing them
+const PROBLEMS: &[(&str, &str, &str)] = &[
+    ("400", "BadRequest", "The request is malformed"),
+    (
+        "401",
+        "Unauthorized",
+        "The bearer token is missing, invalid or expired",
+    ),
+    (
+        "403",
+        "Forbidden",
+        "The caller may not perform this operation",
+    ),
+    (
+        "404",
+        "NotFound",
+        "The resource does not exist in the organization",
+    ),
+    (
+        "409",
+        "Conflict",
+        "The request conflicts with the current state",
+    ),
+    (
+        "412",
+        "PreconditionFailed",
+        "The resource no longer has the `ETag` given in `If-Match`",
+    ),
+    (
+        "422",
+        "UnprocessableEntity",
+        "The request failed validation",
+    ),
+    ("500", "InternalServerError", "An unexpected error"),
+    (
+        "503",
+        "ServiceUnavailable",
+        "The database is busy; retry after `Retry-After` seconds",
+    ),
+];
+
+/// Adds the [`PROBLEMS`] each authenticated operation can return: 404 when
+/// it has path parameters, 400 and 422 when it takes input, 409 when it
+/// writes, 412 when it takes `If-Match`. Operations with their own `security` are public and left alone.
+struct ProblemResponses;
+
+impl ProblemResponses {
+    fn applicable(method: &str, operation: &Operation) -> Vec<&'static str> {
+        let parameters = operation.parameters.as_deref().unwrap_or_default();
+        let has_input = !parameters.is_empty() || operation.request_body.is_some();
+        let mut statuses = vec!["401", "403", "500", "503"];
+        if has_input {
+            statuses.extend(["400", "422"]);
+        }
+        if parameters
+            .iter()
+            .any(|p| matches!(p.parameter_in, ParameterIn::Path))
+        {
+            statuses.push("404");
+        }
+        if method != "get" {
+            statuses.push("409");
+        }
+        if parameters
+            .iter()
+            .any(|p| matches!(p.parameter_in, ParameterIn::Header) && p.name == "If-Match")
+        {
+            statuses.push("412");
+        }
+        statuses
+    }
+}
+
+impl Modify for ProblemResponses {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        let components = openapi.components.get_or_insert_with(Components::new);
+        for (status, name, description) in PROBLEMS {
+            let mut response = ResponseBuilder::new().description(*description).content(
+                PROBLEM_CONTENT_TYPE,
+                ContentBuilder::new()
+                    .schema(Some(RefOr::Ref(utoipa::openapi::Ref::from_schema_name(
+                        "Problem",
+                    ))))
+                    .build(),
+            );
+            if *status == "503" {
+                response = response.header(
+                    "Retry-After",
+                    HeaderBuilder::new()
+                        .schema(ObjectBuilder::new().schema_type(Type::Integer))
+                        .description(Some("Seconds to wait before retrying"))
+                        .build(),
+                );
+            }
+            components
+                .responses
+                .insert(name.to_string(), RefOr::T(response.build()));
+        }
+
+        for item in openapi.paths.paths.values_mut() {
+            let operations = [
+                ("get", &mut item.get),
+                ("post", &mut item.post),
+                ("put", &mut item.put),
+                ("delete", &mut item.delete),
+            ];
+            for (method, operation) in operations {
+                let Some(operation) = operation else {
+                    continue;
+                };
+                if operation.security.is_some() {
+                    continue;
+                }
+                for status in Self::applicable(method, operation) {
+                    let (_, name, _) = PROBLEMS.iter().find(|(s, _, _)| *s == status).unwrap();
+                    operation.responses.responses.insert(
+                        status.to_string(),
+                        RefOr::Ref(utoipa::openapi::Ref::from_response_name(*name)),
+                    );
+                }
+            }
+        }
+    }
+}
+
+/// Documents the `Idempotency-Key` header, see [`crate::idempotency`], on
+/// each authenticated operation that writes
+struct IdempotencyKey;
+
+impl Modify for IdempotencyKey {
+    fn modify(&self, openapi: &mut utoipa::openapi::OpenApi) {
+        for item in openapi.paths.paths.values_mut() {
+            for operation in [&mut item.post, &mut item.put, &mut item.delete]
+                .into_iter()
+                .flatten()
+            {
+                if operation.security.is_some() {
+                    continue;
+                }
+                let header = ParameterBuilder::new()
+                    .name("Idempotency-Key")
+                    .parameter_in(ParameterIn::Header)
+                    .required(Required::False)
+                    .schema(Some(
+                        ObjectBuilder::new()
+                            .schema_type(Type::String)
+                            .min_length(Some(1))
+                            .max_length(Some(idempotency::MAX_KEY_LEN)),
+                    ))
+                    .description(Some(
+                        "Runs the request once per key; retries with the same key within \
+                         24 hours get the first response back",
+                    ))
+                    .build();
+                operation
+                    .parameters
+                    .get_or_insert_with(Vec::new)
+                    .push(header);
+            }
+        }
+    }
+}
+
+/// Schema of a string identifier that must match `pattern`
+pub fn string_schema(pattern: &str, example: &str) -> RefOr<Schema> {
+    ObjectBuilder::new()
+        .schema_type(Type::String)
+        .pattern(Some(pattern))
+        .examples([example])
+        .into()
+}
+
+/// The document as served, pretty-printed
+pub fn spec_json() -> String {
+    let mut openapi = ApiDoc::openapi();
+    // The crate has no license field, which would otherwise show as ""
+    openapi.info.license = None;
+    let mut json = openapi
+        .to_pretty_json()
+        .expect("OpenAPI document serializes");
+    json.push('\n');
+    json
+}
+
+static SPEC: Lazy<String> = Lazy::new(spec_json);
+
+pub async fn openapi_json() -> impl IntoResponse {
+    ([(header::CONTENT_TYPE, "application/json")], SPEC.as_str())
+}
+
+/// Swagger UI page rendering the document
+pub async fn docs() -> Html<&'static str> {
+    Html(DOCS_PAGE)
+}
+
+const DOCS_PAGE: &str = r##"<!doctype html>
+<html lang="en">
+<head>
+  <meta charset="utf-8">
+  <title>Scho1ar API</title>
+  <link rel="stylesheet" href="https://unpkg.com/swagger-ui-dist@5/swagger-ui.css">
+</head>
+<body>
+  <div id="swagger-ui"></div>
+  <script src="https://unpkg.com/swagger-ui-dist@5/swagger-ui-bundle.js" crossorigin></script>
+  <script>
+    window.onload = () => {
+      window.ui = SwaggerUIBundle({ url: "/api/openapi.json", dom_id: "#swagger-ui" });
+    };
+  </script>
+</body>
+</html>
+"##;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::{env, fs, path::Path};
+
+    #[test]
+    fn test_spec_is_current() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR")).join("openapi.json");
+        let spec = spec_json();
+        if env::var_os("UPDATE_OPENAPI").is_some() {
+            fs::write(&path, &spec).unwrap();
+            return;
+        }
+        let committed = fs::read_to_string(&path).unwrap_or_default();
+        assert!(
+            committed == spec,
+            "openapi.json is out of date; regenerate it with `UPDATE_OPENAPI=1 cargo test openapi`"
+        );
+
+        let schemas = ApiDoc::openapi().components.unwrap().schemas;
+        for reference in spec.split("\"#/components/schemas/").skip(1) {
+            let name = &reference[..reference.find('"').unwrap()];
+            assert!(schemas.contains_key(name), "{} is not in components", name);
+        }
+    }
+}
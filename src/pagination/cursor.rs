@@ -0,0 +1,55 @@
+//! Signed page cursors
+//!
+//! A cursor holds the sort key and id of the last item of a page, as JSON,
+//! followed by an HMAC of it so clients cannot forge positions. Both parts
+//! are base64url-encoded and joined by a `.`.
+
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sha2::Sha256;
+use uuid::Uuid;
+
+use crate::config::CursorKey;
+
+/// Position after which the next page starts
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct Cursor {
+    /// The `sort` parameter the cursor was issued for
+    #[serde(rename = "s")]
+    pub sort: String,
+    /// Sort key of the last item
+    #[serde(rename = "v")]
+    pub value: Value,
+    /// Id of the last item, breaking ties between equal sort keys
+    pub id: Uuid,
+}
+
+fn mac(key: &CursorKey, payload: &[u8]) -> Hmac<Sha256> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key.0).expect("HMAC accepts keys of any length");
+    mac.update(payload);
+    mac
+}
+
+impl Cursor {
+    pub fn encode(&self, key: &CursorKey) -> String {
+        let payload = serde_json::to_vec(self).expect("cursor serializes");
+        let signature = mac(key, &payload).finalize().into_bytes();
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&payload),
+            URL_SAFE_NO_PAD.encode(signature)
+        )
+    }
+
+    /// The cursor `encoded` holds, if it was signed with `key`
+    pub fn decode(encoded: &str, key: &CursorKey) -> Option<Self> {
+        let (payload, signature) = encoded.split_once('.')?;
+        let payload = URL_SAFE_NO_PAD.decode(payload).ok()?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).ok()?;
+        mac(key, &payload).verify_slice(&signature).ok()?;
+        serde_json::from_slice(&payload).ok()
+    }
+}
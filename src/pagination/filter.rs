@@ -0,0 +1,230 @@
+//! `field:op:value` filter expressions
+//!
+//! Fields and columns come from a listing's fixed [`Field`] list and values
+//! are always bound, so a filter cannot inject SQL.
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde_json::Value;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use super::{Field, FieldKind};
+use crate::costs::period::day_start;
+
+/// Most values an `in` filter may list
+const MAX_IN_VALUES: usize = 100;
+
+/// How a filter compares a field with its value
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Op {
+    Eq,
+    Ne,
+    Lt,
+    Lte,
+    Gt,
+    Gte,
+    /// Any of comma-separated values
+    In,
+    /// Case-insensitive substring
+    Contains,
+}
+
+impl Op {
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "eq" => Some(Self::Eq),
+            "ne" => Some(Self::Ne),
+            "lt" => Some(Self::Lt),
+            "lte" => Some(Self::Lte),
+            "gt" => Some(Self::Gt),
+            "gte" => Some(Self::Gte),
+            "in" => Some(Self::In),
+            "contains" => Some(Self::Contains),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::Eq => "eq",
+            Self::Ne => "ne",
+            Self::Lt => "lt",
+            Self::Lte => "lte",
+            Self::Gt => "gt",
+            Self::Gte => "gte",
+            Self::In => "in",
+            Self::Contains => "contains",
+        }
+    }
+
+    fn applies_to(self, kind: FieldKind) -> bool {
+        match self {
+            Self::Eq | Self::Ne | Self::In => true,
+            Self::Lt | Self::Lte | Self::Gt | Self::Gte => {
+                matches!(
+                    kind,
+                    FieldKind::Text | FieldKind::Timestamp | FieldKind::Integer | FieldKind::Number
+                )
+            }
+            Self::Contains => kind == FieldKind::Text,
+        }
+    }
+}
+
+/// A typed value bound into a query
+#[derive(Debug, Clone, PartialEq)]
+pub enum SqlValue {
+    Text(String),
+    Uuid(Uuid),
+    Timestamp(DateTime<Utc>),
+    Integer(i64),
+    Number(f64),
+    Boolean(bool),
+}
+
+impl SqlValue {
+    /// Parse a filter value given in the query string
+    pub fn parse(kind: FieldKind, s: &str) -> Result<Self, String> {
+        match kind {
+            FieldKind::Text => Ok(Self::Text(s.to_string())),
+            FieldKind::Uuid => s
+                .parse()
+                .map(Self::Uuid)
+                .map_err(|_| format!("'{}' is not a UUID", s)),
+            FieldKind::Timestamp => DateTime::parse_from_rfc3339(s)
+                .map(|t| t.with_timezone(&Utc))
+                .or_else(|_| NaiveDate::parse_from_str(s, "%Y-%m-%d").map(day_start))
+                .map(Self::Timestamp)
+                .map_err(|_| format!("'{}' is not an RFC 3339 timestamp or a date", s)),
+            FieldKind::Integer => s
+                .parse()
+                .map(Self::Integer)
+                .map_err(|_| format!("'{}' is not an integer", s)),
+            FieldKind::Number => s
+                .parse::<f64>()
+                .ok()
+                .filter(|n| n.is_finite())
+                .map(Self::Number)
+                .ok_or_else(|| format!("'{}' is not a number", s)),
+            FieldKind::Boolean => s
+                .parse()
+                .map(Self::Boolean)
+                .map_err(|_| format!("'{}' is not true or false", s)),
+        }
+    }
+
+    /// Read a value as the item serialized it, e.g. the sort key in a cursor
+    pub fn from_json(kind: FieldKind, value: &Value) -> Option<Self> {
+        match (kind, value) {
+            (FieldKind::Integer, Value::Number(n)) => n.as_i64().map(Self::Integer),
+            (FieldKind::Number, Value::Number(n)) => n.as_f64().map(Self::Number),
+            (FieldKind::Boolean, Value::Bool(b)) => Some(Self::Boolean(*b)),
+            (FieldKind::Integer | FieldKind::Number | FieldKind::Boolean, _) => None,
+            (_, Value::String(s)) => Self::parse(kind, s).ok(),
+            _ => None,
+        }
+    }
+
+    pub fn push_bind(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        match self {
+            Self::Text(v) => builder.push_bind(v.clone()),
+            Self::Uuid(v) => builder.push_bind(*v),
+            Self::Timestamp(v) => builder.push_bind(*v),
+            Self::Integer(v) => builder.push_bind(*v),
+            Self::Number(v) => builder.push_bind(*v),
+            Self::Boolean(v) => builder.push_bind(*v),
+        };
+    }
+}
+
+/// `%<s>%` for `ILIKE`, with the wildcards in `s` escaped
+pub fn contains_pattern(s: &str) -> String {
+    format!(
+        "%{}%",
+        s.replace('\\', "\\\\")
+            .replace('%', "\\%")
+            .replace('_', "\\_")
+    )
+}
+
+/// One `field:op:value` condition
+#[derive(Debug, Clone, PartialEq)]
+pub struct Filter {
+    pub field: &'static Field,
+    pub op: Op,
+    pub values: Vec<SqlValue>,
+}
+
+impl Filter {
+    /// Parse an expression against the fields of a listing. The value is
+    /// everything after the second `:`, so it may contain colons itself.
+    pub fn parse(expr: &str, fields: &'static [Field]) -> Result<Self, String> {
+        let mut parts = expr.splitn(3, ':');
+        let (Some(name), Some(op), Some(value)) = (parts.next(), parts.next(), parts.next()) else {
+            return Err(format!("'{}' is not a field:op:value filter", expr));
+        };
+        let field = fields.iter().find(|f| f.name == name).ok_or_else(|| {
+            format!(
+                "unknown filter field '{}', expected one of {}",
+                name,
+                field_names(fields.iter())
+            )
+        })?;
+        let op = Op::parse(op)
+            .filter(|op| op.applies_to(field.kind))
+            .ok_or_else(|| format!("'{}' is not a valid operator for '{}'", op, name))?;
+
+        let values = if op == Op::In {
+            let values = value
+                .split(',')
+                .map(|v| SqlValue::parse(field.kind, v))
+                .collect::<Result<Vec<_>, _>>()?;
+            if values.len() > MAX_IN_VALUES {
+                return Err(format!("at most {} values may be listed", MAX_IN_VALUES));
+            }
+            values
+        } else if op == Op::Contains {
+            vec![SqlValue::Text(contains_pattern(value))]
+        } else {
+            vec![SqlValue::parse(field.kind, value)?]
+        };
+
+        Ok(Filter { field, op, values })
+    }
+
+    /// Push the condition, without a leading ` AND `
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        builder.push(self.field.column);
+        let operator = match self.op {
+            Op::Eq => " = ",
+            Op::Ne => " <> ",
+            Op::Lt => " < ",
+            Op::Lte => " <= ",
+            Op::Gt => " > ",
+            Op::Gte => " >= ",
+            Op::Contains => " ILIKE ",
+            Op::In => {
+                builder.push(" IN (");
+                for (i, value) in self.values.iter().enumerate() {
+                    if i > 0 {
+                        builder.push(", ");
+                    }
+                    value.push_bind(builder);
+                }
+                builder.push(")");
+                return;
+            }
+        };
+        builder.push(operator);
+        self.values[0].push_bind(builder);
+    }
+}
+
+/// `a, b or c`, for error messages
+pub fn field_names<'a>(fields: impl Iterator<Item = &'a Field>) -> String {
+    let names: Vec<&str> = fields.map(|f| f.name).collect();
+    match names.split_last() {
+        Some((last, rest)) if !rest.is_empty() => format!("{} or {}", rest.join(", "), last),
+        _ => names.join(""),
+    }
+}
@@ -0,0 +1,435 @@
+//! Cursor pagination, sorting and filtering of list endpoints
+//!
+//! A list endpoint takes [`PageParams`] from the query string:
+//!
+//! - `limit`: items per page, up to `PAGE_SIZE_MAX`
+//! - `sort`: a field to order by, `-` prefixed for descending order
+//! - `filter`: repeated `field:op:value` conditions, e.g.
+//!   `filter=provider:eq:aws&filter=createdAt:gte:2026-01-01`
+//! - `cursor`: the `nextCursor` of the previous page
+//!
+//! and returns a [`Page`]. The fields a collection can be sorted and
+//! filtered on are declared once as a [`Listing`]; resolving the parameters
+//! against it gives a [`PageQuery`] that appends the conditions, order and
+//! limit to any `QueryBuilder`:
+//!
+//! ```ignore
+//! let page = params.resolve(&LISTING, &state.config.pagination)?;
+//! let mut builder = QueryBuilder::new("SELECT ... FROM t WHERE organization_id = ");
+//! builder.push_bind(org_id);
+//! page.push_conditions(&mut builder);
+//! page.push_order_and_limit(&mut builder);
+//! let items: Vec<Item> = builder.build_query_as().fetch_all(db).await?;
+//! Ok(page.page(items))
+//! ```
+//!
+//! Pages are keyset-based: the cursor holds the sort key and id of the last
+//! item, so rows inserted or deleted meanwhile don't shift later pages.
+
+mod cursor;
+mod filter;
+
+pub use cursor::Cursor;
+pub use filter::{contains_pattern, Filter, Op, SqlValue};
+
+use std::collections::BTreeMap;
+
+use axum::{async_trait, extract::FromRequestParts, http::request::Parts};
+use serde::{Deserialize, Serialize};
+use sqlx::{Postgres, QueryBuilder};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::config::{CursorKey, PaginationConfig};
+use crate::error::{AppError, AppResult, FieldError};
+use crate::validation::ValidatedQuery;
+
+/// Type of a field, which decides how its filter values are parsed and
+/// which operators apply
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FieldKind {
+    Text,
+    Uuid,
+    Timestamp,
+    Integer,
+    /// Floating point, e.g. amounts of money
+    Number,
+    Boolean,
+}
+
+/// A field of a listed item that can be filtered on, named as in the item's
+/// JSON
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Field {
+    pub name: &'static str,
+    /// SQL expression of the field
+    pub column: &'static str,
+    pub kind: FieldKind,
+    pub sortable: bool,
+}
+
+impl Field {
+    pub const fn new(name: &'static str, column: &'static str, kind: FieldKind) -> Self {
+        Field {
+            name,
+            column,
+            kind,
+            sortable: true,
+        }
+    }
+
+    /// Exclude the field from sorting. Nullable columns must be, as the
+    /// keyset comparison does not handle nulls.
+    pub const fn filter_only(mut self) -> Self {
+        self.sortable = false;
+        self
+    }
+}
+
+/// The fields of a collection and its default sort
+///
+/// The listed table needs a unique `id` column, which breaks ties between
+/// equal sort keys, and each item must serialize `id` and its sortable
+/// fields under their names.
+#[derive(Debug)]
+pub struct Listing {
+    pub fields: &'static [Field],
+    /// `sort` used when a request gives none
+    pub default_sort: &'static str,
+}
+
+/// A page of a collection
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Pass as `cursor` to get the next page; null on the last page
+    pub next_cursor: Option<String>,
+}
+
+/// Paging, sorting and filtering parameters of a list endpoint
+#[derive(Debug, Clone, Default, Deserialize, Validate, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct PageParams {
+    /// Items per page
+    #[param(minimum = 1)]
+    pub limit: Option<i64>,
+    /// `nextCursor` of the previous page
+    pub cursor: Option<String>,
+    /// Field to sort by, prefixed with `-` for descending order
+    pub sort: Option<String>,
+    /// `field:op:value` conditions, all of which must hold. `op` is one of
+    /// `eq`, `ne`, `lt`, `lte`, `gt`, `gte`, `in` (comma-separated values)
+    /// or `contains` (case-insensitive).
+    #[serde(default)]
+    pub filter: Vec<String>,
+}
+
+#[async_trait]
+impl<S> FromRequestParts<S> for PageParams
+where
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let ValidatedQuery(params) = ValidatedQuery::from_request_parts(parts, state).await?;
+        Ok(params)
+    }
+}
+
+fn invalid(pointer: String, code: &str, message: String) -> AppError {
+    AppError::InvalidFields(
+        format!("{}: {}", pointer, message),
+        vec![FieldError {
+            pointer,
+            code: code.to_string(),
+            message,
+            params: BTreeMap::new(),
+        }],
+    )
+}
+
+impl PageParams {
+    /// Check the parameters against the fields of `listing`
+    pub fn resolve(
+        &self,
+        listing: &'static Listing,
+        config: &PaginationConfig,
+    ) -> AppResult<PageQuery> {
+        let limit = self.limit.unwrap_or(config.default_page_size);
+        if !(1..=config.max_page_size).contains(&limit) {
+            return Err(invalid(
+                "/limit".to_string(),
+                "range",
+                format!("must be between 1 and {}", config.max_page_size),
+            ));
+        }
+
+        let sort_param = self.sort.as_deref().unwrap_or(listing.default_sort);
+        let (descending, name) = match sort_param.strip_prefix('-') {
+            Some(name) => (true, name),
+            None => (false, sort_param),
+        };
+        let sort = listing
+            .fields
+            .iter()
+            .find(|f| f.sortable && f.name == name)
+            .ok_or_else(|| {
+                invalid(
+                    "/sort".to_string(),
+                    "sort",
+                    format!(
+                        "unknown sort field '{}', expected {}",
+                        name,
+                        filter::field_names(listing.fields.iter().filter(|f| f.sortable))
+                    ),
+                )
+            })?;
+
+        let filters = self
+            .filter
+            .iter()
+            .enumerate()
+            .map(|(i, expr)| {
+                Filter::parse(expr, listing.fields)
+                    .map_err(|message| invalid(format!("/filter/{}", i), "filter", message))
+            })
+            .collect::<AppResult<Vec<_>>>()?;
+
+        let after = match &self.cursor {
+            None => None,
+            Some(encoded) => {
+                let cursor_error =
+                    |message: &str| invalid("/cursor".to_string(), "cursor", message.to_string());
+                let cursor = Cursor::decode(encoded, &config.cursor_key)
+                    .ok_or_else(|| cursor_error("is not a cursor issued by this API"))?;
+                if cursor.sort != sort_param {
+                    return Err(cursor_error("was issued for a different sort"));
+                }
+                let value = SqlValue::from_json(sort.kind, &cursor.value)
+                    .ok_or_else(|| cursor_error("is not a cursor issued by this API"))?;
+                Some((value, cursor.id))
+            }
+        };
+
+        Ok(PageQuery {
+            sort,
+            descending,
+            sort_param: sort_param.to_string(),
+            filters,
+            after,
+            limit,
+            cursor_key: config.cursor_key.clone(),
+        })
+    }
+}
+
+/// Resolved [`PageParams`] of a listing
+#[derive(Debug, Clone)]
+pub struct PageQuery {
+    sort: &'static Field,
+    descending: bool,
+    sort_param: String,
+    filters: Vec<Filter>,
+    /// Sort key and id of the last item of the previous page
+    after: Option<(SqlValue, Uuid)>,
+    limit: i64,
+    cursor_key: CursorKey,
+}
+
+impl PageQuery {
+    /// Push ` AND ...` clauses for the filters and the cursor position
+    pub fn push_conditions(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        for filter in &self.filters {
+            builder.push(" AND ");
+            filter.push_sql(builder);
+        }
+        if let Some((value, id)) = &self.after {
+            builder
+                .push(" AND (")
+                .push(self.sort.column)
+                .push(", id) ")
+                .push(if self.descending { "<" } else { ">" })
+                .push(" (");
+            value.push_bind(builder);
+            builder.push(", ").push_bind(*id).push(")");
+        }
+    }
+
+    /// Push the ` ORDER BY` and ` LIMIT` clauses. One more item than fits
+    /// the page is fetched to tell whether another page follows.
+    pub fn push_order_and_limit(&self, builder: &mut QueryBuilder<'_, Postgres>) {
+        let direction = if self.descending { "DESC" } else { "ASC" };
+        builder
+            .push(format!(
+                " ORDER BY {} {}, id {} LIMIT ",
+                self.sort.column, direction, direction
+            ))
+            .push_bind(self.limit + 1);
+    }
+
+    /// The page of `items` fetched by a query built with this one
+    pub fn page<T: Serialize>(&self, mut items: Vec<T>) -> Page<T> {
+        let mut next_cursor = None;
+        if items.len() as i64 > self.limit {
+            items.truncate(self.limit as usize);
+            next_cursor = items.last().and_then(|last| self.cursor_after(last));
+        }
+        Page { items, next_cursor }
+    }
+
+    fn cursor_after<T: Serialize>(&self, item: &T) -> Option<String> {
+        let json = serde_json::to_value(item).ok()?;
+        let value = json.get(self.sort.name).cloned();
+        let id = json
+            .get("id")
+            .and_then(|id| id.as_str())
+            .and_then(|id| id.parse().ok());
+        let (Some(value), Some(id)) = (value, id) else {
+            tracing::error!(
+                "Listed item has no '{}' or 'id' to continue from",
+                self.sort.name
+            );
+            return None;
+        };
+        let cursor = Cursor {
+            sort: self.sort_param.clone(),
+            value,
+            id,
+        };
+        Some(cursor.encode(&self.cursor_key))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    const LISTING: Listing = Listing {
+        fields: &[
+            Field::new("name", "name", FieldKind::Text),
+            Field::new("createdAt", "created_at", FieldKind::Timestamp),
+            Field::new("roleArn", "role_arn", FieldKind::Text).filter_only(),
+        ],
+        default_sort: "name",
+    };
+
+    fn config() -> PaginationConfig {
+        PaginationConfig {
+            default_page_size: 2,
+            max_page_size: 10,
+            cursor_key: CursorKey(b"secret".to_vec()),
+        }
+    }
+
+    fn sql(query: &PageQuery) -> String {
+        let mut builder = QueryBuilder::new("SELECT * FROM t WHERE true");
+        query.push_conditions(&mut builder);
+        query.push_order_and_limit(&mut builder);
+        builder.sql().to_string()
+    }
+
+    fn pointer(error: AppError) -> String {
+        match error {
+            AppError::InvalidFields(_, errors) => errors[0].pointer.clone(),
+            other => panic!("unexpected error {:?}", other),
+        }
+    }
+
+    #[test]
+    fn test_page_query() {
+        let params = PageParams {
+            sort: Some("-createdAt".to_string()),
+            filter: vec![
+                "name:contains:50%".to_string(),
+                "roleArn:eq:arn:aws:iam::123456789012:role/x".to_string(),
+                "name:in:a,b".to_string(),
+            ],
+            ..PageParams::default()
+        };
+        let query = params.resolve(&LISTING, &config()).unwrap();
+        assert_eq!(
+            query.filters[0].values,
+            vec![SqlValue::Text("%50\\%%".into())]
+        );
+        assert_eq!(
+            sql(&query),
+            "SELECT * FROM t WHERE true AND name ILIKE $1 AND role_arn = $2 \
+             AND name IN ($3, $4) ORDER BY created_at DESC, id DESC LIMIT $5"
+        );
+
+        // Three items fetched for a page of two continue after the second
+        let items = vec![
+            json!({"id": Uuid::nil(), "createdAt": "2026-01-03T00:00:00Z"}),
+            json!({"id": Uuid::max(), "createdAt": "2026-01-02T00:00:00Z"}),
+            json!({"id": Uuid::nil(), "createdAt": "2026-01-01T00:00:00Z"}),
+        ];
+        let page = query.page(items);
+        assert_eq!(page.items.len(), 2);
+        let params = PageParams {
+            cursor: page.next_cursor,
+            ..params
+        };
+        let next = params.resolve(&LISTING, &config()).unwrap();
+        assert_eq!(
+            next.after,
+            Some((
+                SqlValue::Timestamp("2026-01-02T00:00:00Z".parse().unwrap()),
+                Uuid::max()
+            ))
+        );
+        assert!(sql(&next).contains("AND (created_at, id) < ($5, $6) ORDER BY"));
+
+        // The last page has no cursor
+        assert_eq!(
+            next.page(vec![json!({"id": Uuid::nil()})]).next_cursor,
+            None
+        );
+
+        let invalid =
+            |params: PageParams| pointer(params.resolve(&LISTING, &config()).unwrap_err());
+        let with_sort = |sort: &str| PageParams {
+            sort: Some(sort.to_string()),
+            ..PageParams::default()
+        };
+        let with_filter = |filter: &str| PageParams {
+            filter: vec!["name:eq:x".to_string(), filter.to_string()],
+            ..PageParams::default()
+        };
+        assert_eq!(invalid(with_sort("roleArn")), "/sort");
+        assert_eq!(invalid(with_filter("name:like:x")), "/filter/1");
+        assert_eq!(invalid(with_filter("createdAt:contains:x")), "/filter/1");
+        assert_eq!(invalid(with_filter("createdAt:gt:yesterday")), "/filter/1");
+        assert_eq!(invalid(with_filter("owner:eq:x")), "/filter/1");
+        assert_eq!(
+            SqlValue::from_json(FieldKind::Number, &json!(12.5)),
+            Some(SqlValue::Number(12.5))
+        );
+        assert!(SqlValue::parse(FieldKind::Number, "NaN").is_err());
+        assert_eq!(invalid(with_filter("name")), "/filter/1");
+        let over_limit = PageParams {
+            limit: Some(11),
+            ..PageParams::default()
+        };
+        assert_eq!(invalid(over_limit), "/limit");
+        let cursor = Cursor {
+            sort: "name".to_string(),
+            value: json!("a"),
+            id: Uuid::nil(),
+        };
+        let tampered = PageParams {
+            cursor: Some(cursor.encode(&CursorKey(b"other".to_vec()))),
+            ..PageParams::default()
+        };
+        assert_eq!(invalid(tampered), "/cursor");
+        let other_sort = PageParams {
+            cursor: Some(cursor.encode(&config().cursor_key)),
+            sort: Some("-name".to_string()),
+            ..PageParams::default()
+        };
+        assert_eq!(invalid(other_sort), "/cursor");
+    }
+}
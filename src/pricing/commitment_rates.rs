@@ -0,0 +1,176 @@
+//! Discounts of AWS commitment offerings relative to on-demand rates
+//!
+//! The catalog ships with the binary so recommendations work offline. Rates
+//! are expressed as a discount off the on-demand price, which is taken from
+//! the `pricing/publicOnDemandCost` of ingested usage.
+
+use once_cell::sync::Lazy;
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+
+static BUNDLED: Lazy<CommitmentRateCatalog> = Lazy::new(|| {
+    serde_json::from_str(include_str!("data/aws_commitment_rates.json"))
+        .expect("bundled commitment rate catalog is valid JSON")
+});
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum OfferingKind {
+    /// Any instance family, size, region and OS
+    ComputeSavingsPlan,
+    /// One instance family in one region
+    Ec2InstanceSavingsPlan,
+    /// One instance type in one region
+    StandardReservedInstance,
+    /// One instance type in one region, exchangeable for other types
+    ConvertibleReservedInstance,
+}
+
+impl OfferingKind {
+    pub const ALL: [OfferingKind; 4] = [
+        OfferingKind::ComputeSavingsPlan,
+        OfferingKind::Ec2InstanceSavingsPlan,
+        OfferingKind::StandardReservedInstance,
+        OfferingKind::ConvertibleReservedInstance,
+    ];
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+pub enum Term {
+    #[serde(rename = "1yr")]
+    OneYear,
+    #[serde(rename = "3yr")]
+    ThreeYears,
+}
+
+impl Term {
+    pub const ALL: [Term; 2] = [Term::OneYear, Term::ThreeYears];
+
+    pub fn months(self) -> u32 {
+        match self {
+            Term::OneYear => 12,
+            Term::ThreeYears => 36,
+        }
+    }
+}
+
+#[derive(
+    Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize, ToSchema,
+)]
+#[serde(rename_all = "camelCase")]
+pub enum PaymentOption {
+    NoUpfront,
+    PartialUpfront,
+    AllUpfront,
+}
+
+impl PaymentOption {
+    pub const ALL: [PaymentOption; 3] = [
+        PaymentOption::NoUpfront,
+        PaymentOption::PartialUpfront,
+        PaymentOption::AllUpfront,
+    ];
+
+    /// Share of the total commitment paid at purchase
+    pub fn upfront_fraction(self) -> f64 {
+        match self {
+            PaymentOption::NoUpfront => 0.0,
+            PaymentOption::PartialUpfront => 0.5,
+            PaymentOption::AllUpfront => 1.0,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentRate {
+    pub offering: OfferingKind,
+    pub term: Term,
+    pub payment_option: PaymentOption,
+    /// Family the rate is specific to; generic rates apply to all others
+    #[serde(default)]
+    pub instance_family: Option<String>,
+    /// Fraction taken off the on-demand rate, e.g. `0.3` for 30% off
+    pub discount: f64,
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct CommitmentRateCatalog {
+    pub version: String,
+    pub currency: String,
+    pub rates: Vec<CommitmentRate>,
+}
+
+impl CommitmentRateCatalog {
+    /// The catalog compiled into the binary
+    pub fn bundled() -> &'static CommitmentRateCatalog {
+        &BUNDLED
+    }
+
+    /// Discount of an offering, preferring a rate specific to the family
+    pub fn discount(
+        &self,
+        offering: OfferingKind,
+        term: Term,
+        payment_option: PaymentOption,
+        instance_family: Option<&str>,
+    ) -> Option<f64> {
+        let mut generic = None;
+        for rate in &self.rates {
+            if rate.offering != offering
+                || rate.term != term
+                || rate.payment_option != payment_option
+            {
+                continue;
+            }
+            match rate.instance_family.as_deref() {
+                None => generic = Some(rate.discount),
+                Some(family) if Some(family) == instance_family => return Some(rate.discount),
+                Some(_) => {}
+            }
+        }
+        generic
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_bundled_catalog_lookup() {
+        let catalog = CommitmentRateCatalog::bundled();
+        let generic = catalog
+            .discount(
+                OfferingKind::StandardReservedInstance,
+                Term::OneYear,
+                PaymentOption::NoUpfront,
+                Some("m5"),
+            )
+            .unwrap();
+        let burstable = catalog
+            .discount(
+                OfferingKind::StandardReservedInstance,
+                Term::OneYear,
+                PaymentOption::NoUpfront,
+                Some("t3"),
+            )
+            .unwrap();
+        assert!(burstable < generic);
+
+        // Every offering has a generic rate for every term and payment option
+        for offering in OfferingKind::ALL {
+            for term in Term::ALL {
+                for payment in PaymentOption::ALL {
+                    let discount = catalog.discount(offering, term, payment, None).unwrap();
+                    assert!((0.0..1.0).contains(&discount));
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,241 @@
+//! Loading price files from the local pricing directory
+//!
+//! Files are laid out as `<dir>/<provider>/<file>`, for example
+//! `pricing/aws/AmazonEC2-us-east-1.json` or `pricing/gcp/compute.json`.
+//! Loading is idempotent: files whose content or version was already loaded
+//! are skipped.
+//!
+//! Files are read as a stream on a blocking thread, which hands prices over
+//! in batches to be inserted while it parses the rest.
+
+use std::fs::File;
+use std::io::BufReader;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use tokio::sync::{mpsc, oneshot};
+use utoipa::ToSchema;
+
+use super::model::{CatalogInfo, NewPrice, PriceCatalog};
+use super::parse::{self, PriceSink};
+use super::store;
+use crate::costs::Provider;
+use crate::db::DbPool;
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase", tag = "status")]
+pub enum LoadOutcome {
+    Loaded {
+        source: String,
+        catalog: PriceCatalog,
+    },
+    Unchanged {
+        source: String,
+    },
+    Failed {
+        source: String,
+        error: String,
+    },
+}
+
+/// Prices handed from the parser to the loader at a time
+const BATCH_SIZE: usize = 5000;
+
+/// Parse a price file according to its provider and extension
+pub fn parse_file(provider: Provider, path: &Path, sink: &mut dyn PriceSink) -> Result<(), String> {
+    let stem = path
+        .file_stem()
+        .and_then(|s| s.to_str())
+        .unwrap_or_default();
+    let extension = path
+        .extension()
+        .and_then(|s| s.to_str())
+        .map(str::to_ascii_lowercase);
+
+    let open = || {
+        File::open(path)
+            .map(BufReader::new)
+            .map_err(|e| e.to_string())
+    };
+    match (provider, extension.as_deref()) {
+        (Provider::Aws, Some("json")) => parse::parse_aws_json(open()?, sink),
+        (Provider::Aws, Some("csv")) => parse::parse_aws_csv(open()?, sink),
+        (Provider::Azure, Some("json")) => parse::parse_azure_json(stem, open()?, sink),
+        (Provider::Gcp, Some("json")) => parse::parse_gcp_json(stem, open()?, sink),
+        _ => Err(format!(
+            "unsupported {} price file '{}'",
+            provider,
+            path.display()
+        )),
+    }
+}
+
+/// Hands the catalog and batches of prices from the parser thread to
+/// [`load_file`]. Sending fails once the loader stopped listening, which
+/// stops the parser.
+struct ChannelSink {
+    info: Option<oneshot::Sender<CatalogInfo>>,
+    batches: mpsc::Sender<Vec<NewPrice>>,
+    batch: Vec<NewPrice>,
+}
+
+impl ChannelSink {
+    fn flush(&mut self) -> Result<(), String> {
+        if self.batch.is_empty() {
+            return Ok(());
+        }
+        let batch = std::mem::replace(&mut self.batch, Vec::with_capacity(BATCH_SIZE));
+        self.batches
+            .blocking_send(batch)
+            .map_err(|_| "loading stopped".to_string())
+    }
+}
+
+impl PriceSink for ChannelSink {
+    fn begin(&mut self, info: CatalogInfo) -> Result<(), String> {
+        self.info
+            .take()
+            .ok_or("catalog started twice")?
+            .send(info)
+            .map_err(|_| "loading stopped".to_string())
+    }
+
+    fn push(&mut self, price: NewPrice) -> Result<(), String> {
+        self.batch.push(price);
+        if self.batch.len() >= BATCH_SIZE {
+            self.flush()?;
+        }
+        Ok(())
+    }
+}
+
+/// SHA-256 of the file, read in chunks
+async fn file_checksum(path: PathBuf) -> std::io::Result<String> {
+    tokio::task::spawn_blocking(move || {
+        let mut hasher = Sha256::new();
+        std::io::copy(&mut File::open(path)?, &mut hasher)?;
+        Ok(hex::encode(hasher.finalize()))
+    })
+    .await
+    .unwrap_or_else(|e| Err(std::io::Error::other(e)))
+}
+
+fn is_price_file(path: &Path) -> bool {
+    matches!(
+        path.extension().and_then(|s| s.to_str()),
+        Some("json" | "csv")
+    )
+}
+
+/// Price files directly in `dir`, in name order
+async fn price_files(dir: &Path) -> Vec<PathBuf> {
+    let Ok(mut entries) = tokio::fs::read_dir(dir).await else {
+        return Vec::new();
+    };
+    let mut paths = Vec::new();
+    while let Ok(Some(entry)) = entries.next_entry().await {
+        let path = entry.path();
+        let is_file = tokio::fs::metadata(&path)
+            .await
+            .is_ok_and(|metadata| metadata.is_file());
+        if is_file && is_price_file(&path) {
+            paths.push(path);
+        }
+    }
+    paths.sort();
+    paths
+}
+
+async fn load_file(
+    db: &DbPool,
+    provider: Provider,
+    path: PathBuf,
+) -> Result<LoadOutcome, sqlx::Error> {
+    let source = path.display().to_string();
+    let checksum = match file_checksum(path.clone()).await {
+        Ok(checksum) => checksum,
+        Err(e) => {
+            return Ok(LoadOutcome::Failed {
+                source,
+                error: e.to_string(),
+            })
+        }
+    };
+    if store::checksum_loaded(db, provider.as_str(), &checksum).await? {
+        return Ok(LoadOutcome::Unchanged { source });
+    }
+
+    // Offer files can be hundreds of megabytes; keep parsing off the runtime.
+    // Returning early drops the receivers, which stops the parser.
+    let (info_sender, info) = oneshot::channel();
+    let (batch_sender, mut batches) = mpsc::channel(2);
+    let parser = tokio::task::spawn_blocking(move || {
+        let mut sink = ChannelSink {
+            info: Some(info_sender),
+            batches: batch_sender,
+            batch: Vec::with_capacity(BATCH_SIZE),
+        };
+        parse_file(provider, &path, &mut sink)?;
+        sink.flush()
+    });
+    let parsed = |result: Result<Result<(), String>, tokio::task::JoinError>| {
+        result.unwrap_or_else(|e| Err(format!("parser panicked: {}", e)))
+    };
+
+    let Ok(info) = info.await else {
+        let error = parsed(parser.await)
+            .err()
+            .unwrap_or_else(|| "no catalog in file".to_string());
+        return Ok(LoadOutcome::Failed { source, error });
+    };
+    let version = info
+        .version
+        .clone()
+        .unwrap_or_else(|| checksum[..16].to_string());
+    if store::catalog_exists(db, provider.as_str(), &info.name, &version).await? {
+        return Ok(LoadOutcome::Unchanged { source });
+    }
+
+    let mut tx = db.begin().await?;
+    let catalog = store::insert_catalog(&mut tx, &info, &version, &source, &checksum).await?;
+    let mut price_count = 0;
+    while let Some(batch) = batches.recv().await {
+        store::insert_prices(&mut tx, catalog.id, &batch).await?;
+        price_count += batch.len();
+    }
+    // The transaction rolls back when dropped
+    if let Err(error) = parsed(parser.await) {
+        return Ok(LoadOutcome::Failed { source, error });
+    }
+    let catalog = store::finish_catalog(&mut tx, catalog.id, price_count as i32).await?;
+    tx.commit().await?;
+
+    Ok(LoadOutcome::Loaded { source, catalog })
+}
+
+/// Load every price file below `dir` not loaded before
+pub async fn sync_directory(db: &DbPool, dir: &Path) -> Result<Vec<LoadOutcome>, sqlx::Error> {
+    let mut outcomes = Vec::new();
+    for provider in [Provider::Aws, Provider::Azure, Provider::Gcp] {
+        for path in price_files(&dir.join(provider.as_str())).await {
+            let outcome = load_file(db, provider, path).await?;
+            match &outcome {
+                LoadOutcome::Loaded { source, catalog } => tracing::info!(
+                    "Loaded {} prices from {} as {} {} {}",
+                    catalog.price_count,
+                    source,
+                    catalog.provider,
+                    catalog.name,
+                    catalog.version
+                ),
+                LoadOutcome::Failed { source, error } => {
+                    tracing::warn!("Failed to load prices from {}: {}", source, error)
+                }
+                LoadOutcome::Unchanged { .. } => {}
+            }
+            outcomes.push(outcome);
+        }
+    }
+    Ok(outcomes)
+}
@@ -0,0 +1,16 @@
+//! Offline cloud pricing data
+//!
+//! List prices are loaded from provider price files on local disk into
+//! versioned catalogs (`price_catalogs`/`prices`), so lookups work without
+//! network access and estimates can name the catalog version they used.
+//! Commitment discounts used by purchase recommendations ship with the
+//! binary.
+
+pub mod commitment_rates;
+pub mod loader;
+pub mod model;
+pub mod parse;
+pub mod store;
+
+pub use commitment_rates::{CommitmentRateCatalog, OfferingKind, PaymentOption, Term};
+pub use model::{Price, PriceCatalog, PriceQuery, PriceTier};
@@ -0,0 +1,214 @@
+//! Pricing API and storage types
+
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use crate::costs::Provider;
+
+/// One rate of a tiered price, covering usage from `start_usage` up to
+/// `end_usage` (unbounded when absent)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceTier {
+    pub start_usage: f64,
+    pub end_usage: Option<f64>,
+    pub unit_price: f64,
+}
+
+/// A price parsed from a provider file, before it is stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewPrice {
+    pub service: String,
+    pub sku: String,
+    pub product_family: Option<String>,
+    pub region: Option<String>,
+    pub description: Option<String>,
+    pub price_type: String,
+    pub term: Option<String>,
+    pub unit: String,
+    pub currency: String,
+    pub effective_from: Option<DateTime<Utc>>,
+    pub tiers: Vec<PriceTier>,
+    /// Shared by the prices of one product
+    pub attributes: Arc<BTreeMap<String, String>>,
+}
+
+/// Identity of one provider price file, known before its prices
+#[derive(Debug, Clone)]
+pub struct CatalogInfo {
+    pub provider: Provider,
+    pub name: String,
+    /// Provider-assigned version, when the file carries one
+    pub version: Option<String>,
+    pub publication_date: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Clone, Serialize, sqlx::FromRow, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct PriceCatalog {
+    pub id: Uuid,
+    pub provider: String,
+    pub name: String,
+    pub version: String,
+    pub source: String,
+    pub checksum: String,
+    pub publication_date: Option<DateTime<Utc>>,
+    pub price_count: i32,
+    pub loaded_at: DateTime<Utc>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Price {
+    pub id: Uuid,
+    pub catalog_id: Uuid,
+    pub catalog_version: String,
+    pub provider: String,
+    pub service: String,
+    pub sku: String,
+    pub product_family: Option<String>,
+    pub region: Option<String>,
+    pub description: Option<String>,
+    pub price_type: String,
+    pub term: Option<String>,
+    pub unit: String,
+    pub currency: String,
+    pub effective_from: Option<DateTime<Utc>>,
+    pub tiers: Vec<PriceTier>,
+    pub attributes: BTreeMap<String, String>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct PriceRow {
+    pub id: Uuid,
+    pub catalog_id: Uuid,
+    pub catalog_version: String,
+    pub provider: String,
+    pub service: String,
+    pub sku: String,
+    pub product_family: Option<String>,
+    pub region: Option<String>,
+    pub description: Option<String>,
+    pub price_type: String,
+    pub term: Option<String>,
+    pub unit: String,
+    pub currency: String,
+    pub effective_from: Option<DateTime<Utc>>,
+    pub tiers: Json<Vec<PriceTier>>,
+    pub attributes: Json<BTreeMap<String, String>>,
+}
+
+impl From<PriceRow> for Price {
+    fn from(row: PriceRow) -> Self {
+        Price {
+            id: row.id,
+            catalog_id: row.catalog_id,
+            catalog_version: row.catalog_version,
+            provider: row.provider,
+            service: row.service,
+            sku: row.sku,
+            product_family: row.product_family,
+            region: row.region,
+            description: row.description,
+            price_type: row.price_type,
+            term: row.term,
+            unit: row.unit,
+            currency: row.currency,
+            effective_from: row.effective_from,
+            tiers: row.tiers.0,
+            attributes: row.attributes.0,
+        }
+    }
+}
+
+/// Filters of a price lookup. Without a catalog id or version the latest
+/// catalog of each provider file is searched.
+#[derive(Debug, Clone, Default)]
+pub struct PriceQuery {
+    pub provider: Option<Provider>,
+    pub catalog_id: Option<Uuid>,
+    pub version: Option<String>,
+    pub service: Option<String>,
+    pub product_family: Option<String>,
+    pub sku: Option<String>,
+    pub region: Option<String>,
+    pub price_type: Option<String>,
+    /// Attributes the price must have, e.g. `instanceType = m5.large`
+    pub attributes: BTreeMap<String, String>,
+    pub limit: i64,
+}
+
+impl PriceQuery {
+    pub const DEFAULT_LIMIT: i64 = 100;
+    pub const MAX_LIMIT: i64 = 1000;
+
+    /// Query parameters prefixed with this filter on price attributes, e.g.
+    /// `attr.instanceType=m5.large`
+    const ATTRIBUTE_PREFIX: &'static str = "attr.";
+
+    /// Build a query from URL query parameters
+    pub fn from_params(params: HashMap<String, String>) -> Result<Self, String> {
+        let mut query = PriceQuery {
+            limit: Self::DEFAULT_LIMIT,
+            ..PriceQuery::default()
+        };
+
+        for (key, value) in params {
+            match key.as_str() {
+                "provider" => query.provider = Some(value.parse()?),
+                "catalogId" => {
+                    query.catalog_id = Some(value.parse().map_err(|_| "invalid catalogId")?)
+                }
+                "version" => query.version = Some(value),
+                "service" => query.service = Some(value),
+                "productFamily" => query.product_family = Some(value),
+                "sku" => query.sku = Some(value),
+                "region" => query.region = Some(value),
+                "priceType" => query.price_type = Some(value),
+                "limit" => {
+                    query.limit = value
+                        .parse()
+                        .ok()
+                        .filter(|limit| (1..=Self::MAX_LIMIT).contains(limit))
+                        .ok_or_else(|| format!("limit must be between 1 and {}", Self::MAX_LIMIT))?
+                }
+                other => match other.strip_prefix(Self::ATTRIBUTE_PREFIX) {
+                    Some(attribute) if !attribute.is_empty() => {
+                        query.attributes.insert(attribute.to_string(), value);
+                    }
+                    _ => return Err(format!("unknown query parameter '{}'", other)),
+                },
+            }
+        }
+
+        Ok(query)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_price_query_from_params() {
+        let params = HashMap::from([
+            ("provider".to_string(), "aws".to_string()),
+            ("attr.instanceType".to_string(), "m5.large".to_string()),
+        ]);
+        let query = PriceQuery::from_params(params).unwrap();
+        assert_eq!(query.provider, Some(Provider::Aws));
+        assert_eq!(query.limit, PriceQuery::DEFAULT_LIMIT);
+        assert_eq!(query.attributes["instanceType"], "m5.large");
+
+        let unknown = HashMap::from([("instanceType".to_string(), "m5.large".to_string())]);
+        assert!(PriceQuery::from_params(unknown).is_err());
+        let limit = HashMap::from([("limit".to_string(), "5000".to_string())]);
+        assert!(PriceQuery::from_params(limit).is_err());
+    }
+}
@@ -0,0 +1,885 @@
+//! Parsers for provider price files
+//!
+//! - AWS Price List bulk offer files, JSON or CSV
+//! - Azure retail prices API responses (`{"Items": [...]}` or a bare array)
+//! - GCP Cloud Billing catalog SKU listings (`{"skus": [...]}`)
+//!
+//! Parsers hand prices to a [`PriceSink`] as they go. AWS offer files can be
+//! hundreds of megabytes, so they are read as a stream and never held whole.
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::io::Read;
+use std::sync::Arc;
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::de::{self, DeserializeSeed, IgnoredAny, MapAccess, Visitor};
+use serde::{Deserialize, Deserializer};
+
+use super::model::{CatalogInfo, NewPrice, PriceTier};
+use crate::costs::Provider;
+
+/// Receives the catalog of a price file and then its prices, as parsed
+pub trait PriceSink {
+    /// Called once, before the first price
+    fn begin(&mut self, info: CatalogInfo) -> Result<(), String>;
+    fn push(&mut self, price: NewPrice) -> Result<(), String>;
+}
+
+/// Parse `"Inf"`-style open upper bounds as unbounded
+fn parse_bound(s: &str) -> Result<Option<f64>, String> {
+    if s.is_empty() || s.eq_ignore_ascii_case("inf") {
+        return Ok(None);
+    }
+    s.parse::<f64>()
+        .map(Some)
+        .map_err(|_| format!("invalid usage bound '{}'", s))
+}
+
+fn parse_price(s: &str) -> Result<f64, String> {
+    s.parse::<f64>()
+        .ok()
+        .filter(|p| p.is_finite())
+        .ok_or_else(|| format!("invalid price '{}'", s))
+}
+
+/// Turn tier start amounts into contiguous ranges; each tier ends where the
+/// next begins
+fn close_tiers(mut tiers: Vec<(f64, f64)>) -> Vec<PriceTier> {
+    tiers.sort_by(|a, b| a.0.total_cmp(&b.0));
+    let starts: Vec<f64> = tiers.iter().map(|t| t.0).skip(1).collect();
+    tiers
+        .into_iter()
+        .enumerate()
+        .map(|(i, (start_usage, unit_price))| PriceTier {
+            start_usage,
+            end_usage: starts.get(i).copied(),
+            unit_price,
+        })
+        .collect()
+}
+
+/// AWS reserved term, e.g. `1yr standard All Upfront`
+fn aws_term(attributes: &BTreeMap<String, String>) -> Option<String> {
+    let parts: Vec<&str> = ["LeaseContractLength", "OfferingClass", "PurchaseOption"]
+        .iter()
+        .filter_map(|key| attributes.get(*key).map(String::as_str))
+        .filter(|value| !value.is_empty())
+        .collect();
+    (!parts.is_empty()).then(|| parts.join(" "))
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AwsProduct {
+    product_family: Option<String>,
+    #[serde(default)]
+    attributes: BTreeMap<String, String>,
+}
+
+/// A product with its attributes ready to share between its prices
+struct Product {
+    family: Option<String>,
+    attributes: Arc<BTreeMap<String, String>>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AwsTerm {
+    effective_date: Option<DateTime<Utc>>,
+    price_dimensions: HashMap<String, AwsPriceDimension>,
+    #[serde(default)]
+    term_attributes: BTreeMap<String, String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AwsPriceDimension {
+    description: Option<String>,
+    #[serde(default)]
+    begin_range: String,
+    unit: String,
+    price_per_unit: BTreeMap<String, String>,
+}
+
+/// What the offer visitor needs to turn terms into prices
+struct AwsOffer<'a> {
+    service: String,
+    products: HashMap<String, Product>,
+    sink: &'a mut dyn PriceSink,
+}
+
+impl AwsOffer<'_> {
+    /// One price per unit and currency of the term
+    fn push_term(&mut self, price_type: &str, sku: &str, term: AwsTerm) -> Result<(), String> {
+        let Some(product) = self.products.get(sku) else {
+            return Ok(());
+        };
+        // Only reserved terms add attributes; others share the product's
+        let attributes = if term.term_attributes.is_empty() {
+            Arc::clone(&product.attributes)
+        } else {
+            let mut attributes = (*product.attributes).clone();
+            attributes.extend(term.term_attributes);
+            Arc::new(attributes)
+        };
+
+        // Group dimensions by unit and currency
+        type Group<'a> = (Option<&'a str>, Vec<(f64, f64)>);
+        let mut groups: BTreeMap<(&str, &str), Group> = BTreeMap::new();
+        for dimension in term.price_dimensions.values() {
+            let start = parse_bound(&dimension.begin_range)?.unwrap_or(0.0);
+            for (currency, price) in &dimension.price_per_unit {
+                let group = groups
+                    .entry((dimension.unit.as_str(), currency.as_str()))
+                    .or_insert((dimension.description.as_deref(), Vec::new()));
+                group.1.push((start, parse_price(price)?));
+            }
+        }
+
+        for ((unit, currency), (description, tiers)) in groups {
+            self.sink.push(NewPrice {
+                service: self.service.clone(),
+                sku: sku.to_string(),
+                product_family: product.family.clone(),
+                region: attributes.get("regionCode").cloned(),
+                description: description.map(String::from),
+                price_type: price_type.to_string(),
+                term: aws_term(&attributes),
+                unit: unit.to_string(),
+                currency: currency.to_string(),
+                effective_from: term.effective_date,
+                tiers: close_tiers(tiers),
+                attributes: Arc::clone(&attributes),
+            })?;
+        }
+        Ok(())
+    }
+}
+
+/// Visits `terms` (price type → SKU → offer term code → term) one SKU at a
+/// time
+struct AwsTerms<'a, 'b>(&'a mut AwsOffer<'b>);
+
+impl<'de> DeserializeSeed<'de> for AwsTerms<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for AwsTerms<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AWS offer terms by price type")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(price_type) = map.next_key::<String>()? {
+            map.next_value_seed(AwsTermsBySku {
+                offer: self.0,
+                price_type,
+            })?;
+        }
+        Ok(())
+    }
+}
+
+struct AwsTermsBySku<'a, 'b> {
+    offer: &'a mut AwsOffer<'b>,
+    price_type: String,
+}
+
+impl<'de> DeserializeSeed<'de> for AwsTermsBySku<'_, '_> {
+    type Value = ();
+
+    fn deserialize<D: Deserializer<'de>>(self, deserializer: D) -> Result<(), D::Error> {
+        deserializer.deserialize_map(self)
+    }
+}
+
+impl<'de> Visitor<'de> for AwsTermsBySku<'_, '_> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("AWS offer terms by SKU")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        while let Some(sku) = map.next_key::<String>()? {
+            let terms: HashMap<String, AwsTerm> = map.next_value()?;
+            for term in terms.into_values() {
+                self.offer
+                    .push_term(&self.price_type, &sku, term)
+                    .map_err(de::Error::custom)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Visits the top level of an offer file. `offerCode` and `products` come
+/// before `terms` in AWS files, so prices can be emitted while reading terms.
+struct AwsOfferFile<'a> {
+    sink: &'a mut dyn PriceSink,
+}
+
+impl<'de, 'a> Visitor<'de> for AwsOfferFile<'a> {
+    type Value = ();
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an AWS offer file")
+    }
+
+    fn visit_map<A: MapAccess<'de>>(self, mut map: A) -> Result<(), A::Error> {
+        let mut name: Option<String> = None;
+        let mut version: Option<String> = None;
+        let mut publication_date: Option<DateTime<Utc>> = None;
+        let mut products: Option<HashMap<String, AwsProduct>> = None;
+        let mut sink = Some(self.sink);
+
+        while let Some(key) = map.next_key::<String>()? {
+            match key.as_str() {
+                "offerCode" => name = Some(map.next_value()?),
+                "version" => version = Some(map.next_value()?),
+                "publicationDate" => publication_date = map.next_value()?,
+                "products" => products = Some(map.next_value()?),
+                "terms" => {
+                    let (Some(name), Some(products), Some(sink)) =
+                        (name.clone(), products.take(), sink.take())
+                    else {
+                        return Err(de::Error::custom(
+                            "offerCode and products must come before terms",
+                        ));
+                    };
+                    sink.begin(CatalogInfo {
+                        provider: Provider::Aws,
+                        name: name.clone(),
+                        version: version.clone(),
+                        publication_date,
+                    })
+                    .map_err(de::Error::custom)?;
+                    let mut offer = AwsOffer {
+                        service: name,
+                        products: products
+                            .into_iter()
+                            .map(|(sku, product)| {
+                                let product = Product {
+                                    family: product.product_family,
+                                    attributes: Arc::new(product.attributes),
+                                };
+                                (sku, product)
+                            })
+                            .collect(),
+                        sink,
+                    };
+                    map.next_value_seed(AwsTerms(&mut offer))?;
+                }
+                _ => {
+                    map.next_value::<IgnoredAny>()?;
+                }
+            }
+        }
+
+        // An offer without terms has no prices
+        if let Some(sink) = sink {
+            sink.begin(CatalogInfo {
+                provider: Provider::Aws,
+                name: name.ok_or_else(|| de::Error::missing_field("offerCode"))?,
+                version,
+                publication_date,
+            })
+            .map_err(de::Error::custom)?;
+        }
+        Ok(())
+    }
+}
+
+/// Parse an AWS Price List bulk offer file in JSON format.
+///
+/// Each SKU and offer term yields one price per unit (reserved terms carry
+/// an upfront `Quantity` fee next to the hourly rate).
+pub fn parse_aws_json(input: impl Read, sink: &mut dyn PriceSink) -> Result<(), String> {
+    let mut deserializer = serde_json::Deserializer::from_reader(input);
+    deserializer
+        .deserialize_map(AwsOfferFile { sink })
+        .and_then(|()| deserializer.end())
+        .map_err(|e| format!("invalid AWS offer file: {}", e))
+}
+
+/// `Instance Type` → `instanceType`, matching the JSON attribute names
+fn aws_csv_attribute_key(header: &str) -> String {
+    let mut key = String::new();
+    for (i, word) in header.split_whitespace().enumerate() {
+        let mut chars = word.chars();
+        if let Some(first) = chars.next() {
+            if i == 0 {
+                key.extend(first.to_lowercase());
+            } else {
+                key.extend(first.to_uppercase());
+            }
+            key.push_str(chars.as_str());
+        }
+    }
+    key
+}
+
+/// Columns of an AWS CSV offer file that describe the rate rather than the
+/// product
+const AWS_CSV_PRICE_COLUMNS: [&str; 11] = [
+    "SKU",
+    "OfferTermCode",
+    "RateCode",
+    "TermType",
+    "PriceDescription",
+    "EffectiveDate",
+    "StartingRange",
+    "EndingRange",
+    "Unit",
+    "PricePerUnit",
+    "Currency",
+];
+
+/// Columns kept under their original name so reserved terms read the same
+/// as in JSON files
+const AWS_CSV_TERM_COLUMNS: [&str; 3] = ["LeaseContractLength", "OfferingClass", "PurchaseOption"];
+
+fn aws_csv_catalog(metadata: &HashMap<String, String>) -> Result<CatalogInfo, String> {
+    Ok(CatalogInfo {
+        provider: Provider::Aws,
+        name: metadata
+            .get("OfferCode")
+            .cloned()
+            .ok_or("missing OfferCode metadata line")?,
+        version: metadata.get("Version").cloned(),
+        publication_date: metadata
+            .get("Publication Date")
+            .and_then(|d| d.parse::<DateTime<Utc>>().ok()),
+    })
+}
+
+/// Prices of one offer term by unit, with their tiers
+type TermGroups = BTreeMap<String, (NewPrice, Vec<(f64, f64)>)>;
+
+fn push_groups(groups: TermGroups, sink: &mut dyn PriceSink) -> Result<(), String> {
+    for (_, (mut price, tiers)) in groups {
+        price.tiers = close_tiers(tiers);
+        sink.push(price)?;
+    }
+    Ok(())
+}
+
+/// Parse an AWS Price List bulk offer file in CSV format: a few
+/// `"Key","Value"` metadata lines, then one row per price dimension. The rows
+/// of an offer term are consecutive, so each term's prices are emitted once
+/// the next term starts.
+pub fn parse_aws_csv(input: impl Read, sink: &mut dyn PriceSink) -> Result<(), String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .has_headers(false)
+        .flexible(true)
+        .from_reader(input);
+
+    let mut metadata = HashMap::new();
+    let mut header: Option<Vec<String>> = None;
+    let mut term: Option<(String, String)> = None;
+    let mut groups = TermGroups::new();
+
+    for (i, record) in reader.records().enumerate() {
+        let line = i + 1;
+        let record = record.map_err(|e| format!("line {}: {}", line, e))?;
+        let Some(columns) = &header else {
+            if record.get(0) == Some("SKU") {
+                sink.begin(aws_csv_catalog(&metadata)?)?;
+                header = Some(record.iter().map(String::from).collect());
+            } else if let (Some(key), Some(value)) = (record.get(0), record.get(1)) {
+                metadata.insert(key.to_string(), value.to_string());
+            }
+            continue;
+        };
+
+        let mut fields = HashMap::new();
+        let mut attributes = BTreeMap::new();
+        for (name, value) in columns.iter().zip(record.iter()) {
+            if AWS_CSV_PRICE_COLUMNS.contains(&name.as_str()) {
+                fields.insert(name.as_str(), value);
+            } else if !value.is_empty() {
+                let key = if AWS_CSV_TERM_COLUMNS.contains(&name.as_str()) {
+                    name.clone()
+                } else {
+                    aws_csv_attribute_key(name)
+                };
+                attributes.insert(key, value.to_string());
+            }
+        }
+        let field = |name: &str| fields.get(name).copied().unwrap_or_default();
+
+        let sku = field("SKU").to_string();
+        if sku.is_empty() {
+            return Err(format!("line {}: missing SKU", line));
+        }
+        let unit = field("Unit").to_string();
+        let price =
+            parse_price(field("PricePerUnit")).map_err(|e| format!("line {}: {}", line, e))?;
+        let start = parse_bound(field("StartingRange"))
+            .map_err(|e| format!("line {}: {}", line, e))?
+            .unwrap_or(0.0);
+        let effective_from = NaiveDate::parse_from_str(field("EffectiveDate"), "%Y-%m-%d")
+            .ok()
+            .and_then(|d| d.and_hms_opt(0, 0, 0))
+            .map(|d| d.and_utc());
+
+        let row_term = (sku.clone(), field("OfferTermCode").to_string());
+        if term.as_ref() != Some(&row_term) {
+            push_groups(std::mem::take(&mut groups), sink)?;
+            term = Some(row_term);
+        }
+        let group = groups.entry(unit.clone()).or_insert_with(|| {
+            let service = attributes
+                .get("serviceCode")
+                .cloned()
+                .or_else(|| metadata.get("OfferCode").cloned())
+                .unwrap_or_default();
+            (
+                NewPrice {
+                    service,
+                    sku,
+                    product_family: attributes.get("productFamily").cloned(),
+                    region: attributes.get("regionCode").cloned(),
+                    description: Some(field("PriceDescription").to_string())
+                        .filter(|d| !d.is_empty()),
+                    price_type: field("TermType").to_string(),
+                    term: aws_term(&attributes),
+                    unit,
+                    currency: field("Currency").to_string(),
+                    effective_from,
+                    tiers: Vec::new(),
+                    attributes: Arc::new(attributes),
+                },
+                Vec::new(),
+            )
+        });
+        group.1.push((start, price));
+    }
+
+    if header.is_none() {
+        sink.begin(aws_csv_catalog(&metadata)?)?;
+    }
+    push_groups(groups, sink)
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum AzureFile {
+    Page {
+        #[serde(rename = "Items")]
+        items: Vec<AzureItem>,
+    },
+    Items(Vec<AzureItem>),
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct AzureItem {
+    currency_code: String,
+    #[serde(default)]
+    tier_minimum_units: f64,
+    unit_price: f64,
+    arm_region_name: Option<String>,
+    effective_start_date: Option<DateTime<Utc>>,
+    meter_id: String,
+    meter_name: Option<String>,
+    product_id: Option<String>,
+    sku_id: Option<String>,
+    product_name: Option<String>,
+    sku_name: Option<String>,
+    service_name: String,
+    service_family: Option<String>,
+    unit_of_measure: String,
+    #[serde(rename = "type")]
+    price_type: String,
+    arm_sku_name: Option<String>,
+    reservation_term: Option<String>,
+}
+
+/// Parse Azure retail prices, either a saved API page or an array of items.
+/// Items differing only by `tierMinimumUnits` form one tiered price.
+pub fn parse_azure_json(
+    name: &str,
+    input: impl Read,
+    sink: &mut dyn PriceSink,
+) -> Result<(), String> {
+    let file: AzureFile = serde_json::from_reader(input)
+        .map_err(|e| format!("invalid Azure retail price file: {}", e))?;
+    let items = match file {
+        AzureFile::Page { items } | AzureFile::Items(items) => items,
+    };
+
+    type Key = (
+        String,
+        String,
+        Option<String>,
+        Option<String>,
+        Option<DateTime<Utc>>,
+    );
+    let mut groups: BTreeMap<Key, (NewPrice, Vec<(f64, f64)>)> = BTreeMap::new();
+    for item in items {
+        let region = item.arm_region_name.filter(|r| !r.is_empty());
+        let key = (
+            item.meter_id.clone(),
+            item.price_type.clone(),
+            item.reservation_term.clone(),
+            region.clone(),
+            item.effective_start_date,
+        );
+        let group = groups.entry(key).or_insert_with(|| {
+            let attributes = [
+                ("meterId", Some(item.meter_id.clone())),
+                ("meterName", item.meter_name.clone()),
+                ("productId", item.product_id.clone()),
+                ("productName", item.product_name.clone()),
+                ("skuName", item.sku_name.clone()),
+                ("armSkuName", item.arm_sku_name.clone()),
+            ]
+            .into_iter()
+            .filter_map(|(k, v)| v.filter(|v| !v.is_empty()).map(|v| (k.to_string(), v)))
+            .collect();
+            (
+                NewPrice {
+                    service: item.service_name.clone(),
+                    sku: item.sku_id.clone().unwrap_or_else(|| item.meter_id.clone()),
+                    product_family: item.service_family.clone(),
+                    region,
+                    description: item.meter_name.clone(),
+                    price_type: item.price_type.clone(),
+                    term: item.reservation_term.clone(),
+                    unit: item.unit_of_measure.clone(),
+                    currency: item.currency_code.clone(),
+                    effective_from: item.effective_start_date,
+                    tiers: Vec::new(),
+                    attributes: Arc::new(attributes),
+                },
+                Vec::new(),
+            )
+        });
+        group.1.push((item.tier_minimum_units, item.unit_price));
+    }
+
+    sink.begin(CatalogInfo {
+        provider: Provider::Azure,
+        name: name.to_string(),
+        version: None,
+        publication_date: None,
+    })?;
+    for (mut price, tiers) in groups.into_values() {
+        price.tiers = close_tiers(tiers);
+        sink.push(price)?;
+    }
+    Ok(())
+}
+
+#[derive(Deserialize)]
+struct GcpFile {
+    skus: Vec<GcpSku>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcpSku {
+    name: Option<String>,
+    sku_id: String,
+    description: Option<String>,
+    category: GcpCategory,
+    #[serde(default)]
+    service_regions: Vec<String>,
+    #[serde(default)]
+    pricing_info: Vec<GcpPricingInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcpCategory {
+    service_display_name: String,
+    resource_family: Option<String>,
+    resource_group: Option<String>,
+    usage_type: String,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcpPricingInfo {
+    effective_time: Option<DateTime<Utc>>,
+    pricing_expression: GcpPricingExpression,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcpPricingExpression {
+    usage_unit: String,
+    #[serde(default)]
+    tiered_rates: Vec<GcpTieredRate>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcpTieredRate {
+    #[serde(default)]
+    start_usage_amount: f64,
+    unit_price: GcpMoney,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct GcpMoney {
+    currency_code: String,
+    /// Whole units, serialized as a string (int64)
+    #[serde(default)]
+    units: Option<String>,
+    #[serde(default)]
+    nanos: i64,
+}
+
+impl GcpMoney {
+    fn amount(&self) -> Result<f64, String> {
+        let units = match self.units.as_deref() {
+            None | Some("") => 0.0,
+            Some(units) => parse_price(units)?,
+        };
+        Ok(units + self.nanos as f64 / 1e9)
+    }
+}
+
+/// Parse a GCP Cloud Billing catalog SKU listing. SKUs available in several
+/// regions yield one price per region.
+pub fn parse_gcp_json(
+    name: &str,
+    input: impl Read,
+    sink: &mut dyn PriceSink,
+) -> Result<(), String> {
+    let file: GcpFile =
+        serde_json::from_reader(input).map_err(|e| format!("invalid GCP SKU file: {}", e))?;
+
+    sink.begin(CatalogInfo {
+        provider: Provider::Gcp,
+        name: name.to_string(),
+        version: None,
+        publication_date: None,
+    })?;
+    for sku in file.skus {
+        let mut attributes = BTreeMap::new();
+        if let Some(group) = &sku.category.resource_group {
+            attributes.insert("resourceGroup".to_string(), group.clone());
+        }
+        if let Some(name) = &sku.name {
+            attributes.insert("name".to_string(), name.clone());
+        }
+        let regions: Vec<Option<String>> = if sku.service_regions.is_empty() {
+            vec![None]
+        } else {
+            sku.service_regions.iter().cloned().map(Some).collect()
+        };
+        let attributes = Arc::new(attributes);
+
+        for info in &sku.pricing_info {
+            let expression = &info.pricing_expression;
+            let Some(currency) = expression
+                .tiered_rates
+                .first()
+                .map(|rate| rate.unit_price.currency_code.clone())
+            else {
+                continue;
+            };
+            let tiers = expression
+                .tiered_rates
+                .iter()
+                .map(|rate| Ok((rate.start_usage_amount, rate.unit_price.amount()?)))
+                .collect::<Result<Vec<_>, String>>()?;
+            let tiers = close_tiers(tiers);
+
+            for region in &regions {
+                sink.push(NewPrice {
+                    service: sku.category.service_display_name.clone(),
+                    sku: sku.sku_id.clone(),
+                    product_family: sku.category.resource_family.clone(),
+                    region: region.clone(),
+                    description: sku.description.clone(),
+                    price_type: sku.category.usage_type.clone(),
+                    term: None,
+                    unit: expression.usage_unit.clone(),
+                    currency: currency.clone(),
+                    effective_from: info.effective_time,
+                    tiers: tiers.clone(),
+                    attributes: Arc::clone(&attributes),
+                })?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// A parsed file kept whole for assertions
+    #[derive(Default)]
+    struct Collected {
+        info: Option<CatalogInfo>,
+        prices: Vec<NewPrice>,
+    }
+
+    impl PriceSink for Collected {
+        fn begin(&mut self, info: CatalogInfo) -> Result<(), String> {
+            assert!(self.info.is_none(), "catalog started twice");
+            self.info = Some(info);
+            Ok(())
+        }
+
+        fn push(&mut self, price: NewPrice) -> Result<(), String> {
+            assert!(self.info.is_some(), "price before the catalog");
+            self.prices.push(price);
+            Ok(())
+        }
+    }
+
+    fn collect(parse: impl FnOnce(&mut dyn PriceSink) -> Result<(), String>) -> Collected {
+        let mut collected = Collected::default();
+        parse(&mut collected).unwrap();
+        collected
+    }
+
+    #[test]
+    fn test_parse_aws_json_and_csv_agree() {
+        let json = br#"{
+            "formatVersion": "v1.0",
+            "offerCode": "AmazonEC2",
+            "version": "20260901000000",
+            "publicationDate": "2026-09-01T00:00:00Z",
+            "products": {
+                "ABC": {
+                    "sku": "ABC",
+                    "productFamily": "Compute Instance",
+                    "attributes": {"regionCode": "us-east-1", "instanceType": "m5.large"}
+                }
+            },
+            "terms": {
+                "OnDemand": {
+                    "ABC": {
+                        "ABC.JRTCKXETXF": {
+                            "effectiveDate": "2026-09-01T00:00:00Z",
+                            "priceDimensions": {
+                                "ABC.JRTCKXETXF.6YS6EN2CT7": {
+                                    "description": "$0.096 per On Demand Linux m5.large Instance Hour",
+                                    "beginRange": "0",
+                                    "endRange": "Inf",
+                                    "unit": "Hrs",
+                                    "pricePerUnit": {"USD": "0.0960000000"}
+                                }
+                            },
+                            "termAttributes": {}
+                        }
+                    }
+                }
+            }
+        }"#;
+        let csv = b"\"FormatVersion\",\"v1.0\"\n\
+\"Disclaimer\",\"...\"\n\
+\"Publication Date\",\"2026-09-01T00:00:00Z\"\n\
+\"Version\",\"20260901000000\"\n\
+\"OfferCode\",\"AmazonEC2\"\n\
+\"SKU\",\"OfferTermCode\",\"RateCode\",\"TermType\",\"PriceDescription\",\"EffectiveDate\",\"StartingRange\",\"EndingRange\",\"Unit\",\"PricePerUnit\",\"Currency\",\"Product Family\",\"serviceCode\",\"Instance Type\",\"Region Code\"\n\
+\"ABC\",\"JRTCKXETXF\",\"ABC.JRTCKXETXF.6YS6EN2CT7\",\"OnDemand\",\"$0.096 per On Demand Linux m5.large Instance Hour\",\"2026-09-01\",\"0\",\"Inf\",\"Hrs\",\"0.0960000000\",\"USD\",\"Compute Instance\",\"AmazonEC2\",\"m5.large\",\"us-east-1\"\n";
+
+        let from_json = collect(|sink| parse_aws_json(&json[..], sink));
+        let from_csv = collect(|sink| parse_aws_csv(&csv[..], sink));
+        for catalog in [&from_json, &from_csv] {
+            let info = catalog.info.as_ref().unwrap();
+            assert_eq!(info.name, "AmazonEC2");
+            assert_eq!(info.version.as_deref(), Some("20260901000000"));
+            assert_eq!(catalog.prices.len(), 1);
+            let price = &catalog.prices[0];
+            assert_eq!(price.region.as_deref(), Some("us-east-1"));
+            assert_eq!(price.price_type, "OnDemand");
+            assert_eq!(price.attributes["instanceType"], "m5.large");
+            assert_eq!(
+                price.tiers,
+                vec![PriceTier {
+                    start_usage: 0.0,
+                    end_usage: None,
+                    unit_price: 0.096
+                }]
+            );
+        }
+        assert_eq!(
+            from_json.prices[0].effective_from,
+            from_csv.prices[0].effective_from
+        );
+    }
+
+    #[test]
+    fn test_parse_aws_csv_groups_consecutive_tiers() {
+        let csv = b"\"OfferCode\",\"AWSDataTransfer\"\n\
+\"SKU\",\"OfferTermCode\",\"RateCode\",\"TermType\",\"StartingRange\",\"Unit\",\"PricePerUnit\",\"Currency\"\n\
+\"DT1\",\"JRTCKXETXF\",\"DT1.JRTCKXETXF.1\",\"OnDemand\",\"10240\",\"GB\",\"0.085\",\"USD\"\n\
+\"DT1\",\"JRTCKXETXF\",\"DT1.JRTCKXETXF.0\",\"OnDemand\",\"0\",\"GB\",\"0.09\",\"USD\"\n\
+\"DT2\",\"JRTCKXETXF\",\"DT2.JRTCKXETXF.0\",\"OnDemand\",\"0\",\"GB\",\"0.02\",\"USD\"\n";
+
+        let catalog = collect(|sink| parse_aws_csv(&csv[..], sink));
+        assert_eq!(catalog.prices.len(), 2);
+        let tiers = &catalog.prices[0].tiers;
+        assert_eq!(tiers.len(), 2);
+        assert_eq!(tiers[0].end_usage, Some(10240.0));
+        assert_eq!(tiers[1].unit_price, 0.085);
+        assert_eq!(catalog.prices[1].sku, "DT2");
+    }
+
+    #[test]
+    fn test_parse_aws_json_needs_products_before_terms() {
+        let json = br#"{"offerCode": "AmazonEC2", "version": "1", "terms": {}, "products": {}}"#;
+        let error = parse_aws_json(&json[..], &mut Collected::default()).unwrap_err();
+        assert!(error.contains("must come before terms"), "{}", error);
+    }
+
+    #[test]
+    fn test_parse_azure_tiers() {
+        let json = br#"{"Items": [
+            {"currencyCode": "USD", "tierMinimumUnits": 5.0, "unitPrice": 0.08,
+             "armRegionName": "eastus", "effectiveStartDate": "2026-01-01T00:00:00Z",
+             "meterId": "m1", "skuId": "S1/0001", "serviceName": "Bandwidth",
+             "unitOfMeasure": "1 GB", "type": "Consumption"},
+            {"currencyCode": "USD", "tierMinimumUnits": 0.0, "unitPrice": 0.0,
+             "armRegionName": "eastus", "effectiveStartDate": "2026-01-01T00:00:00Z",
+             "meterId": "m1", "skuId": "S1/0001", "serviceName": "Bandwidth",
+             "unitOfMeasure": "1 GB", "type": "Consumption"}
+        ]}"#;
+        let catalog = collect(|sink| parse_azure_json("bandwidth", &json[..], sink));
+        assert_eq!(catalog.prices.len(), 1);
+        let tiers = &catalog.prices[0].tiers;
+        assert_eq!(tiers[0].end_usage, Some(5.0));
+        assert_eq!(tiers[1].unit_price, 0.08);
+    }
+
+    #[test]
+    fn test_parse_gcp_regions_and_money() {
+        let json = br#"{"skus": [{
+            "name": "services/6F81-5844-456A/skus/0013-863C-A2FF",
+            "skuId": "0013-863C-A2FF",
+            "description": "N1 Predefined Instance Core",
+            "category": {"serviceDisplayName": "Compute Engine", "resourceFamily": "Compute",
+                         "resourceGroup": "N1Standard", "usageType": "OnDemand"},
+            "serviceRegions": ["us-central1", "us-east1"],
+            "pricingInfo": [{
+                "effectiveTime": "2026-09-01T00:00:00Z",
+                "pricingExpression": {"usageUnit": "h", "tieredRates": [
+                    {"startUsageAmount": 0, "unitPrice": {"currencyCode": "USD", "units": "1", "nanos": 31611000}}
+                ]}
+            }]
+        }]}"#;
+        let catalog = collect(|sink| parse_gcp_json("compute", &json[..], sink));
+        assert_eq!(catalog.prices.len(), 2);
+        assert!((catalog.prices[0].tiers[0].unit_price - 1.031611).abs() < 1e-12);
+        assert_eq!(catalog.prices[1].region.as_deref(), Some("us-east1"));
+    }
+}
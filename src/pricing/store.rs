@@ -0,0 +1,187 @@
+//! Persistence and lookup of price catalogs
+
+use sqlx::types::Json;
+use sqlx::{Postgres, QueryBuilder, Transaction};
+use uuid::Uuid;
+
+use super::model::{CatalogInfo, NewPrice, Price, PriceCatalog, PriceQuery, PriceRow};
+use crate::db::DbPool;
+
+const CATALOG_COLUMNS: &str =
+    "id, provider, name, version, source, checksum, publication_date, price_count, loaded_at";
+
+pub async fn list_catalogs(db: &DbPool) -> Result<Vec<PriceCatalog>, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "SELECT {} FROM price_catalogs ORDER BY provider, name, loaded_at DESC",
+        CATALOG_COLUMNS
+    ))
+    .fetch_all(db)
+    .await
+}
+
+/// Whether a file with this content was already loaded for the provider
+pub async fn checksum_loaded(
+    db: &DbPool,
+    provider: &str,
+    checksum: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM price_catalogs WHERE provider = $1 AND checksum = $2)",
+    )
+    .bind(provider)
+    .bind(checksum)
+    .fetch_one(db)
+    .await
+}
+
+pub async fn catalog_exists(
+    db: &DbPool,
+    provider: &str,
+    name: &str,
+    version: &str,
+) -> Result<bool, sqlx::Error> {
+    sqlx::query_scalar(
+        "SELECT EXISTS (
+             SELECT 1 FROM price_catalogs WHERE provider = $1 AND name = $2 AND version = $3
+         )",
+    )
+    .bind(provider)
+    .bind(name)
+    .bind(version)
+    .fetch_one(db)
+    .await
+}
+
+/// Start storing a catalog. Its prices are added with [`insert_prices`] and
+/// it is completed with [`finish_catalog`] in the same transaction, so
+/// lookups never see a partially loaded catalog.
+pub async fn insert_catalog(
+    tx: &mut Transaction<'_, Postgres>,
+    info: &CatalogInfo,
+    version: &str,
+    source: &str,
+    checksum: &str,
+) -> Result<PriceCatalog, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "INSERT INTO price_catalogs
+             (provider, name, version, source, checksum, publication_date, price_count)
+         VALUES ($1, $2, $3, $4, $5, $6, 0)
+         RETURNING {}",
+        CATALOG_COLUMNS
+    ))
+    .bind(info.provider.as_str())
+    .bind(&info.name)
+    .bind(version)
+    .bind(source)
+    .bind(checksum)
+    .bind(info.publication_date)
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn insert_prices(
+    tx: &mut Transaction<'_, Postgres>,
+    catalog_id: Uuid,
+    prices: &[NewPrice],
+) -> Result<(), sqlx::Error> {
+    // Stay well below the 65535 bind parameter limit
+    const CHUNK_SIZE: usize = 1000;
+
+    for chunk in prices.chunks(CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO prices (catalog_id, service, sku, product_family, region, description, \
+             price_type, term, unit, currency, effective_from, tiers, attributes) ",
+        );
+        builder.push_values(chunk, |mut row, price| {
+            row.push_bind(catalog_id)
+                .push_bind(&price.service)
+                .push_bind(&price.sku)
+                .push_bind(&price.product_family)
+                .push_bind(&price.region)
+                .push_bind(&price.description)
+                .push_bind(&price.price_type)
+                .push_bind(&price.term)
+                .push_bind(&price.unit)
+                .push_bind(&price.currency)
+                .push_bind(price.effective_from)
+                .push_bind(Json(&price.tiers))
+                .push_bind(Json(&*price.attributes));
+        });
+        builder.build().execute(&mut **tx).await?;
+    }
+
+    Ok(())
+}
+
+/// Record the number of prices stored for the catalog
+pub async fn finish_catalog(
+    tx: &mut Transaction<'_, Postgres>,
+    catalog_id: Uuid,
+    price_count: i32,
+) -> Result<PriceCatalog, sqlx::Error> {
+    sqlx::query_as(&format!(
+        "UPDATE price_catalogs SET price_count = $2 WHERE id = $1 RETURNING {}",
+        CATALOG_COLUMNS
+    ))
+    .bind(catalog_id)
+    .bind(price_count)
+    .fetch_one(&mut **tx)
+    .await
+}
+
+pub async fn lookup_prices(db: &DbPool, query: &PriceQuery) -> Result<Vec<Price>, sqlx::Error> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+        "WITH catalogs AS (
+             SELECT DISTINCT ON (provider, name) id, provider, version FROM price_catalogs
+             WHERE TRUE",
+    );
+    if let Some(provider) = query.provider {
+        builder
+            .push(" AND provider = ")
+            .push_bind(provider.as_str());
+    }
+    if let Some(id) = query.catalog_id {
+        builder.push(" AND id = ").push_bind(id);
+    }
+    if let Some(version) = &query.version {
+        builder.push(" AND version = ").push_bind(version);
+    }
+    builder.push(
+        " ORDER BY provider, name, loaded_at DESC
+         )
+         SELECT p.id, p.catalog_id, c.version AS catalog_version, c.provider, p.service, p.sku,
+                p.product_family, p.region, p.description, p.price_type, p.term, p.unit,
+                p.currency, p.effective_from, p.tiers, p.attributes
+         FROM prices p
+         JOIN catalogs c ON c.id = p.catalog_id
+         WHERE TRUE",
+    );
+    if let Some(service) = &query.service {
+        builder.push(" AND p.service = ").push_bind(service);
+    }
+    if let Some(product_family) = &query.product_family {
+        builder
+            .push(" AND p.product_family = ")
+            .push_bind(product_family);
+    }
+    if let Some(sku) = &query.sku {
+        builder.push(" AND p.sku = ").push_bind(sku);
+    }
+    if let Some(region) = &query.region {
+        builder.push(" AND p.region = ").push_bind(region);
+    }
+    if let Some(price_type) = &query.price_type {
+        builder.push(" AND p.price_type = ").push_bind(price_type);
+    }
+    if !query.attributes.is_empty() {
+        builder
+            .push(" AND p.attributes @> ")
+            .push_bind(Json(&query.attributes));
+    }
+    builder
+        .push(" ORDER BY p.service, p.sku, p.price_type, p.term NULLS FIRST, p.unit LIMIT ")
+        .push_bind(query.limit);
+
+    let rows: Vec<PriceRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(rows.into_iter().map(Price::from).collect())
+}
@@ -0,0 +1,19 @@
+//! Cost optimization recommendations
+//!
+//! Waste detection runs rules over the resource inventory and the spend
+//! linked to it, keeping one finding per resource and rule. Findings are
+//! refreshed by each evaluation, resolved once their condition no longer
+//! holds, and can be snoozed or dismissed with a reason.
+//!
+//! Rightsizing compares running instances' utilization with the instance
+//! types of the pricing catalog and is computed on request.
+
+pub mod model;
+pub mod rightsizing;
+pub mod store;
+pub mod waste;
+
+pub use model::{
+    FindingQuery, FindingStatus, Severity, WasteFinding, WasteRule, WasteSettings, WasteSummary,
+};
+pub use rightsizing::{RightsizingRecommendation, RightsizingRequest, Risk, SkippedInstance};
@@ -0,0 +1,291 @@
+//! Waste finding API and storage types
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+/// Condition a waste finding reports
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum WasteRule {
+    /// EBS volume not attached to any instance
+    UnattachedVolume,
+    /// Elastic IP not associated with an instance or network interface
+    IdleElasticIp,
+    /// Stopped instance still paying for its attached volumes
+    StoppedInstance,
+    /// EBS snapshot older than the configured age
+    OldSnapshot,
+    /// Load balancer without registered targets
+    IdleLoadBalancer,
+}
+
+impl WasteRule {
+    pub const ALL: [WasteRule; 5] = [
+        WasteRule::UnattachedVolume,
+        WasteRule::IdleElasticIp,
+        WasteRule::StoppedInstance,
+        WasteRule::OldSnapshot,
+        WasteRule::IdleLoadBalancer,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            WasteRule::UnattachedVolume => "unattached_volume",
+            WasteRule::IdleElasticIp => "idle_elastic_ip",
+            WasteRule::StoppedInstance => "stopped_instance",
+            WasteRule::OldSnapshot => "old_snapshot",
+            WasteRule::IdleLoadBalancer => "idle_load_balancer",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Option<Self> {
+        Self::ALL.into_iter().find(|rule| rule.as_str() == s)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Low,
+    Medium,
+    High,
+}
+
+impl Severity {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Severity::Low => "low",
+            Severity::Medium => "medium",
+            Severity::High => "high",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "high" => Severity::High,
+            "medium" => Severity::Medium,
+            _ => Severity::Low,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum FindingStatus {
+    Open,
+    Snoozed,
+    Dismissed,
+    /// The condition no longer holds
+    Resolved,
+}
+
+impl FindingStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            FindingStatus::Open => "open",
+            FindingStatus::Snoozed => "snoozed",
+            FindingStatus::Dismissed => "dismissed",
+            FindingStatus::Resolved => "resolved",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "snoozed" => FindingStatus::Snoozed,
+            "dismissed" => FindingStatus::Dismissed,
+            "resolved" => FindingStatus::Resolved,
+            _ => FindingStatus::Open,
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WasteSettings {
+    /// Days a resource must have been idle before it is reported
+    #[validate(range(min = 0, max = 365))]
+    #[schema(minimum = 0, maximum = 365)]
+    pub idle_days: i32,
+    /// Snapshots older than this many days are reported
+    #[validate(range(min = 1, max = 3650))]
+    #[schema(minimum = 1, maximum = 3650)]
+    pub snapshot_age_days: i32,
+    /// Days of linked spend used to estimate savings
+    #[validate(range(min = 1, max = 90))]
+    #[schema(minimum = 1, maximum = 90)]
+    pub lookback_days: i32,
+    #[serde(default)]
+    pub disabled_rules: Vec<WasteRule>,
+}
+
+impl Default for WasteSettings {
+    fn default() -> Self {
+        WasteSettings {
+            idle_days: 7,
+            snapshot_age_days: 90,
+            lookback_days: 30,
+            disabled_rules: Vec::new(),
+        }
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct WasteSettingsRow {
+    pub idle_days: i32,
+    pub snapshot_age_days: i32,
+    pub lookback_days: i32,
+    pub disabled_rules: Vec<String>,
+}
+
+impl From<WasteSettingsRow> for WasteSettings {
+    fn from(row: WasteSettingsRow) -> Self {
+        WasteSettings {
+            idle_days: row.idle_days,
+            snapshot_age_days: row.snapshot_age_days,
+            lookback_days: row.lookback_days,
+            disabled_rules: row
+                .disabled_rules
+                .iter()
+                .filter_map(|rule| WasteRule::parse(rule))
+                .collect(),
+        }
+    }
+}
+
+/// A live resource with what detection needs to know about it
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct WasteCandidate {
+    pub id: Uuid,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub region: String,
+    pub state: Option<String>,
+    pub attributes: Json<Value>,
+    pub first_seen_at: DateTime<Utc>,
+    /// When the resource was first seen in its current state
+    pub state_since: Option<DateTime<Utc>>,
+    /// Linked spend over the lookback window
+    pub linked_cost: Option<f64>,
+    /// Hours from the first linked line item of the window to its end
+    pub linked_hours: Option<f64>,
+}
+
+/// A detected finding, before it is merged with stored findings
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewFinding {
+    pub resource_id: Uuid,
+    pub rule: WasteRule,
+    pub severity: Severity,
+    pub message: String,
+    pub estimated_monthly_savings: f64,
+    pub evidence: Value,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WasteFinding {
+    pub id: Uuid,
+    pub resource_id: Uuid,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub resource_name: Option<String>,
+    pub account_id: String,
+    pub region: String,
+    pub rule: WasteRule,
+    pub severity: Severity,
+    pub message: String,
+    pub estimated_monthly_savings: f64,
+    pub currency: String,
+    /// Observations the finding was based on
+    pub evidence: Value,
+    pub status: FindingStatus,
+    /// Why the finding was snoozed or dismissed
+    pub status_reason: Option<String>,
+    pub status_changed_by: Option<String>,
+    pub status_changed_at: Option<DateTime<Utc>>,
+    pub snoozed_until: Option<DateTime<Utc>>,
+    pub first_detected_at: DateTime<Utc>,
+    pub last_detected_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct WasteFindingRow {
+    pub id: Uuid,
+    pub resource_id: Uuid,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub resource_name: Option<String>,
+    pub account_id: String,
+    pub region: String,
+    pub rule: String,
+    pub severity: String,
+    pub message: String,
+    pub estimated_monthly_savings: f64,
+    pub currency: String,
+    pub evidence: Json<Value>,
+    pub status: String,
+    pub status_reason: Option<String>,
+    pub status_changed_by: Option<String>,
+    pub status_changed_at: Option<DateTime<Utc>>,
+    pub snoozed_until: Option<DateTime<Utc>>,
+    pub first_detected_at: DateTime<Utc>,
+    pub last_detected_at: DateTime<Utc>,
+    pub resolved_at: Option<DateTime<Utc>>,
+}
+
+impl From<WasteFindingRow> for WasteFinding {
+    fn from(row: WasteFindingRow) -> Self {
+        WasteFinding {
+            id: row.id,
+            resource_id: row.resource_id,
+            resource_type: row.resource_type,
+            provider_id: row.provider_id,
+            resource_name: row.resource_name,
+            account_id: row.account_id,
+            region: row.region,
+            // The column's CHECK constraint only admits known rules
+            rule: WasteRule::parse(&row.rule).unwrap_or(WasteRule::UnattachedVolume),
+            severity: Severity::parse(&row.severity),
+            message: row.message,
+            estimated_monthly_savings: row.estimated_monthly_savings,
+            currency: row.currency,
+            evidence: row.evidence.0,
+            status: FindingStatus::parse(&row.status),
+            status_reason: row.status_reason,
+            status_changed_by: row.status_changed_by,
+            status_changed_at: row.status_changed_at,
+            snoozed_until: row.snoozed_until,
+            first_detected_at: row.first_detected_at,
+            last_detected_at: row.last_detected_at,
+            resolved_at: row.resolved_at,
+        }
+    }
+}
+
+/// Filters of the findings list
+#[derive(Debug, Clone, Default, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct FindingQuery {
+    /// Defaults to open findings
+    pub status: Option<FindingStatus>,
+    pub rule: Option<WasteRule>,
+    pub severity: Option<Severity>,
+    pub resource_id: Option<Uuid>,
+}
+
+/// Totals of the findings matching a [`FindingQuery`]
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct WasteSummary {
+    pub finding_count: i64,
+    /// Sum of the findings' savings
+    pub estimated_monthly_savings: f64,
+    pub currency: String,
+}
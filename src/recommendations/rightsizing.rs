@@ -0,0 +1,412 @@
+//! Instance rightsizing
+//!
+//! Sizes each running EC2 instance to its p95 CPU and memory utilization
+//! plus headroom and proposes the cheapest on-demand instance type of the
+//! region that still fits, compared at list prices from the pricing catalog.
+
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::pricing::Price;
+use crate::utilization::{MetricStats, UtilizationMetric};
+
+/// Hours in an average month, as used by AWS pricing
+const HOURS_PER_MONTH: f64 = 730.0;
+
+/// Projected p95 CPU above which a change is no longer low risk
+const CPU_P95_CAUTION: f64 = 60.0;
+/// Projected p95 memory above which a change is no longer low risk
+const MEMORY_P95_CAUTION: f64 = 80.0;
+
+fn default_lookback_days() -> u32 {
+    14
+}
+
+fn default_headroom() -> f64 {
+    0.2
+}
+
+fn default_min_samples() -> u32 {
+    24
+}
+
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RightsizingRequest {
+    #[serde(default = "default_lookback_days")]
+    #[validate(range(min = 1, max = 90))]
+    #[schema(minimum = 1, maximum = 90)]
+    pub lookback_days: u32,
+    /// Capacity kept above p95 utilization, e.g. `0.2` for 20%
+    #[serde(default = "default_headroom")]
+    #[validate(range(min = 0.0, max = 1.0))]
+    #[schema(minimum = 0.0, maximum = 1.0)]
+    pub headroom: f64,
+    /// CPU samples an instance needs in the window to be sized
+    #[serde(default = "default_min_samples")]
+    #[validate(range(min = 1, max = 100000))]
+    #[schema(minimum = 1, maximum = 100000)]
+    pub min_samples: u32,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Risk {
+    Low,
+    Medium,
+    High,
+}
+
+/// A running instance and its utilization over the window
+#[derive(Debug, Clone)]
+pub struct RightsizingCandidate {
+    pub resource_id: Uuid,
+    pub provider_id: String,
+    pub name: Option<String>,
+    pub region: String,
+    pub instance_type: String,
+    pub stats: Vec<MetricStats>,
+}
+
+/// An on-demand instance type as priced in the catalog
+#[derive(Debug, Clone, PartialEq)]
+pub struct InstanceOffer {
+    pub instance_type: String,
+    pub vcpu: f64,
+    pub memory_gib: f64,
+    /// Sustained network bandwidth, when the catalog states it
+    pub network_gbps: Option<f64>,
+    pub arm: bool,
+    pub accelerated: bool,
+    pub hourly: f64,
+    pub currency: String,
+}
+
+impl InstanceOffer {
+    /// Read an offer from an hourly on-demand EC2 price
+    pub fn from_price(price: &Price) -> Option<Self> {
+        let attributes = &price.attributes;
+        let number = |key: &str| -> Option<f64> {
+            attributes
+                .get(key)?
+                .split_whitespace()
+                .next()?
+                .replace(',', "")
+                .parse()
+                .ok()
+        };
+        let hourly = price.tiers.first()?.unit_price;
+        if hourly <= 0.0 {
+            return None;
+        }
+        Some(InstanceOffer {
+            instance_type: attributes.get("instanceType")?.clone(),
+            vcpu: number("vcpu")?,
+            memory_gib: number("memory")?,
+            network_gbps: attributes
+                .get("networkPerformance")
+                .and_then(|n| network_gbps(n)),
+            arm: attributes
+                .get("physicalProcessor")
+                .is_some_and(|p| p.contains("Graviton")),
+            accelerated: ["gpu", "fpga", "inferentia", "trainium"]
+                .iter()
+                .any(|key| number(key).is_some_and(|n| n > 0.0)),
+            hourly,
+            currency: price.currency.clone(),
+        })
+    }
+
+    fn family(&self) -> &str {
+        self.instance_type
+            .split_once('.')
+            .map_or(&self.instance_type, |(family, _)| family)
+    }
+
+    fn burstable(&self) -> bool {
+        let family = self.family();
+        family.starts_with('t') && family[1..].starts_with(|c: char| c.is_ascii_digit())
+    }
+
+    /// Types not offered as general replacements: Mac hosts and bare metal
+    fn special(&self) -> bool {
+        self.family().starts_with("mac") || self.instance_type.ends_with(".metal")
+    }
+}
+
+/// Bandwidth of a catalog `networkPerformance` value such as `25 Gigabit`,
+/// `Up to 10 Gigabit` or `Moderate`
+fn network_gbps(performance: &str) -> Option<f64> {
+    let performance = performance.trim();
+    if let Some(amount) = performance.strip_suffix("Gigabit") {
+        let amount = amount.trim();
+        let amount = amount.strip_prefix("Up to").unwrap_or(amount).trim();
+        return amount.parse().ok();
+    }
+    match performance {
+        "Very Low" => Some(0.05),
+        "Low" => Some(0.1),
+        "Low to Moderate" => Some(0.3),
+        "Moderate" => Some(0.5),
+        "High" => Some(1.0),
+        _ => None,
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct InstanceSizing {
+    pub instance_type: String,
+    pub vcpu: f64,
+    pub memory_gib: f64,
+    pub hourly_cost: f64,
+    pub monthly_cost: f64,
+}
+
+impl From<&InstanceOffer> for InstanceSizing {
+    fn from(offer: &InstanceOffer) -> Self {
+        InstanceSizing {
+            instance_type: offer.instance_type.clone(),
+            vcpu: offer.vcpu,
+            memory_gib: offer.memory_gib,
+            hourly_cost: offer.hourly,
+            monthly_cost: offer.hourly * HOURS_PER_MONTH,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RightsizingRecommendation {
+    pub resource_id: Uuid,
+    pub provider_id: String,
+    pub resource_name: Option<String>,
+    pub region: String,
+    pub current: InstanceSizing,
+    pub proposed: InstanceSizing,
+    pub estimated_monthly_savings: f64,
+    pub currency: String,
+    pub risk: Risk,
+    /// Utilization over the window the sizing is based on
+    pub utilization: Vec<MetricStats>,
+    /// Expected p95 and peak CPU on the proposed type
+    pub projected_cpu_p95: f64,
+    pub projected_cpu_max: f64,
+    /// Expected p95 memory on the proposed type, when memory is measured
+    pub projected_memory_p95: Option<f64>,
+    pub notes: Vec<String>,
+}
+
+/// An instance no recommendation was made for, and why
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SkippedInstance {
+    pub resource_id: Uuid,
+    pub provider_id: String,
+    pub instance_type: String,
+    pub reason: String,
+}
+
+fn stat(stats: &[MetricStats], metric: UtilizationMetric) -> Option<&MetricStats> {
+    stats.iter().find(|s| s.metric == metric)
+}
+
+/// Size one instance against the offers of its region
+pub fn recommend(
+    candidate: &RightsizingCandidate,
+    offers: &[InstanceOffer],
+    request: &RightsizingRequest,
+) -> Result<RightsizingRecommendation, String> {
+    let scale = 1.0 + request.headroom;
+
+    let cpu = stat(&candidate.stats, UtilizationMetric::Cpu)
+        .filter(|cpu| cpu.samples >= i64::from(request.min_samples))
+        .ok_or_else(|| {
+            format!(
+                "needs at least {} CPU samples in the last {} days",
+                request.min_samples, request.lookback_days
+            )
+        })?;
+    let memory = stat(&candidate.stats, UtilizationMetric::Memory);
+    let current = offers
+        .iter()
+        .find(|offer| offer.instance_type == candidate.instance_type)
+        .ok_or_else(|| {
+            format!(
+                "no on-demand price for {} in {}",
+                candidate.instance_type, candidate.region
+            )
+        })?;
+    if current.accelerated {
+        return Err("accelerated instances are not rightsized".into());
+    }
+
+    let required_vcpu = current.vcpu * cpu.p95 / 100.0 * scale;
+    let required_memory = match memory {
+        Some(memory) => current.memory_gib * memory.p95 / 100.0 * scale,
+        None => current.memory_gib,
+    };
+    // Network statistics are bytes per second; compare them in gigabits
+    let required_gbps = [UtilizationMetric::NetworkIn, UtilizationMetric::NetworkOut]
+        .into_iter()
+        .filter_map(|metric| stat(&candidate.stats, metric))
+        .map(|s| s.p95 * 8.0 / 1e9 * scale)
+        .fold(0.0, f64::max);
+
+    let proposed = offers
+        .iter()
+        .filter(|offer| {
+            offer.hourly < current.hourly
+                && offer.arm == current.arm
+                && !offer.accelerated
+                && !offer.special()
+                && (current.burstable() || !offer.burstable())
+                && offer.vcpu >= required_vcpu
+                && offer.memory_gib >= required_memory
+                && offer.network_gbps.is_none_or(|gbps| gbps >= required_gbps)
+        })
+        .min_by(|a, b| {
+            a.hourly
+                .total_cmp(&b.hourly)
+                .then(b.vcpu.total_cmp(&a.vcpu))
+                .then(a.instance_type.cmp(&b.instance_type))
+        })
+        .ok_or_else(|| "no cheaper instance type fits the observed utilization".to_string())?;
+
+    let projected_cpu_p95 = cpu.p95 * current.vcpu / proposed.vcpu;
+    let projected_cpu_max = cpu.maximum * current.vcpu / proposed.vcpu;
+    let projected_memory_p95 = memory.map(|m| m.p95 * current.memory_gib / proposed.memory_gib);
+
+    let mut notes = Vec::new();
+    if memory.is_none() {
+        notes.push(
+            "memory utilization is not measured; the proposal keeps at least the current memory"
+                .to_string(),
+        );
+    }
+    if proposed.burstable() {
+        notes.push("burstable type: sustained load above its baseline uses CPU credits".into());
+    }
+    if projected_cpu_max > 100.0 {
+        notes.push(format!(
+            "peak CPU of {:.0}% would exceed the proposed type's capacity",
+            projected_cpu_max
+        ));
+    }
+
+    let risk = if projected_cpu_max > 100.0 {
+        Risk::High
+    } else if memory.is_none()
+        || projected_cpu_p95 > CPU_P95_CAUTION
+        || projected_memory_p95.is_some_and(|m| m > MEMORY_P95_CAUTION)
+    {
+        Risk::Medium
+    } else {
+        Risk::Low
+    };
+
+    Ok(RightsizingRecommendation {
+        resource_id: candidate.resource_id,
+        provider_id: candidate.provider_id.clone(),
+        resource_name: candidate.name.clone(),
+        region: candidate.region.clone(),
+        current: current.into(),
+        proposed: proposed.into(),
+        estimated_monthly_savings: (current.hourly - proposed.hourly) * HOURS_PER_MONTH,
+        currency: current.currency.clone(),
+        risk,
+        utilization: candidate.stats.clone(),
+        projected_cpu_p95,
+        projected_cpu_max,
+        projected_memory_p95,
+        notes,
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::Utc;
+
+    use super::*;
+
+    fn offer(instance_type: &str, vcpu: f64, memory_gib: f64, hourly: f64) -> InstanceOffer {
+        InstanceOffer {
+            instance_type: instance_type.to_string(),
+            vcpu,
+            memory_gib,
+            network_gbps: Some(10.0),
+            arm: instance_type.contains("g."),
+            accelerated: false,
+            hourly,
+            currency: "USD".to_string(),
+        }
+    }
+
+    fn stats(metric: UtilizationMetric, p95: f64, maximum: f64) -> MetricStats {
+        MetricStats {
+            metric,
+            samples: 336,
+            average: p95 / 2.0,
+            p50: p95 / 2.0,
+            p95,
+            maximum,
+            first: Utc::now(),
+            last: Utc::now(),
+        }
+    }
+
+    #[test]
+    fn test_recommend() {
+        let offers = vec![
+            offer("m5.2xlarge", 8.0, 32.0, 0.384),
+            offer("m5.xlarge", 4.0, 16.0, 0.192),
+            offer("m5.large", 2.0, 8.0, 0.096),
+            offer("c5.large", 2.0, 4.0, 0.085),
+            offer("m6g.large", 2.0, 8.0, 0.077),
+            offer("t3.large", 2.0, 8.0, 0.0832),
+        ];
+        let request = RightsizingRequest {
+            lookback_days: 14,
+            headroom: 0.2,
+            min_samples: 24,
+        };
+        let mut candidate = RightsizingCandidate {
+            resource_id: Uuid::nil(),
+            provider_id: "i-0abc".to_string(),
+            name: None,
+            region: "us-east-1".to_string(),
+            instance_type: "m5.2xlarge".to_string(),
+            stats: vec![
+                stats(UtilizationMetric::Cpu, 15.0, 20.0),
+                stats(UtilizationMetric::Memory, 20.0, 25.0),
+            ],
+        };
+
+        // 8 vCPU at 15% needs 1.44 vCPU, 32 GiB at 20% needs 7.68 GiB; the
+        // Graviton and burstable types are left out and c5 lacks memory
+        let rec = recommend(&candidate, &offers, &request).unwrap();
+        assert_eq!(rec.proposed.instance_type, "m5.large");
+        assert!((rec.estimated_monthly_savings - 0.288 * 730.0).abs() < 1e-9);
+        assert_eq!(rec.projected_cpu_p95, 60.0);
+        assert_eq!(rec.risk, Risk::Low);
+
+        // A rare spike that would saturate the smaller type raises the risk
+        candidate.stats[0] = stats(UtilizationMetric::Cpu, 15.0, 90.0);
+        let rec = recommend(&candidate, &offers, &request).unwrap();
+        assert_eq!(rec.risk, Risk::High);
+
+        // Without memory data the current memory is kept
+        candidate.instance_type = "m5.xlarge".to_string();
+        candidate.stats = vec![stats(UtilizationMetric::Cpu, 10.0, 20.0)];
+        assert!(recommend(&candidate, &offers, &request).is_err());
+        candidate.instance_type = "m5.2xlarge".to_string();
+        assert!(recommend(&candidate, &offers, &request).is_err());
+
+        candidate.stats[0].samples = 10;
+        assert!(recommend(&candidate, &offers, &request)
+            .unwrap_err()
+            .contains("CPU samples"));
+    }
+}
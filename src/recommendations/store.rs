@@ -0,0 +1,504 @@
+//! Waste settings, detection inputs and stored findings, and rightsizing inputs
+
+use std::collections::{BTreeMap, BTreeSet, HashMap};
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::types::Json;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use super::model::{
+    FindingQuery, FindingStatus, WasteCandidate, WasteFinding, WasteFindingRow, WasteSettings,
+    WasteSettingsRow, WasteSummary,
+};
+use super::rightsizing::{
+    self, InstanceOffer, RightsizingCandidate, RightsizingRecommendation, RightsizingRequest,
+    SkippedInstance,
+};
+use super::waste;
+use crate::costs::Provider;
+use crate::db::DbPool;
+use crate::pagination::{Field, FieldKind, Listing, Page, PageQuery};
+use crate::pricing::{self, PriceQuery};
+use crate::utilization;
+
+/// Resource types some waste rule applies to
+const CANDIDATE_TYPES: &[&str] = &[
+    "ebs_volume",
+    "elastic_ip",
+    "ec2_instance",
+    "ebs_snapshot",
+    "load_balancer",
+];
+
+/// Status as seen by users: a snooze ends by itself once it expires
+const EFFECTIVE_STATUS: &str =
+    "CASE WHEN f.status = 'snoozed' AND f.snoozed_until <= NOW() THEN 'open' ELSE f.status END";
+
+/// The organization's waste settings, or the defaults if never saved
+pub async fn get_settings(db: &DbPool, org_id: &str) -> Result<WasteSettings, sqlx::Error> {
+    let row: Option<WasteSettingsRow> = sqlx::query_as(
+        "SELECT idle_days, snapshot_age_days, lookback_days, disabled_rules
+         FROM waste_settings WHERE organization_id = $1",
+    )
+    .bind(org_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(WasteSettings::from).unwrap_or_default())
+}
+
+pub async fn put_settings(
+    db: &DbPool,
+    org_id: &str,
+    settings: &WasteSettings,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO waste_settings
+             (organization_id, idle_days, snapshot_age_days, lookback_days, disabled_rules)
+         VALUES ($1, $2, $3, $4, $5)
+         ON CONFLICT (organization_id) DO UPDATE
+         SET idle_days = EXCLUDED.idle_days,
+             snapshot_age_days = EXCLUDED.snapshot_age_days,
+             lookback_days = EXCLUDED.lookback_days,
+             disabled_rules = EXCLUDED.disabled_rules",
+    )
+    .bind(org_id)
+    .bind(settings.idle_days)
+    .bind(settings.snapshot_age_days)
+    .bind(settings.lookback_days)
+    .bind(
+        settings
+            .disabled_rules
+            .iter()
+            .map(|rule| rule.as_str())
+            .collect::<Vec<_>>(),
+    )
+    .execute(db)
+    .await?;
+
+    Ok(())
+}
+
+/// Live resources of the types rules look at, with when they entered their
+/// current state and their linked spend since `since`
+async fn candidates(
+    db: &DbPool,
+    org_id: &str,
+    since: DateTime<Utc>,
+    now: DateTime<Utc>,
+) -> Result<Vec<WasteCandidate>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT r.id, r.resource_type, r.provider_id, r.region, r.state, r.attributes,
+                r.first_seen_at,
+                (SELECT MAX(v.observed_at) FROM resource_versions v
+                 WHERE v.resource_id = r.id
+                   AND (v.change_kind IN ('created', 'restored')
+                        OR jsonb_path_exists(v.changes,
+                               '$[*] ? (@.field == \"state\"
+                                        || @.field == \"attributes.registeredTargetCount\")'))
+                ) AS state_since,
+                c.cost AS linked_cost, c.hours AS linked_hours
+         FROM resources r
+         LEFT JOIN LATERAL (
+             SELECT SUM(l.cost) AS cost,
+                    EXTRACT(EPOCH FROM ($3 - MIN(l.usage_start)))::float8 / 3600 AS hours
+             FROM cost_line_items l
+             WHERE l.linked_resource_id = r.id AND l.usage_start >= $2 AND l.usage_start < $3
+         ) c ON TRUE
+         WHERE r.organization_id = $1 AND r.deleted_at IS NULL AND r.resource_type = ANY($4)",
+    )
+    .bind(org_id)
+    .bind(since)
+    .bind(now)
+    .bind(CANDIDATE_TYPES)
+    .fetch_all(db)
+    .await
+}
+
+/// Catalog list prices of the regions of `candidates`, for waste savings
+async fn region_prices(
+    db: &DbPool,
+    candidates: &[WasteCandidate],
+) -> Result<HashMap<String, waste::RegionPrices>, sqlx::Error> {
+    // Services and product families holding the prices waste rules use
+    const LOOKUPS: [(&str, Option<&str>); 4] = [
+        ("AmazonEC2", Some("Storage")),
+        ("AmazonEC2", Some("Storage Snapshot")),
+        ("AmazonVPC", None),
+        ("AWSELB", None),
+    ];
+
+    let regions: BTreeSet<&str> = candidates.iter().map(|c| c.region.as_str()).collect();
+    let mut by_region = HashMap::new();
+    for region in regions {
+        let mut prices = Vec::new();
+        for (service, product_family) in LOOKUPS {
+            let query = PriceQuery {
+                provider: Some(Provider::Aws),
+                service: Some(service.to_string()),
+                product_family: product_family.map(str::to_string),
+                region: Some(region.to_string()),
+                price_type: Some("OnDemand".to_string()),
+                limit: PriceQuery::MAX_LIMIT,
+                ..PriceQuery::default()
+            };
+            prices.extend(pricing::store::lookup_prices(db, &query).await?);
+        }
+        by_region.insert(
+            region.to_string(),
+            waste::RegionPrices::from_prices(&prices),
+        );
+    }
+    Ok(by_region)
+}
+
+/// Run the waste rules over the organization's inventory as of `now`.
+/// Detected findings are stored or refreshed and findings no longer
+/// detected are resolved. Returns the findings that are newly open,
+/// including resolved and expired snoozed findings detected again.
+pub async fn evaluate(
+    db: &DbPool,
+    org_id: &str,
+    now: DateTime<Utc>,
+) -> Result<Vec<WasteFinding>, sqlx::Error> {
+    const CHUNK_SIZE: usize = 500;
+
+    let settings = get_settings(db, org_id).await?;
+    let since = now - Duration::days(i64::from(settings.lookback_days));
+    let candidates = candidates(db, org_id, since, now).await?;
+    let prices = region_prices(db, &candidates).await?;
+    let detected = waste::detect(&candidates, &settings, &prices, now);
+
+    let mut tx = db.begin().await?;
+
+    // Stored and effective statuses before this evaluation, to tell which
+    // findings it opens
+    let previous: HashMap<(Uuid, String), (String, String)> =
+        sqlx::query_as::<_, (Uuid, String, String, String)>(&format!(
+            "SELECT f.resource_id, f.rule, f.status, {} FROM waste_findings f
+             WHERE f.organization_id = $1
+             FOR UPDATE",
+            EFFECTIVE_STATUS
+        ))
+        .bind(org_id)
+        .fetch_all(&mut *tx)
+        .await?
+        .into_iter()
+        .map(|(resource_id, rule, status, effective)| ((resource_id, rule), (status, effective)))
+        .collect();
+
+    let mut opened = Vec::new();
+    for chunk in detected.chunks(CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO waste_findings AS f (organization_id, resource_id, rule, severity, \
+             message, estimated_monthly_savings, evidence, first_detected_at, last_detected_at) ",
+        );
+        builder.push_values(chunk, |mut row, finding| {
+            row.push_bind(org_id)
+                .push_bind(finding.resource_id)
+                .push_bind(finding.rule.as_str())
+                .push_bind(finding.severity.as_str())
+                .push_bind(&finding.message)
+                .push_bind(finding.estimated_monthly_savings)
+                .push_bind(Json(&finding.evidence))
+                .push_bind(now)
+                .push_bind(now);
+        });
+        builder.push(format!(
+            " ON CONFLICT (resource_id, rule) DO UPDATE SET
+                 severity = EXCLUDED.severity,
+                 message = EXCLUDED.message,
+                 estimated_monthly_savings = EXCLUDED.estimated_monthly_savings,
+                 evidence = EXCLUDED.evidence,
+                 last_detected_at = EXCLUDED.last_detected_at,
+                 resolved_at = NULL,
+                 status = CASE WHEN ({status}) IN ('open', 'resolved') THEN 'open'
+                               ELSE f.status END,
+                 snoozed_until = CASE WHEN ({status}) = 'open' THEN NULL
+                                      ELSE f.snoozed_until END
+             RETURNING f.resource_id, f.rule, f.id",
+            status = EFFECTIVE_STATUS
+        ));
+        let rows: Vec<(Uuid, String, Uuid)> = builder.build_query_as().fetch_all(&mut *tx).await?;
+        opened.extend(rows.into_iter().filter_map(|(resource_id, rule, id)| {
+            let opens = match previous.get(&(resource_id, rule)) {
+                None => true,
+                Some((status, effective)) => status != effective || status == "resolved",
+            };
+            opens.then_some(id)
+        }));
+    }
+
+    sqlx::query(
+        "UPDATE waste_findings
+         SET status = 'resolved', resolved_at = $2, snoozed_until = NULL
+         WHERE organization_id = $1 AND status IN ('open', 'snoozed') AND last_detected_at < $2",
+    )
+    .bind(org_id)
+    .bind(now)
+    .execute(&mut *tx)
+    .await?;
+
+    tx.commit().await?;
+
+    list_findings_by_id(db, org_id, &opened).await
+}
+
+const FINDING_SELECT: &str = "SELECT f.id, f.resource_id, r.resource_type, r.provider_id, \
+     r.name AS resource_name, r.account_id, r.region, f.rule, f.severity, f.message, \
+     f.estimated_monthly_savings, f.currency, f.evidence, f.status_reason, f.status_changed_by, \
+     f.status_changed_at, f.snoozed_until, f.first_detected_at, f.last_detected_at, \
+     f.resolved_at, ";
+
+fn finding_query(org_id: &str) -> QueryBuilder<'_, Postgres> {
+    let mut builder = QueryBuilder::new(format!(
+        "{}{} AS status FROM waste_findings f JOIN resources r ON r.id = f.resource_id \
+         WHERE f.organization_id = ",
+        FINDING_SELECT, EFFECTIVE_STATUS
+    ));
+    builder.push_bind(org_id);
+    builder
+}
+
+/// Sort and filter fields of the findings list. Columns are those of the
+/// derived table [`matching_findings`] selects from.
+pub const LISTING: Listing = Listing {
+    fields: &[
+        Field::new(
+            "estimatedMonthlySavings",
+            "estimated_monthly_savings",
+            FieldKind::Number,
+        ),
+        Field::new("firstDetectedAt", "first_detected_at", FieldKind::Timestamp),
+        Field::new("lastDetectedAt", "last_detected_at", FieldKind::Timestamp),
+        Field::new("resourceType", "resource_type", FieldKind::Text),
+        Field::new("accountId", "account_id", FieldKind::Text),
+        Field::new("region", "region", FieldKind::Text),
+        Field::new("resolvedAt", "resolved_at", FieldKind::Timestamp).filter_only(),
+    ],
+    default_sort: "-estimatedMonthlySavings",
+};
+
+/// `<select> FROM` the findings matching the query, with their effective
+/// status, as a derived table whose columns need no qualifying
+fn matching_findings<'a>(
+    select: &str,
+    org_id: &'a str,
+    query: &FindingQuery,
+) -> QueryBuilder<'a, Postgres> {
+    let mut builder = QueryBuilder::new(format!(
+        "{} FROM ({}{} AS status FROM waste_findings f JOIN resources r ON r.id = f.resource_id \
+         WHERE f.organization_id = ",
+        select, FINDING_SELECT, EFFECTIVE_STATUS
+    ));
+    builder.push_bind(org_id);
+    builder
+        .push(") findings WHERE status = ")
+        .push_bind(query.status.unwrap_or(FindingStatus::Open).as_str());
+    if let Some(rule) = query.rule {
+        builder.push(" AND rule = ").push_bind(rule.as_str());
+    }
+    if let Some(severity) = query.severity {
+        builder
+            .push(" AND severity = ")
+            .push_bind(severity.as_str());
+    }
+    if let Some(resource_id) = query.resource_id {
+        builder.push(" AND resource_id = ").push_bind(resource_id);
+    }
+    builder
+}
+
+/// A page of the findings matching the query
+pub async fn list_findings(
+    db: &DbPool,
+    org_id: &str,
+    query: &FindingQuery,
+    page: &PageQuery,
+) -> Result<Page<WasteFinding>, sqlx::Error> {
+    let mut builder = matching_findings("SELECT *", org_id, query);
+    page.push_conditions(&mut builder);
+    page.push_order_and_limit(&mut builder);
+
+    let rows: Vec<WasteFindingRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(page.page(rows.into_iter().map(WasteFinding::from).collect()))
+}
+
+/// Count and savings of all findings matching the query
+pub async fn summarize_findings(
+    db: &DbPool,
+    org_id: &str,
+    query: &FindingQuery,
+) -> Result<WasteSummary, sqlx::Error> {
+    let (finding_count, estimated_monthly_savings): (i64, f64) = matching_findings(
+        "SELECT COUNT(*), COALESCE(SUM(estimated_monthly_savings), 0)::float8",
+        org_id,
+        query,
+    )
+    .build_query_as()
+    .fetch_one(db)
+    .await?;
+
+    Ok(WasteSummary {
+        finding_count,
+        estimated_monthly_savings,
+        currency: "USD".to_string(),
+    })
+}
+
+async fn list_findings_by_id(
+    db: &DbPool,
+    org_id: &str,
+    ids: &[Uuid],
+) -> Result<Vec<WasteFinding>, sqlx::Error> {
+    if ids.is_empty() {
+        return Ok(Vec::new());
+    }
+    let mut builder = finding_query(org_id);
+    builder
+        .push(" AND f.id = ANY(")
+        .push_bind(ids)
+        .push(") ORDER BY f.estimated_monthly_savings DESC, f.id");
+
+    let rows: Vec<WasteFindingRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(rows.into_iter().map(WasteFinding::from).collect())
+}
+
+pub async fn get_finding(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<WasteFinding>, sqlx::Error> {
+    let mut builder = finding_query(org_id);
+    builder.push(" AND f.id = ").push_bind(id);
+
+    let row: Option<WasteFindingRow> = builder.build_query_as().fetch_optional(db).await?;
+    Ok(row.map(WasteFinding::from))
+}
+
+/// Snooze, dismiss or reopen a finding on behalf of `user_id`. Returns
+/// `None` if no such finding exists.
+pub async fn set_status(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    status: FindingStatus,
+    snoozed_until: Option<DateTime<Utc>>,
+    reason: Option<&str>,
+    user_id: &str,
+) -> Result<Option<WasteFinding>, sqlx::Error> {
+    let updated = sqlx::query(
+        "UPDATE waste_findings
+         SET status = $3, snoozed_until = $4, status_reason = $5, status_changed_by = $6,
+             status_changed_at = NOW()
+         WHERE organization_id = $1 AND id = $2",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(status.as_str())
+    .bind(snoozed_until)
+    .bind(reason)
+    .bind(user_id)
+    .execute(db)
+    .await?;
+
+    if updated.rows_affected() == 0 {
+        return Ok(None);
+    }
+    get_finding(db, org_id, id).await
+}
+
+#[derive(sqlx::FromRow)]
+struct InstanceRow {
+    id: Uuid,
+    provider_id: String,
+    name: Option<String>,
+    region: String,
+    instance_type: String,
+    windows: bool,
+}
+
+/// Size the organization's running EC2 instances to their utilization over
+/// the request's lookback window ending at `now`. Returns recommendations,
+/// largest savings first, and the instances left out with the reason why.
+pub async fn rightsize(
+    db: &DbPool,
+    org_id: &str,
+    request: &RightsizingRequest,
+    now: DateTime<Utc>,
+) -> Result<(Vec<RightsizingRecommendation>, Vec<SkippedInstance>), sqlx::Error> {
+    let instances: Vec<InstanceRow> = sqlx::query_as(
+        "SELECT id, provider_id, name, region, attributes->>'instanceType' AS instance_type,
+                COALESCE(attributes->>'platform' = 'windows', FALSE) AS windows
+         FROM resources
+         WHERE organization_id = $1 AND deleted_at IS NULL AND resource_type = 'ec2_instance'
+           AND state = 'running' AND attributes ? 'instanceType'
+         ORDER BY region, provider_id",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    let ids: Vec<Uuid> = instances.iter().map(|i| i.id).collect();
+    let start = now - Duration::days(i64::from(request.lookback_days));
+    let mut stats = utilization::store::stats(db, &ids, start, now).await?;
+
+    let mut offers: HashMap<(String, bool), Vec<InstanceOffer>> = HashMap::new();
+    let mut recommendations = Vec::new();
+    let mut skipped = Vec::new();
+    for instance in instances {
+        let key = (instance.region.clone(), instance.windows);
+        if !offers.contains_key(&key) {
+            let query = PriceQuery {
+                provider: Some(Provider::Aws),
+                service: Some("AmazonEC2".to_string()),
+                region: Some(instance.region.clone()),
+                price_type: Some("OnDemand".to_string()),
+                attributes: BTreeMap::from([
+                    (
+                        "operatingSystem".to_string(),
+                        if instance.windows { "Windows" } else { "Linux" }.to_string(),
+                    ),
+                    ("tenancy".to_string(), "Shared".to_string()),
+                    ("preInstalledSw".to_string(), "NA".to_string()),
+                    ("capacitystatus".to_string(), "Used".to_string()),
+                ]),
+                // Every instance type of the region
+                limit: 10_000,
+                ..PriceQuery::default()
+            };
+            let region_offers = pricing::store::lookup_prices(db, &query)
+                .await?
+                .iter()
+                .filter(|price| price.unit == "Hrs")
+                .filter_map(InstanceOffer::from_price)
+                .collect();
+            offers.insert(key.clone(), region_offers);
+        }
+
+        let candidate = RightsizingCandidate {
+            resource_id: instance.id,
+            provider_id: instance.provider_id,
+            name: instance.name,
+            region: instance.region,
+            instance_type: instance.instance_type,
+            stats: stats.remove(&instance.id).unwrap_or_default(),
+        };
+        match rightsizing::recommend(&candidate, &offers[&key], request) {
+            Ok(recommendation) => recommendations.push(recommendation),
+            Err(reason) => skipped.push(SkippedInstance {
+                resource_id: candidate.resource_id,
+                provider_id: candidate.provider_id,
+                instance_type: candidate.instance_type,
+                reason,
+            }),
+        }
+    }
+
+    recommendations.sort_by(|a, b| {
+        b.estimated_monthly_savings
+            .total_cmp(&a.estimated_monthly_savings)
+    });
+    Ok((recommendations, skipped))
+}
@@ -0,0 +1,576 @@
+//! Waste detection rules
+//!
+//! Each rule looks at one resource type of the inventory. Savings are the
+//! resource's linked spend over the lookback window scaled to a month, or,
+//! when nothing is linked yet, an estimate from the pricing catalog's list
+//! prices for the resource's region. Without a catalog price, us-east-1
+//! list prices are the last resort, which the finding's evidence notes.
+
+use std::collections::HashMap;
+
+use chrono::{DateTime, NaiveDateTime, Utc};
+use serde_json::{json, Value};
+
+use super::model::{NewFinding, Severity, WasteCandidate, WasteRule, WasteSettings};
+use crate::pricing::Price;
+
+/// Hours in an average month, as used by AWS pricing
+const HOURS_PER_MONTH: f64 = 730.0;
+
+/// Region of the default list prices below, used when the pricing catalog
+/// has no price for a resource's region
+const DEFAULT_PRICE_REGION: &str = "us-east-1";
+
+/// EBS storage list prices per GB-month by volume type
+const VOLUME_GB_MONTH: &[(&str, f64)] = &[
+    ("gp2", 0.10),
+    ("gp3", 0.08),
+    ("io1", 0.125),
+    ("io2", 0.125),
+    ("st1", 0.045),
+    ("sc1", 0.015),
+    ("standard", 0.05),
+];
+const SNAPSHOT_GB_MONTH: f64 = 0.05;
+const PUBLIC_IPV4_HOUR: f64 = 0.005;
+const LOAD_BALANCER_HOUR: f64 = 0.0225;
+
+/// Monthly savings from which findings are of medium and high severity
+const MEDIUM_SAVINGS: f64 = 20.0;
+const HIGH_SAVINGS: f64 = 100.0;
+
+/// List prices of one region from the pricing catalog, for the resources
+/// waste rules estimate
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct RegionPrices {
+    /// EBS storage per GB-month by volume type
+    pub volume_gb_month: HashMap<String, f64>,
+    pub snapshot_gb_month: Option<f64>,
+    /// Public IPv4 address not associated with anything, per hour
+    pub public_ipv4_hour: Option<f64>,
+    /// Load balancer hours by type, e.g. `application`
+    pub load_balancer_hour: HashMap<String, f64>,
+}
+
+impl RegionPrices {
+    /// Pick the rates waste rules use out of a region's on-demand prices
+    pub fn from_prices(prices: &[Price]) -> Self {
+        let mut rates = RegionPrices::default();
+        for price in prices {
+            let Some(rate) = price.tiers.first().map(|tier| tier.unit_price) else {
+                continue;
+            };
+            let usage_type = price.attributes.get("usagetype").map_or("", String::as_str);
+            match (
+                price.service.as_str(),
+                price.product_family.as_deref(),
+                price.unit.as_str(),
+            ) {
+                ("AmazonEC2", Some("Storage"), "GB-Mo") => {
+                    if let Some(volume_type) = price.attributes.get("volumeApiName") {
+                        rates.volume_gb_month.insert(volume_type.clone(), rate);
+                    }
+                }
+                ("AmazonEC2", Some("Storage Snapshot"), "GB-Mo")
+                    if usage_type.ends_with("EBS:SnapshotUsage") =>
+                {
+                    rates.snapshot_gb_month = Some(rate);
+                }
+                ("AmazonVPC", _, "Hrs") if usage_type.ends_with("PublicIPv4:IdleAddress") => {
+                    rates.public_ipv4_hour = Some(rate);
+                }
+                ("AWSELB", Some(family), "Hrs") if usage_type.ends_with("LoadBalancerUsage") => {
+                    if let Some(kind) = family.strip_prefix("Load Balancer-") {
+                        rates.load_balancer_hour.insert(kind.to_lowercase(), rate);
+                    }
+                }
+                _ => {}
+            }
+        }
+        rates
+    }
+}
+
+/// Estimated monthly cost of a resource and where it came from
+struct Estimate {
+    monthly: f64,
+    basis: &'static str,
+}
+
+impl Estimate {
+    /// Cost of `quantity` at the catalog's rate, or else at the default one
+    fn listed(catalog_rate: Option<f64>, default_rate: f64, quantity: f64) -> Self {
+        match catalog_rate {
+            Some(rate) => Estimate {
+                monthly: rate * quantity,
+                basis: "listPrice",
+            },
+            None => Estimate {
+                monthly: default_rate * quantity,
+                basis: "defaultListPrice",
+            },
+        }
+    }
+
+    fn of(candidate: &WasteCandidate, list_price: Estimate) -> Self {
+        match (candidate.linked_cost, candidate.linked_hours) {
+            (Some(cost), Some(hours)) if cost > 0.0 && hours > 0.0 => Estimate {
+                monthly: cost / hours * HOURS_PER_MONTH,
+                basis: "linkedCost",
+            },
+            _ => list_price,
+        }
+    }
+}
+
+fn severity(monthly_savings: f64) -> Severity {
+    if monthly_savings >= HIGH_SAVINGS {
+        Severity::High
+    } else if monthly_savings >= MEDIUM_SAVINGS {
+        Severity::Medium
+    } else {
+        Severity::Low
+    }
+}
+
+/// Numeric attribute, which provider descriptions usually hold as text
+fn number(attributes: &Value, key: &str) -> Option<f64> {
+    match attributes.get(key)? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.parse().ok(),
+        _ => None,
+    }
+}
+
+fn text<'a>(attributes: &'a Value, key: &str) -> Option<&'a str> {
+    attributes.get(key).and_then(Value::as_str)
+}
+
+fn volume_list_price(attributes: &Value, prices: &RegionPrices) -> Estimate {
+    let volume_type = text(attributes, "volumeType");
+    let default_rate = volume_type
+        .and_then(|t| VOLUME_GB_MONTH.iter().find(|(name, _)| *name == t))
+        .map_or(0.10, |(_, rate)| *rate);
+    Estimate::listed(
+        volume_type.and_then(|t| prices.volume_gb_month.get(t).copied()),
+        default_rate,
+        number(attributes, "size").unwrap_or(0.0),
+    )
+}
+
+/// Stop time from an EC2 state transition reason such as
+/// `User initiated (2026-10-01 12:00:00 GMT)`
+fn stopped_at(reason: &str) -> Option<DateTime<Utc>> {
+    let inner = reason.split_once('(')?.1.split_once(')')?.0;
+    let timestamp = inner.rsplit_once(' ').map_or(inner, |(time, _zone)| time);
+    NaiveDateTime::parse_from_str(timestamp, "%Y-%m-%d %H:%M:%S")
+        .ok()
+        .map(|t| t.and_utc())
+}
+
+/// Detect waste among live resources as of `now`, with list prices by region
+pub fn detect(
+    candidates: &[WasteCandidate],
+    settings: &WasteSettings,
+    prices: &HashMap<String, RegionPrices>,
+    now: DateTime<Utc>,
+) -> Vec<NewFinding> {
+    let no_prices = RegionPrices::default();
+    let enabled = |rule: WasteRule| !settings.disabled_rules.contains(&rule);
+    let idle_since = |c: &WasteCandidate| c.state_since.unwrap_or(c.first_seen_at);
+    let idle_days = |since: DateTime<Utc>| (now - since).num_days();
+    let idle_enough = |since: DateTime<Utc>| idle_days(since) >= i64::from(settings.idle_days);
+
+    // Volumes by the instance they are attached to
+    let mut attached: HashMap<&str, Vec<&WasteCandidate>> = HashMap::new();
+    for volume in candidates
+        .iter()
+        .filter(|c| c.resource_type == "ebs_volume")
+    {
+        if let Some(Value::Array(attachments)) = volume.attributes.get("attachmentSet") {
+            for instance_id in attachments.iter().filter_map(|a| text(a, "instanceId")) {
+                attached.entry(instance_id).or_default().push(volume);
+            }
+        }
+    }
+
+    let mut findings = Vec::new();
+    let mut push = |candidate: &WasteCandidate,
+                    rule: WasteRule,
+                    message: String,
+                    estimate: Estimate,
+                    mut evidence: Value| {
+        evidence["savingsBasis"] = json!(estimate.basis);
+        if estimate.basis == "defaultListPrice" {
+            evidence["listPriceRegion"] = json!(DEFAULT_PRICE_REGION);
+        }
+        findings.push(NewFinding {
+            resource_id: candidate.id,
+            rule,
+            severity: severity(estimate.monthly),
+            message,
+            estimated_monthly_savings: estimate.monthly,
+            evidence,
+        });
+    };
+
+    for c in candidates {
+        let attributes = &c.attributes.0;
+        let state = c.state.as_deref();
+        let rates = prices.get(&c.region).unwrap_or(&no_prices);
+        match c.resource_type.as_str() {
+            "ebs_volume" if state == Some("available") && enabled(WasteRule::UnattachedVolume) => {
+                let since = idle_since(c);
+                if !idle_enough(since) {
+                    continue;
+                }
+                let estimate = Estimate::of(c, volume_list_price(attributes, rates));
+                push(
+                    c,
+                    WasteRule::UnattachedVolume,
+                    format!(
+                        "EBS volume {} ({} GiB {}) has been unattached for {} day(s)",
+                        c.provider_id,
+                        number(attributes, "size").unwrap_or(0.0),
+                        text(attributes, "volumeType").unwrap_or("unknown"),
+                        idle_days(since)
+                    ),
+                    estimate,
+                    json!({
+                        "state": state,
+                        "sizeGb": number(attributes, "size"),
+                        "volumeType": text(attributes, "volumeType"),
+                        "createTime": text(attributes, "createTime"),
+                        "idleSince": since,
+                    }),
+                );
+            }
+            "elastic_ip" if state == Some("unassociated") && enabled(WasteRule::IdleElasticIp) => {
+                let since = idle_since(c);
+                if !idle_enough(since) {
+                    continue;
+                }
+                let estimate = Estimate::of(
+                    c,
+                    Estimate::listed(rates.public_ipv4_hour, PUBLIC_IPV4_HOUR, HOURS_PER_MONTH),
+                );
+                let address = text(attributes, "publicIp").unwrap_or(&c.provider_id);
+                push(
+                    c,
+                    WasteRule::IdleElasticIp,
+                    format!(
+                        "Elastic IP {} has not been associated for {} day(s)",
+                        address,
+                        idle_days(since)
+                    ),
+                    estimate,
+                    json!({
+                        "publicIp": text(attributes, "publicIp"),
+                        "allocationId": text(attributes, "allocationId"),
+                        "idleSince": since,
+                    }),
+                );
+            }
+            "ec2_instance" if state == Some("stopped") && enabled(WasteRule::StoppedInstance) => {
+                let since = text(attributes, "reason")
+                    .and_then(stopped_at)
+                    .unwrap_or_else(|| idle_since(c));
+                let volumes = attached.get(c.provider_id.as_str());
+                let Some(volumes) = volumes.filter(|_| idle_enough(since)) else {
+                    continue;
+                };
+                let estimates: Vec<(&WasteCandidate, Estimate)> = volumes
+                    .iter()
+                    .map(|v| {
+                        (
+                            *v,
+                            Estimate::of(v, volume_list_price(&v.attributes.0, rates)),
+                        )
+                    })
+                    .collect();
+                let size: f64 = volumes
+                    .iter()
+                    .filter_map(|v| number(&v.attributes.0, "size"))
+                    .sum();
+                let monthly = estimates.iter().map(|(_, e)| e.monthly).sum();
+                let basis = if estimates.iter().all(|(_, e)| e.basis == "linkedCost") {
+                    "linkedCost"
+                } else if estimates.iter().any(|(_, e)| e.basis == "defaultListPrice") {
+                    "defaultListPrice"
+                } else {
+                    "listPrice"
+                };
+                push(
+                    c,
+                    WasteRule::StoppedInstance,
+                    format!(
+                        "Instance {} has been stopped for {} day(s) with {} attached volume(s) ({} GiB)",
+                        c.provider_id,
+                        idle_days(since),
+                        volumes.len(),
+                        size
+                    ),
+                    Estimate { monthly, basis },
+                    json!({
+                        "instanceType": text(attributes, "instanceType"),
+                        "stoppedSince": since,
+                        "volumes": estimates
+                            .iter()
+                            .map(|(v, e)| json!({
+                                "volumeId": v.provider_id,
+                                "sizeGb": number(&v.attributes.0, "size"),
+                                "volumeType": text(&v.attributes.0, "volumeType"),
+                                "monthlyCost": e.monthly,
+                            }))
+                            .collect::<Vec<_>>(),
+                    }),
+                );
+            }
+            "ebs_snapshot" if enabled(WasteRule::OldSnapshot) => {
+                let Some(started) = text(attributes, "startTime")
+                    .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                    .map(|t| t.with_timezone(&Utc))
+                else {
+                    continue;
+                };
+                let age = (now - started).num_days();
+                if age < i64::from(settings.snapshot_age_days) {
+                    continue;
+                }
+                let size = number(attributes, "volumeSize").unwrap_or(0.0);
+                let volume_id = text(attributes, "volumeId");
+                let volume_exists = volume_id.is_some_and(|id| {
+                    candidates
+                        .iter()
+                        .any(|v| v.resource_type == "ebs_volume" && v.provider_id == id)
+                });
+                push(
+                    c,
+                    WasteRule::OldSnapshot,
+                    format!("Snapshot {} is {} day(s) old", c.provider_id, age),
+                    Estimate::of(
+                        c,
+                        Estimate::listed(rates.snapshot_gb_month, SNAPSHOT_GB_MONTH, size),
+                    ),
+                    json!({
+                        "startTime": started,
+                        "ageDays": age,
+                        "volumeSizeGb": size,
+                        "volumeId": volume_id,
+                        "sourceVolumeExists": volume_exists,
+                    }),
+                );
+            }
+            "load_balancer" if enabled(WasteRule::IdleLoadBalancer) => {
+                if number(attributes, "registeredTargetCount") != Some(0.0) {
+                    continue;
+                }
+                let since = idle_since(c);
+                if !idle_enough(since) {
+                    continue;
+                }
+                push(
+                    c,
+                    WasteRule::IdleLoadBalancer,
+                    format!(
+                        "Load balancer {} has had no registered targets for {} day(s)",
+                        text(attributes, "LoadBalancerName").unwrap_or(&c.provider_id),
+                        idle_days(since)
+                    ),
+                    Estimate::of(
+                        c,
+                        Estimate::listed(
+                            text(attributes, "Type")
+                                .and_then(|t| rates.load_balancer_hour.get(t).copied()),
+                            LOAD_BALANCER_HOUR,
+                            HOURS_PER_MONTH,
+                        ),
+                    ),
+                    json!({
+                        "type": text(attributes, "Type"),
+                        "registeredTargetCount": 0,
+                        "idleSince": since,
+                    }),
+                );
+            }
+            _ => {}
+        }
+    }
+    findings
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::pricing::PriceTier;
+    use chrono::{Duration, TimeZone};
+    use sqlx::types::Json;
+    use uuid::Uuid;
+
+    fn price(
+        service: &str,
+        family: &str,
+        unit: &str,
+        rate: f64,
+        attributes: &[(&str, &str)],
+    ) -> Price {
+        Price {
+            id: Uuid::new_v4(),
+            catalog_id: Uuid::new_v4(),
+            catalog_version: "1".to_string(),
+            provider: "aws".to_string(),
+            service: service.to_string(),
+            sku: "SKU".to_string(),
+            product_family: Some(family.to_string()),
+            region: Some("us-east-1".to_string()),
+            description: None,
+            price_type: "OnDemand".to_string(),
+            term: None,
+            unit: unit.to_string(),
+            currency: "USD".to_string(),
+            effective_from: None,
+            tiers: vec![PriceTier {
+                start_usage: 0.0,
+                end_usage: None,
+                unit_price: rate,
+            }],
+            attributes: attributes
+                .iter()
+                .map(|(key, value)| (key.to_string(), value.to_string()))
+                .collect(),
+        }
+    }
+
+    fn candidate(
+        resource_type: &str,
+        provider_id: &str,
+        state: &str,
+        attributes: Value,
+        idle_days: i64,
+    ) -> WasteCandidate {
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 0, 0, 0).unwrap();
+        WasteCandidate {
+            id: Uuid::new_v4(),
+            resource_type: resource_type.to_string(),
+            provider_id: provider_id.to_string(),
+            region: "us-east-1".to_string(),
+            state: Some(state.to_string()),
+            attributes: Json(attributes),
+            first_seen_at: now - Duration::days(365),
+            state_since: Some(now - Duration::days(idle_days)),
+            linked_cost: None,
+            linked_hours: None,
+        }
+    }
+
+    #[test]
+    fn test_detect_waste() {
+        let now = Utc.with_ymd_and_hms(2026, 10, 18, 0, 0, 0).unwrap();
+        let mut unattached = candidate(
+            "ebs_volume",
+            "vol-1",
+            "available",
+            json!({"size": "500", "volumeType": "gp3"}),
+            10,
+        );
+        unattached.linked_cost = Some(30.0);
+        unattached.linked_hours = Some(240.0);
+        let candidates = vec![
+            unattached,
+            // Detached too recently
+            candidate("ebs_volume", "vol-2", "available", json!({"size": "8"}), 1),
+            candidate(
+                "ebs_volume",
+                "vol-3",
+                "in-use",
+                json!({"size": "100", "volumeType": "gp2",
+                       "attachmentSet": [{"instanceId": "i-1"}]}),
+                100,
+            ),
+            candidate(
+                "ec2_instance",
+                "i-1",
+                "stopped",
+                json!({"reason": "User initiated (2026-09-01 10:00:00 GMT)"}),
+                0,
+            ),
+            candidate(
+                "ebs_snapshot",
+                "snap-1",
+                "completed",
+                json!({"startTime": "2026-01-01T00:00:00.000Z", "volumeSize": "100",
+                       "volumeId": "vol-gone"}),
+                200,
+            ),
+            candidate(
+                "load_balancer",
+                "arn:lb",
+                "active",
+                json!({"registeredTargetCount": 2}),
+                30,
+            ),
+        ];
+
+        let prices = HashMap::from([(
+            "us-east-1".to_string(),
+            RegionPrices::from_prices(&[
+                price(
+                    "AmazonEC2",
+                    "Storage",
+                    "GB-Mo",
+                    0.12,
+                    &[("volumeApiName", "gp2")],
+                ),
+                price(
+                    "AWSELB",
+                    "Load Balancer-Application",
+                    "Hrs",
+                    0.0252,
+                    &[("usagetype", "LoadBalancerUsage")],
+                ),
+            ]),
+        )]);
+        assert_eq!(
+            prices["us-east-1"].load_balancer_hour["application"],
+            0.0252
+        );
+        assert_eq!(prices["us-east-1"].snapshot_gb_month, None);
+
+        let findings = detect(&candidates, &WasteSettings::default(), &prices, now);
+        let rules: Vec<WasteRule> = findings.iter().map(|f| f.rule).collect();
+        assert_eq!(
+            rules,
+            vec![
+                WasteRule::UnattachedVolume,
+                WasteRule::StoppedInstance,
+                WasteRule::OldSnapshot
+            ]
+        );
+
+        // 30 over 240 hours of linked spend
+        assert_eq!(findings[0].estimated_monthly_savings, 91.25);
+        assert_eq!(findings[0].severity, Severity::Medium);
+        // Stopped on the reported date despite the recent state change, and
+        // paying for 100 GiB of gp2 at the catalog's price
+        assert_eq!(findings[1].estimated_monthly_savings, 12.0);
+        assert_eq!(findings[1].evidence["savingsBasis"], json!("listPrice"));
+        assert_eq!(
+            findings[1].evidence["stoppedSince"],
+            json!(Utc.with_ymd_and_hms(2026, 9, 1, 10, 0, 0).unwrap())
+        );
+        assert_eq!(findings[2].evidence["sourceVolumeExists"], json!(false));
+        // No snapshot price in the catalog
+        assert_eq!(findings[2].estimated_monthly_savings, 5.0);
+        assert_eq!(
+            findings[2].evidence["savingsBasis"],
+            json!("defaultListPrice")
+        );
+        assert_eq!(findings[2].evidence["listPriceRegion"], json!("us-east-1"));
+
+        let settings = WasteSettings {
+            disabled_rules: vec![WasteRule::OldSnapshot],
+            ..WasteSettings::default()
+        };
+        assert_eq!(detect(&candidates, &settings, &prices, now).len(), 2);
+    }
+}
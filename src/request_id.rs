@@ -0,0 +1,140 @@
+//! Request ids
+//!
+//! Each request carries the `X-Request-Id` it came with, or a generated one
+//! when it had none, back on its response. Errors include it so a report
+//! from a client can be matched with the server's logs.
+//!
+//! Everything logged while handling a request is inside a `request` span
+//! with its id, method and path, and once authenticated the caller's user
+//! and organization ids.
+
+use std::time::Instant;
+
+use axum::{
+    extract::Request,
+    http::{header, HeaderMap, HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use tracing::{field, Instrument, Span};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-request-id");
+
+/// Longest client-supplied id that is kept
+const MAX_LEN: usize = 128;
+
+/// Headers whose values are credentials, never logged
+const SENSITIVE_HEADERS: [HeaderName; 4] = [
+    header::AUTHORIZATION,
+    header::PROXY_AUTHORIZATION,
+    header::COOKIE,
+    header::SET_COOKIE,
+];
+
+tokio::task_local! {
+    static REQUEST_ID: String;
+}
+
+/// Id of the request being handled, if any
+pub fn current() -> Option<String> {
+    REQUEST_ID.try_with(Clone::clone).ok()
+}
+
+/// The client's id when it is usable, otherwise a new one
+fn request_id(header: Option<&HeaderValue>) -> String {
+    header
+        .and_then(|value| value.to_str().ok())
+        .filter(|id| {
+            !id.is_empty() && id.len() <= MAX_LEN && id.bytes().all(|b| b.is_ascii_graphic())
+        })
+        .map(str::to_string)
+        .unwrap_or_else(|| Uuid::new_v4().to_string())
+}
+
+/// Mark credentials so `Debug` output, and so logs, show `Sensitive`
+/// instead of them
+fn redact(headers: &mut HeaderMap) {
+    for name in &SENSITIVE_HEADERS {
+        if let header::Entry::Occupied(mut entry) = headers.entry(name) {
+            for value in entry.iter_mut() {
+                value.set_sensitive(true);
+            }
+        }
+    }
+}
+
+/// Add the authenticated caller to the span of the request being handled
+pub fn record_caller(claims: &Claims) {
+    let span = Span::current();
+    span.record("user_id", claims.user_id());
+    if let Some(org_id) = claims.organization_id() {
+        span.record("org_id", org_id);
+    }
+}
+
+/// Middleware giving every request an id, available to handlers through
+/// [`current`] and returned in the `X-Request-Id` response header, and a
+/// span logging it
+pub async fn propagate(mut request: Request, next: Next) -> Response {
+    let id = request_id(request.headers().get(&REQUEST_ID_HEADER));
+    redact(request.headers_mut());
+    let span = tracing::info_span!(
+        "request",
+        request_id = %id,
+        method = %request.method(),
+        path = %request.uri().path(),
+        user_id = field::Empty,
+        org_id = field::Empty,
+    );
+    span.in_scope(|| tracing::debug!(headers = ?request.headers(), "Request started"));
+
+    let started = Instant::now();
+    let mut response = REQUEST_ID
+        .scope(id.clone(), next.run(request))
+        .instrument(span.clone())
+        .await;
+    span.in_scope(|| {
+        tracing::info!(
+            status = response.status().as_u16(),
+            latency_ms = started.elapsed().as_millis() as u64,
+            "Request finished"
+        )
+    });
+
+    if let Ok(value) = HeaderValue::from_str(&id) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_request_id() {
+        let given = HeaderValue::from_static("req-42");
+        assert_eq!(request_id(Some(&given)), "req-42");
+
+        let spaced = HeaderValue::from_static("two words");
+        assert!(Uuid::parse_str(&request_id(Some(&spaced))).is_ok());
+        assert!(Uuid::parse_str(&request_id(None)).is_ok());
+
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            header::AUTHORIZATION,
+            HeaderValue::from_static("Bearer t0ken"),
+        );
+        headers.append(header::COOKIE, HeaderValue::from_static("a=s3cret"));
+        headers.append(header::COOKIE, HeaderValue::from_static("b=s3cret"));
+        headers.insert(header::ACCEPT, HeaderValue::from_static("application/json"));
+        redact(&mut headers);
+        let logged = format!("{:?}", headers);
+        assert!(!logged.contains("t0ken") && !logged.contains("s3cret"));
+        assert!(logged.contains("application/json"));
+        assert_eq!(headers[header::AUTHORIZATION], "Bearer t0ken");
+    }
+}
@@ -0,0 +1,565 @@
+//! Listing AWS resources
+//!
+//! Each collector lists one resource type in one region and converts the
+//! API's XML description into a [`NewResource`], keeping the description
+//! itself as the resource's attributes.
+
+use std::collections::BTreeMap;
+
+use serde_json::json;
+use tokio::task::JoinSet;
+
+use super::model::{DiscoveryError, NewResource, ResourceType};
+use crate::aws::{AwsClient, AwsError, Element, Pagination, Service, DEFAULT_REGION};
+
+/// ELBv2 `DescribeTags` accepts at most 20 ARNs per call
+const ELB_TAG_BATCH: usize = 20;
+
+/// Buckets whose location and tags are fetched concurrently
+const BUCKET_CONCURRENCY: usize = 8;
+
+fn partition(region: &str) -> &'static str {
+    if region.starts_with("cn-") {
+        "aws-cn"
+    } else if region.starts_with("us-gov-") {
+        "aws-us-gov"
+    } else {
+        "aws"
+    }
+}
+
+fn param(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+/// Tags from a list of `{key, value}` (EC2) or `{Key, Value}` entries
+fn tags(list: &[Element]) -> BTreeMap<String, String> {
+    list.iter()
+        .filter_map(|tag| {
+            let key = tag.text_at(&["key"]).or_else(|| tag.text_at(&["Key"]))?;
+            let value = tag
+                .text_at(&["value"])
+                .or_else(|| tag.text_at(&["Value"]))
+                .unwrap_or_default();
+            Some((key.to_string(), value.to_string()))
+        })
+        .collect()
+}
+
+/// EC2 resource from an `item` of a describe call
+fn ec2_resource(
+    item: &Element,
+    resource_type: ResourceType,
+    id_field: &str,
+    state: Option<&str>,
+    arn: impl FnOnce(&str) -> String,
+    region: &str,
+) -> Option<NewResource> {
+    let provider_id = item.text_at(&[id_field])?.to_string();
+    let tags = tags(item.list(&["tagSet"]));
+    Some(NewResource {
+        resource_type,
+        arn: Some(arn(&provider_id)),
+        name: tags.get("Name").cloned(),
+        region: region.to_string(),
+        state: state.map(str::to_string),
+        tags,
+        attributes: item.to_json(),
+        provider_id,
+    })
+}
+
+pub fn parse_instances(pages: &[Element], account_id: &str, region: &str) -> Vec<NewResource> {
+    pages
+        .iter()
+        .flat_map(|page| page.list(&["reservationSet"]))
+        .flat_map(|reservation| reservation.list(&["instancesSet"]))
+        .filter_map(|item| {
+            ec2_resource(
+                item,
+                ResourceType::Ec2Instance,
+                "instanceId",
+                item.text_at(&["instanceState", "name"]),
+                |id| {
+                    format!(
+                        "arn:{}:ec2:{}:{}:instance/{}",
+                        partition(region),
+                        region,
+                        account_id,
+                        id
+                    )
+                },
+                region,
+            )
+        })
+        .collect()
+}
+
+pub fn parse_volumes(pages: &[Element], account_id: &str, region: &str) -> Vec<NewResource> {
+    pages
+        .iter()
+        .flat_map(|page| page.list(&["volumeSet"]))
+        .filter_map(|item| {
+            ec2_resource(
+                item,
+                ResourceType::EbsVolume,
+                "volumeId",
+                item.text_at(&["status"]),
+                |id| {
+                    format!(
+                        "arn:{}:ec2:{}:{}:volume/{}",
+                        partition(region),
+                        region,
+                        account_id,
+                        id
+                    )
+                },
+                region,
+            )
+        })
+        .collect()
+}
+
+pub fn parse_snapshots(pages: &[Element], region: &str) -> Vec<NewResource> {
+    pages
+        .iter()
+        .flat_map(|page| page.list(&["snapshotSet"]))
+        .filter_map(|item| {
+            ec2_resource(
+                item,
+                ResourceType::EbsSnapshot,
+                "snapshotId",
+                item.text_at(&["status"]),
+                // Snapshot ARNs carry no account id
+                |id| format!("arn:{}:ec2:{}::snapshot/{}", partition(region), region, id),
+                region,
+            )
+        })
+        .collect()
+}
+
+pub fn parse_addresses(page: &Element, account_id: &str, region: &str) -> Vec<NewResource> {
+    page.list(&["addressesSet"])
+        .iter()
+        .filter_map(|item| {
+            // EC2-Classic addresses have no allocation id
+            let id_field = if item.text_at(&["allocationId"]).is_some() {
+                "allocationId"
+            } else {
+                "publicIp"
+            };
+            let state = if item.text_at(&["associationId"]).is_some()
+                || item.text_at(&["instanceId"]).is_some()
+            {
+                "associated"
+            } else {
+                "unassociated"
+            };
+            ec2_resource(
+                item,
+                ResourceType::ElasticIp,
+                id_field,
+                Some(state),
+                |id| {
+                    format!(
+                        "arn:{}:ec2:{}:{}:elastic-ip/{}",
+                        partition(region),
+                        region,
+                        account_id,
+                        id
+                    )
+                },
+                region,
+            )
+        })
+        .collect()
+}
+
+pub fn parse_db_instances(pages: &[Element], region: &str) -> Vec<NewResource> {
+    pages
+        .iter()
+        .flat_map(|page| page.list(&["DescribeDBInstancesResult", "DBInstances"]))
+        .filter_map(|db| {
+            let identifier = db.text_at(&["DBInstanceIdentifier"])?.to_string();
+            Some(NewResource {
+                resource_type: ResourceType::RdsInstance,
+                arn: db.text_at(&["DBInstanceArn"]).map(str::to_string),
+                name: Some(identifier.clone()),
+                region: region.to_string(),
+                state: db.text_at(&["DBInstanceStatus"]).map(str::to_string),
+                tags: tags(db.list(&["TagList"])),
+                attributes: db.to_json(),
+                provider_id: identifier,
+            })
+        })
+        .collect()
+}
+
+/// Load balancers without tags, which take a separate call
+pub fn parse_load_balancers(pages: &[Element], region: &str) -> Vec<NewResource> {
+    pages
+        .iter()
+        .flat_map(|page| page.list(&["DescribeLoadBalancersResult", "LoadBalancers"]))
+        .filter_map(|lb| {
+            let arn = lb.text_at(&["LoadBalancerArn"])?.to_string();
+            Some(NewResource {
+                resource_type: ResourceType::LoadBalancer,
+                provider_id: arn.clone(),
+                arn: Some(arn),
+                name: lb.text_at(&["LoadBalancerName"]).map(str::to_string),
+                region: region.to_string(),
+                state: lb.text_at(&["State", "Code"]).map(str::to_string),
+                tags: BTreeMap::new(),
+                attributes: lb.to_json(),
+            })
+        })
+        .collect()
+}
+
+async fn instances(
+    client: &AwsClient,
+    account_id: &str,
+    region: &str,
+) -> Result<Vec<NewResource>, AwsError> {
+    let pages = client
+        .query_all(
+            Service::Ec2,
+            region,
+            "DescribeInstances",
+            &[param("MaxResults", "1000")],
+            Pagination::EC2,
+        )
+        .await?;
+    Ok(parse_instances(&pages, account_id, region))
+}
+
+async fn volumes(
+    client: &AwsClient,
+    account_id: &str,
+    region: &str,
+) -> Result<Vec<NewResource>, AwsError> {
+    let pages = client
+        .query_all(
+            Service::Ec2,
+            region,
+            "DescribeVolumes",
+            &[param("MaxResults", "500")],
+            Pagination::EC2,
+        )
+        .await?;
+    Ok(parse_volumes(&pages, account_id, region))
+}
+
+async fn snapshots(client: &AwsClient, region: &str) -> Result<Vec<NewResource>, AwsError> {
+    let pages = client
+        .query_all(
+            Service::Ec2,
+            region,
+            "DescribeSnapshots",
+            &[param("Owner.1", "self"), param("MaxResults", "1000")],
+            Pagination::EC2,
+        )
+        .await?;
+    Ok(parse_snapshots(&pages, region))
+}
+
+async fn addresses(
+    client: &AwsClient,
+    account_id: &str,
+    region: &str,
+) -> Result<Vec<NewResource>, AwsError> {
+    let page = client
+        .query(Service::Ec2, region, "DescribeAddresses", &[])
+        .await?;
+    Ok(parse_addresses(&page, account_id, region))
+}
+
+async fn db_instances(client: &AwsClient, region: &str) -> Result<Vec<NewResource>, AwsError> {
+    let pages = client
+        .query_all(
+            Service::Rds,
+            region,
+            "DescribeDBInstances",
+            &[],
+            Pagination::MARKER,
+        )
+        .await?;
+    Ok(parse_db_instances(&pages, region))
+}
+
+async fn load_balancers(client: &AwsClient, region: &str) -> Result<Vec<NewResource>, AwsError> {
+    let pages = client
+        .query_all(
+            Service::ElasticLoadBalancingV2,
+            region,
+            "DescribeLoadBalancers",
+            &[],
+            Pagination::NEXT_MARKER,
+        )
+        .await?;
+    let mut resources = parse_load_balancers(&pages, region);
+
+    let mut tags_by_arn: BTreeMap<String, BTreeMap<String, String>> = BTreeMap::new();
+    for batch in resources.chunks(ELB_TAG_BATCH) {
+        let params: Vec<(String, String)> = batch
+            .iter()
+            .enumerate()
+            .map(|(i, lb)| {
+                (
+                    format!("ResourceArns.member.{}", i + 1),
+                    lb.provider_id.clone(),
+                )
+            })
+            .collect();
+        let response = client
+            .query(
+                Service::ElasticLoadBalancingV2,
+                region,
+                "DescribeTags",
+                &params,
+            )
+            .await?;
+        for description in response.list(&["DescribeTagsResult", "TagDescriptions"]) {
+            if let Some(arn) = description.text_at(&["ResourceArn"]) {
+                tags_by_arn.insert(arn.to_string(), tags(description.list(&["Tags"])));
+            }
+        }
+    }
+    // Target counts only feed the idle load balancer rule, so without them,
+    // e.g. lacking `elasticloadbalancing:DescribeTargetHealth`, the load
+    // balancers are still listed, just without the attribute
+    let targets = match registered_targets(client, region).await {
+        Ok(targets) => Some(targets),
+        Err(e) => {
+            tracing::warn!("Failed to count load balancer targets in {}: {}", region, e);
+            None
+        }
+    };
+    for lb in &mut resources {
+        lb.tags = tags_by_arn.remove(&lb.provider_id).unwrap_or_default();
+        if let (Some(targets), Some(attributes)) = (&targets, lb.attributes.as_object_mut()) {
+            attributes.insert(
+                "registeredTargetCount".to_string(),
+                json!(targets.get(&lb.provider_id).copied().unwrap_or(0)),
+            );
+        }
+    }
+    Ok(resources)
+}
+
+/// Number of targets registered behind each load balancer, by ARN, summed
+/// over its target groups
+async fn registered_targets(
+    client: &AwsClient,
+    region: &str,
+) -> Result<BTreeMap<String, usize>, AwsError> {
+    let pages = client
+        .query_all(
+            Service::ElasticLoadBalancingV2,
+            region,
+            "DescribeTargetGroups",
+            &[],
+            Pagination::NEXT_MARKER,
+        )
+        .await?;
+
+    let mut counts = BTreeMap::new();
+    for group in pages
+        .iter()
+        .flat_map(|page| page.list(&["DescribeTargetGroupsResult", "TargetGroups"]))
+    {
+        let load_balancers = group.list(&["LoadBalancerArns"]);
+        let Some(arn) = group.text_at(&["TargetGroupArn"]) else {
+            continue;
+        };
+        if load_balancers.is_empty() {
+            continue;
+        }
+        let health = client
+            .query(
+                Service::ElasticLoadBalancingV2,
+                region,
+                "DescribeTargetHealth",
+                &[param("TargetGroupArn", arn)],
+            )
+            .await?;
+        let targets = health
+            .list(&["DescribeTargetHealthResult", "TargetHealthDescriptions"])
+            .len();
+        for lb in load_balancers {
+            *counts.entry(lb.text.clone()).or_insert(0) += targets;
+        }
+    }
+    Ok(counts)
+}
+
+/// Regions enabled for the account
+pub async fn enabled_regions(client: &AwsClient) -> Result<Vec<String>, AwsError> {
+    let response = client
+        .query(Service::Ec2, DEFAULT_REGION, "DescribeRegions", &[])
+        .await?;
+    Ok(response
+        .list(&["regionInfo"])
+        .iter()
+        .filter_map(|region| region.text_at(&["regionName"]).map(str::to_string))
+        .collect())
+}
+
+/// List every regional resource type in `region`. Types that fail are
+/// reported as errors without affecting the others.
+pub async fn discover_region(
+    client: &AwsClient,
+    account_id: &str,
+    region: &str,
+) -> (Vec<NewResource>, Vec<DiscoveryError>) {
+    let results = tokio::join!(
+        instances(client, account_id, region),
+        volumes(client, account_id, region),
+        snapshots(client, region),
+        addresses(client, account_id, region),
+        db_instances(client, region),
+        load_balancers(client, region),
+    );
+    let results = [
+        (ResourceType::Ec2Instance, results.0),
+        (ResourceType::EbsVolume, results.1),
+        (ResourceType::EbsSnapshot, results.2),
+        (ResourceType::ElasticIp, results.3),
+        (ResourceType::RdsInstance, results.4),
+        (ResourceType::LoadBalancer, results.5),
+    ];
+
+    let mut resources = Vec::new();
+    let mut errors = Vec::new();
+    for (resource_type, result) in results {
+        match result {
+            Ok(found) => resources.extend(found),
+            Err(e) => errors.push(DiscoveryError {
+                region: Some(region.to_string()),
+                resource_type: Some(resource_type),
+                message: e.to_string(),
+            }),
+        }
+    }
+    (resources, errors)
+}
+
+/// Region of a bucket from its `LocationConstraint`
+fn bucket_region(location: &Element) -> String {
+    match location.text.as_str() {
+        "" => DEFAULT_REGION.to_string(),
+        "EU" => "eu-west-1".to_string(),
+        region => region.to_string(),
+    }
+}
+
+async fn bucket(client: AwsClient, bucket: Element) -> Result<NewResource, (String, AwsError)> {
+    let name = bucket.text_at(&["Name"]).unwrap_or_default().to_string();
+    let path = format!("/{}", name);
+
+    let location = client
+        .s3_get(DEFAULT_REGION, &path, &[param("location", "")])
+        .await
+        .map_err(|e| (name.clone(), e))?;
+    let region = location
+        .as_ref()
+        .map(bucket_region)
+        .unwrap_or_else(|| DEFAULT_REGION.to_string());
+
+    // Buckets without tags answer NoSuchTagSet with a 404
+    let tagging = client
+        .s3_get(&region, &path, &[param("tagging", "")])
+        .await
+        .map_err(|e| (name.clone(), e))?;
+    let tags = tagging
+        .map(|t| tags(t.list(&["TagSet"])))
+        .unwrap_or_default();
+
+    Ok(NewResource {
+        resource_type: ResourceType::S3Bucket,
+        arn: Some(format!("arn:{}:s3:::{}", partition(&region), name)),
+        name: Some(name.clone()),
+        state: None,
+        tags,
+        attributes: json!({
+            "Name": name,
+            "CreationDate": bucket.text_at(&["CreationDate"]),
+            "Region": region,
+        }),
+        region,
+        provider_id: name,
+    })
+}
+
+/// List S3 buckets, which are global but each stored in its own region
+pub async fn discover_buckets(client: &AwsClient) -> (Vec<NewResource>, Vec<DiscoveryError>) {
+    let error = |region: Option<&str>, message: String| DiscoveryError {
+        region: region.map(str::to_string),
+        resource_type: Some(ResourceType::S3Bucket),
+        message,
+    };
+
+    let buckets = match client.s3_get(DEFAULT_REGION, "/", &[]).await {
+        Ok(Some(response)) => response.list(&["Buckets"]).to_vec(),
+        Ok(None) => Vec::new(),
+        Err(e) => return (Vec::new(), vec![error(None, e.to_string())]),
+    };
+
+    let mut resources = Vec::new();
+    let mut errors = Vec::new();
+    for batch in buckets.chunks(BUCKET_CONCURRENCY) {
+        let mut tasks = JoinSet::new();
+        for entry in batch {
+            tasks.spawn(bucket(client.clone(), entry.clone()));
+        }
+        while let Some(result) = tasks.join_next().await {
+            match result {
+                Ok(Ok(resource)) => resources.push(resource),
+                Ok(Err((name, e))) => errors.push(error(None, format!("bucket {}: {}", name, e))),
+                Err(e) => errors.push(error(None, e.to_string())),
+            }
+        }
+    }
+    (resources, errors)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_instances() {
+        let xml = br#"<DescribeInstancesResponse xmlns="http://ec2.amazonaws.com/doc/2016-11-15/">
+            <reservationSet>
+                <item>
+                    <ownerId>123456789012</ownerId>
+                    <instancesSet>
+                        <item>
+                            <instanceId>i-0abc</instanceId>
+                            <instanceType>m5.large</instanceType>
+                            <instanceState><code>80</code><name>stopped</name></instanceState>
+                            <tagSet>
+                                <item><key>Name</key><value>web-1</value></item>
+                                <item><key>team</key><value>payments</value></item>
+                            </tagSet>
+                        </item>
+                    </instancesSet>
+                </item>
+            </reservationSet>
+        </DescribeInstancesResponse>"#;
+        let page = Element::parse(xml).unwrap();
+
+        let resources = parse_instances(&[page], "123456789012", "cn-north-1");
+        assert_eq!(resources.len(), 1);
+        let instance = &resources[0];
+        assert_eq!(instance.provider_id, "i-0abc");
+        assert_eq!(
+            instance.arn.as_deref(),
+            Some("arn:aws-cn:ec2:cn-north-1:123456789012:instance/i-0abc")
+        );
+        assert_eq!(instance.name.as_deref(), Some("web-1"));
+        assert_eq!(instance.state.as_deref(), Some("stopped"));
+        assert_eq!(instance.tags["team"], "payments");
+        assert_eq!(instance.attributes["instanceType"], "m5.large");
+    }
+}
@@ -0,0 +1,114 @@
+//! Resource snapshots and the field-level differences between them
+
+use serde_json::{json, Value};
+
+use super::model::{FieldChange, NewResource};
+
+/// Attributes left out of diffs: ones that change on every observation
+/// without the resource changing, such as RDS's latest restorable time, and
+/// raw tag lists, whose changes already show as `tags.<key>`
+const IGNORED_ATTRIBUTES: &[&str] = &["LatestRestorableTime", "tagSet", "TagList"];
+
+/// The observed state of a resource kept in its history
+pub fn snapshot(resource: &NewResource) -> Value {
+    json!({
+        "name": resource.name,
+        "state": resource.state,
+        "tags": resource.tags,
+        "attributes": resource.attributes,
+    })
+}
+
+/// Fields that differ between two snapshots. Objects are compared field by
+/// field with dotted paths (`tags.team`, `attributes.instanceType`); other
+/// values, including arrays, are compared whole.
+pub fn diff(before: &Value, after: &Value) -> Vec<FieldChange> {
+    let mut changes = Vec::new();
+    diff_into(&mut changes, "", before, after);
+    changes.retain(|change| {
+        !IGNORED_ATTRIBUTES
+            .iter()
+            .any(|attribute| change.field == format!("attributes.{}", attribute))
+    });
+    changes
+}
+
+fn diff_into(changes: &mut Vec<FieldChange>, path: &str, before: &Value, after: &Value) {
+    match (before, after) {
+        (Value::Object(before_fields), Value::Object(after_fields)) => {
+            let mut keys: Vec<&String> = before_fields.keys().chain(after_fields.keys()).collect();
+            keys.sort();
+            keys.dedup();
+            for key in keys {
+                let field = if path.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{}.{}", path, key)
+                };
+                diff_into(
+                    changes,
+                    &field,
+                    before_fields.get(key).unwrap_or(&Value::Null),
+                    after_fields.get(key).unwrap_or(&Value::Null),
+                );
+            }
+        }
+        _ if before != after => changes.push(FieldChange {
+            field: path.to_string(),
+            before: before.clone(),
+            after: after.clone(),
+        }),
+        _ => {}
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::collections::BTreeMap;
+
+    use crate::resources::ResourceType;
+
+    fn instance(instance_type: &str, tags: &[(&str, &str)]) -> NewResource {
+        NewResource {
+            resource_type: ResourceType::Ec2Instance,
+            provider_id: "i-1".to_string(),
+            arn: None,
+            name: Some("web".to_string()),
+            region: "us-east-1".to_string(),
+            state: Some("running".to_string()),
+            tags: tags
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect::<BTreeMap<_, _>>(),
+            attributes: json!({
+                "instanceType": instance_type,
+                "LatestRestorableTime": instance_type,
+                "placement": {"availabilityZone": "us-east-1a"}
+            }),
+        }
+    }
+
+    #[test]
+    fn test_diff_snapshots() {
+        let before = snapshot(&instance("m5.large", &[("team", "a"), ("env", "prod")]));
+        let after = snapshot(&instance("m5.xlarge", &[("team", "b"), ("owner", "x")]));
+
+        let changes = diff(&before, &after);
+        let fields: Vec<&str> = changes.iter().map(|c| c.field.as_str()).collect();
+        assert_eq!(
+            fields,
+            vec![
+                "attributes.instanceType",
+                "tags.env",
+                "tags.owner",
+                "tags.team"
+            ]
+        );
+        assert_eq!(changes[0].before, json!("m5.large"));
+        assert_eq!(changes[0].after, json!("m5.xlarge"));
+        assert_eq!(changes[1].after, Value::Null);
+
+        assert!(diff(&before, &before).is_empty());
+    }
+}
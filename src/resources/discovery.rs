@@ -0,0 +1,171 @@
+//! Running resource discovery for a cloud account
+
+use std::collections::BTreeMap;
+
+use chrono::Utc;
+use uuid::Uuid;
+
+use super::aws;
+use super::model::{DiscoveryError, DiscoveryRun, ResourceType, RunCounts, RunStatus};
+use super::store;
+use crate::aws::AwsClient;
+use crate::cloud_accounts::CloudAccount;
+use crate::config::AwsConfig;
+use crate::costs::Provider;
+use crate::db::DbPool;
+use crate::notifications::{self, NotificationEvent};
+
+const SESSION_NAME: &str = "scho1ar-discovery";
+
+/// Client authenticated for the account: the backend's credentials, or the
+/// account's role assumed with them under `session_name`
+pub async fn account_client(
+    config: &AwsConfig,
+    account: &CloudAccount,
+    session_name: &str,
+) -> Result<AwsClient, String> {
+    if account.provider != Provider::Aws.as_str() {
+        return Err(format!(
+            "{} accounts are not supported yet",
+            account.provider
+        ));
+    }
+    let credentials = config
+        .credentials
+        .clone()
+        .ok_or("AWS credentials are not configured")?;
+    let client = AwsClient::new(credentials, config.endpoints.clone());
+
+    let client = match &account.role_arn {
+        Some(role_arn) => {
+            let credentials = client
+                .assume_role(role_arn, &account.external_id, session_name)
+                .await
+                .map_err(|e| format!("could not assume {}: {}", role_arn, e))?;
+            client.with_credentials(credentials)
+        }
+        None => client,
+    };
+
+    let caller = client
+        .caller_account()
+        .await
+        .map_err(|e| format!("could not verify credentials: {}", e))?;
+    if caller != account.account_id {
+        return Err(format!(
+            "credentials belong to account {} instead of {}",
+            caller, account.account_id
+        ));
+    }
+    Ok(client)
+}
+
+/// Discover the account's resources, recording them region by region, then
+/// mark resources no longer found as deleted and link waiting line items
+async fn discover(
+    db: &DbPool,
+    config: &AwsConfig,
+    org_id: &str,
+    account: &CloudAccount,
+    run_id: Uuid,
+) -> Result<(RunCounts, Vec<DiscoveryError>), String> {
+    let client = account_client(config, account, SESSION_NAME).await?;
+    let regions = if account.regions.is_empty() {
+        aws::enabled_regions(&client)
+            .await
+            .map_err(|e| format!("could not list regions: {}", e))?
+    } else {
+        account.regions.clone()
+    };
+    let store_error = |e: sqlx::Error| format!("could not store resources: {}", e);
+
+    let seen_at = Utc::now();
+    let mut counts = RunCounts::default();
+    let mut errors = Vec::new();
+    // Resource types and regions listed completely, where a missing
+    // resource means it was deleted
+    let mut scopes = Vec::new();
+
+    for region in &regions {
+        let (resources, region_errors) =
+            aws::discover_region(&client, &account.account_id, region).await;
+        counts.changed +=
+            store::record_resources(db, org_id, account, Some(run_id), &resources, seen_at)
+                .await
+                .map_err(store_error)?;
+        counts.resources += resources.len();
+
+        scopes.extend(
+            ResourceType::REGIONAL
+                .into_iter()
+                .filter(|t| !region_errors.iter().any(|e| e.resource_type == Some(*t)))
+                .map(|t| (t, Some(region.clone()))),
+        );
+        errors.extend(region_errors);
+    }
+
+    let (buckets, bucket_errors) = aws::discover_buckets(&client).await;
+    counts.changed += store::record_resources(db, org_id, account, Some(run_id), &buckets, seen_at)
+        .await
+        .map_err(store_error)?;
+    counts.resources += buckets.len();
+    if bucket_errors.is_empty() {
+        scopes.push((ResourceType::S3Bucket, None));
+    }
+    errors.extend(bucket_errors);
+
+    counts.deleted = store::mark_deleted(db, account.id, Some(run_id), &scopes, seen_at)
+        .await
+        .map_err(store_error)?;
+
+    // Costs ingested before these resources were first seen
+    store::link_line_items(db, org_id)
+        .await
+        .map_err(|e| format!("could not link line items: {}", e))?;
+
+    Ok((counts, errors))
+}
+
+/// Carry out a started run and record its outcome
+pub async fn run(
+    db: &DbPool,
+    config: &AwsConfig,
+    org_id: &str,
+    account: &CloudAccount,
+    run: &DiscoveryRun,
+) -> Result<(), sqlx::Error> {
+    let (status, counts, errors) = match discover(db, config, org_id, account, run.id).await {
+        Ok((counts, errors)) if errors.is_empty() => (RunStatus::Succeeded, counts, errors),
+        Ok((counts, errors)) => (RunStatus::Partial, counts, errors),
+        Err(message) => (
+            RunStatus::Failed,
+            RunCounts::default(),
+            vec![DiscoveryError {
+                region: None,
+                resource_type: None,
+                message,
+            }],
+        ),
+    };
+
+    tracing::info!(
+        "Discovery of account {} finished as {}: {} resources, {} changed, {} deleted, {} errors",
+        account.account_id,
+        status.as_str(),
+        counts.resources,
+        counts.changed,
+        counts.deleted,
+        errors.len()
+    );
+    store::finish_run(db, run.id, status, counts, &errors).await?;
+
+    if status == RunStatus::Failed {
+        let vars = BTreeMap::from([
+            ("account".to_string(), account.name.clone()),
+            ("account_id".to_string(), account.account_id.clone()),
+            ("error".to_string(), errors[0].message.clone()),
+        ]);
+        notifications::store::notify(db, org_id, NotificationEvent::DiscoveryFailed, vars).await?;
+    }
+    Ok(())
+}
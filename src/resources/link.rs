@@ -0,0 +1,92 @@
+//! Keys linking billing line items to inventory resources
+//!
+//! Billing data and provider APIs identify the same resource differently:
+//! CUR reports EC2 instances and EBS volumes by id but RDS instances and load
+//! balancers by ARN, and S3 buckets by name. Line item resource ids are
+//! normalized to a single key, and each resource lists the keys it may be
+//! billed under.
+
+use super::model::{NewResource, ResourceType};
+
+/// Normalized form of a line item's resource id. EC2 ARNs reduce to the id
+/// after the last `/` and S3 ARNs to the bucket name; other ARNs and plain
+/// ids are kept as they are.
+pub fn normalize_resource_id(resource_id: &str) -> Option<String> {
+    let resource_id = resource_id.trim();
+    if resource_id.is_empty() {
+        return None;
+    }
+
+    let parts: Vec<&str> = resource_id.splitn(6, ':').collect();
+    let key = match parts.as_slice() {
+        ["arn", _, "ec2", _, _, resource] => resource.rsplit('/').next().unwrap_or(resource),
+        ["arn", _, "s3", "", "", resource] => resource.split('/').next().unwrap_or(resource),
+        _ => resource_id,
+    };
+    (!key.is_empty()).then(|| key.to_string())
+}
+
+/// Keys a line item for the resource may carry after normalization
+pub fn link_keys(resource: &NewResource) -> Vec<String> {
+    let mut keys = vec![resource.provider_id.clone()];
+    match resource.resource_type {
+        ResourceType::RdsInstance | ResourceType::LoadBalancer => {
+            keys.extend(resource.arn.clone());
+        }
+        // Address charges may be reported against the public IP
+        ResourceType::ElasticIp => {
+            keys.extend(
+                resource
+                    .attributes
+                    .get("publicIp")
+                    .and_then(|ip| ip.as_str())
+                    .filter(|ip| !ip.is_empty())
+                    .map(str::to_string),
+            );
+        }
+        _ => {}
+    }
+    keys.dedup();
+    keys
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    #[test]
+    fn test_normalize_resource_id() {
+        let normalize = |id: &str| normalize_resource_id(id);
+        assert_eq!(normalize("i-0abc").as_deref(), Some("i-0abc"));
+        assert_eq!(
+            normalize("arn:aws:ec2:us-east-1:123456789012:instance/i-0abc").as_deref(),
+            Some("i-0abc")
+        );
+        assert_eq!(
+            normalize("arn:aws:ec2:us-east-1::snapshot/snap-1").as_deref(),
+            Some("snap-1")
+        );
+        assert_eq!(
+            normalize("arn:aws:s3:::logs-bucket/2026/10").as_deref(),
+            Some("logs-bucket")
+        );
+        assert_eq!(
+            normalize("arn:aws:rds:us-east-1:123456789012:db:orders").as_deref(),
+            Some("arn:aws:rds:us-east-1:123456789012:db:orders")
+        );
+        assert_eq!(normalize("  "), None);
+
+        let address = NewResource {
+            resource_type: ResourceType::ElasticIp,
+            provider_id: "eipalloc-1".to_string(),
+            arn: Some("arn:aws:ec2:us-east-1:123456789012:elastic-ip/eipalloc-1".to_string()),
+            name: None,
+            region: "us-east-1".to_string(),
+            state: None,
+            tags: Default::default(),
+            attributes: json!({"publicIp": "198.51.100.7"}),
+        };
+        assert_eq!(link_keys(&address), vec!["eipalloc-1", "198.51.100.7"]);
+    }
+}
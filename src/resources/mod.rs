@@ -0,0 +1,27 @@
+//! Resource inventory
+//!
+//! Discovery lists the resources of a connected cloud account (EC2
+//! instances, EBS volumes and snapshots, Elastic IPs, RDS instances, load
+//! balancers and S3 buckets for AWS) region by region and upserts them into
+//! `resources`, keeping the provider's description as raw attributes. Each
+//! discovery is recorded in `discovery_runs` with the regions and resource
+//! types that failed.
+//!
+//! Every observed change adds a version to `resource_versions` with a
+//! snapshot and the fields that changed. Resources a run no longer finds in
+//! a region and type it listed successfully are marked deleted.
+//!
+//! Cost line items are linked to the resources they bill for by normalized
+//! resource id, once when they are ingested and again after each discovery.
+
+pub mod aws;
+pub mod compute;
+pub mod discovery;
+pub mod link;
+pub mod model;
+pub mod store;
+
+pub use model::{
+    ChangeKind, DiscoveryError, DiscoveryRun, FieldChange, Resource, ResourceQuery,
+    ResourceSummary, ResourceType, ResourceVersion, RunStatus,
+};
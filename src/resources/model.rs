@@ -0,0 +1,420 @@
+//! Resource inventory API and storage types
+
+use std::collections::{BTreeMap, HashMap};
+use std::fmt;
+use std::str::FromStr;
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use serde_json::Value;
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "snake_case")]
+pub enum ResourceType {
+    Ec2Instance,
+    EbsVolume,
+    EbsSnapshot,
+    RdsInstance,
+    ElasticIp,
+    LoadBalancer,
+    S3Bucket,
+}
+
+impl ResourceType {
+    /// Types discovered per region; S3 buckets are listed globally
+    pub const REGIONAL: [ResourceType; 6] = [
+        ResourceType::Ec2Instance,
+        ResourceType::EbsVolume,
+        ResourceType::EbsSnapshot,
+        ResourceType::ElasticIp,
+        ResourceType::RdsInstance,
+        ResourceType::LoadBalancer,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ResourceType::Ec2Instance => "ec2_instance",
+            ResourceType::EbsVolume => "ebs_volume",
+            ResourceType::EbsSnapshot => "ebs_snapshot",
+            ResourceType::RdsInstance => "rds_instance",
+            ResourceType::ElasticIp => "elastic_ip",
+            ResourceType::LoadBalancer => "load_balancer",
+            ResourceType::S3Bucket => "s3_bucket",
+        }
+    }
+}
+
+impl fmt::Display for ResourceType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ResourceType {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ec2_instance" => Ok(ResourceType::Ec2Instance),
+            "ebs_volume" => Ok(ResourceType::EbsVolume),
+            "ebs_snapshot" => Ok(ResourceType::EbsSnapshot),
+            "rds_instance" => Ok(ResourceType::RdsInstance),
+            "elastic_ip" => Ok(ResourceType::ElasticIp),
+            "load_balancer" => Ok(ResourceType::LoadBalancer),
+            "s3_bucket" => Ok(ResourceType::S3Bucket),
+            other => Err(format!("unknown resource type '{}'", other)),
+        }
+    }
+}
+
+/// A resource as reported by a provider API, before it is stored
+#[derive(Debug, Clone, PartialEq)]
+pub struct NewResource {
+    pub resource_type: ResourceType,
+    pub provider_id: String,
+    pub arn: Option<String>,
+    pub name: Option<String>,
+    pub region: String,
+    pub state: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub attributes: Value,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Resource {
+    pub id: Uuid,
+    pub cloud_account_id: Uuid,
+    pub provider: String,
+    pub account_id: String,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub arn: Option<String>,
+    pub name: Option<String>,
+    pub region: String,
+    pub state: Option<String>,
+    pub tags: BTreeMap<String, String>,
+    pub attributes: Value,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    /// Current version in the resource's history
+    pub version: i32,
+    /// When discovery stopped finding the resource
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ResourceRow {
+    pub id: Uuid,
+    pub cloud_account_id: Uuid,
+    pub provider: String,
+    pub account_id: String,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub arn: Option<String>,
+    pub name: Option<String>,
+    pub region: String,
+    pub state: Option<String>,
+    pub tags: Json<BTreeMap<String, String>>,
+    pub attributes: Json<Value>,
+    pub first_seen_at: DateTime<Utc>,
+    pub last_seen_at: DateTime<Utc>,
+    pub version: i32,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl From<ResourceRow> for Resource {
+    fn from(row: ResourceRow) -> Self {
+        Resource {
+            id: row.id,
+            cloud_account_id: row.cloud_account_id,
+            provider: row.provider,
+            account_id: row.account_id,
+            resource_type: row.resource_type,
+            provider_id: row.provider_id,
+            arn: row.arn,
+            name: row.name,
+            region: row.region,
+            state: row.state,
+            tags: row.tags.0,
+            attributes: row.attributes.0,
+            first_seen_at: row.first_seen_at,
+            last_seen_at: row.last_seen_at,
+            version: row.version,
+            deleted_at: row.deleted_at,
+        }
+    }
+}
+
+/// Inventory details shown next to the cost of a resource
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceSummary {
+    pub resource_type: String,
+    pub provider_id: String,
+    pub name: Option<String>,
+    pub region: String,
+    pub state: Option<String>,
+    /// Value of the resource's `owner` tag, matched case-insensitively
+    pub owner: Option<String>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ResourceSummaryRow {
+    pub id: Uuid,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub name: Option<String>,
+    pub region: String,
+    pub state: Option<String>,
+    pub tags: Json<BTreeMap<String, String>>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+impl From<ResourceSummaryRow> for ResourceSummary {
+    fn from(row: ResourceSummaryRow) -> Self {
+        let owner = row
+            .tags
+            .0
+            .into_iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case("owner"))
+            .map(|(_, value)| value);
+        ResourceSummary {
+            resource_type: row.resource_type,
+            provider_id: row.provider_id,
+            name: row.name,
+            region: row.region,
+            state: row.state,
+            owner,
+            deleted_at: row.deleted_at,
+        }
+    }
+}
+
+/// Filters of a resource listing
+#[derive(Debug, Clone, Default)]
+pub struct ResourceQuery {
+    pub cloud_account_id: Option<Uuid>,
+    pub resource_type: Option<ResourceType>,
+    pub region: Option<String>,
+    pub state: Option<String>,
+    /// Case-insensitive substring of the name, provider id or ARN
+    pub search: Option<String>,
+    /// Tags the resource must have, e.g. `team = payments`
+    pub tags: BTreeMap<String, String>,
+    /// Also list resources discovery no longer finds
+    pub include_deleted: bool,
+}
+
+impl ResourceQuery {
+    /// Paging parameters, read by [`crate::pagination::PageParams`]
+    const PAGE_PARAMS: [&'static str; 4] = ["limit", "cursor", "sort", "filter"];
+
+    /// Query parameters prefixed with this filter on tags, e.g. `tag.team=payments`
+    const TAG_PREFIX: &'static str = "tag.";
+
+    /// Build a query from URL query parameters
+    pub fn from_params(params: HashMap<String, String>) -> Result<Self, String> {
+        let mut query = ResourceQuery::default();
+
+        for (key, value) in params {
+            match key.as_str() {
+                "cloudAccountId" => {
+                    query.cloud_account_id =
+                        Some(value.parse().map_err(|_| "invalid cloudAccountId")?)
+                }
+                "resourceType" => query.resource_type = Some(value.parse()?),
+                "region" => query.region = Some(value),
+                "state" => query.state = Some(value),
+                "search" => query.search = Some(value),
+                "includeDeleted" => {
+                    query.include_deleted = value
+                        .parse()
+                        .map_err(|_| "includeDeleted must be true or false")?
+                }
+                key if Self::PAGE_PARAMS.contains(&key) => {}
+                other => match other.strip_prefix(Self::TAG_PREFIX) {
+                    Some(tag) if !tag.is_empty() => {
+                        query.tags.insert(tag.to_string(), value);
+                    }
+                    _ => return Err(format!("unknown query parameter '{}'", other)),
+                },
+            }
+        }
+
+        Ok(query)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+    /// Found again after having been marked deleted
+    Restored,
+}
+
+impl ChangeKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+            ChangeKind::Restored => "restored",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "created" => ChangeKind::Created,
+            "deleted" => ChangeKind::Deleted,
+            "restored" => ChangeKind::Restored,
+            _ => ChangeKind::Updated,
+        }
+    }
+}
+
+/// A field that differs from the previous version, e.g. `state` or
+/// `tags.team`; `null` stands for an absent value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct FieldChange {
+    pub field: String,
+    pub before: Value,
+    pub after: Value,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceVersion {
+    pub version: i32,
+    pub change_kind: ChangeKind,
+    pub observed_at: DateTime<Utc>,
+    pub discovery_run_id: Option<Uuid>,
+    pub changes: Vec<FieldChange>,
+    /// The resource as observed (`name`, `state`, `tags`, `attributes`),
+    /// only included on request
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub snapshot: Option<Value>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ResourceVersionRow {
+    pub version: i32,
+    pub change_kind: String,
+    pub observed_at: DateTime<Utc>,
+    pub discovery_run_id: Option<Uuid>,
+    pub changes: Json<Vec<FieldChange>>,
+    pub snapshot: Option<Json<Value>>,
+}
+
+impl From<ResourceVersionRow> for ResourceVersion {
+    fn from(row: ResourceVersionRow) -> Self {
+        ResourceVersion {
+            version: row.version,
+            change_kind: ChangeKind::parse(&row.change_kind),
+            observed_at: row.observed_at,
+            discovery_run_id: row.discovery_run_id,
+            changes: row.changes.0,
+            snapshot: row.snapshot.map(|s| s.0),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum RunStatus {
+    Running,
+    Succeeded,
+    /// Finished, but some regions or resource types could not be listed
+    Partial,
+    Failed,
+}
+
+impl RunStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            RunStatus::Running => "running",
+            RunStatus::Succeeded => "succeeded",
+            RunStatus::Partial => "partial",
+            RunStatus::Failed => "failed",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "succeeded" => RunStatus::Succeeded,
+            "partial" => RunStatus::Partial,
+            "failed" => RunStatus::Failed,
+            _ => RunStatus::Running,
+        }
+    }
+}
+
+/// A failure to list one resource type in one region (or of the whole run
+/// when both are absent)
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryError {
+    pub region: Option<String>,
+    pub resource_type: Option<ResourceType>,
+    pub message: String,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct DiscoveryRun {
+    pub id: Uuid,
+    pub cloud_account_id: Uuid,
+    pub status: RunStatus,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub resource_count: i32,
+    /// Resources created, changed or found again
+    pub changed_count: i32,
+    pub deleted_count: i32,
+    pub errors: Vec<DiscoveryError>,
+}
+
+/// What a finished discovery run found
+#[derive(Debug, Clone, Copy, Default)]
+pub struct RunCounts {
+    pub resources: usize,
+    pub changed: usize,
+    pub deleted: u64,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct DiscoveryRunRow {
+    pub id: Uuid,
+    pub cloud_account_id: Uuid,
+    pub status: String,
+    pub started_at: DateTime<Utc>,
+    pub finished_at: Option<DateTime<Utc>>,
+    pub resource_count: i32,
+    pub changed_count: i32,
+    pub deleted_count: i32,
+    pub errors: Json<Vec<DiscoveryError>>,
+}
+
+impl From<DiscoveryRunRow> for DiscoveryRun {
+    fn from(row: DiscoveryRunRow) -> Self {
+        DiscoveryRun {
+            id: row.id,
+            cloud_account_id: row.cloud_account_id,
+            status: RunStatus::parse(&row.status),
+            started_at: row.started_at,
+            finished_at: row.finished_at,
+            resource_count: row.resource_count,
+            changed_count: row.changed_count,
+            deleted_count: row.deleted_count,
+            errors: row.errors.0,
+        }
+    }
+}
@@ -0,0 +1,526 @@
+//! Persistence of discovered resources and discovery runs
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use serde_json::Value;
+use sqlx::types::Json;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use super::compute;
+use super::link;
+use super::model::{
+    ChangeKind, DiscoveryError, DiscoveryRun, DiscoveryRunRow, FieldChange, NewResource, Resource,
+    ResourceQuery, ResourceRow, ResourceSummary, ResourceSummaryRow, ResourceType, ResourceVersion,
+    ResourceVersionRow, RunCounts, RunStatus,
+};
+use crate::cloud_accounts::CloudAccount;
+use crate::db::DbPool;
+use crate::pagination::{contains_pattern, Field, FieldKind, Listing, Page, PageQuery};
+
+const RESOURCE_COLUMNS: &str = "id, cloud_account_id, provider, account_id, resource_type, \
+     provider_id, arn, name, region, state, tags, attributes, first_seen_at, last_seen_at, \
+     version, deleted_at";
+
+const RUN_COLUMNS: &str = "id, cloud_account_id, status, started_at, finished_at, \
+     resource_count, changed_count, deleted_count, errors";
+
+/// Runs still marked running after this long are assumed to have died with
+/// the process that ran them
+const STALE_RUN_HOURS: i32 = 2;
+
+#[derive(sqlx::FromRow)]
+struct ExistingResource {
+    resource_type: String,
+    region: String,
+    provider_id: String,
+    version: i32,
+    deleted_at: Option<DateTime<Utc>>,
+    snapshot: Json<Value>,
+}
+
+/// Resource type, region and provider id, which identify a resource within
+/// an account
+type ResourceKey = (String, String, String);
+
+fn resource_key(resource: &NewResource) -> ResourceKey {
+    (
+        resource.resource_type.as_str().to_string(),
+        resource.region.clone(),
+        resource.provider_id.clone(),
+    )
+}
+
+/// A change to record in a resource's history
+struct Change {
+    key: ResourceKey,
+    kind: ChangeKind,
+    version: i32,
+    snapshot: Value,
+    changes: Vec<FieldChange>,
+}
+
+/// Insert or refresh resources seen by a discovery run, adding a history
+/// version to each one that is new, changed or found again. Returns the
+/// number of versions added.
+pub async fn record_resources(
+    db: &DbPool,
+    org_id: &str,
+    account: &CloudAccount,
+    run_id: Option<Uuid>,
+    resources: &[NewResource],
+    seen_at: DateTime<Utc>,
+) -> Result<usize, sqlx::Error> {
+    const CHUNK_SIZE: usize = 500;
+
+    // A resource listed twice would make the upsert touch its row twice
+    let mut keys = HashSet::new();
+    let resources: Vec<&NewResource> = resources
+        .iter()
+        .filter(|r| keys.insert((r.resource_type, r.region.as_str(), r.provider_id.as_str())))
+        .collect();
+
+    let mut recorded = 0;
+    for chunk in resources.chunks(CHUNK_SIZE) {
+        let mut tx = db.begin().await?;
+
+        let existing: Vec<ExistingResource> = sqlx::query_as(
+            "SELECT r.resource_type, r.region, r.provider_id, r.version, r.deleted_at,
+                    jsonb_build_object('name', r.name, 'state', r.state, 'tags', r.tags,
+                                       'attributes', r.attributes) AS snapshot
+             FROM resources r
+             JOIN UNNEST($2::text[], $3::text[], $4::text[]) AS k(resource_type, region, provider_id)
+               USING (resource_type, region, provider_id)
+             WHERE r.cloud_account_id = $1
+             FOR UPDATE OF r",
+        )
+        .bind(account.id)
+        .bind(chunk.iter().map(|r| r.resource_type.as_str()).collect::<Vec<_>>())
+        .bind(chunk.iter().map(|r| r.region.as_str()).collect::<Vec<_>>())
+        .bind(chunk.iter().map(|r| r.provider_id.as_str()).collect::<Vec<_>>())
+        .fetch_all(&mut *tx)
+        .await?;
+        let existing: HashMap<ResourceKey, ExistingResource> = existing
+            .into_iter()
+            .map(|e| {
+                (
+                    (
+                        e.resource_type.clone(),
+                        e.region.clone(),
+                        e.provider_id.clone(),
+                    ),
+                    e,
+                )
+            })
+            .collect();
+
+        let mut versions = Vec::with_capacity(chunk.len());
+        let mut changes = Vec::new();
+        for resource in chunk {
+            let key = resource_key(resource);
+            let snapshot = compute::snapshot(resource);
+            let change = match existing.get(&key) {
+                None => Some((ChangeKind::Created, 1, Vec::new())),
+                Some(previous) => {
+                    let diff = compute::diff(&previous.snapshot.0, &snapshot);
+                    if previous.deleted_at.is_some() {
+                        Some((ChangeKind::Restored, previous.version + 1, diff))
+                    } else if !diff.is_empty() {
+                        Some((ChangeKind::Updated, previous.version + 1, diff))
+                    } else {
+                        versions.push(previous.version);
+                        None
+                    }
+                }
+            };
+            if let Some((kind, version, diff)) = change {
+                versions.push(version);
+                changes.push(Change {
+                    key,
+                    kind,
+                    version,
+                    snapshot,
+                    changes: diff,
+                });
+            }
+        }
+
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO resources (organization_id, cloud_account_id, provider, account_id, \
+             resource_type, provider_id, arn, name, region, state, tags, attributes, \
+             first_seen_at, last_seen_at, version, link_keys) ",
+        );
+        builder.push_values(
+            chunk.iter().zip(&versions),
+            |mut row, (resource, version)| {
+                row.push_bind(org_id)
+                    .push_bind(account.id)
+                    .push_bind(&account.provider)
+                    .push_bind(&account.account_id)
+                    .push_bind(resource.resource_type.as_str())
+                    .push_bind(&resource.provider_id)
+                    .push_bind(&resource.arn)
+                    .push_bind(&resource.name)
+                    .push_bind(&resource.region)
+                    .push_bind(&resource.state)
+                    .push_bind(Json(&resource.tags))
+                    .push_bind(Json(&resource.attributes))
+                    .push_bind(seen_at)
+                    .push_bind(seen_at)
+                    .push_bind(*version)
+                    .push_bind(link::link_keys(resource));
+            },
+        );
+        builder.push(
+            " ON CONFLICT (cloud_account_id, resource_type, region, provider_id) DO UPDATE SET
+                 arn = EXCLUDED.arn,
+                 name = EXCLUDED.name,
+                 state = EXCLUDED.state,
+                 tags = EXCLUDED.tags,
+                 attributes = EXCLUDED.attributes,
+                 last_seen_at = EXCLUDED.last_seen_at,
+                 version = EXCLUDED.version,
+                 link_keys = EXCLUDED.link_keys,
+                 deleted_at = NULL
+             RETURNING id, resource_type, region, provider_id",
+        );
+        let ids: HashMap<ResourceKey, Uuid> = builder
+            .build_query_as::<(Uuid, String, String, String)>()
+            .fetch_all(&mut *tx)
+            .await?
+            .into_iter()
+            .map(|(id, resource_type, region, provider_id)| {
+                ((resource_type, region, provider_id), id)
+            })
+            .collect();
+
+        if !changes.is_empty() {
+            let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+                "INSERT INTO resource_versions (resource_id, version, discovery_run_id, \
+                 observed_at, change_kind, snapshot, changes) ",
+            );
+            builder.push_values(&changes, |mut row, change| {
+                row.push_bind(ids[&change.key])
+                    .push_bind(change.version)
+                    .push_bind(run_id)
+                    .push_bind(seen_at)
+                    .push_bind(change.kind.as_str())
+                    .push_bind(Json(&change.snapshot))
+                    .push_bind(Json(&change.changes));
+            });
+            builder.build().execute(&mut *tx).await?;
+        }
+
+        tx.commit().await?;
+        recorded += changes.len();
+    }
+    Ok(recorded)
+}
+
+/// Mark resources not seen since `seen_at` as deleted, within the resource
+/// types and regions a run listed successfully (`None` meaning every
+/// region). Returns the number of resources marked.
+pub async fn mark_deleted(
+    db: &DbPool,
+    cloud_account_id: Uuid,
+    run_id: Option<Uuid>,
+    scopes: &[(ResourceType, Option<String>)],
+    seen_at: DateTime<Utc>,
+) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "WITH gone AS (
+             UPDATE resources r SET deleted_at = $5, version = r.version + 1
+             WHERE r.cloud_account_id = $1
+               AND r.deleted_at IS NULL
+               AND r.last_seen_at < $5
+               AND EXISTS (
+                   SELECT 1 FROM UNNEST($3::text[], $4::text[]) AS s(resource_type, region)
+                   WHERE s.resource_type = r.resource_type
+                     AND (s.region IS NULL OR s.region = r.region)
+               )
+             RETURNING r.id, r.version, r.name, r.state, r.tags, r.attributes
+         )
+         INSERT INTO resource_versions
+             (resource_id, version, discovery_run_id, observed_at, change_kind, snapshot)
+         SELECT id, version, $2, $5, 'deleted',
+                jsonb_build_object('name', name, 'state', state, 'tags', tags,
+                                   'attributes', attributes)
+         FROM gone",
+    )
+    .bind(cloud_account_id)
+    .bind(run_id)
+    .bind(
+        scopes
+            .iter()
+            .map(|(resource_type, _)| resource_type.as_str())
+            .collect::<Vec<_>>(),
+    )
+    .bind(
+        scopes
+            .iter()
+            .map(|(_, region)| region.clone())
+            .collect::<Vec<_>>(),
+    )
+    .bind(seen_at)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected())
+}
+
+/// Versions of a resource, newest first. Returns `None` if the resource
+/// does not belong to the organization.
+pub async fn resource_history(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    include_snapshots: bool,
+) -> Result<Option<Vec<ResourceVersion>>, sqlx::Error> {
+    let exists: bool = sqlx::query_scalar(
+        "SELECT EXISTS (SELECT 1 FROM resources WHERE organization_id = $1 AND id = $2)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .fetch_one(db)
+    .await?;
+    if !exists {
+        return Ok(None);
+    }
+
+    let rows: Vec<ResourceVersionRow> = sqlx::query_as(
+        "SELECT version, change_kind, observed_at, discovery_run_id, changes,
+                CASE WHEN $2 THEN snapshot END AS snapshot
+         FROM resource_versions
+         WHERE resource_id = $1
+         ORDER BY version DESC",
+    )
+    .bind(id)
+    .bind(include_snapshots)
+    .fetch_all(db)
+    .await?;
+
+    Ok(Some(rows.into_iter().map(ResourceVersion::from).collect()))
+}
+
+/// Sort and filter fields of the resource list. `search` and `tag.<key>`
+/// parameters filter it as well, see [`ResourceQuery`].
+pub const LISTING: Listing = Listing {
+    fields: &[
+        Field::new("resourceType", "resource_type", FieldKind::Text),
+        Field::new("providerId", "provider_id", FieldKind::Text),
+        Field::new("region", "region", FieldKind::Text),
+        Field::new("provider", "provider", FieldKind::Text),
+        Field::new("accountId", "account_id", FieldKind::Text),
+        Field::new("cloudAccountId", "cloud_account_id", FieldKind::Uuid),
+        Field::new("firstSeenAt", "first_seen_at", FieldKind::Timestamp),
+        Field::new("lastSeenAt", "last_seen_at", FieldKind::Timestamp),
+        Field::new("name", "name", FieldKind::Text).filter_only(),
+        Field::new("state", "state", FieldKind::Text).filter_only(),
+    ],
+    default_sort: "resourceType",
+};
+
+pub async fn list_resources(
+    db: &DbPool,
+    org_id: &str,
+    query: &ResourceQuery,
+    page: &PageQuery,
+) -> Result<Page<Resource>, sqlx::Error> {
+    let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(format!(
+        "SELECT {} FROM resources WHERE organization_id = ",
+        RESOURCE_COLUMNS
+    ));
+    builder.push_bind(org_id);
+    if !query.include_deleted {
+        builder.push(" AND deleted_at IS NULL");
+    }
+    if let Some(account_id) = query.cloud_account_id {
+        builder
+            .push(" AND cloud_account_id = ")
+            .push_bind(account_id);
+    }
+    if let Some(resource_type) = query.resource_type {
+        builder
+            .push(" AND resource_type = ")
+            .push_bind(resource_type.as_str());
+    }
+    if let Some(region) = &query.region {
+        builder.push(" AND region = ").push_bind(region);
+    }
+    if let Some(state) = &query.state {
+        builder.push(" AND state = ").push_bind(state);
+    }
+    if let Some(search) = &query.search {
+        let pattern = contains_pattern(search);
+        builder
+            .push(" AND (name ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR provider_id ILIKE ")
+            .push_bind(pattern.clone())
+            .push(" OR arn ILIKE ")
+            .push_bind(pattern)
+            .push(")");
+    }
+    if !query.tags.is_empty() {
+        builder.push(" AND tags @> ").push_bind(Json(&query.tags));
+    }
+    page.push_conditions(&mut builder);
+    page.push_order_and_limit(&mut builder);
+
+    let rows: Vec<ResourceRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(page.page(rows.into_iter().map(Resource::from).collect()))
+}
+
+pub async fn get_resource(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<Resource>, sqlx::Error> {
+    let row: Option<ResourceRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM resources WHERE organization_id = $1 AND id = $2",
+        RESOURCE_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(Resource::from))
+}
+
+/// Record the start of a discovery run. Returns `None` if a run is already
+/// in progress for the account.
+pub async fn start_run(
+    db: &DbPool,
+    org_id: &str,
+    cloud_account_id: Uuid,
+) -> Result<Option<DiscoveryRun>, sqlx::Error> {
+    sqlx::query(
+        "UPDATE discovery_runs
+         SET status = 'failed', finished_at = NOW(),
+             errors = errors || '[{\"message\": \"discovery did not finish\"}]'::jsonb
+         WHERE cloud_account_id = $1 AND status = 'running'
+           AND started_at < NOW() - make_interval(hours => $2)",
+    )
+    .bind(cloud_account_id)
+    .bind(STALE_RUN_HOURS)
+    .execute(db)
+    .await?;
+
+    let row: Option<DiscoveryRunRow> = sqlx::query_as(&format!(
+        "INSERT INTO discovery_runs (organization_id, cloud_account_id)
+         VALUES ($1, $2)
+         ON CONFLICT (cloud_account_id) WHERE status = 'running' DO NOTHING
+         RETURNING {}",
+        RUN_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(cloud_account_id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(DiscoveryRun::from))
+}
+
+pub async fn finish_run(
+    db: &DbPool,
+    id: Uuid,
+    status: RunStatus,
+    counts: RunCounts,
+    errors: &[DiscoveryError],
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "UPDATE discovery_runs
+         SET status = $2, finished_at = NOW(), resource_count = $3, changed_count = $4,
+             deleted_count = $5, errors = $6
+         WHERE id = $1",
+    )
+    .bind(id)
+    .bind(status.as_str())
+    .bind(counts.resources as i32)
+    .bind(counts.changed as i32)
+    .bind(counts.deleted as i32)
+    .bind(Json(errors))
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+pub async fn get_run(db: &DbPool, id: Uuid) -> Result<Option<DiscoveryRun>, sqlx::Error> {
+    let row: Option<DiscoveryRunRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM discovery_runs WHERE id = $1",
+        RUN_COLUMNS
+    ))
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(DiscoveryRun::from))
+}
+
+pub async fn list_runs(
+    db: &DbPool,
+    org_id: &str,
+    cloud_account_id: Uuid,
+) -> Result<Vec<DiscoveryRun>, sqlx::Error> {
+    let rows: Vec<DiscoveryRunRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM discovery_runs
+         WHERE organization_id = $1 AND cloud_account_id = $2
+         ORDER BY started_at DESC
+         LIMIT 50",
+        RUN_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(cloud_account_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(DiscoveryRun::from).collect())
+}
+
+/// Link the organization's unlinked line items to resources billed under
+/// their normalized resource id. Returns the number of line items linked.
+pub async fn link_line_items(db: &DbPool, org_id: &str) -> Result<u64, sqlx::Error> {
+    let result = sqlx::query(
+        "UPDATE cost_line_items c
+         SET linked_resource_id = r.id
+         FROM resources r
+         WHERE c.organization_id = $1
+           AND c.linked_resource_id IS NULL
+           AND c.resource_key IS NOT NULL
+           AND r.organization_id = c.organization_id
+           AND r.provider = c.provider
+           AND r.account_id = c.account_id
+           AND c.resource_key = ANY(r.link_keys)",
+    )
+    .bind(org_id)
+    .execute(db)
+    .await?;
+    Ok(result.rows_affected())
+}
+
+/// Inventory details of the given resources, keyed by id
+pub async fn resource_summaries(
+    db: &DbPool,
+    org_id: &str,
+    ids: &[Uuid],
+) -> Result<HashMap<Uuid, ResourceSummary>, sqlx::Error> {
+    if ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows: Vec<ResourceSummaryRow> = sqlx::query_as(
+        "SELECT id, resource_type, provider_id, name, region, state, tags, deleted_at
+         FROM resources
+         WHERE organization_id = $1 AND id = ANY($2)",
+    )
+    .bind(org_id)
+    .bind(ids)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.id, ResourceSummary::from(row)))
+        .collect())
+}
@@ -0,0 +1,428 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::{header, StatusCode},
+    response::{IntoResponse, Response},
+    Json,
+};
+use serde::Deserialize;
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Claims;
+use crate::chargeback::export;
+use crate::chargeback::store::{self, CostCenterInput};
+use crate::chargeback::{CostCenter, RateRule, Statement, StatementDetail};
+use crate::conditional::{self, IfMatch, Tagged};
+use crate::costs::Month;
+use crate::error::{AppError, AppResult};
+use crate::tags::normalize::TagCondition;
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct CostCenterRequest {
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub name: String,
+    #[validate(length(min = 1, max = 64))]
+    #[schema(min_length = 1, max_length = 64)]
+    pub code: Option<String>,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    #[validate(nested)]
+    pub conditions: Vec<TagCondition>,
+}
+
+impl From<CostCenterRequest> for CostCenterInput {
+    fn from(req: CostCenterRequest) -> Self {
+        CostCenterInput {
+            name: req.name,
+            code: req.code,
+            priority: req.priority,
+            conditions: req.conditions,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/cost-centers",
+    tag = "chargeback",
+    responses((status = 200, description = "Cost centers", body = Vec<CostCenter>))
+)]
+pub async fn list_cost_centers(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<CostCenter>>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::list_cost_centers(&state.db, org_id).await?))
+}
+
+fn cost_center_not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Cost center {} not found", id))
+}
+
+async fn find_cost_center(state: &AppState, org_id: &str, id: Uuid) -> AppResult<CostCenter> {
+    store::get_cost_center(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| cost_center_not_found(id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/cost-centers/{id}",
+    tag = "chargeback",
+    params(("id" = Uuid, Path, description = "Cost center id")),
+    responses((status = 200, description = "The cost center", body = CostCenter, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn get_cost_center(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<CostCenter>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(find_cost_center(&state, org_id, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/cost-centers",
+    tag = "chargeback",
+    request_body = CostCenterRequest,
+    responses((status = 201, description = "The created cost center", body = CostCenter, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn create_cost_center(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<CostCenterRequest>,
+) -> AppResult<(StatusCode, Tagged<CostCenter>)> {
+    let org_id = claims.require_organization_id()?;
+    let center = store::create_cost_center(&state.db, org_id, payload.into()).await?;
+    Ok((StatusCode::CREATED, Tagged(center)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/cost-centers/{id}",
+    tag = "chargeback",
+    params(("id" = Uuid, Path, description = "Cost center id"), IfMatch),
+    request_body = CostCenterRequest,
+    responses((status = 200, description = "The updated cost center", body = CostCenter, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn update_cost_center(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<CostCenterRequest>,
+) -> AppResult<Tagged<CostCenter>> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_cost_center(&state, org_id, id).await?)?;
+    store::update_cost_center(&state.db, org_id, id, payload.into(), version)
+        .await?
+        .map(Tagged)
+        .ok_or_else(|| conditional::changed_or(version, cost_center_not_found(id)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/cost-centers/{id}",
+    tag = "chargeback",
+    params(("id" = Uuid, Path, description = "Cost center id"), IfMatch),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_cost_center(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_cost_center(&state, org_id, id).await?)?;
+    if store::cost_center_has_statements(&state.db, org_id, id).await? {
+        return Err(AppError::Conflict(
+            "Cost center has statements and cannot be deleted".to_string(),
+        ));
+    }
+    if store::delete_cost_center(&state.db, org_id, id, version)
+        .await
+        .map_err(|e| AppError::from_delete("cost_centers", e))?
+    {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(version, cost_center_not_found(id)))
+    }
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RateRuleRequest {
+    pub cost_center_id: Option<Uuid>,
+    #[validate(length(min = 1, max = 256))]
+    #[schema(min_length = 1, max_length = 256)]
+    pub service: Option<String>,
+    /// Positive for a markup, negative for a discount
+    #[validate(range(min = -100.0, max = 1000.0))]
+    #[schema(minimum = -100.0, maximum = 1000.0)]
+    pub percent: f64,
+    #[validate(length(max = 512))]
+    #[schema(max_length = 512)]
+    pub description: Option<String>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/chargeback/rules",
+    tag = "chargeback",
+    responses((status = 200, description = "Rate rules", body = Vec<RateRule>))
+)]
+pub async fn list_rate_rules(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<RateRule>>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::list_rate_rules(&state.db, org_id).await?))
+}
+
+fn rate_rule_not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Rate rule {} not found", id))
+}
+
+async fn find_rate_rule(state: &AppState, org_id: &str, id: Uuid) -> AppResult<RateRule> {
+    store::get_rate_rule(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| rate_rule_not_found(id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/chargeback/rules/{id}",
+    tag = "chargeback",
+    params(("id" = Uuid, Path, description = "Rate rule id")),
+    responses((status = 200, description = "The rate rule", body = RateRule, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn get_rate_rule(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<RateRule>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(find_rate_rule(&state, org_id, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/chargeback/rules",
+    tag = "chargeback",
+    request_body = RateRuleRequest,
+    responses((status = 201, description = "The created rate rule", body = RateRule, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn create_rate_rule(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<RateRuleRequest>,
+) -> AppResult<(StatusCode, Tagged<RateRule>)> {
+    let org_id = claims.require_organization_id()?;
+    let rule = store::create_rate_rule(
+        &state.db,
+        org_id,
+        payload.cost_center_id,
+        payload.service,
+        payload.percent,
+        payload.description,
+    )
+    .await?
+    .ok_or_else(|| AppError::BadRequest("Unknown cost center".to_string()))?;
+
+    Ok((StatusCode::CREATED, Tagged(rule)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/chargeback/rules/{id}",
+    tag = "chargeback",
+    params(("id" = Uuid, Path, description = "Rate rule id"), IfMatch),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_rate_rule(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_rate_rule(&state, org_id, id).await?)?;
+    if store::delete_rate_rule(&state.db, org_id, id, version).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(version, rate_rule_not_found(id)))
+    }
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+pub struct GenerateRequest {
+    pub month: Month,
+}
+
+/// Generate or refresh the statements of every cost center for a month
+#[utoipa::path(
+    post,
+    path = "/api/chargeback/statements/generate",
+    tag = "chargeback",
+    request_body = GenerateRequest,
+    responses(
+        (status = 200, description = "The generated statements", body = Vec<Statement>),
+    )
+)]
+pub async fn generate_statements(
+    State(state): State<AppState>,
+    claims: Claims,
+    Json(payload): Json<GenerateRequest>,
+) -> AppResult<Json<Vec<Statement>>> {
+    let org_id = claims.require_organization_id()?;
+    let statements = store::generate_statements(&state.db, org_id, payload.month).await?;
+    Ok(Json(statements))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct StatementListQuery {
+    pub month: Option<Month>,
+    pub cost_center_id: Option<Uuid>,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/chargeback/statements",
+    tag = "chargeback",
+    params(StatementListQuery),
+    responses((status = 200, description = "Statements", body = Vec<Statement>))
+)]
+pub async fn list_statements(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<StatementListQuery>,
+) -> AppResult<Json<Vec<Statement>>> {
+    let org_id = claims.require_organization_id()?;
+    let statements =
+        store::list_statements(&state.db, org_id, query.month, query.cost_center_id).await?;
+    Ok(Json(statements))
+}
+
+async fn find_statement(state: &AppState, org_id: &str, id: Uuid) -> AppResult<StatementDetail> {
+    store::get_statement(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| AppError::NotFound(format!("Statement {} not found", id)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/chargeback/statements/{id}",
+    tag = "chargeback",
+    params(("id" = Uuid, Path, description = "Statement id")),
+    responses(
+        (status = 200, description = "The statement and its lines", body = StatementDetail),
+    )
+)]
+pub async fn get_statement(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<StatementDetail>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(find_statement(&state, org_id, id).await?))
+}
+
+/// Lock a statement once finance has approved it (organization admins only)
+#[utoipa::path(
+    post,
+    path = "/api/chargeback/statements/{id}/close",
+    tag = "chargeback",
+    params(("id" = Uuid, Path, description = "Statement id")),
+    responses(
+        (status = 200, description = "The closed statement", body = StatementDetail),
+    )
+)]
+pub async fn close_statement(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<StatementDetail>> {
+    let org_id = claims.require_organization_admin()?;
+    if !store::close_statement(&state.db, org_id, id, claims.user_id()).await? {
+        // Distinguish a missing statement from one that is already closed
+        find_statement(&state, org_id, id).await?;
+        return Err(AppError::Conflict(format!(
+            "Statement {} is already closed",
+            id
+        )));
+    }
+    Ok(Json(find_statement(&state, org_id, id).await?))
+}
+
+#[derive(Debug, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExportFormat {
+    Csv,
+    Pdf,
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ExportQuery {
+    pub format: ExportFormat,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/chargeback/statements/{id}/export",
+    tag = "chargeback",
+    params(
+        ("id" = Uuid, Path, description = "Statement id"),
+        ExportQuery,
+    ),
+    responses(
+        (status = 200, description = "The statement as a file download", content(
+            (String = "text/csv"),
+            (String = "application/pdf"),
+        )),
+    )
+)]
+pub async fn export_statement(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ExportQuery>,
+) -> AppResult<Response> {
+    let org_id = claims.require_organization_id()?;
+    let detail = find_statement(&state, org_id, id).await?;
+    let file_stem = format!(
+        "chargeback-{}-{}",
+        detail.statement.period, detail.statement.cost_center_id
+    );
+
+    let (content_type, extension, body) = match query.format {
+        ExportFormat::Csv => (
+            "text/csv; charset=utf-8",
+            "csv",
+            export::to_csv(&detail).map_err(|e| AppError::Internal(e.to_string()))?,
+        ),
+        ExportFormat::Pdf => ("application/pdf", "pdf", export::to_pdf(&detail)),
+    };
+
+    Ok((
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}.{}\"", file_stem, extension),
+            ),
+        ],
+        body,
+    )
+        .into_response())
+}
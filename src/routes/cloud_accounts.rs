@@ -0,0 +1,200 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::cloud_accounts::store;
+use crate::cloud_accounts::{CloudAccount, CloudAccountUpdate, NewCloudAccount};
+use crate::conditional::{self, IfMatch, Tagged};
+use crate::error::{AppError, AppResult};
+use crate::jobs::{self, JobPayload, NewJob};
+use crate::pagination::{Page, PageParams};
+use crate::resources::{self, DiscoveryRun};
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Cloud account {} not found", id))
+}
+
+async fn find_account(state: &AppState, org_id: &str, id: Uuid) -> AppResult<CloudAccount> {
+    store::get_account(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| not_found(id))
+}
+
+/// List connected accounts, sorted by `name`, `provider`, `accountId` or
+/// `createdAt`, which `roleArn` can also filter on
+#[utoipa::path(
+    get,
+    path = "/api/cloud-accounts",
+    tag = "cloud-accounts",
+    params(PageParams),
+    responses(
+        (status = 200, description = "Connected accounts", body = Page<CloudAccount>),
+    )
+)]
+pub async fn list_accounts(
+    State(state): State<AppState>,
+    claims: Claims,
+    params: PageParams,
+) -> AppResult<Json<Page<CloudAccount>>> {
+    let org_id = claims.require_organization_id()?;
+    let page = params.resolve(&store::LISTING, &state.config.pagination)?;
+    Ok(Json(store::list_accounts(&state.db, org_id, &page).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/cloud-accounts/{id}",
+    tag = "cloud-accounts",
+    params(("id" = Uuid, Path, description = "Cloud account id")),
+    responses((status = 200, description = "The account", body = CloudAccount, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn get_account(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<CloudAccount>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(find_account(&state, org_id, id).await?))
+}
+
+/// Connect a cloud account (organization admins only). The response carries
+/// the external id to require in the role's trust policy.
+#[utoipa::path(
+    post,
+    path = "/api/cloud-accounts",
+    tag = "cloud-accounts",
+    request_body = NewCloudAccount,
+    responses(
+        (status = 201, description = "The connected account", body = CloudAccount, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn create_account(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<NewCloudAccount>,
+) -> AppResult<(StatusCode, Tagged<CloudAccount>)> {
+    let org_id = claims.require_organization_admin()?;
+    let account = store::create_account(&state.db, org_id, &payload)
+        .await?
+        .ok_or_else(|| {
+            AppError::Conflict(format!(
+                "{} account {} is already connected",
+                payload.provider, payload.account_id
+            ))
+        })?;
+    Ok((StatusCode::CREATED, Tagged(account)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/cloud-accounts/{id}",
+    tag = "cloud-accounts",
+    params(("id" = Uuid, Path, description = "Cloud account id"), IfMatch),
+    request_body = CloudAccountUpdate,
+    responses(
+        (status = 200, description = "The updated account", body = CloudAccount, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn update_account(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<CloudAccountUpdate>,
+) -> AppResult<Tagged<CloudAccount>> {
+    let org_id = claims.require_organization_admin()?;
+    let version = if_match.check(&find_account(&state, org_id, id).await?)?;
+    store::update_account(&state.db, org_id, id, &payload, version)
+        .await?
+        .map(Tagged)
+        .ok_or_else(|| conditional::changed_or(version, not_found(id)))
+}
+
+/// Disconnect an account, deleting its discovered resources
+#[utoipa::path(
+    delete,
+    path = "/api/cloud-accounts/{id}",
+    tag = "cloud-accounts",
+    params(("id" = Uuid, Path, description = "Cloud account id"), IfMatch),
+    responses((status = 204, description = "Disconnected"))
+)]
+pub async fn delete_account(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_admin()?;
+    let version = if_match.check(&find_account(&state, org_id, id).await?)?;
+    if store::delete_account(&state.db, org_id, id, version).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(version, not_found(id)))
+    }
+}
+
+/// Queue discovery of the account's resources
+#[utoipa::path(
+    post,
+    path = "/api/cloud-accounts/{id}/discover",
+    tag = "cloud-accounts",
+    params(("id" = Uuid, Path, description = "Cloud account id")),
+    responses(
+        (status = 202, description = "The queued discovery run", body = DiscoveryRun),
+    )
+)]
+pub async fn start_discovery(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<(StatusCode, Json<DiscoveryRun>)> {
+    let org_id = claims.require_organization_admin()?;
+    let account = store::get_account(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| not_found(id))?;
+    let run = resources::store::start_run(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| {
+            AppError::Conflict("Discovery is already running for this account".to_string())
+        })?;
+
+    let job = NewJob::new(
+        Some(org_id),
+        JobPayload::Discovery {
+            cloud_account_id: account.id,
+            run_id: run.id,
+        },
+    );
+    jobs::store::enqueue(&state.db, &job).await?;
+
+    Ok((StatusCode::ACCEPTED, Json(run)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/cloud-accounts/{id}/discovery-runs",
+    tag = "cloud-accounts",
+    params(("id" = Uuid, Path, description = "Cloud account id")),
+    responses(
+        (status = 200, description = "Discovery runs", body = Vec<DiscoveryRun>),
+    )
+)]
+pub async fn list_discovery_runs(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<DiscoveryRun>>> {
+    let org_id = claims.require_organization_id()?;
+    if store::get_account(&state.db, org_id, id).await?.is_none() {
+        return Err(not_found(id));
+    }
+    Ok(Json(
+        resources::store::list_runs(&state.db, org_id, id).await?,
+    ))
+}
@@ -0,0 +1,279 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Claims;
+use crate::cloud_ids::{Arn, AwsAccountId};
+use crate::commitments::recommend::{self, CommitmentRecommendation, RecommendationRequest, Scope};
+use crate::commitments::store;
+use crate::commitments::{
+    AlertSettings, CommitmentAlert, CommitmentSummary, CoveragePoint, UtilizationPoint,
+};
+use crate::costs::period::day_start;
+use crate::error::{AppError, AppResult};
+use crate::pricing::CommitmentRateCatalog;
+use crate::validation::{ValidatedJson, ValidatedQuery};
+use crate::AppState;
+
+/// Longest period a single report may cover
+const MAX_REPORT_DAYS: i64 = 366;
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct ReportQuery {
+    /// First day included (defaults to 30 days before `end`)
+    pub start: Option<NaiveDate>,
+    /// Day after the last day included (defaults to today)
+    pub end: Option<NaiveDate>,
+    /// Restrict utilization to one commitment
+    pub commitment_arn: Option<Arn>,
+    /// Restrict coverage to one account
+    pub account_id: Option<AwsAccountId>,
+}
+
+impl ReportQuery {
+    fn period(&self) -> AppResult<(NaiveDate, NaiveDate)> {
+        let end = self.end.unwrap_or_else(|| Utc::now().date_naive());
+        let start = self.start.unwrap_or(end - Duration::days(30));
+        if start >= end {
+            return Err(AppError::BadRequest("start must be before end".to_string()));
+        }
+        if (end - start).num_days() > MAX_REPORT_DAYS {
+            return Err(AppError::BadRequest(format!(
+                "period may not exceed {} days",
+                MAX_REPORT_DAYS
+            )));
+        }
+        Ok((start, end))
+    }
+}
+
+/// Commitments with fees in the period and their utilization over it
+#[utoipa::path(
+    get,
+    path = "/api/commitments",
+    tag = "commitments",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Commitments", body = Vec<CommitmentSummary>),
+    )
+)]
+pub async fn list_commitments(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedQuery(query): ValidatedQuery<ReportQuery>,
+) -> AppResult<Json<Vec<CommitmentSummary>>> {
+    let org_id = claims.require_organization_id()?;
+    let (start, end) = query.period()?;
+    let summaries = store::commitment_summaries(&state.db, org_id, start, end).await?;
+    Ok(Json(summaries))
+}
+
+/// Daily utilization per commitment
+#[utoipa::path(
+    get,
+    path = "/api/commitments/utilization",
+    tag = "commitments",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Daily utilization", body = Vec<UtilizationPoint>),
+    )
+)]
+pub async fn utilization(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedQuery(query): ValidatedQuery<ReportQuery>,
+) -> AppResult<Json<Vec<UtilizationPoint>>> {
+    let org_id = claims.require_organization_id()?;
+    let (start, end) = query.period()?;
+    let points = store::daily_utilization(
+        &state.db,
+        org_id,
+        start,
+        end,
+        query.commitment_arn.as_ref().map(Arn::as_str),
+    )
+    .await?;
+    Ok(Json(points))
+}
+
+/// Daily coverage per account and instance family
+#[utoipa::path(
+    get,
+    path = "/api/commitments/coverage",
+    tag = "commitments",
+    params(ReportQuery),
+    responses(
+        (status = 200, description = "Daily coverage", body = Vec<CoveragePoint>),
+    )
+)]
+pub async fn coverage(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedQuery(query): ValidatedQuery<ReportQuery>,
+) -> AppResult<Json<Vec<CoveragePoint>>> {
+    let org_id = claims.require_organization_id()?;
+    let (start, end) = query.period()?;
+    let points = store::daily_coverage(
+        &state.db,
+        org_id,
+        start,
+        end,
+        query.account_id.as_ref().map(AwsAccountId::as_str),
+    )
+    .await?;
+    Ok(Json(points))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/commitments/alert-settings",
+    tag = "commitments",
+    responses((status = 200, description = "Alert settings", body = AlertSettings))
+)]
+pub async fn get_alert_settings(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<AlertSettings>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::get_alert_settings(&state.db, org_id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/commitments/alert-settings",
+    tag = "commitments",
+    request_body = AlertSettings,
+    responses(
+        (status = 200, description = "The saved settings", body = AlertSettings),
+    )
+)]
+pub async fn put_alert_settings(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(settings): ValidatedJson<AlertSettings>,
+) -> AppResult<Json<AlertSettings>> {
+    let org_id = claims.require_organization_id()?;
+    store::put_alert_settings(&state.db, org_id, &settings).await?;
+    Ok(Json(settings))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct AlertsQuery {
+    #[serde(default)]
+    pub include_acknowledged: bool,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/commitments/alerts",
+    tag = "commitments",
+    params(AlertsQuery),
+    responses((status = 200, description = "Alerts", body = Vec<CommitmentAlert>))
+)]
+pub async fn list_alerts(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<AlertsQuery>,
+) -> AppResult<Json<Vec<CommitmentAlert>>> {
+    let org_id = claims.require_organization_id()?;
+    let alerts = store::list_alerts(&state.db, org_id, query.include_acknowledged).await?;
+    Ok(Json(alerts))
+}
+
+/// Check commitments against the alert settings now; returns newly raised alerts
+#[utoipa::path(
+    post,
+    path = "/api/commitments/alerts/evaluate",
+    tag = "commitments",
+    responses(
+        (status = 200, description = "Newly raised alerts", body = Vec<CommitmentAlert>),
+    )
+)]
+pub async fn evaluate_alerts(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<CommitmentAlert>>> {
+    let org_id = claims.require_organization_id()?;
+    let today = Utc::now().date_naive();
+    Ok(Json(
+        store::evaluate_alerts(&state.db, org_id, today).await?,
+    ))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/commitments/alerts/{id}/acknowledge",
+    tag = "commitments",
+    params(("id" = Uuid, Path, description = "Alert id")),
+    responses(
+        (status = 200, description = "The acknowledged alert", body = CommitmentAlert),
+    )
+)]
+pub async fn acknowledge_alert(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<CommitmentAlert>> {
+    let org_id = claims.require_organization_id()?;
+    store::acknowledge_alert(&state.db, org_id, id, claims.user_id())
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Commitment alert {} not found", id)))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RecommendationReport {
+    pub catalog_version: String,
+    pub currency: String,
+    pub lookback_days: u32,
+    pub risk: f64,
+    pub scope: Scope,
+    pub recommendations: Vec<CommitmentRecommendation>,
+}
+
+/// Size Savings Plan and Reserved Instance purchases against the hourly
+/// on-demand usage of the last `lookbackDays` full days
+#[utoipa::path(
+    post,
+    path = "/api/commitments/recommendations",
+    tag = "commitments",
+    request_body = RecommendationRequest,
+    responses(
+        (status = 200, description = "Recommended purchases", body = RecommendationReport),
+    )
+)]
+pub async fn recommendations(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(request): ValidatedJson<RecommendationRequest>,
+) -> AppResult<Json<RecommendationReport>> {
+    let org_id = claims.require_organization_id()?;
+
+    let end = day_start(Utc::now().date_naive());
+    let start = end - Duration::days(i64::from(request.lookback_days));
+    let (usage, covered) = store::recommendation_usage(&state.db, org_id, start, end).await?;
+
+    let catalog = CommitmentRateCatalog::bundled();
+    let hours = (end - start).num_hours() as usize;
+    let recommendations = recommend::recommend(&request, catalog, start, hours, &usage, &covered);
+
+    Ok(Json(RecommendationReport {
+        catalog_version: catalog.version.clone(),
+        currency: catalog.currency.clone(),
+        lookback_days: request.lookback_days,
+        risk: request.risk,
+        scope: request.scope,
+        recommendations,
+    }))
+}
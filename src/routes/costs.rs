@@ -0,0 +1,47 @@
+use axum::{extract::State, Json};
+
+use crate::auth::Claims;
+use crate::costs::{CostQuery, CostQueryParams, CostQueryResult};
+use crate::error::AppResult;
+use crate::validation::{ValidatedJson, ValidatedQuery};
+use crate::AppState;
+
+/// Run a filtered, grouped cost query over the organization's line items
+#[utoipa::path(
+    post,
+    path = "/api/costs/query",
+    tag = "costs",
+    request_body = CostQuery,
+    responses(
+        (status = 200, description = "Cost rows and totals", body = CostQueryResult),
+    )
+)]
+pub async fn query_costs(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(query): ValidatedJson<CostQuery>,
+) -> AppResult<Json<CostQueryResult>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(query.execute(&state.db, org_id).await?))
+}
+
+/// The cost query with its filters in the query string, for links and
+/// simple clients. Tag filters need the `POST` form.
+#[utoipa::path(
+    get,
+    path = "/api/costs",
+    tag = "costs",
+    params(CostQueryParams),
+    responses(
+        (status = 200, description = "Cost rows and totals", body = CostQueryResult),
+    )
+)]
+pub async fn get_costs(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedQuery(params): ValidatedQuery<CostQueryParams>,
+) -> AppResult<Json<CostQueryResult>> {
+    let org_id = claims.require_organization_id()?;
+    let query = CostQuery::from(params);
+    Ok(Json(query.execute(&state.db, org_id).await?))
+}
@@ -1,15 +1,25 @@
 use axum::{extract::State, http::StatusCode, Json};
 use serde::Serialize;
+use utoipa::ToSchema;
 
 use crate::AppState;
 
-#[derive(Serialize)]
+#[derive(Serialize, ToSchema)]
 pub struct HealthResponse {
     pub status: String,
     pub version: String,
     pub database: String,
 }
 
+#[utoipa::path(
+    get,
+    path = "/health",
+    tag = "health",
+    security(()),
+    responses(
+        (status = 200, description = "Service and database status", body = HealthResponse),
+    )
+)]
 pub async fn health_check(
     State(state): State<AppState>,
 ) -> Result<Json<HealthResponse>, StatusCode> {
@@ -26,6 +36,13 @@ pub async fn health_check(
     }))
 }
 
+#[utoipa::path(
+    get,
+    path = "/ready",
+    tag = "health",
+    security(()),
+    responses((status = 200, description = "The service is ready"))
+)]
 pub async fn ready_check() -> StatusCode {
     StatusCode::OK
 }
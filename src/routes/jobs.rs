@@ -0,0 +1,140 @@
+use axum::{
+    extract::{Path, State},
+    Json,
+};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::error::{AppError, AppResult};
+use crate::jobs::{store, Job, JobStatus};
+use crate::pagination::{Page, PageParams};
+use crate::AppState;
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Job {} not found", id))
+}
+
+/// The organization's background jobs (organization admins only), newest
+/// first by default. Sorted by `createdAt`, `runAt`, `status`, `kind` or
+/// `attempts`, which `finishedAt` can also filter on.
+#[utoipa::path(
+    get,
+    path = "/api/jobs",
+    tag = "jobs",
+    params(PageParams),
+    responses((status = 200, description = "Jobs", body = Page<Job>))
+)]
+pub async fn list_jobs(
+    State(state): State<AppState>,
+    claims: Claims,
+    params: PageParams,
+) -> AppResult<Json<Page<Job>>> {
+    let org_id = claims.require_organization_admin()?;
+    let page = params.resolve(&store::LISTING, &state.config.pagination)?;
+    Ok(Json(
+        store::list_jobs(&state.db, Some(org_id), &page).await?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/jobs/{id}",
+    tag = "jobs",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses((status = 200, description = "The job", body = Job))
+)]
+pub async fn get_job(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Job>> {
+    let org_id = claims.require_organization_admin()?;
+    find_job(&state, Some(org_id), id).await.map(Json)
+}
+
+/// Queue a dead job again with fresh attempts (organization admins only)
+#[utoipa::path(
+    post,
+    path = "/api/jobs/{id}/retry",
+    tag = "jobs",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses((status = 200, description = "The requeued job", body = Job))
+)]
+pub async fn retry_job(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Job>> {
+    let org_id = claims.require_organization_admin()?;
+    retry(&state, Some(org_id), id).await.map(Json)
+}
+
+/// System jobs, which belong to no organization (platform admins only),
+/// newest first by default. Sorted and filtered like `/api/jobs`.
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs",
+    tag = "jobs",
+    params(PageParams),
+    responses((status = 200, description = "System jobs", body = Page<Job>))
+)]
+pub async fn list_system_jobs(
+    State(state): State<AppState>,
+    claims: Claims,
+    params: PageParams,
+) -> AppResult<Json<Page<Job>>> {
+    claims.require_platform_admin(&state.config.platform_admins)?;
+    let page = params.resolve(&store::LISTING, &state.config.pagination)?;
+    Ok(Json(store::list_jobs(&state.db, None, &page).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/admin/jobs/{id}",
+    tag = "jobs",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses((status = 200, description = "The system job", body = Job))
+)]
+pub async fn get_system_job(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Job>> {
+    claims.require_platform_admin(&state.config.platform_admins)?;
+    find_job(&state, None, id).await.map(Json)
+}
+
+/// Queue a dead system job again with fresh attempts (platform admins only)
+#[utoipa::path(
+    post,
+    path = "/api/admin/jobs/{id}/retry",
+    tag = "jobs",
+    params(("id" = Uuid, Path, description = "Job id")),
+    responses((status = 200, description = "The requeued job", body = Job))
+)]
+pub async fn retry_system_job(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Job>> {
+    claims.require_platform_admin(&state.config.platform_admins)?;
+    retry(&state, None, id).await.map(Json)
+}
+
+async fn find_job(state: &AppState, org_id: Option<&str>, id: Uuid) -> AppResult<Job> {
+    store::get_job(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| not_found(id))
+}
+
+async fn retry(state: &AppState, org_id: Option<&str>, id: Uuid) -> AppResult<Job> {
+    let job = find_job(state, org_id, id).await?;
+    if job.status != JobStatus::Dead {
+        return Err(AppError::Conflict(
+            "Only dead jobs can be retried".to_string(),
+        ));
+    }
+    store::retry_job(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| AppError::Conflict("The same job is already queued or running".to_string()))
+}
@@ -1,8 +1,30 @@
+pub mod chargeback;
+pub mod cloud_accounts;
+pub mod commitments;
+pub mod costs;
 pub mod health;
+pub mod jobs;
+pub mod notifications;
+pub mod pricing;
+pub mod recommendations;
+pub mod resources;
+pub mod schedules;
+pub mod tags;
+pub mod unit_metrics;
 
-use axum::{middleware, routing::get, Router};
+use axum::{
+    http::Uri,
+    middleware,
+    routing::{delete, get, post},
+    Router,
+};
 
 use crate::auth::{require_auth, Claims};
+use crate::conditional;
+use crate::error::AppError;
+use crate::idempotency;
+use crate::openapi;
+use crate::request_id;
 use crate::AppState;
 
 pub fn create_router(state: AppState) -> Router {
@@ -12,32 +34,282 @@ pub fn create_router(state: AppState) -> Router {
         .route("/ready", get(health::ready_check))
         // API routes
         .nest("/api", api_routes(state.clone()))
+        .fallback(route_not_found)
+        .layer(middleware::from_fn(request_id::propagate))
         .with_state(state)
 }
 
 fn api_routes(state: AppState) -> Router<AppState> {
     // Public API routes
-    let public_routes = Router::new().route("/", get(api_root));
+    let public_routes = Router::new()
+        .route("/", get(api_root))
+        .route("/openapi.json", get(openapi::openapi_json))
+        .route("/docs", get(openapi::docs));
 
     // Protected API routes (require authentication)
-    let protected_routes =
-        Router::new()
-            .route("/me", get(get_current_user))
-            .layer(middleware::from_fn_with_state(
-                (state.jwks_cache.clone(), state.config.clerk.clone()),
-                require_auth,
-            ));
+    let protected_routes = Router::new()
+        .route("/me", get(get_current_user))
+        // Cost explorer
+        .route("/costs", get(costs::get_costs))
+        .route("/costs/query", post(costs::query_costs))
+        // Tag normalization and coverage
+        .route("/tags", get(tags::tag_coverage))
+        .route("/tags/policy", get(tags::get_policy).put(tags::put_policy))
+        .route(
+            "/tags/virtual",
+            get(tags::list_virtual_tags).post(tags::create_virtual_tag),
+        )
+        .route(
+            "/tags/virtual/:id",
+            get(tags::get_virtual_tag)
+                .put(tags::update_virtual_tag)
+                .delete(tags::delete_virtual_tag),
+        )
+        .route("/tags/renormalize", post(tags::renormalize))
+        // Chargeback
+        .route(
+            "/cost-centers",
+            get(chargeback::list_cost_centers).post(chargeback::create_cost_center),
+        )
+        .route(
+            "/cost-centers/:id",
+            get(chargeback::get_cost_center)
+                .put(chargeback::update_cost_center)
+                .delete(chargeback::delete_cost_center),
+        )
+        .route(
+            "/chargeback/rules",
+            get(chargeback::list_rate_rules).post(chargeback::create_rate_rule),
+        )
+        .route(
+            "/chargeback/rules/:id",
+            get(chargeback::get_rate_rule).delete(chargeback::delete_rate_rule),
+        )
+        .route("/chargeback/statements", get(chargeback::list_statements))
+        .route(
+            "/chargeback/statements/generate",
+            post(chargeback::generate_statements),
+        )
+        .route("/chargeback/statements/:id", get(chargeback::get_statement))
+        .route(
+            "/chargeback/statements/:id/close",
+            post(chargeback::close_statement),
+        )
+        .route(
+            "/chargeback/statements/:id/export",
+            get(chargeback::export_statement),
+        )
+        // Unit economics
+        .route(
+            "/business-metrics",
+            get(unit_metrics::list_business_metrics).post(unit_metrics::create_business_metric),
+        )
+        .route(
+            "/business-metrics/:id",
+            get(unit_metrics::get_business_metric).delete(unit_metrics::delete_business_metric),
+        )
+        .route(
+            "/business-metrics/:id/values",
+            get(unit_metrics::list_metric_values).post(unit_metrics::push_metric_values),
+        )
+        .route(
+            "/business-metrics/:id/values/csv",
+            post(unit_metrics::upload_metric_values_csv),
+        )
+        .route(
+            "/unit-metrics",
+            get(unit_metrics::list_unit_metrics).post(unit_metrics::create_unit_metric),
+        )
+        .route(
+            "/unit-metrics/:id",
+            get(unit_metrics::get_unit_metric)
+                .put(unit_metrics::update_unit_metric)
+                .delete(unit_metrics::delete_unit_metric),
+        )
+        .route(
+            "/unit-metrics/:id/series",
+            get(unit_metrics::unit_metric_series),
+        )
+        // Commitments
+        .route("/commitments", get(commitments::list_commitments))
+        .route("/commitments/utilization", get(commitments::utilization))
+        .route("/commitments/coverage", get(commitments::coverage))
+        .route(
+            "/commitments/recommendations",
+            post(commitments::recommendations),
+        )
+        .route(
+            "/commitments/alert-settings",
+            get(commitments::get_alert_settings).put(commitments::put_alert_settings),
+        )
+        .route("/commitments/alerts", get(commitments::list_alerts))
+        .route(
+            "/commitments/alerts/evaluate",
+            post(commitments::evaluate_alerts),
+        )
+        .route(
+            "/commitments/alerts/:id/acknowledge",
+            post(commitments::acknowledge_alert),
+        )
+        // Pricing catalog
+        .route("/pricing", get(pricing::lookup_prices))
+        .route("/pricing/catalogs", get(pricing::list_catalogs))
+        .route("/pricing/catalogs/sync", post(pricing::sync_catalogs))
+        // Cloud accounts and resource inventory
+        .route(
+            "/cloud-accounts",
+            get(cloud_accounts::list_accounts).post(cloud_accounts::create_account),
+        )
+        .route(
+            "/cloud-accounts/:id",
+            get(cloud_accounts::get_account)
+                .put(cloud_accounts::update_account)
+                .delete(cloud_accounts::delete_account),
+        )
+        .route(
+            "/cloud-accounts/:id/discover",
+            post(cloud_accounts::start_discovery),
+        )
+        .route(
+            "/cloud-accounts/:id/discovery-runs",
+            get(cloud_accounts::list_discovery_runs),
+        )
+        .route("/resources", get(resources::list_resources))
+        .route("/resources/:id", get(resources::get_resource))
+        .route("/resources/:id/history", get(resources::resource_history))
+        .route("/resources/:id/costs", get(resources::resource_costs))
+        .route(
+            "/resources/:id/metrics",
+            get(resources::resource_metrics).post(resources::push_resource_metrics),
+        )
+        .route(
+            "/resources/:id/metrics/import",
+            post(resources::import_resource_metrics),
+        )
+        // Recommendations
+        .route("/recommendations/waste", get(recommendations::list_waste))
+        .route(
+            "/recommendations/waste/summary",
+            get(recommendations::waste_summary),
+        )
+        .route(
+            "/recommendations/waste/evaluate",
+            post(recommendations::evaluate_waste),
+        )
+        .route(
+            "/recommendations/waste/settings",
+            get(recommendations::get_waste_settings).put(recommendations::put_waste_settings),
+        )
+        .route(
+            "/recommendations/waste/:id",
+            get(recommendations::get_waste_finding),
+        )
+        .route(
+            "/recommendations/waste/:id/snooze",
+            post(recommendations::snooze_waste_finding),
+        )
+        .route(
+            "/recommendations/waste/:id/dismiss",
+            post(recommendations::dismiss_waste_finding),
+        )
+        .route(
+            "/recommendations/waste/:id/reopen",
+            post(recommendations::reopen_waste_finding),
+        )
+        .route(
+            "/recommendations/rightsizing",
+            post(recommendations::rightsizing),
+        )
+        // Start/stop schedules
+        .route(
+            "/schedules",
+            get(schedules::list_schedules).post(schedules::create_schedule),
+        )
+        .route(
+            "/schedules/:id",
+            get(schedules::get_schedule)
+                .put(schedules::update_schedule)
+                .delete(schedules::delete_schedule),
+        )
+        .route(
+            "/schedules/:id/resources",
+            get(schedules::schedule_resources),
+        )
+        .route("/schedules/:id/savings", get(schedules::schedule_savings))
+        .route("/schedules/:id/executions", get(schedules::list_executions))
+        .route("/schedules/:id/run", post(schedules::run_schedule))
+        .route(
+            "/schedules/:id/overrides",
+            get(schedules::list_overrides).post(schedules::create_override),
+        )
+        .route(
+            "/schedules/:id/overrides/:override_id",
+            delete(schedules::delete_override),
+        )
+        // Notification channels
+        .route(
+            "/notification-channels",
+            get(notifications::list_channels).post(notifications::create_channel),
+        )
+        .route(
+            "/notification-channels/:id",
+            get(notifications::get_channel)
+                .put(notifications::update_channel)
+                .delete(notifications::delete_channel),
+        )
+        .route(
+            "/notification-channels/:id/test",
+            post(notifications::test_channel),
+        )
+        .route(
+            "/notification-channels/:id/deliveries",
+            get(notifications::list_deliveries),
+        )
+        // Background jobs
+        .route("/jobs", get(jobs::list_jobs))
+        .route("/jobs/:id", get(jobs::get_job))
+        .route("/jobs/:id/retry", post(jobs::retry_job))
+        .route("/admin/jobs", get(jobs::list_system_jobs))
+        .route("/admin/jobs/:id", get(jobs::get_system_job))
+        .route("/admin/jobs/:id/retry", post(jobs::retry_system_job))
+        .layer(middleware::from_fn(conditional::not_modified))
+        // Runs after authentication, which it needs the claims of
+        .layer(middleware::from_fn_with_state(
+            state.clone(),
+            idempotency::deduplicate,
+        ))
+        .layer(middleware::from_fn_with_state(
+            (state.jwks_cache.clone(), state.config.clerk.clone()),
+            require_auth,
+        ));
 
     // Merge public and protected routes
     public_routes.merge(protected_routes)
 }
 
-async fn api_root() -> &'static str {
+async fn route_not_found(uri: Uri) -> AppError {
+    AppError::NotFound(format!("No route for {}", uri.path()))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api",
+    tag = "health",
+    security(()),
+    responses((status = 200, description = "API name and version", body = String))
+)]
+pub async fn api_root() -> &'static str {
     "Scho1ar API v0.1.0"
 }
 
 /// Example protected endpoint that returns the current user's claims
-async fn get_current_user(claims: Claims) -> axum::Json<serde_json::Value> {
+#[utoipa::path(
+    get,
+    path = "/api/me",
+    tag = "account",
+    responses((status = 200, description = "User, organization and role ids", body = Object))
+)]
+pub async fn get_current_user(claims: Claims) -> axum::Json<serde_json::Value> {
     axum::Json(serde_json::json!({
         "userId": claims.user_id(),
         "organizationId": claims.organization_id(),
@@ -0,0 +1,189 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::conditional::{self, IfMatch, Tagged};
+use crate::error::{AppError, AppResult};
+use crate::notifications::send::allowed_url;
+use crate::notifications::store;
+use crate::notifications::{ChannelInput, Delivery, NotificationChannel};
+use crate::pagination::{Page, PageParams};
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Notification channel {} not found", id))
+}
+
+async fn find_channel(state: &AppState, org_id: &str, id: Uuid) -> AppResult<NotificationChannel> {
+    store::get_channel(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| not_found(id))
+}
+
+fn check_url(state: &AppState, input: &ChannelInput) -> AppResult<()> {
+    match &input.url {
+        Some(url) if !allowed_url(&state.config.notifications, url) => Err(AppError::Validation(
+            format!("url: '{}' must use HTTPS and a public host", url),
+        )),
+        _ => Ok(()),
+    }
+}
+
+// Channels hold webhook URLs and signing secrets, so all of these are for
+// organization admins only
+
+#[utoipa::path(
+    get,
+    path = "/api/notification-channels",
+    tag = "notifications",
+    responses(
+        (status = 200, description = "Notification channels", body = Vec<NotificationChannel>),
+    )
+)]
+pub async fn list_channels(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<NotificationChannel>>> {
+    let org_id = claims.require_organization_admin()?;
+    Ok(Json(store::list_channels(&state.db, org_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/notification-channels/{id}",
+    tag = "notifications",
+    params(("id" = Uuid, Path, description = "Channel id")),
+    responses(
+        (status = 200, description = "The channel", body = NotificationChannel, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn get_channel(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<NotificationChannel>> {
+    let org_id = claims.require_organization_admin()?;
+    Ok(Tagged(find_channel(&state, org_id, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/notification-channels",
+    tag = "notifications",
+    request_body = ChannelInput,
+    responses(
+        (status = 201, description = "The created channel", body = NotificationChannel, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn create_channel(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<ChannelInput>,
+) -> AppResult<(StatusCode, Tagged<NotificationChannel>)> {
+    let org_id = claims.require_organization_admin()?;
+    check_url(&state, &payload)?;
+    let channel = store::create_channel(&state.db, org_id, &payload, claims.user_id()).await?;
+    Ok((StatusCode::CREATED, Tagged(channel)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/notification-channels/{id}",
+    tag = "notifications",
+    params(("id" = Uuid, Path, description = "Channel id"), IfMatch),
+    request_body = ChannelInput,
+    responses(
+        (status = 200, description = "The updated channel", body = NotificationChannel, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn update_channel(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<ChannelInput>,
+) -> AppResult<Tagged<NotificationChannel>> {
+    let org_id = claims.require_organization_admin()?;
+    check_url(&state, &payload)?;
+    let version = if_match.check(&find_channel(&state, org_id, id).await?)?;
+    store::update_channel(&state.db, org_id, id, &payload, version)
+        .await?
+        .map(Tagged)
+        .ok_or_else(|| conditional::changed_or(version, not_found(id)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/notification-channels/{id}",
+    tag = "notifications",
+    params(("id" = Uuid, Path, description = "Channel id"), IfMatch),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_channel(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_admin()?;
+    let version = if_match.check(&find_channel(&state, org_id, id).await?)?;
+    if store::delete_channel(&state.db, org_id, id, version).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(version, not_found(id)))
+    }
+}
+
+/// Queue a test message to the channel; its outcome shows in the
+/// delivery log
+#[utoipa::path(
+    post,
+    path = "/api/notification-channels/{id}/test",
+    tag = "notifications",
+    params(("id" = Uuid, Path, description = "Channel id")),
+    responses((status = 202, description = "The queued delivery", body = Delivery))
+)]
+pub async fn test_channel(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<(StatusCode, Json<Delivery>)> {
+    let org_id = claims.require_organization_admin()?;
+    let channel = find_channel(&state, org_id, id).await?;
+    let delivery = store::send_test(&state.db, org_id, &channel).await?;
+    Ok((StatusCode::ACCEPTED, Json(delivery)))
+}
+
+/// Messages sent or being sent through the channel, newest first by
+/// default. Sorted by `createdAt`, `event`, `status` or `attempts`, which
+/// `deliveredAt` can also filter on.
+#[utoipa::path(
+    get,
+    path = "/api/notification-channels/{id}/deliveries",
+    tag = "notifications",
+    params(
+        ("id" = Uuid, Path, description = "Channel id"),
+        PageParams,
+    ),
+    responses(
+        (status = 200, description = "Deliveries", body = Page<Delivery>),
+    )
+)]
+pub async fn list_deliveries(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    params: PageParams,
+) -> AppResult<Json<Page<Delivery>>> {
+    let org_id = claims.require_organization_admin()?;
+    find_channel(&state, org_id, id).await?;
+    let page = params.resolve(&store::DELIVERY_LISTING, &state.config.pagination)?;
+    Ok(Json(
+        store::list_deliveries(&state.db, org_id, id, &page).await?,
+    ))
+}
@@ -0,0 +1,80 @@
+use std::collections::HashMap;
+
+use axum::{
+    extract::{Query, State},
+    Json,
+};
+
+use crate::auth::Claims;
+use crate::costs::Provider;
+use crate::error::{AppError, AppResult};
+use crate::pricing::loader::{self, LoadOutcome};
+use crate::pricing::store;
+use crate::pricing::{Price, PriceCatalog, PriceQuery};
+use crate::AppState;
+
+/// Look up list prices. Searches the latest catalog of each price file
+/// unless `catalogId` or `version` pins one.
+#[utoipa::path(
+    get,
+    path = "/api/pricing",
+    tag = "pricing",
+    params(
+        ("provider" = Option<Provider>, Query),
+        ("catalogId" = Option<Uuid>, Query, description = "Catalog to search instead of the latest ones"),
+        ("version" = Option<String>, Query, description = "Catalog version to search"),
+        ("service" = Option<String>, Query),
+        ("productFamily" = Option<String>, Query),
+        ("sku" = Option<String>, Query),
+        ("region" = Option<String>, Query),
+        ("priceType" = Option<String>, Query),
+        ("limit" = Option<i64>, Query, minimum = 1, maximum = 1000, description = "Defaults to 100"),
+        ("attr.<name>" = Option<String>, Query, description = "Product attribute to match, e.g. `attr.instanceType=m5.large`"),
+    ),
+    responses((status = 200, description = "Matching prices", body = Vec<Price>))
+)]
+pub async fn lookup_prices(
+    State(state): State<AppState>,
+    _claims: Claims,
+    Query(params): Query<HashMap<String, String>>,
+) -> AppResult<Json<Vec<Price>>> {
+    let query = PriceQuery::from_params(params).map_err(AppError::BadRequest)?;
+    Ok(Json(store::lookup_prices(&state.db, &query).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/pricing/catalogs",
+    tag = "pricing",
+    responses(
+        (status = 200, description = "Loaded price catalogs", body = Vec<PriceCatalog>),
+    )
+)]
+pub async fn list_catalogs(
+    State(state): State<AppState>,
+    _claims: Claims,
+) -> AppResult<Json<Vec<PriceCatalog>>> {
+    Ok(Json(store::list_catalogs(&state.db).await?))
+}
+
+/// Load price files added to the pricing directory (organization admins only)
+#[utoipa::path(
+    post,
+    path = "/api/pricing/catalogs/sync",
+    tag = "pricing",
+    responses(
+        (status = 200, description = "Outcome of each price file", body = Vec<LoadOutcome>),
+    )
+)]
+pub async fn sync_catalogs(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<LoadOutcome>>> {
+    claims.require_organization_admin()?;
+    let dir =
+        state.config.pricing_data_dir.as_ref().ok_or_else(|| {
+            AppError::BadRequest("PRICING_DATA_DIR is not configured".to_string())
+        })?;
+
+    Ok(Json(loader::sync_directory(&state.db, dir).await?))
+}
@@ -0,0 +1,315 @@
+use axum::{
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Claims;
+use crate::error::{AppError, AppResult};
+use crate::pagination::{Page, PageParams};
+use crate::recommendations::store;
+use crate::recommendations::{
+    FindingQuery, FindingStatus, RightsizingRecommendation, RightsizingRequest, SkippedInstance,
+    WasteFinding, WasteSettings, WasteSummary,
+};
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Waste finding {} not found", id))
+}
+
+/// Waste findings, open ones unless `status` says otherwise, filtered by
+/// `rule`, `severity` and `resourceId`. Sorted by `estimatedMonthlySavings`
+/// (largest first by default), `firstDetectedAt`, `lastDetectedAt`,
+/// `resourceType`, `accountId` or `region`, which `resolvedAt` can also
+/// filter on.
+#[utoipa::path(
+    get,
+    path = "/api/recommendations/waste",
+    tag = "recommendations",
+    params(FindingQuery, PageParams),
+    responses((status = 200, description = "Findings", body = Page<WasteFinding>))
+)]
+pub async fn list_waste(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<FindingQuery>,
+    page: PageParams,
+) -> AppResult<Json<Page<WasteFinding>>> {
+    let org_id = claims.require_organization_id()?;
+    let page = page.resolve(&store::LISTING, &state.config.pagination)?;
+    Ok(Json(
+        store::list_findings(&state.db, org_id, &query, &page).await?,
+    ))
+}
+
+/// Number and estimated savings of the findings `list_waste` would list
+#[utoipa::path(
+    get,
+    path = "/api/recommendations/waste/summary",
+    tag = "recommendations",
+    params(FindingQuery),
+    responses((status = 200, description = "Finding totals", body = WasteSummary))
+)]
+pub async fn waste_summary(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<FindingQuery>,
+) -> AppResult<Json<WasteSummary>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(
+        store::summarize_findings(&state.db, org_id, &query).await?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/recommendations/waste/{id}",
+    tag = "recommendations",
+    params(("id" = Uuid, Path, description = "Finding id")),
+    responses((status = 200, description = "The finding", body = WasteFinding))
+)]
+pub async fn get_waste_finding(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<WasteFinding>> {
+    let org_id = claims.require_organization_id()?;
+    store::get_finding(&state.db, org_id, id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| not_found(id))
+}
+
+/// Run the waste rules now; returns newly opened findings
+#[utoipa::path(
+    post,
+    path = "/api/recommendations/waste/evaluate",
+    tag = "recommendations",
+    responses(
+        (status = 200, description = "Newly opened findings", body = Vec<WasteFinding>),
+    )
+)]
+pub async fn evaluate_waste(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<WasteFinding>>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::evaluate(&state.db, org_id, Utc::now()).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/recommendations/waste/settings",
+    tag = "recommendations",
+    responses(
+        (status = 200, description = "Waste rule settings", body = WasteSettings),
+    )
+)]
+pub async fn get_waste_settings(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<WasteSettings>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::get_settings(&state.db, org_id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/recommendations/waste/settings",
+    tag = "recommendations",
+    request_body = WasteSettings,
+    responses(
+        (status = 200, description = "The saved settings", body = WasteSettings),
+    )
+)]
+pub async fn put_waste_settings(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(settings): ValidatedJson<WasteSettings>,
+) -> AppResult<Json<WasteSettings>> {
+    let org_id = claims.require_organization_id()?;
+    store::put_settings(&state.db, org_id, &settings).await?;
+    Ok(Json(settings))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SnoozeRequest {
+    /// The finding reopens at this time if still detected
+    pub until: DateTime<Utc>,
+    #[validate(length(min = 1, max = 1000))]
+    #[schema(min_length = 1, max_length = 1000)]
+    pub reason: String,
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct DismissRequest {
+    #[validate(length(min = 1, max = 1000))]
+    #[schema(min_length = 1, max_length = 1000)]
+    pub reason: String,
+}
+
+/// Findings whose condition no longer holds cannot change status
+async fn require_unresolved(state: &AppState, org_id: &str, id: Uuid) -> AppResult<()> {
+    let finding = store::get_finding(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| not_found(id))?;
+    if finding.status == FindingStatus::Resolved {
+        return Err(AppError::Conflict(format!(
+            "Waste finding {} is already resolved",
+            id
+        )));
+    }
+    Ok(())
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/recommendations/waste/{id}/snooze",
+    tag = "recommendations",
+    params(("id" = Uuid, Path, description = "Finding id")),
+    request_body = SnoozeRequest,
+    responses(
+        (status = 200, description = "The snoozed finding", body = WasteFinding),
+    )
+)]
+pub async fn snooze_waste_finding(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    ValidatedJson(request): ValidatedJson<SnoozeRequest>,
+) -> AppResult<Json<WasteFinding>> {
+    let org_id = claims.require_organization_id()?;
+    if request.until <= Utc::now() {
+        return Err(AppError::BadRequest(
+            "until must be in the future".to_string(),
+        ));
+    }
+    require_unresolved(&state, org_id, id).await?;
+    store::set_status(
+        &state.db,
+        org_id,
+        id,
+        FindingStatus::Snoozed,
+        Some(request.until),
+        Some(&request.reason),
+        claims.user_id(),
+    )
+    .await?
+    .map(Json)
+    .ok_or_else(|| not_found(id))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/recommendations/waste/{id}/dismiss",
+    tag = "recommendations",
+    params(("id" = Uuid, Path, description = "Finding id")),
+    request_body = DismissRequest,
+    responses(
+        (status = 200, description = "The dismissed finding", body = WasteFinding),
+    )
+)]
+pub async fn dismiss_waste_finding(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    ValidatedJson(request): ValidatedJson<DismissRequest>,
+) -> AppResult<Json<WasteFinding>> {
+    let org_id = claims.require_organization_id()?;
+    require_unresolved(&state, org_id, id).await?;
+    store::set_status(
+        &state.db,
+        org_id,
+        id,
+        FindingStatus::Dismissed,
+        None,
+        Some(&request.reason),
+        claims.user_id(),
+    )
+    .await?
+    .map(Json)
+    .ok_or_else(|| not_found(id))
+}
+
+/// Undo a snooze or dismissal
+#[utoipa::path(
+    post,
+    path = "/api/recommendations/waste/{id}/reopen",
+    tag = "recommendations",
+    params(("id" = Uuid, Path, description = "Finding id")),
+    responses(
+        (status = 200, description = "The reopened finding", body = WasteFinding),
+    )
+)]
+pub async fn reopen_waste_finding(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<WasteFinding>> {
+    let org_id = claims.require_organization_id()?;
+    require_unresolved(&state, org_id, id).await?;
+    store::set_status(
+        &state.db,
+        org_id,
+        id,
+        FindingStatus::Open,
+        None,
+        None,
+        claims.user_id(),
+    )
+    .await?
+    .map(Json)
+    .ok_or_else(|| not_found(id))
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RightsizingReport {
+    pub lookback_days: u32,
+    pub headroom: f64,
+    /// Sum of the recommendations' savings
+    pub estimated_monthly_savings: f64,
+    pub currency: String,
+    pub recommendations: Vec<RightsizingRecommendation>,
+    pub skipped: Vec<SkippedInstance>,
+}
+
+/// Propose cheaper instance types for running EC2 instances from their p95
+/// utilization over the last `lookbackDays` days plus `headroom`
+#[utoipa::path(
+    post,
+    path = "/api/recommendations/rightsizing",
+    tag = "recommendations",
+    request_body = RightsizingRequest,
+    responses(
+        (status = 200, description = "Rightsizing recommendations", body = RightsizingReport),
+    )
+)]
+pub async fn rightsizing(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(request): ValidatedJson<RightsizingRequest>,
+) -> AppResult<Json<RightsizingReport>> {
+    let org_id = claims.require_organization_id()?;
+    let (recommendations, skipped) =
+        store::rightsize(&state.db, org_id, &request, Utc::now()).await?;
+
+    Ok(Json(RightsizingReport {
+        lookback_days: request.lookback_days,
+        headroom: request.headroom,
+        estimated_monthly_savings: recommendations
+            .iter()
+            .map(|r| r.estimated_monthly_savings)
+            .sum(),
+        currency: "USD".to_string(),
+        recommendations,
+        skipped,
+    }))
+}
@@ -0,0 +1,320 @@
+use std::collections::HashMap;
+
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    Json,
+};
+use chrono::{DateTime, Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Claims;
+use crate::costs::{CostFilter, CostQuery, CostQueryResult, Granularity};
+use crate::error::{AppError, AppResult};
+use crate::pagination::{Page, PageParams};
+use crate::resources::store;
+use crate::resources::{Resource, ResourceQuery, ResourceType, ResourceVersion};
+use crate::utilization::import::parse_cloudwatch;
+use crate::utilization::{self, MetricSample, MetricStats, UtilizationMetric};
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+/// List discovered resources, filtered by `cloudAccountId`, `resourceType`,
+/// `region`, `state`, `search` and `tag.<key>` parameters. Deleted resources
+/// are left out unless `includeDeleted=true`. Pages sort by `resourceType`,
+/// `providerId`, `region`, `provider`, `accountId`, `cloudAccountId`,
+/// `firstSeenAt` or `lastSeenAt`, and can also filter on `name` and `state`.
+#[utoipa::path(
+    get,
+    path = "/api/resources",
+    tag = "resources",
+    params(
+        ("cloudAccountId" = Option<Uuid>, Query),
+        ("resourceType" = Option<ResourceType>, Query),
+        ("region" = Option<String>, Query),
+        ("state" = Option<String>, Query),
+        ("search" = Option<String>, Query),
+        ("includeDeleted" = Option<bool>, Query),
+        ("tag.<key>" = Option<String>, Query, description = "Tag value to match, e.g. `tag.team=data`"),
+        PageParams,
+    ),
+    responses((status = 200, description = "Resources", body = Page<Resource>))
+)]
+pub async fn list_resources(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(params): Query<HashMap<String, String>>,
+    page: PageParams,
+) -> AppResult<Json<Page<Resource>>> {
+    let org_id = claims.require_organization_id()?;
+    let query = ResourceQuery::from_params(params).map_err(AppError::BadRequest)?;
+    let page = page.resolve(&store::LISTING, &state.config.pagination)?;
+    Ok(Json(
+        store::list_resources(&state.db, org_id, &query, &page).await?,
+    ))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/resources/{id}",
+    tag = "resources",
+    params(("id" = Uuid, Path, description = "Resource id")),
+    responses((status = 200, description = "The resource", body = Resource))
+)]
+pub async fn get_resource(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Resource>> {
+    let org_id = claims.require_organization_id()?;
+    store::get_resource(&state.db, org_id, id)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Resource {} not found", id)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[serde(rename_all = "camelCase")]
+#[into_params(parameter_in = Query)]
+pub struct HistoryQuery {
+    /// Include the full resource as observed at each version
+    #[serde(default)]
+    pub include_snapshots: bool,
+}
+
+/// Versions of a resource, newest first, with the fields each one changed
+#[utoipa::path(
+    get,
+    path = "/api/resources/{id}/history",
+    tag = "resources",
+    params(
+        ("id" = Uuid, Path, description = "Resource id"),
+        HistoryQuery,
+    ),
+    responses(
+        (status = 200, description = "Versions, newest first", body = Vec<ResourceVersion>),
+    )
+)]
+pub async fn resource_history(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    Query(query): Query<HistoryQuery>,
+) -> AppResult<Json<Vec<ResourceVersion>>> {
+    let org_id = claims.require_organization_id()?;
+    store::resource_history(&state.db, org_id, id, query.include_snapshots)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::NotFound(format!("Resource {} not found", id)))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ResourceCostsQuery {
+    /// First day included (defaults to 30 days before `end`)
+    pub start: Option<NaiveDate>,
+    /// Day after the last day included (defaults to tomorrow)
+    pub end: Option<NaiveDate>,
+    /// Defaults to daily
+    pub granularity: Option<Granularity>,
+}
+
+/// Spend of the line items linked to a resource over time
+#[utoipa::path(
+    get,
+    path = "/api/resources/{id}/costs",
+    tag = "resources",
+    params(
+        ("id" = Uuid, Path, description = "Resource id"),
+        ResourceCostsQuery,
+    ),
+    responses(
+        (status = 200, description = "Costs linked to the resource", body = CostQueryResult),
+    )
+)]
+pub async fn resource_costs(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ResourceCostsQuery>,
+) -> AppResult<Json<CostQueryResult>> {
+    let org_id = claims.require_organization_id()?;
+    require_resource(&state, org_id, id).await?;
+
+    let end = query
+        .end
+        .unwrap_or_else(|| Utc::now().date_naive() + Duration::days(1));
+    let start = query.start.unwrap_or(end - Duration::days(30));
+    if start >= end {
+        return Err(AppError::BadRequest("start must be before end".to_string()));
+    }
+
+    let query = CostQuery {
+        start,
+        end,
+        granularity: query.granularity.unwrap_or(Granularity::Daily),
+        filter: CostFilter {
+            resource_ids: vec![id],
+            ..CostFilter::default()
+        },
+        group_by: Vec::new(),
+    };
+    Ok(Json(query.execute(&state.db, org_id).await?))
+}
+
+/// Most samples accepted in a single push or import
+const MAX_SAMPLES_PER_REQUEST: usize = 50_000;
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct MetricSamplesRequest {
+    #[validate(length(min = 1, max = 50000), nested)]
+    #[schema(min_items = 1, max_items = 50000)]
+    pub samples: Vec<MetricSample>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MetricSamplesResponse {
+    pub written: u64,
+}
+
+async fn require_resource(state: &AppState, org_id: &str, id: Uuid) -> AppResult<()> {
+    match store::get_resource(&state.db, org_id, id).await? {
+        Some(_) => Ok(()),
+        None => Err(AppError::NotFound(format!("Resource {} not found", id))),
+    }
+}
+
+/// Push utilization samples; samples for the same metric and time are replaced
+#[utoipa::path(
+    post,
+    path = "/api/resources/{id}/metrics",
+    tag = "resources",
+    params(("id" = Uuid, Path, description = "Resource id")),
+    request_body = MetricSamplesRequest,
+    responses(
+        (status = 200, description = "Number of samples written", body = MetricSamplesResponse),
+    )
+)]
+pub async fn push_resource_metrics(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<MetricSamplesRequest>,
+) -> AppResult<Json<MetricSamplesResponse>> {
+    let org_id = claims.require_organization_id()?;
+    require_resource(&state, org_id, id).await?;
+    let written =
+        utilization::store::insert_samples(&state.db, org_id, id, &payload.samples).await?;
+    Ok(Json(MetricSamplesResponse { written }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ImportQuery {
+    /// Period of the exported datapoints in seconds (defaults to 300)
+    pub period: Option<u32>,
+}
+
+/// Import the JSON output of `aws cloudwatch get-metric-statistics` or
+/// `get-metric-data` for the resource
+#[utoipa::path(
+    post,
+    path = "/api/resources/{id}/metrics/import",
+    tag = "resources",
+    params(
+        ("id" = Uuid, Path, description = "Resource id"),
+        ImportQuery,
+    ),
+    request_body(
+        content = Object,
+        description = "Output of `get-metric-statistics` or `get-metric-data`"
+    ),
+    responses(
+        (status = 200, description = "Number of samples written", body = MetricSamplesResponse),
+    )
+)]
+pub async fn import_resource_metrics(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ImportQuery>,
+    body: Bytes,
+) -> AppResult<Json<MetricSamplesResponse>> {
+    let org_id = claims.require_organization_id()?;
+    require_resource(&state, org_id, id).await?;
+
+    let samples =
+        parse_cloudwatch(&body, query.period.unwrap_or(300)).map_err(AppError::Validation)?;
+    if samples.len() > MAX_SAMPLES_PER_REQUEST {
+        return Err(AppError::Validation(format!(
+            "at most {} samples may be imported at once",
+            MAX_SAMPLES_PER_REQUEST
+        )));
+    }
+    for sample in &samples {
+        sample
+            .validate()
+            .map_err(|e| AppError::Validation(e.to_string()))?;
+    }
+
+    let written = utilization::store::insert_samples(&state.db, org_id, id, &samples).await?;
+    Ok(Json(MetricSamplesResponse { written }))
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct MetricsQuery {
+    pub metric: Option<UtilizationMetric>,
+    /// Defaults to 14 days before `end`
+    pub start: Option<DateTime<Utc>>,
+    /// Defaults to now
+    pub end: Option<DateTime<Utc>>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct ResourceMetrics {
+    pub stats: Vec<MetricStats>,
+    pub samples: Vec<MetricSample>,
+}
+
+/// Utilization samples of a resource and their statistics per metric
+#[utoipa::path(
+    get,
+    path = "/api/resources/{id}/metrics",
+    tag = "resources",
+    params(
+        ("id" = Uuid, Path, description = "Resource id"),
+        MetricsQuery,
+    ),
+    responses(
+        (status = 200, description = "Utilization samples and statistics", body = ResourceMetrics),
+    )
+)]
+pub async fn resource_metrics(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    Query(query): Query<MetricsQuery>,
+) -> AppResult<Json<ResourceMetrics>> {
+    let org_id = claims.require_organization_id()?;
+    require_resource(&state, org_id, id).await?;
+
+    let end = query.end.unwrap_or_else(Utc::now);
+    let start = query.start.unwrap_or(end - Duration::days(14));
+    if start >= end {
+        return Err(AppError::BadRequest("start must be before end".to_string()));
+    }
+
+    let stats = utilization::store::stats(&state.db, &[id], start, end)
+        .await?
+        .remove(&id)
+        .unwrap_or_default()
+        .into_iter()
+        .filter(|s| query.metric.is_none_or(|metric| s.metric == metric))
+        .collect();
+    let samples = utilization::store::list_samples(&state.db, id, query.metric, start, end).await?;
+    Ok(Json(ResourceMetrics { stats, samples }))
+}
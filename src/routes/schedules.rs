@@ -0,0 +1,330 @@
+use axum::{
+    extract::{Path, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::Utc;
+use uuid::Uuid;
+
+use crate::auth::Claims;
+use crate::conditional::{self, IfMatch, Tagged};
+use crate::error::{AppError, AppResult};
+use crate::pagination::{Page, PageParams};
+use crate::schedules::schedule::desired_state;
+use crate::schedules::{store, worker};
+use crate::schedules::{
+    NewOverride, Schedule, ScheduleExecution, ScheduleInput, ScheduleOverride, ScheduleSavings,
+    ScheduledResource,
+};
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Schedule {} not found", id))
+}
+
+async fn find_schedule(state: &AppState, org_id: &str, id: Uuid) -> AppResult<Schedule> {
+    store::get_schedule(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| not_found(id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/schedules",
+    tag = "schedules",
+    responses((status = 200, description = "Schedules", body = Vec<Schedule>))
+)]
+pub async fn list_schedules(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<Schedule>>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::list_schedules(&state.db, org_id).await?))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/schedules/{id}",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id")),
+    responses((status = 200, description = "The schedule", body = Schedule, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn get_schedule(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<Schedule>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(find_schedule(&state, org_id, id).await?))
+}
+
+/// Create a schedule (organization admins only)
+#[utoipa::path(
+    post,
+    path = "/api/schedules",
+    tag = "schedules",
+    request_body = ScheduleInput,
+    responses((status = 201, description = "The created schedule", body = Schedule, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn create_schedule(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<ScheduleInput>,
+) -> AppResult<(StatusCode, Tagged<Schedule>)> {
+    let org_id = claims.require_organization_admin()?;
+    let schedule = store::create_schedule(&state.db, org_id, &payload, claims.user_id()).await?;
+    Ok((StatusCode::CREATED, Tagged(schedule)))
+}
+
+/// Replace a schedule (organization admins only)
+#[utoipa::path(
+    put,
+    path = "/api/schedules/{id}",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id"), IfMatch),
+    request_body = ScheduleInput,
+    responses((status = 200, description = "The updated schedule", body = Schedule, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn update_schedule(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<ScheduleInput>,
+) -> AppResult<Tagged<Schedule>> {
+    let org_id = claims.require_organization_admin()?;
+    let version = if_match.check(&find_schedule(&state, org_id, id).await?)?;
+    store::update_schedule(&state.db, org_id, id, &payload, version)
+        .await?
+        .map(Tagged)
+        .ok_or_else(|| conditional::changed_or(version, not_found(id)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/schedules/{id}",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id"), IfMatch),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_schedule(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_admin()?;
+    let version = if_match.check(&find_schedule(&state, org_id, id).await?)?;
+    if store::delete_schedule(&state.db, org_id, id, version).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(version, not_found(id)))
+    }
+}
+
+/// Resources the schedule applies to and the state it wants them in now
+#[utoipa::path(
+    get,
+    path = "/api/schedules/{id}/resources",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id")),
+    responses(
+        (status = 200, description = "Scheduled resources", body = Vec<ScheduledResource>),
+    )
+)]
+pub async fn schedule_resources(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<ScheduledResource>>> {
+    let org_id = claims.require_organization_id()?;
+    let schedule = find_schedule(&state, org_id, id).await?;
+
+    let now = Utc::now();
+    let overrides = store::active_overrides(&state.db, id, now).await?;
+    let resources = store::targets(&state.db, org_id, &schedule, now)
+        .await?
+        .into_iter()
+        .map(|target| {
+            let (desired_state, trigger) = desired_state(&schedule, &overrides, target.id, now);
+            ScheduledResource {
+                resource_id: target.id,
+                resource_type: target.resource_type,
+                provider_id: target.provider_id,
+                name: target.name,
+                region: target.region,
+                state: target.state,
+                desired_state,
+                trigger,
+            }
+        })
+        .collect();
+    Ok(Json(resources))
+}
+
+/// Estimated monthly savings of the hours the schedule keeps resources stopped
+#[utoipa::path(
+    get,
+    path = "/api/schedules/{id}/savings",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id")),
+    responses(
+        (status = 200, description = "Estimated savings", body = ScheduleSavings),
+    )
+)]
+pub async fn schedule_savings(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<ScheduleSavings>> {
+    let org_id = claims.require_organization_id()?;
+    let schedule = find_schedule(&state, org_id, id).await?;
+    Ok(Json(
+        store::savings(&state.db, org_id, &schedule, Utc::now()).await?,
+    ))
+}
+
+/// Starts and stops of the schedule, newest first by default. Sorted by
+/// `executedAt`, `action`, `status` or `trigger`, which `resourceId` can
+/// also filter on.
+#[utoipa::path(
+    get,
+    path = "/api/schedules/{id}/executions",
+    tag = "schedules",
+    params(
+        ("id" = Uuid, Path, description = "Schedule id"),
+        PageParams,
+    ),
+    responses(
+        (status = 200, description = "Executions", body = Page<ScheduleExecution>),
+    )
+)]
+pub async fn list_executions(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    params: PageParams,
+) -> AppResult<Json<Page<ScheduleExecution>>> {
+    let org_id = claims.require_organization_id()?;
+    find_schedule(&state, org_id, id).await?;
+    let page = params.resolve(&store::EXECUTION_LISTING, &state.config.pagination)?;
+    Ok(Json(
+        store::list_executions(&state.db, org_id, id, &page).await?,
+    ))
+}
+
+/// Evaluate the schedule now instead of waiting for the scheduler
+/// (organization admins only); returns the executions logged
+#[utoipa::path(
+    post,
+    path = "/api/schedules/{id}/run",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id")),
+    responses(
+        (status = 200, description = "Executions logged", body = Vec<ScheduleExecution>),
+    )
+)]
+pub async fn run_schedule(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<ScheduleExecution>>> {
+    let org_id = claims.require_organization_admin()?;
+    let schedule = find_schedule(&state, org_id, id).await?;
+    if !schedule.enabled {
+        return Err(AppError::Conflict("Schedule is disabled".to_string()));
+    }
+    Ok(Json(
+        worker::execute(&state.db, &state.config.aws, org_id, &schedule, Utc::now()).await?,
+    ))
+}
+
+/// Overrides still in effect, newest first
+#[utoipa::path(
+    get,
+    path = "/api/schedules/{id}/overrides",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id")),
+    responses(
+        (status = 200, description = "Overrides in effect", body = Vec<ScheduleOverride>),
+    )
+)]
+pub async fn list_overrides(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Json<Vec<ScheduleOverride>>> {
+    let org_id = claims.require_organization_id()?;
+    find_schedule(&state, org_id, id).await?;
+    Ok(Json(
+        store::active_overrides(&state.db, id, Utc::now()).await?,
+    ))
+}
+
+/// Keep the schedule's resources, or one of them, running or stopped until
+/// a given time
+#[utoipa::path(
+    post,
+    path = "/api/schedules/{id}/overrides",
+    tag = "schedules",
+    params(("id" = Uuid, Path, description = "Schedule id")),
+    request_body = NewOverride,
+    responses(
+        (status = 201, description = "The created override", body = ScheduleOverride),
+    )
+)]
+pub async fn create_override(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<NewOverride>,
+) -> AppResult<(StatusCode, Json<ScheduleOverride>)> {
+    let org_id = claims.require_organization_id()?;
+    let schedule = find_schedule(&state, org_id, id).await?;
+
+    let now = Utc::now();
+    if payload.until <= now {
+        return Err(AppError::BadRequest(
+            "until must be in the future".to_string(),
+        ));
+    }
+    if let Some(resource_id) = payload.resource_id {
+        let targets = store::targets(&state.db, org_id, &schedule, now).await?;
+        if !targets.iter().any(|t| t.id == resource_id) {
+            return Err(AppError::BadRequest(format!(
+                "Resource {} is not part of this schedule",
+                resource_id
+            )));
+        }
+    }
+
+    let created = store::create_override(&state.db, org_id, id, &payload, claims.user_id()).await?;
+    Ok((StatusCode::CREATED, Json(created)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/schedules/{id}/overrides/{override_id}",
+    tag = "schedules",
+    params(
+        ("id" = Uuid, Path, description = "Schedule id"),
+        ("override_id" = Uuid, Path, description = "Override id"),
+    ),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_override(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path((id, override_id)): Path<(Uuid, Uuid)>,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_id()?;
+    if store::delete_override(&state.db, org_id, id, override_id).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::NotFound(format!(
+            "Schedule override {} not found",
+            override_id
+        )))
+    }
+}
@@ -0,0 +1,263 @@
+use axum::{
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::{Duration, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::auth::Claims;
+use crate::conditional::{self, IfMatch, Tagged};
+use crate::costs::period::day_start;
+use crate::error::{AppError, AppResult};
+use crate::tags::normalize::TagCondition;
+use crate::tags::store::{self, SavedTagPolicy, TagCoverageReport, VirtualTagInput};
+use crate::tags::{TagPolicy, VirtualTag};
+use crate::validation::ValidatedJson;
+use crate::AppState;
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct CoverageQuery {
+    /// First day included (defaults to 30 days before `end`)
+    pub start: Option<NaiveDate>,
+    /// Day after the last day included (defaults to tomorrow)
+    pub end: Option<NaiveDate>,
+}
+
+/// List distinct tag keys and values with the spend they cover
+#[utoipa::path(
+    get,
+    path = "/api/tags",
+    tag = "tags",
+    params(CoverageQuery),
+    responses(
+        (status = 200, description = "Tag keys and values with their spend", body = TagCoverageReport),
+    )
+)]
+pub async fn tag_coverage(
+    State(state): State<AppState>,
+    claims: Claims,
+    Query(query): Query<CoverageQuery>,
+) -> AppResult<Json<TagCoverageReport>> {
+    let org_id = claims.require_organization_id()?;
+
+    let end = query
+        .end
+        .unwrap_or_else(|| Utc::now().date_naive() + Duration::days(1));
+    let start = query.start.unwrap_or(end - Duration::days(30));
+    if start >= end {
+        return Err(AppError::BadRequest("start must be before end".to_string()));
+    }
+
+    let report = store::tag_coverage(&state.db, org_id, day_start(start), day_start(end)).await?;
+
+    Ok(Json(report))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tags/policy",
+    tag = "tags",
+    responses(
+        (status = 200, description = "The tag normalization policy", body = SavedTagPolicy, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn get_policy(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Tagged<SavedTagPolicy>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(store::get_policy(&state.db, org_id).await?))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/tags/policy",
+    tag = "tags",
+    params(IfMatch),
+    request_body = TagPolicy,
+    responses((status = 200, description = "The saved policy", body = SavedTagPolicy, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn put_policy(
+    State(state): State<AppState>,
+    claims: Claims,
+    if_match: IfMatch,
+    ValidatedJson(policy): ValidatedJson<TagPolicy>,
+) -> AppResult<Tagged<SavedTagPolicy>> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&store::get_policy(&state.db, org_id).await?)?;
+    store::put_policy(&state.db, org_id, &policy, version)
+        .await?
+        .map(Tagged)
+        .ok_or_else(conditional::precondition_failed)
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualTagRequest {
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub key: String,
+    #[validate(length(min = 1, max = 256))]
+    #[schema(min_length = 1, max_length = 256)]
+    pub value: String,
+    #[serde(default)]
+    pub priority: i32,
+    #[serde(default)]
+    #[validate(nested)]
+    pub conditions: Vec<TagCondition>,
+}
+
+impl From<VirtualTagRequest> for VirtualTagInput {
+    fn from(req: VirtualTagRequest) -> Self {
+        VirtualTagInput {
+            key: req.key,
+            value: req.value,
+            priority: req.priority,
+            conditions: req.conditions,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tags/virtual",
+    tag = "tags",
+    responses((status = 200, description = "Virtual tags", body = Vec<VirtualTag>))
+)]
+pub async fn list_virtual_tags(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<VirtualTag>>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::list_virtual_tags(&state.db, org_id).await?))
+}
+
+fn not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Virtual tag {} not found", id))
+}
+
+async fn find_virtual_tag(state: &AppState, org_id: &str, id: Uuid) -> AppResult<VirtualTag> {
+    store::get_virtual_tag(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| not_found(id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/tags/virtual/{id}",
+    tag = "tags",
+    params(("id" = Uuid, Path, description = "Virtual tag id")),
+    responses((status = 200, description = "The virtual tag", body = VirtualTag, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn get_virtual_tag(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<VirtualTag>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(find_virtual_tag(&state, org_id, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/tags/virtual",
+    tag = "tags",
+    request_body = VirtualTagRequest,
+    responses(
+        (status = 201, description = "The created virtual tag", body = VirtualTag, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn create_virtual_tag(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<VirtualTagRequest>,
+) -> AppResult<(StatusCode, Tagged<VirtualTag>)> {
+    let org_id = claims.require_organization_id()?;
+    let tag = store::create_virtual_tag(&state.db, org_id, payload.into()).await?;
+    Ok((StatusCode::CREATED, Tagged(tag)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/tags/virtual/{id}",
+    tag = "tags",
+    params(("id" = Uuid, Path, description = "Virtual tag id"), IfMatch),
+    request_body = VirtualTagRequest,
+    responses(
+        (status = 200, description = "The updated virtual tag", body = VirtualTag, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn update_virtual_tag(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<VirtualTagRequest>,
+) -> AppResult<Tagged<VirtualTag>> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_virtual_tag(&state, org_id, id).await?)?;
+    store::update_virtual_tag(&state.db, org_id, id, payload.into(), version)
+        .await?
+        .map(Tagged)
+        .ok_or_else(|| conditional::changed_or(version, not_found(id)))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/tags/virtual/{id}",
+    tag = "tags",
+    params(("id" = Uuid, Path, description = "Virtual tag id"), IfMatch),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_virtual_tag(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_virtual_tag(&state, org_id, id).await?)?;
+    if store::delete_virtual_tag(&state.db, org_id, id, version).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(version, not_found(id)))
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct RenormalizeResponse {
+    /// The queued job, absent if one was already queued
+    pub job_id: Option<Uuid>,
+}
+
+/// Queue re-applying the current tag rules to all stored line items. Saving
+/// the policy or a virtual tag queues this as well.
+#[utoipa::path(
+    post,
+    path = "/api/tags/renormalize",
+    tag = "tags",
+    responses(
+        (status = 202, description = "Re-normalization queued", body = RenormalizeResponse),
+    )
+)]
+pub async fn renormalize(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<(StatusCode, Json<RenormalizeResponse>)> {
+    let org_id = claims.require_organization_id()?;
+    let mut tx = state.db.begin().await?;
+    let job = store::enqueue_renormalize(&mut tx, org_id).await?;
+    tx.commit().await?;
+    Ok((
+        StatusCode::ACCEPTED,
+        Json(RenormalizeResponse {
+            job_id: job.map(|job| job.id),
+        }),
+    ))
+}
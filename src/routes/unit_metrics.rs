@@ -0,0 +1,440 @@
+use axum::{
+    body::Bytes,
+    extract::{Path, Query, State},
+    http::StatusCode,
+    Json,
+};
+use chrono::NaiveDate;
+use serde::{Deserialize, Serialize};
+use utoipa::{IntoParams, ToSchema};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::auth::Claims;
+use crate::conditional::{self, IfMatch, Tagged};
+use crate::costs::query::check_period;
+use crate::costs::{CostFilter, Granularity, GroupBy};
+use crate::error::{AppError, AppResult};
+use crate::unit_metrics::compute::parse_values_csv;
+use crate::unit_metrics::store::{self, UnitMetricInput};
+use crate::unit_metrics::{Aggregation, BusinessMetric, MetricValue, UnitCostPoint, UnitMetric};
+use crate::validation::{ValidatedJson, ValidatedQuery};
+use crate::AppState;
+
+/// Most values accepted in a single push or upload
+const MAX_VALUES_PER_REQUEST: usize = 50_000;
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BusinessMetricRequest {
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub name: String,
+    #[validate(length(min = 1, max = 64))]
+    #[schema(min_length = 1, max_length = 64)]
+    pub unit: String,
+    #[validate(length(max = 512))]
+    #[schema(max_length = 512)]
+    pub description: Option<String>,
+    #[serde(default)]
+    pub aggregation: Aggregation,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/business-metrics",
+    tag = "unit-metrics",
+    responses(
+        (status = 200, description = "Business metrics", body = Vec<BusinessMetric>),
+    )
+)]
+pub async fn list_business_metrics(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<BusinessMetric>>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::list_business_metrics(&state.db, org_id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/business-metrics",
+    tag = "unit-metrics",
+    request_body = BusinessMetricRequest,
+    responses(
+        (status = 201, description = "The created business metric", body = BusinessMetric, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn create_business_metric(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<BusinessMetricRequest>,
+) -> AppResult<(StatusCode, Tagged<BusinessMetric>)> {
+    let org_id = claims.require_organization_id()?;
+    let metric = store::create_business_metric(
+        &state.db,
+        org_id,
+        &payload.name,
+        &payload.unit,
+        payload.description.as_deref(),
+        payload.aggregation,
+    )
+    .await?;
+    Ok((StatusCode::CREATED, Tagged(metric)))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/business-metrics/{id}",
+    tag = "unit-metrics",
+    params(("id" = Uuid, Path, description = "Business metric id")),
+    responses((status = 200, description = "The business metric", body = BusinessMetric, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn get_business_metric(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<BusinessMetric>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(find_business_metric(&state, org_id, id).await?))
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/business-metrics/{id}",
+    tag = "unit-metrics",
+    params(("id" = Uuid, Path, description = "Business metric id"), IfMatch),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_business_metric(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_business_metric(&state, org_id, id).await?)?;
+    if store::delete_business_metric(&state.db, org_id, id, version).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(
+            version,
+            business_metric_not_found(id),
+        ))
+    }
+}
+
+fn business_metric_not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Business metric {} not found", id))
+}
+
+async fn find_business_metric(
+    state: &AppState,
+    org_id: &str,
+    id: Uuid,
+) -> AppResult<BusinessMetric> {
+    store::get_business_metric(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| business_metric_not_found(id))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+pub struct MetricValuesRequest {
+    #[validate(length(min = 1, max = 50000), nested)]
+    #[schema(min_items = 1, max_items = 50000)]
+    pub values: Vec<MetricValue>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+pub struct MetricValuesResponse {
+    pub written: u64,
+}
+
+/// Push daily values; existing values for the same date and dimension are replaced
+#[utoipa::path(
+    post,
+    path = "/api/business-metrics/{id}/values",
+    tag = "unit-metrics",
+    params(("id" = Uuid, Path, description = "Business metric id")),
+    request_body = MetricValuesRequest,
+    responses(
+        (status = 200, description = "Number of values written", body = MetricValuesResponse),
+    )
+)]
+pub async fn push_metric_values(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    ValidatedJson(payload): ValidatedJson<MetricValuesRequest>,
+) -> AppResult<Json<MetricValuesResponse>> {
+    let org_id = claims.require_organization_id()?;
+    let metric = find_business_metric(&state, org_id, id).await?;
+    let written = store::upsert_metric_values(&state.db, metric.id, &payload.values).await?;
+    Ok(Json(MetricValuesResponse { written }))
+}
+
+/// Upload daily values as CSV with a `date,value[,dimension]` header
+#[utoipa::path(
+    post,
+    path = "/api/business-metrics/{id}/values/csv",
+    tag = "unit-metrics",
+    params(("id" = Uuid, Path, description = "Business metric id")),
+    request_body(content = String, content_type = "text/csv"),
+    responses(
+        (status = 200, description = "Number of values written", body = MetricValuesResponse),
+    )
+)]
+pub async fn upload_metric_values_csv(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    body: Bytes,
+) -> AppResult<Json<MetricValuesResponse>> {
+    let org_id = claims.require_organization_id()?;
+    let metric = find_business_metric(&state, org_id, id).await?;
+
+    let values = parse_values_csv(&body).map_err(AppError::Validation)?;
+    if values.len() > MAX_VALUES_PER_REQUEST {
+        return Err(AppError::Validation(format!(
+            "at most {} values may be uploaded at once",
+            MAX_VALUES_PER_REQUEST
+        )));
+    }
+
+    let written = store::upsert_metric_values(&state.db, metric.id, &values).await?;
+    Ok(Json(MetricValuesResponse { written }))
+}
+
+#[derive(Debug, Deserialize, Validate, IntoParams)]
+#[validate(schema(function = "validate_period"))]
+#[into_params(parameter_in = Query)]
+pub struct PeriodQuery {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+    #[serde(default)]
+    pub granularity: Granularity,
+}
+
+fn validate_period(query: &PeriodQuery) -> Result<(), ValidationError> {
+    check_period(query.start, query.end)
+}
+
+#[derive(Debug, Deserialize, IntoParams)]
+#[into_params(parameter_in = Query)]
+pub struct ValuesQuery {
+    pub start: NaiveDate,
+    pub end: NaiveDate,
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/business-metrics/{id}/values",
+    tag = "unit-metrics",
+    params(
+        ("id" = Uuid, Path, description = "Business metric id"),
+        ValuesQuery,
+    ),
+    responses((status = 200, description = "Daily values", body = Vec<MetricValue>))
+)]
+pub async fn list_metric_values(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    Query(query): Query<ValuesQuery>,
+) -> AppResult<Json<Vec<MetricValue>>> {
+    let org_id = claims.require_organization_id()?;
+    let metric = find_business_metric(&state, org_id, id).await?;
+    let values = store::list_metric_values(&state.db, metric.id, query.start, query.end).await?;
+    Ok(Json(values))
+}
+
+#[derive(Debug, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitMetricRequest {
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub name: String,
+    #[validate(length(max = 512))]
+    #[schema(max_length = 512)]
+    pub description: Option<String>,
+    pub business_metric_id: Uuid,
+    #[serde(default)]
+    pub cost_filter: CostFilter,
+    pub dimension: Option<GroupBy>,
+}
+
+impl From<UnitMetricRequest> for UnitMetricInput {
+    fn from(req: UnitMetricRequest) -> Self {
+        UnitMetricInput {
+            name: req.name,
+            description: req.description,
+            business_metric_id: req.business_metric_id,
+            cost_filter: req.cost_filter,
+            dimension: req.dimension,
+        }
+    }
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/unit-metrics",
+    tag = "unit-metrics",
+    responses((status = 200, description = "Unit metrics", body = Vec<UnitMetric>))
+)]
+pub async fn list_unit_metrics(
+    State(state): State<AppState>,
+    claims: Claims,
+) -> AppResult<Json<Vec<UnitMetric>>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Json(store::list_unit_metrics(&state.db, org_id).await?))
+}
+
+fn unit_metric_not_found(id: Uuid) -> AppError {
+    AppError::NotFound(format!("Unit metric {} not found", id))
+}
+
+async fn find_unit_metric(state: &AppState, org_id: &str, id: Uuid) -> AppResult<UnitMetric> {
+    store::get_unit_metric(&state.db, org_id, id)
+        .await?
+        .ok_or_else(|| unit_metric_not_found(id))
+}
+
+#[utoipa::path(
+    get,
+    path = "/api/unit-metrics/{id}",
+    tag = "unit-metrics",
+    params(("id" = Uuid, Path, description = "Unit metric id")),
+    responses((status = 200, description = "The unit metric", body = UnitMetric, headers(("ETag" = String, description = "Version to send in `If-Match`"))))
+)]
+pub async fn get_unit_metric(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+) -> AppResult<Tagged<UnitMetric>> {
+    let org_id = claims.require_organization_id()?;
+    Ok(Tagged(find_unit_metric(&state, org_id, id).await?))
+}
+
+#[utoipa::path(
+    post,
+    path = "/api/unit-metrics",
+    tag = "unit-metrics",
+    request_body = UnitMetricRequest,
+    responses(
+        (status = 201, description = "The created unit metric", body = UnitMetric, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn create_unit_metric(
+    State(state): State<AppState>,
+    claims: Claims,
+    ValidatedJson(payload): ValidatedJson<UnitMetricRequest>,
+) -> AppResult<(StatusCode, Tagged<UnitMetric>)> {
+    let org_id = claims.require_organization_id()?;
+    let metric = store::create_unit_metric(&state.db, org_id, payload.into())
+        .await?
+        .ok_or_else(|| AppError::BadRequest("Unknown business metric".to_string()))?;
+    Ok((StatusCode::CREATED, Tagged(metric)))
+}
+
+#[utoipa::path(
+    put,
+    path = "/api/unit-metrics/{id}",
+    tag = "unit-metrics",
+    params(("id" = Uuid, Path, description = "Unit metric id"), IfMatch),
+    request_body = UnitMetricRequest,
+    responses(
+        (status = 200, description = "The updated unit metric", body = UnitMetric, headers(("ETag" = String, description = "Version to send in `If-Match`"))),
+    )
+)]
+pub async fn update_unit_metric(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+    ValidatedJson(payload): ValidatedJson<UnitMetricRequest>,
+) -> AppResult<Tagged<UnitMetric>> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_unit_metric(&state, org_id, id).await?)?;
+    find_business_metric(&state, org_id, payload.business_metric_id).await?;
+    store::update_unit_metric(&state.db, org_id, id, payload.into(), version)
+        .await?
+        .map(Tagged)
+        .ok_or_else(|| {
+            conditional::changed_or(
+                version,
+                AppError::NotFound(format!(
+                    "Unit metric {} or its business metric not found",
+                    id
+                )),
+            )
+        })
+}
+
+#[utoipa::path(
+    delete,
+    path = "/api/unit-metrics/{id}",
+    tag = "unit-metrics",
+    params(("id" = Uuid, Path, description = "Unit metric id"), IfMatch),
+    responses((status = 204, description = "Deleted"))
+)]
+pub async fn delete_unit_metric(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    if_match: IfMatch,
+) -> AppResult<StatusCode> {
+    let org_id = claims.require_organization_id()?;
+    let version = if_match.check(&find_unit_metric(&state, org_id, id).await?)?;
+    if store::delete_unit_metric(&state.db, org_id, id, version).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(conditional::changed_or(version, unit_metric_not_found(id)))
+    }
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitMetricSeries {
+    pub unit_metric: UnitMetric,
+    pub granularity: Granularity,
+    pub points: Vec<UnitCostPoint>,
+}
+
+/// Cost-per-unit time series of a unit metric
+#[utoipa::path(
+    get,
+    path = "/api/unit-metrics/{id}/series",
+    tag = "unit-metrics",
+    params(
+        ("id" = Uuid, Path, description = "Unit metric id"),
+        PeriodQuery,
+    ),
+    responses(
+        (status = 200, description = "Cost per unit over the period", body = UnitMetricSeries),
+    )
+)]
+pub async fn unit_metric_series(
+    State(state): State<AppState>,
+    claims: Claims,
+    Path(id): Path<Uuid>,
+    ValidatedQuery(query): ValidatedQuery<PeriodQuery>,
+) -> AppResult<Json<UnitMetricSeries>> {
+    let org_id = claims.require_organization_id()?;
+
+    let unit_metric = find_unit_metric(&state, org_id, id).await?;
+    let points = store::unit_cost_series(
+        &state.db,
+        org_id,
+        &unit_metric,
+        query.start,
+        query.end,
+        query.granularity,
+    )
+    .await?;
+
+    Ok(Json(UnitMetricSeries {
+        unit_metric,
+        granularity: query.granularity,
+        points,
+    }))
+}
@@ -0,0 +1,42 @@
+//! Starting and stopping AWS instances
+
+use super::model::ExecutionAction;
+use crate::aws::{AwsClient, AwsError, Service};
+
+fn param(name: &str, value: &str) -> (String, String) {
+    (name.to_string(), value.to_string())
+}
+
+/// Start or stop an EC2 instance or RDS database instance
+pub async fn apply(
+    client: &AwsClient,
+    resource_type: &str,
+    region: &str,
+    provider_id: &str,
+    action: ExecutionAction,
+) -> Result<(), AwsError> {
+    let (service, operation, params) = match (resource_type, action) {
+        ("rds_instance", ExecutionAction::Start) => (
+            Service::Rds,
+            "StartDBInstance",
+            [param("DBInstanceIdentifier", provider_id)],
+        ),
+        ("rds_instance", ExecutionAction::Stop) => (
+            Service::Rds,
+            "StopDBInstance",
+            [param("DBInstanceIdentifier", provider_id)],
+        ),
+        (_, ExecutionAction::Start) => (
+            Service::Ec2,
+            "StartInstances",
+            [param("InstanceId.1", provider_id)],
+        ),
+        (_, ExecutionAction::Stop) => (
+            Service::Ec2,
+            "StopInstances",
+            [param("InstanceId.1", provider_id)],
+        ),
+    };
+    client.query(service, region, operation, &params).await?;
+    Ok(())
+}
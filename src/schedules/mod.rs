@@ -0,0 +1,19 @@
+//! Start/stop schedules
+//!
+//! A schedule keeps EC2 and RDS instances running during weekly windows of
+//! local time in an IANA time zone and stopped outside them and on
+//! holidays. It applies to resources listed by id and to those matching a
+//! tag selector. Temporary overrides keep resources running or stopped
+//! until a given time, dry-run schedules only log what they would do, and
+//! every start or stop is recorded in the schedule's execution log.
+
+pub mod aws;
+pub mod model;
+pub mod schedule;
+pub mod store;
+pub mod worker;
+
+pub use model::{
+    DesiredState, NewOverride, Schedule, ScheduleExecution, ScheduleInput, ScheduleOverride,
+    ScheduleSavings, ScheduledResource, Trigger,
+};
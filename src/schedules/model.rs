@@ -0,0 +1,450 @@
+//! Schedule API and storage types
+
+use std::collections::BTreeMap;
+
+use chrono::{DateTime, NaiveDate, NaiveTime, Utc, Weekday};
+use chrono_tz::Tz;
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::conditional::Versioned;
+
+/// Local times on `days` during which resources are kept running. A window
+/// whose end is not after its start runs past midnight into the next day.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TimeWindow {
+    #[schema(value_type = Vec<String>, example = json!(["Mon", "Tue", "Wed", "Thu", "Fri"]))]
+    pub days: Vec<Weekday>,
+    pub start: NaiveTime,
+    pub end: NaiveTime,
+}
+
+/// A date on which resources stay stopped all day
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Holiday {
+    pub date: NaiveDate,
+    pub name: Option<String>,
+}
+
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleInput {
+    #[validate(length(min = 1, max = 128))]
+    #[schema(min_length = 1, max_length = 128)]
+    pub name: String,
+    #[validate(length(max = 512))]
+    #[schema(max_length = 512)]
+    pub description: Option<String>,
+    /// IANA time zone the windows are in, e.g. `Europe/Berlin`
+    #[validate(custom(function = "validate_timezone"))]
+    pub timezone: String,
+    #[validate(length(max = 50), custom(function = "validate_windows"))]
+    #[schema(max_items = 50)]
+    pub windows: Vec<TimeWindow>,
+    #[serde(default)]
+    #[validate(length(max = 366))]
+    #[schema(max_items = 366)]
+    pub holidays: Vec<Holiday>,
+    #[serde(default)]
+    #[validate(length(max = 1000))]
+    #[schema(max_items = 1000)]
+    pub resource_ids: Vec<Uuid>,
+    /// Tags a resource must all carry to be scheduled
+    #[serde(default)]
+    #[validate(length(max = 20))]
+    pub tag_selector: BTreeMap<String, String>,
+    #[serde(default = "default_enabled")]
+    pub enabled: bool,
+    #[serde(default)]
+    pub dry_run: bool,
+}
+
+fn default_enabled() -> bool {
+    true
+}
+
+fn invalid(code: &'static str, message: String) -> ValidationError {
+    let mut err = ValidationError::new(code);
+    err.message = Some(message.into());
+    err
+}
+
+fn validate_timezone(timezone: &str) -> Result<(), ValidationError> {
+    timezone
+        .parse::<Tz>()
+        .map(|_| ())
+        .map_err(|_| invalid("timezone", format!("unknown time zone '{}'", timezone)))
+}
+
+fn validate_windows(windows: &[TimeWindow]) -> Result<(), ValidationError> {
+    for window in windows {
+        if window.days.is_empty() {
+            return Err(invalid("windows", "a window needs at least one day".into()));
+        }
+        if window.start == window.end {
+            return Err(invalid(
+                "windows",
+                "a window's start and end must differ".into(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct Schedule {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub timezone: String,
+    pub windows: Vec<TimeWindow>,
+    pub holidays: Vec<Holiday>,
+    pub resource_ids: Vec<Uuid>,
+    pub tag_selector: BTreeMap<String, String>,
+    pub enabled: bool,
+    pub dry_run: bool,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for Schedule {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+impl Schedule {
+    /// The schedule's time zone; stored zones were validated on save
+    pub fn tz(&self) -> Tz {
+        self.timezone.parse().unwrap_or(Tz::UTC)
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ScheduleRow {
+    pub id: Uuid,
+    pub organization_id: String,
+    pub name: String,
+    pub description: Option<String>,
+    pub timezone: String,
+    pub windows: Json<Vec<TimeWindow>>,
+    pub holidays: Json<Vec<Holiday>>,
+    pub resource_ids: Vec<Uuid>,
+    pub tag_selector: Json<BTreeMap<String, String>>,
+    pub enabled: bool,
+    pub dry_run: bool,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<ScheduleRow> for Schedule {
+    fn from(row: ScheduleRow) -> Self {
+        Schedule {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            timezone: row.timezone,
+            windows: row.windows.0,
+            holidays: row.holidays.0,
+            resource_ids: row.resource_ids,
+            tag_selector: row.tag_selector.0,
+            enabled: row.enabled,
+            dry_run: row.dry_run,
+            created_by: row.created_by,
+            created_at: row.created_at,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum DesiredState {
+    Running,
+    Stopped,
+}
+
+impl DesiredState {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DesiredState::Running => "running",
+            DesiredState::Stopped => "stopped",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "running" => DesiredState::Running,
+            _ => DesiredState::Stopped,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum OverrideAction {
+    KeepRunning,
+    KeepStopped,
+}
+
+impl OverrideAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            OverrideAction::KeepRunning => "keep_running",
+            OverrideAction::KeepStopped => "keep_stopped",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "keep_stopped" => OverrideAction::KeepStopped,
+            _ => OverrideAction::KeepRunning,
+        }
+    }
+
+    pub fn state(self) -> DesiredState {
+        match self {
+            OverrideAction::KeepRunning => DesiredState::Running,
+            OverrideAction::KeepStopped => DesiredState::Stopped,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct NewOverride {
+    /// Resource the override is for; every resource of the schedule if absent
+    pub resource_id: Option<Uuid>,
+    pub action: OverrideAction,
+    pub until: DateTime<Utc>,
+    #[validate(length(max = 512))]
+    #[schema(max_length = 512)]
+    pub reason: Option<String>,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleOverride {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub resource_id: Option<Uuid>,
+    pub action: OverrideAction,
+    pub until: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ScheduleOverrideRow {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub resource_id: Option<Uuid>,
+    pub action: String,
+    pub until: DateTime<Utc>,
+    pub reason: Option<String>,
+    pub created_by: Option<String>,
+    pub created_at: DateTime<Utc>,
+}
+
+impl From<ScheduleOverrideRow> for ScheduleOverride {
+    fn from(row: ScheduleOverrideRow) -> Self {
+        ScheduleOverride {
+            id: row.id,
+            schedule_id: row.schedule_id,
+            resource_id: row.resource_id,
+            action: OverrideAction::parse(&row.action),
+            until: row.until,
+            reason: row.reason,
+            created_by: row.created_by,
+            created_at: row.created_at,
+        }
+    }
+}
+
+/// What made a resource's wanted state what it is
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Trigger {
+    Schedule,
+    Override,
+}
+
+impl Trigger {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Trigger::Schedule => "schedule",
+            Trigger::Override => "override",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "override" => Trigger::Override,
+            _ => Trigger::Schedule,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum ExecutionAction {
+    Start,
+    Stop,
+}
+
+impl ExecutionAction {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExecutionAction::Start => "start",
+            ExecutionAction::Stop => "stop",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "start" => ExecutionAction::Start,
+            _ => ExecutionAction::Stop,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum ExecutionStatus {
+    Succeeded,
+    Failed,
+    /// Logged by a dry-run schedule without calling the provider
+    DryRun,
+}
+
+impl ExecutionStatus {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ExecutionStatus::Succeeded => "succeeded",
+            ExecutionStatus::Failed => "failed",
+            ExecutionStatus::DryRun => "dry_run",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "succeeded" => ExecutionStatus::Succeeded,
+            "dry_run" => ExecutionStatus::DryRun,
+            _ => ExecutionStatus::Failed,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleExecution {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub resource_id: Option<Uuid>,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub region: String,
+    pub action: ExecutionAction,
+    pub trigger: Trigger,
+    pub status: ExecutionStatus,
+    pub message: Option<String>,
+    pub executed_at: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct ScheduleExecutionRow {
+    pub id: Uuid,
+    pub schedule_id: Uuid,
+    pub resource_id: Option<Uuid>,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub region: String,
+    pub action: String,
+    pub trigger: String,
+    pub status: String,
+    pub message: Option<String>,
+    pub executed_at: DateTime<Utc>,
+}
+
+impl From<ScheduleExecutionRow> for ScheduleExecution {
+    fn from(row: ScheduleExecutionRow) -> Self {
+        ScheduleExecution {
+            id: row.id,
+            schedule_id: row.schedule_id,
+            resource_id: row.resource_id,
+            resource_type: row.resource_type,
+            provider_id: row.provider_id,
+            region: row.region,
+            action: ExecutionAction::parse(&row.action),
+            trigger: Trigger::parse(&row.trigger),
+            status: ExecutionStatus::parse(&row.status),
+            message: row.message,
+            executed_at: row.executed_at,
+        }
+    }
+}
+
+/// An instance a schedule applies to
+#[derive(Debug, Clone, sqlx::FromRow)]
+pub struct ScheduleTarget {
+    pub id: Uuid,
+    pub cloud_account_id: Uuid,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub name: Option<String>,
+    pub region: String,
+    pub state: Option<String>,
+    pub instance_type: Option<String>,
+    pub windows: bool,
+    /// Linked spend over the last 30 days
+    pub linked_cost: Option<f64>,
+    /// Hours from the first linked line item of the window to its end
+    pub linked_hours: Option<f64>,
+}
+
+/// A scheduled resource with the state the schedule wants it in now
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduledResource {
+    pub resource_id: Uuid,
+    pub resource_type: String,
+    pub provider_id: String,
+    pub name: Option<String>,
+    pub region: String,
+    pub state: Option<String>,
+    pub desired_state: DesiredState,
+    pub trigger: Trigger,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceSavings {
+    pub resource_id: Uuid,
+    pub resource_type: String,
+    pub provider_id: String,
+    /// Cost of running the resource for an hour, when known
+    pub hourly_cost: Option<f64>,
+    /// `linkedCost` or `listPrice`
+    pub basis: Option<&'static str>,
+    pub estimated_monthly_savings: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct ScheduleSavings {
+    pub schedule_id: Uuid,
+    /// Hours per week the schedule keeps resources stopped, holidays aside
+    pub stopped_hours_per_week: f64,
+    pub estimated_monthly_savings: f64,
+    pub currency: String,
+    pub resources: Vec<ResourceSavings>,
+}
@@ -0,0 +1,207 @@
+//! Evaluating schedules
+//!
+//! Windows are matched against the local time of the schedule's zone, so
+//! they follow daylight saving changes. Overrides take precedence over the
+//! windows, a resource's own override over one for the whole schedule.
+
+use chrono::{DateTime, Datelike, NaiveDateTime, Timelike, Utc, Weekday};
+use uuid::Uuid;
+
+use super::model::{DesiredState, Holiday, Schedule, ScheduleOverride, TimeWindow, Trigger};
+
+const MINUTES_PER_WEEK: usize = 7 * 24 * 60;
+
+/// Weeks in an average month
+const WEEKS_PER_MONTH: f64 = 365.0 / 7.0 / 12.0;
+
+/// Whether `local` falls inside one of the windows
+fn in_window(windows: &[TimeWindow], local: NaiveDateTime) -> bool {
+    let day = local.weekday();
+    let time = local.time();
+    windows.iter().any(|window| {
+        if window.start < window.end {
+            window.days.contains(&day) && window.start <= time && time < window.end
+        } else {
+            // Runs past midnight: the evening of a listed day or the early
+            // hours after one
+            (window.days.contains(&day) && time >= window.start)
+                || (window.days.contains(&day.pred()) && time < window.end)
+        }
+    })
+}
+
+fn is_holiday(holidays: &[Holiday], local: NaiveDateTime) -> bool {
+    holidays.iter().any(|h| h.date == local.date())
+}
+
+/// State the schedule's windows and holidays call for at `now`
+pub fn scheduled_state(schedule: &Schedule, now: DateTime<Utc>) -> DesiredState {
+    let local = now.with_timezone(&schedule.tz()).naive_local();
+    if !is_holiday(&schedule.holidays, local) && in_window(&schedule.windows, local) {
+        DesiredState::Running
+    } else {
+        DesiredState::Stopped
+    }
+}
+
+/// State a resource should be in at `now`, taking active overrides into account
+pub fn desired_state(
+    schedule: &Schedule,
+    overrides: &[ScheduleOverride],
+    resource_id: Uuid,
+    now: DateTime<Utc>,
+) -> (DesiredState, Trigger) {
+    // The latest active override for `target`
+    let active = |target: Option<Uuid>| {
+        overrides
+            .iter()
+            .filter(|o| o.until > now && o.resource_id == target)
+            .max_by_key(|o| o.created_at)
+    };
+    match active(Some(resource_id)).or_else(|| active(None)) {
+        Some(o) => (o.action.state(), Trigger::Override),
+        None => (scheduled_state(schedule, now), Trigger::Schedule),
+    }
+}
+
+/// Current state of an instance as one a schedule can ask for; `None` while
+/// it is changing state or in any other state
+pub fn current_state(resource_type: &str, state: Option<&str>) -> Option<DesiredState> {
+    match (resource_type, state?) {
+        ("ec2_instance", "running") | ("rds_instance", "available") => Some(DesiredState::Running),
+        ("ec2_instance", "stopped") | ("rds_instance", "stopped") => Some(DesiredState::Stopped),
+        _ => None,
+    }
+}
+
+/// Hours per week outside every window
+pub fn stopped_hours_per_week(windows: &[TimeWindow]) -> f64 {
+    let mut running = vec![false; MINUTES_PER_WEEK];
+    let minute_of_week = |day: Weekday, minutes: usize| {
+        (day.num_days_from_monday() as usize * 24 * 60 + minutes) % MINUTES_PER_WEEK
+    };
+    for window in windows {
+        let start = (window.start.num_seconds_from_midnight() / 60) as usize;
+        let end = (window.end.num_seconds_from_midnight() / 60) as usize;
+        let length = if start < end {
+            end - start
+        } else {
+            24 * 60 - start + end
+        };
+        for day in &window.days {
+            let first = minute_of_week(*day, start);
+            for offset in 0..length {
+                running[(first + offset) % MINUTES_PER_WEEK] = true;
+            }
+        }
+    }
+    running.iter().filter(|r| !**r).count() as f64 / 60.0
+}
+
+/// Monthly savings of stopping a resource costing `hourly_cost` for
+/// `stopped_hours_per_week`
+pub fn monthly_savings(hourly_cost: f64, stopped_hours_per_week: f64) -> f64 {
+    hourly_cost * stopped_hours_per_week * WEEKS_PER_MONTH
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::{Duration, NaiveDate, NaiveTime, TimeZone};
+    use std::collections::BTreeMap;
+
+    use super::*;
+    use crate::schedules::model::OverrideAction;
+
+    fn schedule(windows: Vec<TimeWindow>) -> Schedule {
+        let now = Utc::now();
+        Schedule {
+            id: Uuid::nil(),
+            name: "office hours".to_string(),
+            description: None,
+            timezone: "Europe/Berlin".to_string(),
+            windows,
+            holidays: vec![Holiday {
+                date: NaiveDate::from_ymd_opt(2026, 12, 25).unwrap(),
+                name: Some("Christmas".to_string()),
+            }],
+            resource_ids: Vec::new(),
+            tag_selector: BTreeMap::new(),
+            enabled: true,
+            dry_run: false,
+            created_by: None,
+            created_at: now,
+            updated_at: now,
+        }
+    }
+
+    fn window(days: &[Weekday], start: u32, end: u32) -> TimeWindow {
+        TimeWindow {
+            days: days.to_vec(),
+            start: NaiveTime::from_hms_opt(start, 0, 0).unwrap(),
+            end: NaiveTime::from_hms_opt(end, 0, 0).unwrap(),
+        }
+    }
+
+    #[test]
+    fn test_schedule_evaluation() {
+        use Weekday::*;
+        let weekdays = [Mon, Tue, Wed, Thu, Fri];
+        let office = schedule(vec![window(&weekdays, 8, 18)]);
+        let utc = |d, h| Utc.with_ymd_and_hms(2026, 12, d, h, 0, 0).unwrap();
+
+        // Thursday 24 December: 08:00 in Berlin is 07:00 UTC
+        assert_eq!(scheduled_state(&office, utc(24, 6)), DesiredState::Stopped);
+        assert_eq!(scheduled_state(&office, utc(24, 7)), DesiredState::Running);
+        assert_eq!(scheduled_state(&office, utc(24, 17)), DesiredState::Stopped);
+        // Christmas is a Friday holiday
+        assert_eq!(scheduled_state(&office, utc(25, 10)), DesiredState::Stopped);
+        assert_eq!(stopped_hours_per_week(&office.windows), 168.0 - 50.0);
+
+        // Overnight window from Friday 22:00 to Saturday 02:00
+        let batch = schedule(vec![window(&[Fri], 22, 2)]);
+        assert_eq!(scheduled_state(&batch, utc(18, 21)), DesiredState::Running);
+        assert_eq!(scheduled_state(&batch, utc(19, 0)), DesiredState::Running);
+        assert_eq!(scheduled_state(&batch, utc(19, 1)), DesiredState::Stopped);
+        assert_eq!(stopped_hours_per_week(&batch.windows), 164.0);
+
+        // A resource's own override beats one for the whole schedule
+        let resource = Uuid::new_v4();
+        let now = utc(24, 18);
+        let override_for = |resource_id, action, created_at| ScheduleOverride {
+            id: Uuid::new_v4(),
+            schedule_id: office.id,
+            resource_id,
+            action,
+            until: now + Duration::hours(3),
+            reason: None,
+            created_by: None,
+            created_at,
+        };
+        let overrides = vec![
+            override_for(Some(resource), OverrideAction::KeepRunning, now),
+            override_for(
+                None,
+                OverrideAction::KeepStopped,
+                now + Duration::minutes(1),
+            ),
+        ];
+        assert_eq!(
+            desired_state(&office, &overrides, resource, now),
+            (DesiredState::Running, Trigger::Override)
+        );
+        assert_eq!(
+            desired_state(&office, &overrides, Uuid::new_v4(), now),
+            (DesiredState::Stopped, Trigger::Override)
+        );
+        assert_eq!(
+            desired_state(&office, &overrides, resource, now + Duration::hours(4)),
+            (DesiredState::Stopped, Trigger::Schedule)
+        );
+
+        assert_eq!(
+            current_state("rds_instance", Some("available")),
+            Some(DesiredState::Running)
+        );
+        assert_eq!(current_state("ec2_instance", Some("stopping")), None);
+    }
+}
@@ -0,0 +1,452 @@
+//! Schedules, their overrides, execution logs and savings estimates
+
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Duration, Utc};
+use sqlx::types::Json;
+use uuid::Uuid;
+
+use super::model::{
+    DesiredState, ExecutionAction, ExecutionStatus, NewOverride, ResourceSavings, Schedule,
+    ScheduleExecution, ScheduleExecutionRow, ScheduleInput, ScheduleOverride, ScheduleOverrideRow,
+    ScheduleRow, ScheduleSavings, ScheduleTarget, Trigger,
+};
+use super::schedule;
+use crate::costs::Provider;
+use crate::db::DbPool;
+use crate::pagination::{Field, FieldKind, Listing, Page, PageQuery};
+use crate::pricing::{self, PriceQuery};
+
+const COLUMNS: &str = "id, organization_id, name, description, timezone, windows, holidays, \
+     resource_ids, tag_selector, enabled, dry_run, created_by, created_at, updated_at";
+
+/// Days of linked spend used to estimate what a resource costs per hour
+const COST_LOOKBACK_DAYS: i64 = 30;
+
+pub async fn list_schedules(db: &DbPool, org_id: &str) -> Result<Vec<Schedule>, sqlx::Error> {
+    let rows: Vec<ScheduleRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM schedules WHERE organization_id = $1 ORDER BY name, id",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(Schedule::from).collect())
+}
+
+pub async fn get_schedule(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<Schedule>, sqlx::Error> {
+    let row: Option<ScheduleRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM schedules WHERE organization_id = $1 AND id = $2",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+    Ok(row.map(Schedule::from))
+}
+
+/// Enabled schedules of every organization, with their organization
+pub async fn enabled_schedules(db: &DbPool) -> Result<Vec<(String, Schedule)>, sqlx::Error> {
+    let rows: Vec<ScheduleRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM schedules WHERE enabled ORDER BY organization_id, id",
+        COLUMNS
+    ))
+    .fetch_all(db)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|row| (row.organization_id.clone(), Schedule::from(row)))
+        .collect())
+}
+
+pub async fn create_schedule(
+    db: &DbPool,
+    org_id: &str,
+    input: &ScheduleInput,
+    user_id: &str,
+) -> Result<Schedule, sqlx::Error> {
+    let row: ScheduleRow = sqlx::query_as(&format!(
+        "INSERT INTO schedules (organization_id, name, description, timezone, windows, holidays,
+                                resource_ids, tag_selector, enabled, dry_run, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(&input.name)
+    .bind(&input.description)
+    .bind(&input.timezone)
+    .bind(Json(&input.windows))
+    .bind(Json(&input.holidays))
+    .bind(&input.resource_ids)
+    .bind(Json(&input.tag_selector))
+    .bind(input.enabled)
+    .bind(input.dry_run)
+    .bind(user_id)
+    .fetch_one(db)
+    .await?;
+    Ok(row.into())
+}
+
+/// Replace a schedule's definition. What the scheduler last applied is
+/// forgotten, so every resource is checked against the new definition.
+pub async fn update_schedule(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    input: &ScheduleInput,
+    version: Option<DateTime<Utc>>,
+) -> Result<Option<Schedule>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let row: Option<ScheduleRow> = sqlx::query_as(&format!(
+        "UPDATE schedules
+         SET name = $3, description = $4, timezone = $5, windows = $6, holidays = $7,
+             resource_ids = $8, tag_selector = $9, enabled = $10, dry_run = $11
+         WHERE organization_id = $1 AND id = $2
+           AND ($12::timestamptz IS NULL OR updated_at = $12)
+         RETURNING {}",
+        COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .bind(&input.name)
+    .bind(&input.description)
+    .bind(&input.timezone)
+    .bind(Json(&input.windows))
+    .bind(Json(&input.holidays))
+    .bind(&input.resource_ids)
+    .bind(Json(&input.tag_selector))
+    .bind(input.enabled)
+    .bind(input.dry_run)
+    .bind(version)
+    .fetch_optional(&mut *tx)
+    .await?;
+
+    if row.is_some() {
+        sqlx::query("DELETE FROM schedule_resource_states WHERE schedule_id = $1")
+            .bind(id)
+            .execute(&mut *tx)
+            .await?;
+    }
+    tx.commit().await?;
+
+    Ok(row.map(Schedule::from))
+}
+
+/// Returns `false` if no such schedule exists, or it is no longer at
+/// `version`
+pub async fn delete_schedule(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query(
+        "DELETE FROM schedules WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(db)
+    .await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// Live EC2 and RDS instances the schedule applies to, with their linked
+/// spend over the last 30 days before `now`
+pub async fn targets(
+    db: &DbPool,
+    org_id: &str,
+    schedule: &Schedule,
+    now: DateTime<Utc>,
+) -> Result<Vec<ScheduleTarget>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT r.id, r.cloud_account_id, r.resource_type, r.provider_id, r.name, r.region,
+                r.state,
+                COALESCE(r.attributes->>'instanceType', r.attributes->>'DBInstanceClass')
+                    AS instance_type,
+                COALESCE(r.attributes->>'platform' = 'windows', FALSE) AS windows,
+                c.cost AS linked_cost, c.hours AS linked_hours
+         FROM resources r
+         LEFT JOIN LATERAL (
+             SELECT SUM(l.cost) AS cost,
+                    EXTRACT(EPOCH FROM ($5 - MIN(l.usage_start)))::float8 / 3600 AS hours
+             FROM cost_line_items l
+             WHERE l.linked_resource_id = r.id AND l.usage_start >= $4 AND l.usage_start < $5
+         ) c ON TRUE
+         WHERE r.organization_id = $1 AND r.deleted_at IS NULL
+           AND r.resource_type IN ('ec2_instance', 'rds_instance')
+           AND (r.id = ANY($2) OR ($3::jsonb <> '{}'::jsonb AND r.tags @> $3::jsonb))
+         ORDER BY r.region, r.provider_id",
+    )
+    .bind(org_id)
+    .bind(&schedule.resource_ids)
+    .bind(Json(&schedule.tag_selector))
+    .bind(now - Duration::days(COST_LOOKBACK_DAYS))
+    .bind(now)
+    .fetch_all(db)
+    .await
+}
+
+const OVERRIDE_COLUMNS: &str =
+    "id, schedule_id, resource_id, action, until, reason, created_by, created_at";
+
+pub async fn create_override(
+    db: &DbPool,
+    org_id: &str,
+    schedule_id: Uuid,
+    input: &NewOverride,
+    user_id: &str,
+) -> Result<ScheduleOverride, sqlx::Error> {
+    let row: ScheduleOverrideRow = sqlx::query_as(&format!(
+        "INSERT INTO schedule_overrides
+             (schedule_id, organization_id, resource_id, action, until, reason, created_by)
+         VALUES ($1, $2, $3, $4, $5, $6, $7)
+         RETURNING {}",
+        OVERRIDE_COLUMNS
+    ))
+    .bind(schedule_id)
+    .bind(org_id)
+    .bind(input.resource_id)
+    .bind(input.action.as_str())
+    .bind(input.until)
+    .bind(&input.reason)
+    .bind(user_id)
+    .fetch_one(db)
+    .await?;
+    Ok(row.into())
+}
+
+/// Overrides of a schedule still in effect at `now`, newest first
+pub async fn active_overrides(
+    db: &DbPool,
+    schedule_id: Uuid,
+    now: DateTime<Utc>,
+) -> Result<Vec<ScheduleOverride>, sqlx::Error> {
+    let rows: Vec<ScheduleOverrideRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM schedule_overrides WHERE schedule_id = $1 AND until > $2
+         ORDER BY created_at DESC",
+        OVERRIDE_COLUMNS
+    ))
+    .bind(schedule_id)
+    .bind(now)
+    .fetch_all(db)
+    .await?;
+    Ok(rows.into_iter().map(ScheduleOverride::from).collect())
+}
+
+/// Returns `false` if no such override exists
+pub async fn delete_override(
+    db: &DbPool,
+    org_id: &str,
+    schedule_id: Uuid,
+    id: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let deleted = sqlx::query(
+        "DELETE FROM schedule_overrides
+         WHERE organization_id = $1 AND schedule_id = $2 AND id = $3",
+    )
+    .bind(org_id)
+    .bind(schedule_id)
+    .bind(id)
+    .execute(db)
+    .await?;
+    Ok(deleted.rows_affected() > 0)
+}
+
+/// State the scheduler last brought each resource of the schedule to
+pub async fn applied_states(
+    db: &DbPool,
+    schedule_id: Uuid,
+) -> Result<HashMap<Uuid, DesiredState>, sqlx::Error> {
+    let rows: Vec<(Uuid, String)> = sqlx::query_as(
+        "SELECT resource_id, desired_state FROM schedule_resource_states WHERE schedule_id = $1",
+    )
+    .bind(schedule_id)
+    .fetch_all(db)
+    .await?;
+    Ok(rows
+        .into_iter()
+        .map(|(id, state)| (id, DesiredState::parse(&state)))
+        .collect())
+}
+
+pub async fn record_applied(
+    db: &DbPool,
+    schedule_id: Uuid,
+    resource_id: Uuid,
+    state: DesiredState,
+    applied_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT INTO schedule_resource_states (schedule_id, resource_id, desired_state, applied_at)
+         VALUES ($1, $2, $3, $4)
+         ON CONFLICT (schedule_id, resource_id) DO UPDATE
+         SET desired_state = EXCLUDED.desired_state, applied_at = EXCLUDED.applied_at",
+    )
+    .bind(schedule_id)
+    .bind(resource_id)
+    .bind(state.as_str())
+    .bind(applied_at)
+    .execute(db)
+    .await?;
+    Ok(())
+}
+
+const EXECUTION_COLUMNS: &str = "id, schedule_id, resource_id, resource_type, provider_id, \
+     region, action, trigger, status, message, executed_at";
+
+/// Outcome of starting or stopping one resource
+pub struct NewExecution<'a> {
+    pub target: &'a ScheduleTarget,
+    pub action: ExecutionAction,
+    pub trigger: Trigger,
+    pub status: ExecutionStatus,
+    pub message: Option<String>,
+    pub executed_at: DateTime<Utc>,
+}
+
+pub async fn insert_execution(
+    db: &DbPool,
+    org_id: &str,
+    schedule_id: Uuid,
+    execution: &NewExecution<'_>,
+) -> Result<ScheduleExecution, sqlx::Error> {
+    let row: ScheduleExecutionRow = sqlx::query_as(&format!(
+        "INSERT INTO schedule_executions
+             (schedule_id, organization_id, resource_id, resource_type, provider_id, region,
+              action, trigger, status, message, executed_at)
+         VALUES ($1, $2, $3, $4, $5, $6, $7, $8, $9, $10, $11)
+         RETURNING {}",
+        EXECUTION_COLUMNS
+    ))
+    .bind(schedule_id)
+    .bind(org_id)
+    .bind(execution.target.id)
+    .bind(&execution.target.resource_type)
+    .bind(&execution.target.provider_id)
+    .bind(&execution.target.region)
+    .bind(execution.action.as_str())
+    .bind(execution.trigger.as_str())
+    .bind(execution.status.as_str())
+    .bind(&execution.message)
+    .bind(execution.executed_at)
+    .fetch_one(db)
+    .await?;
+    Ok(row.into())
+}
+
+pub const EXECUTION_LISTING: Listing = Listing {
+    fields: &[
+        Field::new("executedAt", "executed_at", FieldKind::Timestamp),
+        Field::new("action", "action", FieldKind::Text),
+        Field::new("status", "status", FieldKind::Text),
+        Field::new("trigger", "trigger", FieldKind::Text),
+        Field::new("resourceId", "resource_id", FieldKind::Uuid).filter_only(),
+    ],
+    default_sort: "-executedAt",
+};
+
+/// A page of the executions of a schedule
+pub async fn list_executions(
+    db: &DbPool,
+    org_id: &str,
+    schedule_id: Uuid,
+    page: &PageQuery,
+) -> Result<Page<ScheduleExecution>, sqlx::Error> {
+    let mut builder = sqlx::QueryBuilder::new(format!(
+        "SELECT {} FROM schedule_executions WHERE organization_id = ",
+        EXECUTION_COLUMNS
+    ));
+    builder.push_bind(org_id);
+    builder.push(" AND schedule_id = ").push_bind(schedule_id);
+    page.push_conditions(&mut builder);
+    page.push_order_and_limit(&mut builder);
+
+    let rows: Vec<ScheduleExecutionRow> = builder.build_query_as().fetch_all(db).await?;
+    Ok(page.page(rows.into_iter().map(ScheduleExecution::from).collect()))
+}
+
+/// On-demand list price per hour of an EC2 instance's type
+async fn instance_list_price(
+    db: &DbPool,
+    target: &ScheduleTarget,
+) -> Result<Option<f64>, sqlx::Error> {
+    let Some(instance_type) = target
+        .instance_type
+        .as_ref()
+        .filter(|_| target.resource_type == "ec2_instance")
+    else {
+        return Ok(None);
+    };
+    let query = PriceQuery {
+        provider: Some(Provider::Aws),
+        service: Some("AmazonEC2".to_string()),
+        region: Some(target.region.clone()),
+        price_type: Some("OnDemand".to_string()),
+        attributes: BTreeMap::from([
+            ("instanceType".to_string(), instance_type.clone()),
+            (
+                "operatingSystem".to_string(),
+                if target.windows { "Windows" } else { "Linux" }.to_string(),
+            ),
+            ("tenancy".to_string(), "Shared".to_string()),
+            ("preInstalledSw".to_string(), "NA".to_string()),
+            ("capacitystatus".to_string(), "Used".to_string()),
+        ]),
+        limit: 10,
+        ..PriceQuery::default()
+    };
+    Ok(pricing::store::lookup_prices(db, &query)
+        .await?
+        .iter()
+        .filter(|price| price.unit == "Hrs")
+        .find_map(|price| price.tiers.first().map(|tier| tier.unit_price)))
+}
+
+/// Monthly savings of the schedule's stopped hours for each of its
+/// resources, priced at list price or else at their linked spend
+pub async fn savings(
+    db: &DbPool,
+    org_id: &str,
+    schedule: &Schedule,
+    now: DateTime<Utc>,
+) -> Result<ScheduleSavings, sqlx::Error> {
+    let stopped_hours_per_week = schedule::stopped_hours_per_week(&schedule.windows);
+
+    let mut resources = Vec::new();
+    for target in targets(db, org_id, schedule, now).await? {
+        let linked = match (target.linked_cost, target.linked_hours) {
+            (Some(cost), Some(hours)) if cost > 0.0 && hours > 0.0 => Some(cost / hours),
+            _ => None,
+        };
+        let (hourly_cost, basis) = match instance_list_price(db, &target).await? {
+            Some(price) => (Some(price), Some("listPrice")),
+            None => (linked, linked.map(|_| "linkedCost")),
+        };
+        resources.push(ResourceSavings {
+            resource_id: target.id,
+            resource_type: target.resource_type,
+            provider_id: target.provider_id,
+            hourly_cost,
+            basis,
+            estimated_monthly_savings: hourly_cost.map_or(0.0, |hourly| {
+                schedule::monthly_savings(hourly, stopped_hours_per_week)
+            }),
+        });
+    }
+
+    Ok(ScheduleSavings {
+        schedule_id: schedule.id,
+        stopped_hours_per_week,
+        estimated_monthly_savings: resources.iter().map(|r| r.estimated_monthly_savings).sum(),
+        currency: "USD".to_string(),
+        resources,
+    })
+}
@@ -0,0 +1,139 @@
+//! Applying schedules
+//!
+//! The `run_schedules` recurring task evaluates each enabled schedule every
+//! minute; resources whose wanted state changed since the last evaluation
+//! are started or stopped. Resources already in the wanted state are left
+//! alone, as are resources still changing state, which are retried on the
+//! next evaluation.
+
+use std::collections::hash_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use uuid::Uuid;
+
+use super::aws;
+use super::model::{DesiredState, ExecutionAction, ExecutionStatus, Schedule, ScheduleExecution};
+use super::schedule::{current_state, desired_state};
+use super::store::{self, NewExecution};
+use crate::aws::AwsClient;
+use crate::cloud_accounts;
+use crate::config::AwsConfig;
+use crate::db::DbPool;
+use crate::notifications::{self, NotificationEvent};
+use crate::resources::discovery::account_client;
+
+const SESSION_NAME: &str = "scho1ar-scheduler";
+
+/// Evaluate every enabled schedule once. Failures of single schedules are
+/// logged and do not stop the others.
+pub async fn run_all(db: &DbPool, config: &AwsConfig) -> Result<(), sqlx::Error> {
+    let now = Utc::now();
+    for (org_id, schedule) in store::enabled_schedules(db).await? {
+        if let Err(e) = execute(db, config, &org_id, &schedule, now).await {
+            tracing::error!("Failed to run schedule {}: {}", schedule.id, e);
+        }
+    }
+    Ok(())
+}
+
+/// Bring the schedule's resources to the state it wants them in at `now`.
+/// Returns the executions logged.
+pub async fn execute(
+    db: &DbPool,
+    config: &AwsConfig,
+    org_id: &str,
+    schedule: &Schedule,
+    now: DateTime<Utc>,
+) -> Result<Vec<ScheduleExecution>, sqlx::Error> {
+    let targets = store::targets(db, org_id, schedule, now).await?;
+    let overrides = store::active_overrides(db, schedule.id, now).await?;
+    let applied = store::applied_states(db, schedule.id).await?;
+
+    let mut clients: HashMap<Uuid, Result<AwsClient, String>> = HashMap::new();
+    let mut executions = Vec::new();
+    for target in &targets {
+        let (desired, trigger) = desired_state(schedule, &overrides, target.id, now);
+        if applied.get(&target.id) == Some(&desired) {
+            continue;
+        }
+        let Some(current) = current_state(&target.resource_type, target.state.as_deref()) else {
+            continue;
+        };
+        if current == desired {
+            store::record_applied(db, schedule.id, target.id, desired, now).await?;
+            continue;
+        }
+
+        let action = match desired {
+            DesiredState::Running => ExecutionAction::Start,
+            DesiredState::Stopped => ExecutionAction::Stop,
+        };
+        let (status, message) = if schedule.dry_run {
+            (ExecutionStatus::DryRun, None)
+        } else {
+            if let Entry::Vacant(entry) = clients.entry(target.cloud_account_id) {
+                let client =
+                    match cloud_accounts::store::get_account(db, org_id, target.cloud_account_id)
+                        .await?
+                    {
+                        Some(account) => account_client(config, &account, SESSION_NAME).await,
+                        None => Err("cloud account not found".to_string()),
+                    };
+                entry.insert(client);
+            }
+            let result = match &clients[&target.cloud_account_id] {
+                Ok(client) => aws::apply(
+                    client,
+                    &target.resource_type,
+                    &target.region,
+                    &target.provider_id,
+                    action,
+                )
+                .await
+                .map_err(|e| e.to_string()),
+                Err(e) => Err(e.clone()),
+            };
+            match result {
+                Ok(()) => (ExecutionStatus::Succeeded, None),
+                Err(e) => (ExecutionStatus::Failed, Some(e)),
+            }
+        };
+
+        let execution = NewExecution {
+            target,
+            action,
+            trigger,
+            status,
+            message,
+            executed_at: now,
+        };
+        let execution = store::insert_execution(db, org_id, schedule.id, &execution).await?;
+        if execution.status == ExecutionStatus::Failed {
+            let vars = BTreeMap::from([
+                ("schedule".to_string(), schedule.name.clone()),
+                ("action".to_string(), action.as_str().to_string()),
+                (
+                    "resource".to_string(),
+                    target
+                        .name
+                        .clone()
+                        .unwrap_or_else(|| target.provider_id.clone()),
+                ),
+                ("resource_type".to_string(), target.resource_type.clone()),
+                ("region".to_string(), target.region.clone()),
+                (
+                    "error".to_string(),
+                    execution.message.clone().unwrap_or_default(),
+                ),
+            ]);
+            notifications::store::notify(db, org_id, NotificationEvent::ScheduleActionFailed, vars)
+                .await?;
+        }
+        executions.push(execution);
+        // Failures are not retried until the wanted state changes again
+        store::record_applied(db, schedule.id, target.id, desired, now).await?;
+    }
+
+    Ok(executions)
+}
@@ -0,0 +1,15 @@
+//! Tag normalization and virtual tags
+//!
+//! Providers report the same tag under many spellings (`Env`, `env`,
+//! `environment`). Each organization defines a [`TagPolicy`] that folds these
+//! onto canonical keys and values, plus [`VirtualTag`] rules that assign a tag
+//! to line items that lack it. Normalization runs when line items are
+//! ingested, and every change to the rules queues a
+//! [`JobPayload::RenormalizeTags`](crate::jobs::JobPayload::RenormalizeTags)
+//! job that applies them to the stored line items again.
+
+pub mod normalize;
+pub mod sql;
+pub mod store;
+
+pub use normalize::{LineItemContext, TagNormalizer, TagPolicy, Tags, VirtualTag};
@@ -0,0 +1,460 @@
+//! Tag normalization engine
+//!
+//! Applies an organization's [`TagPolicy`] and virtual tags to the raw tags of
+//! a line item. The engine is pure so it can be shared by ingestion and by the
+//! re-normalization job that runs after the rules change.
+
+use std::collections::btree_map::Entry;
+use std::collections::{BTreeMap, HashMap};
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::openapi::{RefOr, Schema};
+use utoipa::{PartialSchema, ToSchema};
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+use crate::conditional::Versioned;
+use crate::openapi;
+
+/// Tag map as stored on line items (sorted for stable JSON output)
+pub type Tags = BTreeMap<String, String>;
+
+/// How tag keys or values are case-folded when no explicit mapping applies
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseFolding {
+    #[default]
+    Preserve,
+    Lower,
+    Upper,
+}
+
+impl CaseFolding {
+    fn apply(self, s: &str) -> String {
+        match self {
+            CaseFolding::Preserve => s.to_string(),
+            CaseFolding::Lower => s.to_lowercase(),
+            CaseFolding::Upper => s.to_uppercase(),
+        }
+    }
+}
+
+/// Per-organization tag normalization policy
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase", default)]
+pub struct TagPolicy {
+    /// Case folding for keys that have no alias
+    pub key_case: CaseFolding,
+    /// Case folding for values that have no mapping
+    pub value_case: CaseFolding,
+    /// Canonical key -> alternative spellings (matched case-insensitively)
+    #[validate(custom(function = "validate_key_aliases"))]
+    pub key_aliases: BTreeMap<String, Vec<String>>,
+    /// Canonical key -> canonical value -> alternative spellings
+    /// (matched case-insensitively)
+    pub value_mappings: BTreeMap<String, BTreeMap<String, Vec<String>>>,
+}
+
+fn validate_key_aliases(aliases: &BTreeMap<String, Vec<String>>) -> Result<(), ValidationError> {
+    let mut seen: HashMap<String, &str> = HashMap::new();
+    for (canonical, alternatives) in aliases {
+        if canonical.trim().is_empty() {
+            return Err(ValidationError::new("empty_key"));
+        }
+        for alias in std::iter::once(canonical).chain(alternatives) {
+            if let Some(previous) = seen.insert(alias.to_lowercase(), canonical) {
+                if previous != canonical {
+                    let mut err = ValidationError::new("duplicate_alias");
+                    err.message = Some(
+                        format!(
+                            "'{}' is an alias of both '{}' and '{}'",
+                            alias, previous, canonical
+                        )
+                        .into(),
+                    );
+                    return Err(err);
+                }
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Line item field a virtual tag condition can match on
+///
+/// Serialized as `account`, `service`, `region`, `resourceId`, `usageType`
+/// or `tag:<key>` to match on an already normalized tag.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ConditionField {
+    Account,
+    Service,
+    Region,
+    ResourceId,
+    UsageType,
+    Tag(String),
+}
+
+impl ConditionField {
+    fn parse(s: &str) -> Option<Self> {
+        match s {
+            "account" => Some(Self::Account),
+            "service" => Some(Self::Service),
+            "region" => Some(Self::Region),
+            "resourceId" => Some(Self::ResourceId),
+            "usageType" => Some(Self::UsageType),
+            _ => s
+                .strip_prefix("tag:")
+                .filter(|key| !key.is_empty())
+                .map(|key| Self::Tag(key.to_string())),
+        }
+    }
+
+    fn as_string(&self) -> String {
+        match self {
+            Self::Account => "account".to_string(),
+            Self::Service => "service".to_string(),
+            Self::Region => "region".to_string(),
+            Self::ResourceId => "resourceId".to_string(),
+            Self::UsageType => "usageType".to_string(),
+            Self::Tag(key) => format!("tag:{}", key),
+        }
+    }
+}
+
+impl Serialize for ConditionField {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.as_string())
+    }
+}
+
+impl<'de> Deserialize<'de> for ConditionField {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        Self::parse(&s).ok_or_else(|| {
+            serde::de::Error::custom(format!(
+                "unknown condition field '{}', expected account, service, region, \
+                 resourceId, usageType or tag:<key>",
+                s
+            ))
+        })
+    }
+}
+
+impl PartialSchema for ConditionField {
+    fn schema() -> RefOr<Schema> {
+        openapi::string_schema(
+            r"^(account|service|region|resourceId|usageType|tag:.+)$",
+            "tag:team",
+        )
+    }
+}
+
+impl ToSchema for ConditionField {}
+
+/// How a condition compares the field against its values
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum MatchOperator {
+    Equals,
+    Prefix,
+    Contains,
+}
+
+/// A single virtual tag condition; matches when the field matches any value
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate, ToSchema)]
+pub struct TagCondition {
+    pub field: ConditionField,
+    pub operator: MatchOperator,
+    #[validate(length(min = 1, message = "at least one value is required"))]
+    #[schema(min_items = 1)]
+    pub values: Vec<String>,
+}
+
+impl TagCondition {
+    fn matches(&self, item: &LineItemContext<'_>, tags: &Tags) -> bool {
+        let actual = match &self.field {
+            ConditionField::Account => Some(item.account_id),
+            ConditionField::Service => Some(item.service),
+            ConditionField::Region => item.region,
+            ConditionField::ResourceId => item.resource_id,
+            ConditionField::UsageType => item.usage_type,
+            ConditionField::Tag(key) => tags.get(key).map(String::as_str),
+        };
+        let Some(actual) = actual else {
+            return false;
+        };
+        let actual = actual.to_lowercase();
+
+        self.values.iter().any(|expected| {
+            let expected = expected.to_lowercase();
+            match self.operator {
+                MatchOperator::Equals => actual == expected,
+                MatchOperator::Prefix => actual.starts_with(&expected),
+                MatchOperator::Contains => actual.contains(&expected),
+            }
+        })
+    }
+}
+
+/// A tag assigned by rule to line items that lack the key
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct VirtualTag {
+    pub id: Uuid,
+    pub key: String,
+    pub value: String,
+    /// Higher priorities are evaluated first; the first match wins
+    pub priority: i32,
+    /// All conditions must match (an empty list matches everything)
+    pub conditions: Vec<TagCondition>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for VirtualTag {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+/// Line item dimensions virtual tag conditions can refer to
+#[derive(Debug, Clone, Copy, Default)]
+pub struct LineItemContext<'a> {
+    pub account_id: &'a str,
+    pub service: &'a str,
+    pub region: Option<&'a str>,
+    pub resource_id: Option<&'a str>,
+    pub usage_type: Option<&'a str>,
+}
+
+/// Compiled form of a policy and its virtual tags
+#[derive(Debug, Clone, Default)]
+pub struct TagNormalizer {
+    key_case: CaseFolding,
+    value_case: CaseFolding,
+    /// Lowercased alias (including the canonical key itself) -> canonical key
+    key_index: HashMap<String, String>,
+    /// (canonical key, lowercased alias) -> canonical value
+    value_index: HashMap<(String, String), String>,
+    virtual_tags: Vec<VirtualTag>,
+}
+
+impl TagNormalizer {
+    pub fn new(policy: &TagPolicy, mut virtual_tags: Vec<VirtualTag>) -> Self {
+        let mut key_index = HashMap::new();
+        for (canonical, aliases) in &policy.key_aliases {
+            for alias in std::iter::once(canonical).chain(aliases) {
+                key_index.insert(alias.trim().to_lowercase(), canonical.clone());
+            }
+        }
+
+        let mut value_index = HashMap::new();
+        for (key, mappings) in &policy.value_mappings {
+            for (canonical, aliases) in mappings {
+                for alias in std::iter::once(canonical).chain(aliases) {
+                    value_index.insert(
+                        (key.clone(), alias.trim().to_lowercase()),
+                        canonical.clone(),
+                    );
+                }
+            }
+        }
+
+        // Stable sort keeps creation order among equal priorities
+        virtual_tags.sort_by_key(|tag| std::cmp::Reverse(tag.priority));
+
+        Self {
+            key_case: policy.key_case,
+            value_case: policy.value_case,
+            key_index,
+            value_index,
+            virtual_tags,
+        }
+    }
+
+    /// Normalize a raw tag map, then fill in virtual tags for missing keys.
+    ///
+    /// Empty keys and values are dropped. When several raw keys collapse onto
+    /// the same canonical key, a raw key spelled exactly like the canonical
+    /// key wins, otherwise the first in sort order does.
+    pub fn normalize(&self, raw: &Tags, item: &LineItemContext<'_>) -> Tags {
+        let mut normalized = Tags::new();
+        let mut exact = Vec::new();
+        let mut others = Vec::new();
+
+        for (key, value) in raw {
+            let (key, value) = (key.trim(), value.trim());
+            if key.is_empty() || value.is_empty() {
+                continue;
+            }
+            let canonical = self.canonical_key(key);
+            if canonical == key {
+                exact.push((canonical, value));
+            } else {
+                others.push((canonical, value));
+            }
+        }
+
+        for (key, value) in exact.into_iter().chain(others) {
+            if let Entry::Vacant(entry) = normalized.entry(key) {
+                let value = self.canonical_value(entry.key(), value);
+                entry.insert(value);
+            }
+        }
+
+        for tag in &self.virtual_tags {
+            if normalized.contains_key(&tag.key) {
+                continue;
+            }
+            if tag
+                .conditions
+                .iter()
+                .all(|condition| condition.matches(item, &normalized))
+            {
+                normalized.insert(tag.key.clone(), tag.value.clone());
+            }
+        }
+
+        normalized
+    }
+
+    fn canonical_key(&self, key: &str) -> String {
+        self.key_index
+            .get(&key.to_lowercase())
+            .cloned()
+            .unwrap_or_else(|| self.key_case.apply(key))
+    }
+
+    fn canonical_value(&self, key: &str, value: &str) -> String {
+        self.value_index
+            .get(&(key.to_string(), value.to_lowercase()))
+            .cloned()
+            .unwrap_or_else(|| self.value_case.apply(value))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn tags(pairs: &[(&str, &str)]) -> Tags {
+        pairs
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect()
+    }
+
+    fn env_policy() -> TagPolicy {
+        TagPolicy {
+            key_case: CaseFolding::Lower,
+            value_case: CaseFolding::Lower,
+            key_aliases: BTreeMap::from([(
+                "environment".to_string(),
+                vec!["env".to_string(), "stage".to_string()],
+            )]),
+            value_mappings: BTreeMap::from([(
+                "environment".to_string(),
+                BTreeMap::from([("production".to_string(), vec!["prod".to_string()])]),
+            )]),
+        }
+    }
+
+    #[test]
+    fn test_normalize_aliases_and_value_mappings() {
+        let normalizer = TagNormalizer::new(&env_policy(), Vec::new());
+        let item = LineItemContext::default();
+
+        for raw in [
+            tags(&[("Env", "Prod")]),
+            tags(&[("ENV", "production")]),
+            tags(&[("environment", "PRODUCTION")]),
+        ] {
+            assert_eq!(
+                normalizer.normalize(&raw, &item),
+                tags(&[("environment", "production")])
+            );
+        }
+
+        assert_eq!(
+            normalizer.normalize(&tags(&[("Team", "Platform"), ("Owner", "")]), &item),
+            tags(&[("team", "platform")])
+        );
+    }
+
+    #[test]
+    fn test_normalize_prefers_exact_canonical_key() {
+        let normalizer = TagNormalizer::new(&env_policy(), Vec::new());
+        let raw = tags(&[("Env", "dev"), ("environment", "prod")]);
+
+        assert_eq!(
+            normalizer.normalize(&raw, &LineItemContext::default()),
+            tags(&[("environment", "production")])
+        );
+    }
+
+    #[test]
+    fn test_virtual_tags_fill_missing_keys_by_priority() {
+        let condition = |field: &str, values: &[&str]| TagCondition {
+            field: ConditionField::parse(field).unwrap(),
+            operator: MatchOperator::Equals,
+            values: values.iter().map(|v| v.to_string()).collect(),
+        };
+        let virtual_tags = vec![
+            VirtualTag {
+                id: Uuid::new_v4(),
+                key: "team".to_string(),
+                value: "shared".to_string(),
+                priority: 0,
+                conditions: Vec::new(),
+                updated_at: Utc::now(),
+            },
+            VirtualTag {
+                id: Uuid::new_v4(),
+                key: "team".to_string(),
+                value: "data".to_string(),
+                priority: 10,
+                conditions: vec![condition("account", &["111122223333"])],
+                updated_at: Utc::now(),
+            },
+        ];
+        let normalizer = TagNormalizer::new(&TagPolicy::default(), virtual_tags);
+
+        let item = LineItemContext {
+            account_id: "111122223333",
+            service: "AmazonEC2",
+            ..Default::default()
+        };
+        assert_eq!(
+            normalizer.normalize(&Tags::new(), &item),
+            tags(&[("team", "data")])
+        );
+
+        let other = LineItemContext {
+            account_id: "444455556666",
+            ..item
+        };
+        assert_eq!(
+            normalizer.normalize(&Tags::new(), &other),
+            tags(&[("team", "shared")])
+        );
+
+        // Real tags always win over virtual ones
+        assert_eq!(
+            normalizer.normalize(&tags(&[("team", "web")]), &item),
+            tags(&[("team", "web")])
+        );
+    }
+
+    #[test]
+    fn test_validate_rejects_ambiguous_alias() {
+        let policy = TagPolicy {
+            key_aliases: BTreeMap::from([
+                ("environment".to_string(), vec!["env".to_string()]),
+                ("envelope".to_string(), vec!["ENV".to_string()]),
+            ]),
+            ..Default::default()
+        };
+        assert!(policy.validate().is_err());
+        assert!(env_policy().validate().is_ok());
+    }
+}
@@ -0,0 +1,101 @@
+//! Translating line item conditions into SQL over `cost_line_items`
+
+use sqlx::{Postgres, QueryBuilder};
+
+use super::normalize::{ConditionField, MatchOperator, TagCondition};
+
+/// Escape `%`, `_` and `\` so a value can be embedded in a LIKE pattern
+pub fn escape_like(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '%' | '_' | '\\') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+impl TagCondition {
+    /// Push a boolean SQL expression equivalent to this condition.
+    ///
+    /// `alias` is the table alias of `cost_line_items` in the surrounding
+    /// query. Tag conditions match against the normalized `tags` column.
+    pub fn push_sql(&self, builder: &mut QueryBuilder<'_, Postgres>, alias: &str) {
+        builder.push("lower(");
+        match &self.field {
+            ConditionField::Account => builder.push(format!("{}.account_id", alias)),
+            ConditionField::Service => builder.push(format!("{}.service", alias)),
+            ConditionField::Region => builder.push(format!("{}.region", alias)),
+            ConditionField::ResourceId => builder.push(format!("{}.resource_id", alias)),
+            ConditionField::UsageType => builder.push(format!("{}.usage_type", alias)),
+            ConditionField::Tag(key) => builder
+                .push(format!("{}.tags ->> ", alias))
+                .push_bind(key.clone()),
+        };
+        builder.push(")");
+
+        let values = self.values.iter().map(|v| v.to_lowercase());
+        match self.operator {
+            MatchOperator::Equals => builder
+                .push(" = ANY(")
+                .push_bind(values.collect::<Vec<_>>()),
+            MatchOperator::Prefix => builder.push(" LIKE ANY(").push_bind(
+                values
+                    .map(|v| format!("{}%", escape_like(&v)))
+                    .collect::<Vec<_>>(),
+            ),
+            MatchOperator::Contains => builder.push(" LIKE ANY(").push_bind(
+                values
+                    .map(|v| format!("%{}%", escape_like(&v)))
+                    .collect::<Vec<_>>(),
+            ),
+        };
+        builder.push(")");
+    }
+}
+
+/// Push the conjunction of `conditions` (`TRUE` when there are none)
+pub fn push_conditions(
+    builder: &mut QueryBuilder<'_, Postgres>,
+    conditions: &[TagCondition],
+    alias: &str,
+) {
+    if conditions.is_empty() {
+        builder.push("TRUE");
+        return;
+    }
+
+    builder.push("(");
+    for (i, condition) in conditions.iter().enumerate() {
+        if i > 0 {
+            builder.push(" AND ");
+        }
+        condition.push_sql(builder, alias);
+    }
+    builder.push(")");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_condition_sql() {
+        let conditions: Vec<TagCondition> = serde_json::from_value(serde_json::json!([
+            { "field": "tag:team", "operator": "equals", "values": ["Data"] },
+            { "field": "service", "operator": "prefix", "values": ["amazon_"] }
+        ]))
+        .unwrap();
+
+        let mut builder = QueryBuilder::<Postgres>::new("SELECT 1 FROM cost_line_items c WHERE ");
+        push_conditions(&mut builder, &conditions, "c");
+
+        assert_eq!(
+            builder.sql(),
+            "SELECT 1 FROM cost_line_items c WHERE \
+             (lower(c.tags ->> $1) = ANY($2) AND lower(c.service) LIKE ANY($3))"
+        );
+        assert_eq!(escape_like("amazon_"), "amazon\\_");
+    }
+}
@@ -0,0 +1,448 @@
+//! Persistence for tag policies and virtual tags, and tag coverage queries
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::types::Json;
+use sqlx::{Postgres, Transaction};
+use utoipa::ToSchema;
+use uuid::Uuid;
+
+use super::normalize::{LineItemContext, TagCondition, TagNormalizer, TagPolicy, Tags, VirtualTag};
+use crate::conditional::Versioned;
+use crate::db::DbPool;
+use crate::jobs::{self, Job, JobPayload, NewJob};
+
+const VIRTUAL_TAG_COLUMNS: &str = "id, key, value, priority, conditions, updated_at";
+
+/// The organization's tag policy with the time it was last saved
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct SavedTagPolicy {
+    #[serde(flatten)]
+    pub policy: TagPolicy,
+    /// Absent while the organization uses the default policy
+    pub updated_at: Option<DateTime<Utc>>,
+}
+
+impl Versioned for SavedTagPolicy {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at.unwrap_or(DateTime::UNIX_EPOCH)
+    }
+}
+
+/// Fields of a virtual tag supplied when creating or replacing it
+#[derive(Debug, Clone)]
+pub struct VirtualTagInput {
+    pub key: String,
+    pub value: String,
+    pub priority: i32,
+    pub conditions: Vec<TagCondition>,
+}
+
+#[derive(sqlx::FromRow)]
+struct VirtualTagRow {
+    id: Uuid,
+    key: String,
+    value: String,
+    priority: i32,
+    conditions: Json<Vec<TagCondition>>,
+    updated_at: DateTime<Utc>,
+}
+
+impl From<VirtualTagRow> for VirtualTag {
+    fn from(row: VirtualTagRow) -> Self {
+        VirtualTag {
+            id: row.id,
+            key: row.key,
+            value: row.value,
+            priority: row.priority,
+            conditions: row.conditions.0,
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// Queue a [`JobPayload::RenormalizeTags`] job for the organization, as part
+/// of the transaction changing its rules. Returns `None` if one is already
+/// queued; one that is running picks up the change before it finishes.
+pub async fn enqueue_renormalize(
+    tx: &mut Transaction<'_, Postgres>,
+    org_id: &str,
+) -> Result<Option<Job>, sqlx::Error> {
+    let job = NewJob::new(Some(org_id), JobPayload::RenormalizeTags)
+        .unique(format!("renormalize_tags:{}", org_id));
+    jobs::store::enqueue_in(tx, &job).await
+}
+
+/// Load the organization's tag policy, or the default policy if none is set
+pub async fn get_policy(db: &DbPool, org_id: &str) -> Result<SavedTagPolicy, sqlx::Error> {
+    let row: Option<(Json<TagPolicy>, DateTime<Utc>)> =
+        sqlx::query_as("SELECT policy, updated_at FROM tag_policies WHERE organization_id = $1")
+            .bind(org_id)
+            .fetch_optional(db)
+            .await?;
+
+    Ok(match row {
+        Some((policy, updated_at)) => SavedTagPolicy {
+            policy: policy.0,
+            updated_at: Some(updated_at),
+        },
+        None => SavedTagPolicy {
+            policy: TagPolicy::default(),
+            updated_at: None,
+        },
+    })
+}
+
+/// Save the policy and queue the re-normalization of stored line items.
+/// With a `version`, a saved policy is only replaced if it was last saved at
+/// that time; returns `None` if it was not.
+pub async fn put_policy(
+    db: &DbPool,
+    org_id: &str,
+    policy: &TagPolicy,
+    version: Option<DateTime<Utc>>,
+) -> Result<Option<SavedTagPolicy>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let updated_at: Option<DateTime<Utc>> = sqlx::query_scalar(
+        "INSERT INTO tag_policies (organization_id, policy) VALUES ($1, $2)
+         ON CONFLICT (organization_id) DO UPDATE SET policy = EXCLUDED.policy
+         WHERE $3::timestamptz IS NULL OR tag_policies.updated_at = $3
+         RETURNING updated_at",
+    )
+    .bind(org_id)
+    .bind(Json(policy))
+    .bind(version)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if updated_at.is_some() {
+        enqueue_renormalize(&mut tx, org_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(updated_at.map(|updated_at| SavedTagPolicy {
+        policy: policy.clone(),
+        updated_at: Some(updated_at),
+    }))
+}
+
+pub async fn list_virtual_tags(db: &DbPool, org_id: &str) -> Result<Vec<VirtualTag>, sqlx::Error> {
+    let rows: Vec<VirtualTagRow> = sqlx::query_as(&format!(
+        "SELECT {VIRTUAL_TAG_COLUMNS} FROM virtual_tags
+         WHERE organization_id = $1
+         ORDER BY priority DESC, created_at"
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(VirtualTag::from).collect())
+}
+
+pub async fn get_virtual_tag(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<VirtualTag>, sqlx::Error> {
+    let row: Option<VirtualTagRow> = sqlx::query_as(&format!(
+        "SELECT {VIRTUAL_TAG_COLUMNS} FROM virtual_tags WHERE organization_id = $1 AND id = $2"
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(VirtualTag::from))
+}
+
+/// Create a virtual tag and queue the re-normalization of stored line items
+pub async fn create_virtual_tag(
+    db: &DbPool,
+    org_id: &str,
+    input: VirtualTagInput,
+) -> Result<VirtualTag, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let row: VirtualTagRow = sqlx::query_as(&format!(
+        "INSERT INTO virtual_tags (organization_id, key, value, priority, conditions)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING {VIRTUAL_TAG_COLUMNS}"
+    ))
+    .bind(org_id)
+    .bind(&input.key)
+    .bind(&input.value)
+    .bind(input.priority)
+    .bind(Json(&input.conditions))
+    .fetch_one(&mut *tx)
+    .await?;
+    enqueue_renormalize(&mut tx, org_id).await?;
+    tx.commit().await?;
+
+    Ok(row.into())
+}
+
+/// Replace a virtual tag and queue the re-normalization of stored line items.
+/// With a `version`, only a virtual tag last updated at that time is replaced.
+pub async fn update_virtual_tag(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    input: VirtualTagInput,
+    version: Option<DateTime<Utc>>,
+) -> Result<Option<VirtualTag>, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let row: Option<VirtualTagRow> = sqlx::query_as(&format!(
+        "UPDATE virtual_tags SET key = $3, value = $4, priority = $5, conditions = $6
+         WHERE organization_id = $1 AND id = $2
+           AND ($7::timestamptz IS NULL OR updated_at = $7)
+         RETURNING {VIRTUAL_TAG_COLUMNS}"
+    ))
+    .bind(org_id)
+    .bind(id)
+    .bind(&input.key)
+    .bind(&input.value)
+    .bind(input.priority)
+    .bind(Json(&input.conditions))
+    .bind(version)
+    .fetch_optional(&mut *tx)
+    .await?;
+    if row.is_some() {
+        enqueue_renormalize(&mut tx, org_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(row.map(VirtualTag::from))
+}
+
+/// Delete a virtual tag and queue the re-normalization of stored line items.
+/// Returns `false` if no such virtual tag exists, or it was updated since
+/// `version`.
+pub async fn delete_virtual_tag(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let mut tx = db.begin().await?;
+    let result = sqlx::query(
+        "DELETE FROM virtual_tags WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(&mut *tx)
+    .await?;
+    let deleted = result.rows_affected() > 0;
+    if deleted {
+        enqueue_renormalize(&mut tx, org_id).await?;
+    }
+    tx.commit().await?;
+
+    Ok(deleted)
+}
+
+/// The organization's current tag policy and virtual tags
+async fn load_rules(
+    db: &DbPool,
+    org_id: &str,
+) -> Result<(TagPolicy, Vec<VirtualTag>), sqlx::Error> {
+    Ok((
+        get_policy(db, org_id).await?.policy,
+        list_virtual_tags(db, org_id).await?,
+    ))
+}
+
+/// Build a normalizer from the organization's current rules
+pub async fn load_normalizer(db: &DbPool, org_id: &str) -> Result<TagNormalizer, sqlx::Error> {
+    let (policy, virtual_tags) = load_rules(db, org_id).await?;
+    Ok(TagNormalizer::new(&policy, virtual_tags))
+}
+
+#[derive(sqlx::FromRow)]
+struct StoredLineItem {
+    id: Uuid,
+    account_id: String,
+    service: String,
+    region: Option<String>,
+    resource_id: Option<String>,
+    usage_type: Option<String>,
+    raw_tags: Json<Tags>,
+    tags: Json<Tags>,
+}
+
+/// Recompute normalized tags of all stored line items from their raw tags.
+///
+/// Run by the job queued when the policy or virtual tags change, so that
+/// rolled-up spend reflects the current rules. Passes over the line items
+/// again if the rules changed while it ran. Returns the number of line
+/// items updated.
+pub async fn renormalize(db: &DbPool, org_id: &str) -> Result<u64, sqlx::Error> {
+    let mut rules = load_rules(db, org_id).await?;
+    let mut updated = 0;
+    loop {
+        let normalizer = TagNormalizer::new(&rules.0, rules.1.clone());
+        updated += renormalize_with(db, org_id, &normalizer).await?;
+
+        let current = load_rules(db, org_id).await?;
+        if current == rules {
+            return Ok(updated);
+        }
+        rules = current;
+    }
+}
+
+async fn renormalize_with(
+    db: &DbPool,
+    org_id: &str,
+    normalizer: &TagNormalizer,
+) -> Result<u64, sqlx::Error> {
+    const BATCH_SIZE: i64 = 1000;
+
+    let mut updated = 0;
+    let mut after = Uuid::nil();
+
+    loop {
+        let batch: Vec<StoredLineItem> = sqlx::query_as(
+            "SELECT id, account_id, service, region, resource_id, usage_type, raw_tags, tags
+             FROM cost_line_items
+             WHERE organization_id = $1 AND id > $2
+             ORDER BY id
+             LIMIT $3",
+        )
+        .bind(org_id)
+        .bind(after)
+        .bind(BATCH_SIZE)
+        .fetch_all(db)
+        .await?;
+
+        let Some(last) = batch.last() else {
+            break;
+        };
+        after = last.id;
+
+        let mut ids = Vec::new();
+        let mut new_tags = Vec::new();
+        for item in &batch {
+            let context = LineItemContext {
+                account_id: &item.account_id,
+                service: &item.service,
+                region: item.region.as_deref(),
+                resource_id: item.resource_id.as_deref(),
+                usage_type: item.usage_type.as_deref(),
+            };
+            let tags = normalizer.normalize(&item.raw_tags, &context);
+            if tags != item.tags.0 {
+                ids.push(item.id);
+                new_tags.push(Json(tags));
+            }
+        }
+
+        if !ids.is_empty() {
+            let result = sqlx::query(
+                "UPDATE cost_line_items AS c SET tags = u.tags
+                 FROM UNNEST($1::uuid[], $2::jsonb[]) AS u(id, tags)
+                 WHERE c.id = u.id",
+            )
+            .bind(&ids)
+            .bind(&new_tags)
+            .execute(db)
+            .await?;
+            updated += result.rows_affected();
+        }
+    }
+
+    Ok(updated)
+}
+
+/// Spend covered by each tag key and value over a period
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TagCoverageReport {
+    pub start: DateTime<Utc>,
+    pub end: DateTime<Utc>,
+    pub total_spend: f64,
+    /// Spend on line items without any tag
+    pub untagged_spend: f64,
+    pub keys: Vec<TagKeyCoverage>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TagKeyCoverage {
+    pub key: String,
+    pub spend: f64,
+    /// Fraction of total spend carrying this key (0.0 - 1.0)
+    pub coverage: f64,
+    pub values: Vec<TagValueSpend>,
+}
+
+#[derive(Debug, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct TagValueSpend {
+    pub value: String,
+    pub spend: f64,
+}
+
+pub async fn tag_coverage(
+    db: &DbPool,
+    org_id: &str,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<TagCoverageReport, sqlx::Error> {
+    let (total_spend, untagged_spend): (f64, f64) = sqlx::query_as(
+        "SELECT COALESCE(SUM(cost), 0), COALESCE(SUM(cost) FILTER (WHERE tags = '{}'::jsonb), 0)
+         FROM cost_line_items
+         WHERE organization_id = $1 AND usage_start >= $2 AND usage_start < $3",
+    )
+    .bind(org_id)
+    .bind(start)
+    .bind(end)
+    .fetch_one(db)
+    .await?;
+
+    let rows: Vec<(String, String, f64)> = sqlx::query_as(
+        "SELECT t.key, t.value, SUM(c.cost)
+         FROM cost_line_items c, jsonb_each_text(c.tags) AS t(key, value)
+         WHERE c.organization_id = $1 AND c.usage_start >= $2 AND c.usage_start < $3
+         GROUP BY t.key, t.value
+         ORDER BY t.key, SUM(c.cost) DESC",
+    )
+    .bind(org_id)
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await?;
+
+    let mut keys: Vec<TagKeyCoverage> = Vec::new();
+    for (key, value, spend) in rows {
+        match keys.last_mut() {
+            Some(last) if last.key == key => {
+                last.spend += spend;
+                last.values.push(TagValueSpend { value, spend });
+            }
+            _ => keys.push(TagKeyCoverage {
+                key,
+                spend,
+                coverage: 0.0,
+                values: vec![TagValueSpend { value, spend }],
+            }),
+        }
+    }
+
+    for key in &mut keys {
+        key.coverage = if total_spend > 0.0 {
+            key.spend / total_spend
+        } else {
+            0.0
+        };
+    }
+    keys.sort_by(|a, b| b.spend.total_cmp(&a.spend));
+
+    Ok(TagCoverageReport {
+        start,
+        end,
+        total_spend,
+        untagged_spend,
+        keys,
+    })
+}
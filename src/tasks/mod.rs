@@ -0,0 +1,14 @@
+//! Recurring tasks
+//!
+//! Tasks enqueue a job on a cron schedule, such as hourly discovery or the
+//! minutely evaluation of start/stop schedules. A Postgres advisory lock
+//! elects one replica to fire them, their last and next run are persisted,
+//! and runs missed while the backend was down are caught up according to
+//! each task's policy.
+
+pub mod model;
+pub mod registry;
+pub mod scheduler;
+pub mod store;
+
+pub use model::{CatchUp, RecurringTask};
@@ -0,0 +1,147 @@
+//! Recurring task definitions and when they are due
+
+use std::str::FromStr;
+
+use chrono::{DateTime, Duration, Utc};
+use cron::Schedule;
+use serde::Serialize;
+
+use crate::jobs::JobPayload;
+
+/// Most missed runs enqueued at once by [`CatchUp::All`]
+pub const MAX_CATCH_UP_RUNS: usize = 100;
+
+/// How late a run may fire and still count as on time for [`CatchUp::Skip`]
+pub const SKIP_GRACE_SECS: i64 = 5 * 60;
+
+/// What to do about runs missed while no scheduler was running
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub enum CatchUp {
+    /// Drop missed runs and wait for the next one
+    Skip,
+    /// Run once for any number of missed runs
+    Once,
+    /// Run once for every missed run, up to [`MAX_CATCH_UP_RUNS`]
+    All,
+}
+
+impl CatchUp {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CatchUp::Skip => "skip",
+            CatchUp::Once => "once",
+            CatchUp::All => "all",
+        }
+    }
+}
+
+/// A job enqueued on a cron schedule
+#[derive(Debug, Clone)]
+pub struct RecurringTask {
+    pub name: &'static str,
+    pub schedule: Schedule,
+    pub catch_up: CatchUp,
+    pub payload: JobPayload,
+}
+
+impl RecurringTask {
+    /// `schedule` is a cron expression with seconds, in UTC
+    pub fn new(
+        name: &'static str,
+        schedule: &str,
+        catch_up: CatchUp,
+        payload: JobPayload,
+    ) -> Result<Self, cron::error::Error> {
+        Ok(RecurringTask {
+            name,
+            schedule: Schedule::from_str(schedule)?,
+            catch_up,
+            payload,
+        })
+    }
+
+    /// First run after `time`
+    pub fn next_after(&self, time: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(&time).next()
+    }
+
+    /// Scheduled times to enqueue at `now` for a task next due at
+    /// `next_run_at`, oldest first
+    pub fn due_runs(&self, next_run_at: DateTime<Utc>, now: DateTime<Utc>) -> Vec<DateTime<Utc>> {
+        if next_run_at > now {
+            return Vec::new();
+        }
+        let due = std::iter::once(next_run_at)
+            .chain(self.schedule.after(&next_run_at))
+            .take_while(|time| *time <= now);
+        match self.catch_up {
+            CatchUp::Skip => due
+                .last()
+                .filter(|latest| now - *latest <= Duration::seconds(SKIP_GRACE_SECS))
+                .into_iter()
+                .collect(),
+            CatchUp::Once => due.last().into_iter().collect(),
+            CatchUp::All => {
+                let due: Vec<_> = due.collect();
+                due[due.len().saturating_sub(MAX_CATCH_UP_RUNS)..].to_vec()
+            }
+        }
+    }
+}
+
+#[derive(Debug, sqlx::FromRow)]
+pub(super) struct TaskStateRow {
+    pub name: String,
+    pub next_run_at: DateTime<Utc>,
+}
+
+#[cfg(test)]
+mod tests {
+    use chrono::TimeZone;
+
+    use super::*;
+
+    #[test]
+    fn test_due_runs() {
+        let hourly = |catch_up| {
+            RecurringTask::new(
+                "discovery",
+                "0 0 * * * *",
+                catch_up,
+                JobPayload::WasteEvaluation,
+            )
+            .unwrap()
+        };
+        let at = |h, m| Utc.with_ymd_and_hms(2026, 10, 18, h, m, 0).unwrap();
+
+        // On time
+        assert_eq!(
+            hourly(CatchUp::Skip).due_runs(at(3, 0), at(3, 1)),
+            [at(3, 0)]
+        );
+        assert!(hourly(CatchUp::All)
+            .due_runs(at(3, 0), at(2, 59))
+            .is_empty());
+        assert_eq!(hourly(CatchUp::Once).next_after(at(3, 0)), Some(at(4, 0)));
+
+        // Down from before 03:00 until 05:30
+        assert!(hourly(CatchUp::Skip)
+            .due_runs(at(3, 0), at(5, 30))
+            .is_empty());
+        assert_eq!(
+            hourly(CatchUp::Once).due_runs(at(3, 0), at(5, 30)),
+            [at(5, 0)]
+        );
+        assert_eq!(
+            hourly(CatchUp::All).due_runs(at(3, 0), at(5, 30)),
+            [at(3, 0), at(4, 0), at(5, 0)]
+        );
+        assert_eq!(
+            hourly(CatchUp::All)
+                .due_runs(at(3, 0) - Duration::days(30), at(5, 30))
+                .len(),
+            MAX_CATCH_UP_RUNS
+        );
+    }
+}
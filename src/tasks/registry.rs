@@ -0,0 +1,50 @@
+//! The recurring tasks this backend runs
+
+use super::model::{CatchUp, RecurringTask};
+use crate::config::Config;
+use crate::jobs::JobPayload;
+
+/// Tasks to schedule under `config`
+pub fn recurring_tasks(config: &Config) -> Vec<RecurringTask> {
+    let mut tasks = vec![
+        task(
+            "run_schedules",
+            "0 * * * * *",
+            CatchUp::Once,
+            JobPayload::RunSchedules,
+        ),
+        task(
+            "discover_accounts",
+            "0 0 * * * *",
+            CatchUp::Once,
+            JobPayload::DiscoverAccounts,
+        ),
+        task(
+            "purge_idempotency_keys",
+            "0 15 * * * *",
+            CatchUp::Skip,
+            JobPayload::PurgeIdempotencyKeys,
+        ),
+    ];
+    if let Some(dir) = &config.pricing_data_dir {
+        tasks.push(task(
+            "pricing_sync",
+            "0 30 2 * * *",
+            CatchUp::Skip,
+            JobPayload::PricingSync {
+                directory: dir.clone(),
+            },
+        ));
+    }
+    tasks
+}
+
+fn task(
+    name: &'static str,
+    schedule: &str,
+    catch_up: CatchUp,
+    payload: JobPayload,
+) -> RecurringTask {
+    RecurringTask::new(name, schedule, catch_up, payload)
+        .unwrap_or_else(|e| panic!("invalid schedule for task {}: {}", name, e))
+}
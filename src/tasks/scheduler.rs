@@ -0,0 +1,117 @@
+//! The cron scheduler
+//!
+//! Every replica runs the scheduler, but only the one holding a session
+//! advisory lock fires tasks, so each run is enqueued once. The lock lives
+//! on a connection kept out of the pool: if the leader dies or loses its
+//! connection, Postgres releases the lock and another replica takes over
+//! on its next tick. Due tasks are enqueued as jobs in the same transaction
+//! that moves them to their next run.
+
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+use sqlx::{Connection, PgConnection};
+
+use super::model::RecurringTask;
+use super::store;
+use crate::db::DbPool;
+use crate::jobs::{self, NewJob};
+
+/// Advisory lock key held by the leader
+const LEADER_LOCK_KEY: i64 = 0x5343_484F_4C41_5200;
+
+const TICK: Duration = Duration::from_secs(10);
+
+/// Fire `tasks` whenever this replica is the leader, forever
+pub async fn run(db: DbPool, tasks: Vec<RecurringTask>) {
+    let mut interval = tokio::time::interval(TICK);
+    interval.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Skip);
+    let mut leader: Option<PgConnection> = None;
+    loop {
+        interval.tick().await;
+
+        match leader.as_mut() {
+            // Still holding the lock as long as its session is alive
+            Some(conn) => {
+                if let Err(e) = conn.ping().await {
+                    tracing::warn!("Lost scheduler leadership: {}", e);
+                    leader = None;
+                    continue;
+                }
+            }
+            None => match try_lead(&db).await {
+                Ok(Some(conn)) => {
+                    tracing::info!("Became scheduler leader");
+                    leader = Some(conn);
+                    if let Err(e) = store::sync_tasks(&db, &tasks, Utc::now()).await {
+                        tracing::error!("Failed to sync recurring tasks: {}", e);
+                    }
+                }
+                Ok(None) => continue,
+                Err(e) => {
+                    tracing::error!("Failed to take scheduler leadership: {}", e);
+                    continue;
+                }
+            },
+        }
+
+        if let Err(e) = fire_due(&db, &tasks, Utc::now()).await {
+            tracing::error!("Failed to fire recurring tasks: {}", e);
+        }
+    }
+}
+
+/// A connection holding the leader lock, or `None` if another replica
+/// holds it
+async fn try_lead(db: &DbPool) -> Result<Option<PgConnection>, sqlx::Error> {
+    let mut conn = db.acquire().await?.detach();
+    let locked: bool = sqlx::query_scalar("SELECT pg_try_advisory_lock($1)")
+        .bind(LEADER_LOCK_KEY)
+        .fetch_one(&mut conn)
+        .await?;
+    if locked {
+        Ok(Some(conn))
+    } else {
+        conn.close().await?;
+        Ok(None)
+    }
+}
+
+/// Enqueue the runs of tasks due at `now`. Returns the number of jobs
+/// enqueued.
+pub async fn fire_due(
+    db: &DbPool,
+    tasks: &[RecurringTask],
+    now: DateTime<Utc>,
+) -> Result<usize, sqlx::Error> {
+    let names: Vec<&str> = tasks.iter().map(|task| task.name).collect();
+    let mut enqueued = 0;
+
+    let mut tx = db.begin().await?;
+    for row in store::due_tasks(&mut tx, &names, now).await? {
+        let Some(task) = tasks.iter().find(|task| task.name == row.name) else {
+            continue;
+        };
+        let runs = task.due_runs(row.next_run_at, now);
+        let mut last_run = None;
+        for run_at in runs {
+            let job = NewJob::new(None, task.payload.clone()).unique(format!(
+                "task:{}:{}",
+                task.name,
+                run_at.to_rfc3339()
+            ));
+            let job = jobs::store::enqueue_in(&mut tx, &job).await?;
+            enqueued += job.is_some() as usize;
+            last_run = Some((run_at, job.map(|job| job.id)));
+        }
+        // A schedule without further runs is never due again
+        let next_run_at = task.next_after(now).unwrap_or(DateTime::<Utc>::MAX_UTC);
+        store::record_runs(&mut tx, task.name, last_run, next_run_at).await?;
+    }
+    tx.commit().await?;
+
+    if enqueued > 0 {
+        tracing::debug!("Enqueued {} recurring task runs", enqueued);
+    }
+    Ok(enqueued)
+}
@@ -0,0 +1,81 @@
+//! Persisted state of recurring tasks
+
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, Transaction};
+use uuid::Uuid;
+
+use super::model::{RecurringTask, TaskStateRow};
+use crate::db::DbPool;
+
+/// Store the tasks defined in code. New tasks and tasks whose schedule
+/// changed are next due at their first run after `now`; the others keep
+/// their state so runs missed while no scheduler ran are caught up.
+pub async fn sync_tasks(
+    db: &DbPool,
+    tasks: &[RecurringTask],
+    now: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let mut tx = db.begin().await?;
+    for task in tasks {
+        let Some(next_run_at) = task.next_after(now) else {
+            continue;
+        };
+        sqlx::query(
+            "INSERT INTO recurring_tasks (name, schedule, catch_up, next_run_at)
+             VALUES ($1, $2, $3, $4)
+             ON CONFLICT (name) DO UPDATE
+             SET schedule = EXCLUDED.schedule, catch_up = EXCLUDED.catch_up,
+                 next_run_at = CASE WHEN recurring_tasks.schedule = EXCLUDED.schedule
+                                    THEN recurring_tasks.next_run_at
+                                    ELSE EXCLUDED.next_run_at END",
+        )
+        .bind(task.name)
+        .bind(task.schedule.source())
+        .bind(task.catch_up.as_str())
+        .bind(next_run_at)
+        .execute(&mut *tx)
+        .await?;
+    }
+    tx.commit().await
+}
+
+/// Tasks among `names` due at `now`, locked for the rest of the transaction
+pub(super) async fn due_tasks(
+    tx: &mut Transaction<'_, Postgres>,
+    names: &[&str],
+    now: DateTime<Utc>,
+) -> Result<Vec<TaskStateRow>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT name, next_run_at FROM recurring_tasks
+         WHERE name = ANY($1) AND next_run_at <= $2
+         FOR UPDATE",
+    )
+    .bind(names)
+    .bind(now)
+    .fetch_all(&mut **tx)
+    .await
+}
+
+/// Record the runs enqueued for a task and when it is next due
+pub(super) async fn record_runs(
+    tx: &mut Transaction<'_, Postgres>,
+    name: &str,
+    last_run: Option<(DateTime<Utc>, Option<Uuid>)>,
+    next_run_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    let (last_run_at, last_job_id) = last_run.unzip();
+    sqlx::query(
+        "UPDATE recurring_tasks
+         SET next_run_at = $2,
+             last_run_at = COALESCE($3, last_run_at),
+             last_job_id = CASE WHEN $3::timestamptz IS NULL THEN last_job_id ELSE $4 END
+         WHERE name = $1",
+    )
+    .bind(name)
+    .bind(next_run_at)
+    .bind(last_run_at)
+    .bind(last_job_id.flatten())
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
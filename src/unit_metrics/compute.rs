@@ -0,0 +1,132 @@
+//! Cost-per-unit series and business metric CSV parsing
+
+use std::collections::BTreeMap;
+
+use chrono::NaiveDate;
+
+use super::model::{MetricValue, UnitCostPoint};
+
+/// Join spend and units per (period, dimension) into a cost-per-unit series.
+///
+/// Periods with spend but no units get a `null` cost per unit; periods with
+/// units but no spend cost zero.
+pub fn unit_cost_series(
+    costs: &[(NaiveDate, Option<String>, f64)],
+    units: &[(NaiveDate, Option<String>, f64)],
+) -> Vec<UnitCostPoint> {
+    let mut points: BTreeMap<(NaiveDate, Option<String>), (f64, f64)> = BTreeMap::new();
+    for (period, dimension, cost) in costs {
+        points.entry((*period, dimension.clone())).or_default().0 += cost;
+    }
+    for (period, dimension, value) in units {
+        points.entry((*period, dimension.clone())).or_default().1 += value;
+    }
+
+    points
+        .into_iter()
+        .map(|((period, dimension), (cost, units))| UnitCostPoint {
+            period,
+            dimension,
+            cost,
+            units,
+            cost_per_unit: (units > 0.0).then(|| cost / units),
+        })
+        .collect()
+}
+
+/// Parse uploaded metric values.
+///
+/// The first row is a header naming the columns `date` (YYYY-MM-DD), `value`
+/// and optionally `dimension`, in any order.
+pub fn parse_values_csv(input: &[u8]) -> Result<Vec<MetricValue>, String> {
+    let mut reader = csv::ReaderBuilder::new()
+        .trim(csv::Trim::All)
+        .from_reader(input);
+
+    let headers = reader
+        .headers()
+        .map_err(|e| format!("invalid CSV header: {}", e))?
+        .clone();
+    let column = |name: &str| headers.iter().position(|h| h.eq_ignore_ascii_case(name));
+    let date_col = column("date").ok_or("missing 'date' column")?;
+    let value_col = column("value").ok_or("missing 'value' column")?;
+    let dimension_col = column("dimension");
+
+    let mut values = Vec::new();
+    for (i, record) in reader.records().enumerate() {
+        // Header is line 1
+        let line = i + 2;
+        let record = record.map_err(|e| format!("line {}: {}", line, e))?;
+
+        let date = record
+            .get(date_col)
+            .and_then(|d| NaiveDate::parse_from_str(d, "%Y-%m-%d").ok())
+            .ok_or_else(|| format!("line {}: invalid date, expected YYYY-MM-DD", line))?;
+        let value = record
+            .get(value_col)
+            .and_then(|v| v.parse::<f64>().ok())
+            .filter(|v| v.is_finite() && *v >= 0.0)
+            .ok_or_else(|| format!("line {}: value must be a non-negative number", line))?;
+        let dimension = dimension_col
+            .and_then(|c| record.get(c))
+            .unwrap_or_default()
+            .to_string();
+
+        values.push(MetricValue {
+            date,
+            dimension,
+            value,
+        });
+    }
+
+    Ok(values)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn date(s: &str) -> NaiveDate {
+        s.parse().unwrap()
+    }
+
+    #[test]
+    fn test_unit_cost_series_joins_by_period_and_dimension() {
+        let costs = vec![
+            (date("2026-09-01"), Some("acme".to_string()), 100.0),
+            (date("2026-09-01"), Some("globex".to_string()), 30.0),
+        ];
+        let units = vec![
+            (date("2026-09-01"), Some("acme".to_string()), 50.0),
+            (date("2026-09-01"), Some("initech".to_string()), 5.0),
+        ];
+
+        let series = unit_cost_series(&costs, &units);
+        let per_unit: Vec<_> = series
+            .iter()
+            .map(|p| (p.dimension.as_deref().unwrap(), p.cost_per_unit))
+            .collect();
+        assert_eq!(
+            per_unit,
+            vec![
+                ("acme", Some(2.0)),
+                ("globex", None),
+                ("initech", Some(0.0))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_values_csv() {
+        let csv = b"value,date,dimension\n10,2026-09-01,acme\n 2.5 , 2026-09-02 ,\n";
+        let values = parse_values_csv(csv).unwrap();
+        assert_eq!(values.len(), 2);
+        assert_eq!(values[0].dimension, "acme");
+        assert_eq!(values[1].value, 2.5);
+        assert_eq!(values[1].dimension, "");
+
+        let err = parse_values_csv(b"date,value\n2026-09-01,-1\n").unwrap_err();
+        assert_eq!(err, "line 2: value must be a non-negative number");
+        assert!(parse_values_csv(b"day,value\n").is_err());
+    }
+}
@@ -0,0 +1,12 @@
+//! Unit economics: cost per business unit of work
+//!
+//! Customers push business metrics (customers, requests, GB...) as daily
+//! values, optionally broken down by a dimension. A unit metric divides the
+//! spend selected by a cost filter by one of these metrics, producing a
+//! cost-per-unit time series built on the shared cost query.
+
+pub mod compute;
+pub mod model;
+pub mod store;
+
+pub use model::{Aggregation, BusinessMetric, MetricValue, UnitCostPoint, UnitMetric};
@@ -0,0 +1,161 @@
+//! Unit economics API and storage types
+
+use chrono::{DateTime, NaiveDate, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::types::Json;
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::Validate;
+
+use crate::conditional::Versioned;
+use crate::costs::{CostFilter, GroupBy};
+
+/// How daily business metric values combine into a coarser bucket
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "lowercase")]
+pub enum Aggregation {
+    /// Flows such as requests served or GB transferred
+    #[default]
+    Sum,
+    /// Levels such as active customers
+    Average,
+}
+
+impl Aggregation {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            Aggregation::Sum => "sum",
+            Aggregation::Average => "average",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        if s == "average" {
+            Aggregation::Average
+        } else {
+            Aggregation::Sum
+        }
+    }
+
+    pub(super) fn sql_function(self) -> &'static str {
+        match self {
+            Aggregation::Sum => "SUM",
+            Aggregation::Average => "AVG",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct BusinessMetric {
+    pub id: Uuid,
+    pub name: String,
+    pub unit: String,
+    pub description: Option<String>,
+    pub aggregation: Aggregation,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for BusinessMetric {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct BusinessMetricRow {
+    pub id: Uuid,
+    pub name: String,
+    pub unit: String,
+    pub description: Option<String>,
+    pub aggregation: String,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<BusinessMetricRow> for BusinessMetric {
+    fn from(row: BusinessMetricRow) -> Self {
+        BusinessMetric {
+            id: row.id,
+            name: row.name,
+            unit: row.unit,
+            description: row.description,
+            aggregation: Aggregation::parse(&row.aggregation),
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// One daily value of a business metric
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate, sqlx::FromRow, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricValue {
+    pub date: NaiveDate,
+    /// Breakdown key such as a customer or product id ('' when absent)
+    #[serde(default)]
+    #[validate(length(max = 256))]
+    #[schema(max_length = 256)]
+    pub dimension: String,
+    #[validate(range(min = 0.0))]
+    #[schema(minimum = 0.0)]
+    pub value: f64,
+}
+
+#[derive(Debug, Clone, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitMetric {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub business_metric_id: Uuid,
+    /// Selects the spend in the numerator
+    pub cost_filter: CostFilter,
+    /// Cost dimension whose values are matched against metric dimensions;
+    /// without it, spend is divided by the metric total
+    pub dimension: Option<GroupBy>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl Versioned for UnitMetric {
+    fn version(&self) -> DateTime<Utc> {
+        self.updated_at
+    }
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct UnitMetricRow {
+    pub id: Uuid,
+    pub name: String,
+    pub description: Option<String>,
+    pub business_metric_id: Uuid,
+    pub cost_filter: Json<CostFilter>,
+    pub dimension: Option<String>,
+    pub updated_at: DateTime<Utc>,
+}
+
+impl From<UnitMetricRow> for UnitMetric {
+    fn from(row: UnitMetricRow) -> Self {
+        UnitMetric {
+            id: row.id,
+            name: row.name,
+            description: row.description,
+            business_metric_id: row.business_metric_id,
+            cost_filter: row.cost_filter.0,
+            dimension: row.dimension.as_deref().and_then(GroupBy::parse),
+            updated_at: row.updated_at,
+        }
+    }
+}
+
+/// One point of a cost-per-unit time series
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct UnitCostPoint {
+    pub period: NaiveDate,
+    /// Dimension value when the unit metric is broken down
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub dimension: Option<String>,
+    pub cost: f64,
+    pub units: f64,
+    /// `null` when no units were recorded for the period
+    pub cost_per_unit: Option<f64>,
+}
@@ -0,0 +1,338 @@
+//! Persistence for business metrics and unit metrics
+
+use chrono::{DateTime, NaiveDate, Utc};
+use sqlx::types::Json;
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use super::compute;
+use super::model::{
+    Aggregation, BusinessMetric, BusinessMetricRow, MetricValue, UnitCostPoint, UnitMetric,
+    UnitMetricRow,
+};
+use crate::costs::{CostFilter, CostQuery, Granularity, GroupBy};
+use crate::db::DbPool;
+
+pub async fn list_business_metrics(
+    db: &DbPool,
+    org_id: &str,
+) -> Result<Vec<BusinessMetric>, sqlx::Error> {
+    let rows: Vec<BusinessMetricRow> = sqlx::query_as(
+        "SELECT id, name, unit, description, aggregation, updated_at FROM business_metrics
+         WHERE organization_id = $1
+         ORDER BY name",
+    )
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(BusinessMetric::from).collect())
+}
+
+pub async fn get_business_metric(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<BusinessMetric>, sqlx::Error> {
+    let row: Option<BusinessMetricRow> = sqlx::query_as(
+        "SELECT id, name, unit, description, aggregation, updated_at FROM business_metrics
+         WHERE organization_id = $1 AND id = $2",
+    )
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(BusinessMetric::from))
+}
+
+pub async fn create_business_metric(
+    db: &DbPool,
+    org_id: &str,
+    name: &str,
+    unit: &str,
+    description: Option<&str>,
+    aggregation: Aggregation,
+) -> Result<BusinessMetric, sqlx::Error> {
+    let row: BusinessMetricRow = sqlx::query_as(
+        "INSERT INTO business_metrics (organization_id, name, unit, description, aggregation)
+         VALUES ($1, $2, $3, $4, $5)
+         RETURNING id, name, unit, description, aggregation, updated_at",
+    )
+    .bind(org_id)
+    .bind(name)
+    .bind(unit)
+    .bind(description)
+    .bind(aggregation.as_str())
+    .fetch_one(db)
+    .await?;
+
+    Ok(row.into())
+}
+
+/// Returns `false` if no such metric exists, or it was updated since `version`
+pub async fn delete_business_metric(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM business_metrics WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Insert or overwrite daily values. Returns the number of values written.
+pub async fn upsert_metric_values(
+    db: &DbPool,
+    metric_id: Uuid,
+    values: &[MetricValue],
+) -> Result<u64, sqlx::Error> {
+    const CHUNK_SIZE: usize = 1000;
+
+    let mut tx = db.begin().await?;
+    let mut written = 0;
+    for chunk in values.chunks(CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO business_metric_values (metric_id, date, dimension, value) ",
+        );
+        builder.push_values(chunk, |mut row, value| {
+            row.push_bind(metric_id)
+                .push_bind(value.date)
+                .push_bind(&value.dimension)
+                .push_bind(value.value);
+        });
+        builder
+            .push(" ON CONFLICT (metric_id, date, dimension) DO UPDATE SET value = EXCLUDED.value");
+        written += builder.build().execute(&mut *tx).await?.rows_affected();
+    }
+    tx.commit().await?;
+
+    Ok(written)
+}
+
+pub async fn list_metric_values(
+    db: &DbPool,
+    metric_id: Uuid,
+    start: NaiveDate,
+    end: NaiveDate,
+) -> Result<Vec<MetricValue>, sqlx::Error> {
+    sqlx::query_as(
+        "SELECT date, dimension, value FROM business_metric_values
+         WHERE metric_id = $1 AND date >= $2 AND date < $3
+         ORDER BY date, dimension",
+    )
+    .bind(metric_id)
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await
+}
+
+/// Fields of a unit metric supplied when creating or replacing it
+#[derive(Debug, Clone)]
+pub struct UnitMetricInput {
+    pub name: String,
+    pub description: Option<String>,
+    pub business_metric_id: Uuid,
+    pub cost_filter: CostFilter,
+    pub dimension: Option<GroupBy>,
+}
+
+const UNIT_METRIC_COLUMNS: &str =
+    "id, name, description, business_metric_id, cost_filter, dimension, updated_at";
+
+pub async fn list_unit_metrics(db: &DbPool, org_id: &str) -> Result<Vec<UnitMetric>, sqlx::Error> {
+    let rows: Vec<UnitMetricRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM unit_metrics WHERE organization_id = $1 ORDER BY name",
+        UNIT_METRIC_COLUMNS
+    ))
+    .bind(org_id)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(UnitMetric::from).collect())
+}
+
+pub async fn get_unit_metric(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+) -> Result<Option<UnitMetric>, sqlx::Error> {
+    let row: Option<UnitMetricRow> = sqlx::query_as(&format!(
+        "SELECT {} FROM unit_metrics WHERE organization_id = $1 AND id = $2",
+        UNIT_METRIC_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(UnitMetric::from))
+}
+
+/// Returns `None` if the business metric does not belong to the organization
+pub async fn create_unit_metric(
+    db: &DbPool,
+    org_id: &str,
+    input: UnitMetricInput,
+) -> Result<Option<UnitMetric>, sqlx::Error> {
+    let row: Option<UnitMetricRow> = sqlx::query_as(&format!(
+        "INSERT INTO unit_metrics
+             (organization_id, name, description, business_metric_id, cost_filter, dimension)
+         SELECT $1, $2, $3, $4, $5, $6
+         WHERE EXISTS (SELECT 1 FROM business_metrics WHERE organization_id = $1 AND id = $4)
+         RETURNING {}",
+        UNIT_METRIC_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(&input.name)
+    .bind(&input.description)
+    .bind(input.business_metric_id)
+    .bind(Json(&input.cost_filter))
+    .bind(input.dimension.as_ref().map(GroupBy::as_string))
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(UnitMetric::from))
+}
+
+/// Returns `None` if the unit metric or business metric does not exist, or
+/// the unit metric was updated since `version`
+pub async fn update_unit_metric(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    input: UnitMetricInput,
+    version: Option<DateTime<Utc>>,
+) -> Result<Option<UnitMetric>, sqlx::Error> {
+    let row: Option<UnitMetricRow> = sqlx::query_as(&format!(
+        "UPDATE unit_metrics
+         SET name = $3, description = $4, business_metric_id = $5, cost_filter = $6,
+             dimension = $7
+         WHERE organization_id = $1 AND id = $2
+           AND EXISTS (SELECT 1 FROM business_metrics WHERE organization_id = $1 AND id = $5)
+           AND ($8::timestamptz IS NULL OR updated_at = $8)
+         RETURNING {}",
+        UNIT_METRIC_COLUMNS
+    ))
+    .bind(org_id)
+    .bind(id)
+    .bind(&input.name)
+    .bind(&input.description)
+    .bind(input.business_metric_id)
+    .bind(Json(&input.cost_filter))
+    .bind(input.dimension.as_ref().map(GroupBy::as_string))
+    .bind(version)
+    .fetch_optional(db)
+    .await?;
+
+    Ok(row.map(UnitMetric::from))
+}
+
+/// Returns `false` if no such unit metric exists, or it was updated since
+/// `version`
+pub async fn delete_unit_metric(
+    db: &DbPool,
+    org_id: &str,
+    id: Uuid,
+    version: Option<DateTime<Utc>>,
+) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query(
+        "DELETE FROM unit_metrics WHERE organization_id = $1 AND id = $2
+           AND ($3::timestamptz IS NULL OR updated_at = $3)",
+    )
+    .bind(org_id)
+    .bind(id)
+    .bind(version)
+    .execute(db)
+    .await?;
+
+    Ok(result.rows_affected() > 0)
+}
+
+/// Business metric totals per period (and dimension when `by_dimension`)
+async fn metric_units(
+    db: &DbPool,
+    metric: &BusinessMetric,
+    start: NaiveDate,
+    end: NaiveDate,
+    granularity: Granularity,
+    by_dimension: bool,
+) -> Result<Vec<(NaiveDate, Option<String>, f64)>, sqlx::Error> {
+    let unit = granularity.trunc_unit();
+    // Without a breakdown, dimensions are summed per day before the
+    // per-period aggregation so averages are averages of daily totals.
+    let sql = if by_dimension {
+        format!(
+            "SELECT date_trunc('{unit}', date)::date, NULLIF(dimension, ''), {agg}(value)
+             FROM business_metric_values
+             WHERE metric_id = $1 AND date >= $2 AND date < $3
+             GROUP BY 1, 2",
+            unit = unit,
+            agg = metric.aggregation.sql_function()
+        )
+    } else {
+        format!(
+            "SELECT date_trunc('{unit}', date)::date, NULL::text, {agg}(total)
+             FROM (
+                 SELECT date, SUM(value) AS total FROM business_metric_values
+                 WHERE metric_id = $1 AND date >= $2 AND date < $3
+                 GROUP BY date
+             ) daily
+             GROUP BY 1",
+            unit = unit,
+            agg = metric.aggregation.sql_function()
+        )
+    };
+
+    sqlx::query_as(&sql)
+        .bind(metric.id)
+        .bind(start)
+        .bind(end)
+        .fetch_all(db)
+        .await
+}
+
+/// Compute the cost-per-unit series of a unit metric
+pub async fn unit_cost_series(
+    db: &DbPool,
+    org_id: &str,
+    unit_metric: &UnitMetric,
+    start: NaiveDate,
+    end: NaiveDate,
+    granularity: Granularity,
+) -> Result<Vec<UnitCostPoint>, sqlx::Error> {
+    let Some(metric) = get_business_metric(db, org_id, unit_metric.business_metric_id).await?
+    else {
+        return Ok(Vec::new());
+    };
+
+    let query = CostQuery {
+        start,
+        end,
+        granularity,
+        filter: unit_metric.cost_filter.clone(),
+        group_by: unit_metric.dimension.iter().cloned().collect(),
+    };
+    let costs: Vec<(NaiveDate, Option<String>, f64)> = query
+        .execute(db, org_id)
+        .await?
+        .rows
+        .into_iter()
+        .map(|row| (row.period, row.group.into_iter().next().flatten(), row.cost))
+        .collect();
+
+    let by_dimension = unit_metric.dimension.is_some();
+    let units = metric_units(db, &metric, start, end, granularity, by_dimension).await?;
+
+    Ok(compute::unit_cost_series(&costs, &units))
+}
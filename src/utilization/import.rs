@@ -0,0 +1,169 @@
+//! Parsing of CloudWatch metric exports
+//!
+//! Accepts the JSON printed by `aws cloudwatch get-metric-statistics` (one
+//! metric, named by `Label`) and by `aws cloudwatch get-metric-data` (several
+//! metrics in `MetricDataResults`, named by `Label` or else `Id`).
+
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use super::model::{MetricSample, UtilizationMetric};
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct StatisticsExport {
+    label: String,
+    datapoints: Vec<Datapoint>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct Datapoint {
+    timestamp: DateTime<Utc>,
+    average: Option<f64>,
+    maximum: Option<f64>,
+    sum: Option<f64>,
+    unit: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetricDataExport {
+    metric_data_results: Vec<MetricDataResult>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct MetricDataResult {
+    id: String,
+    label: Option<String>,
+    timestamps: Vec<DateTime<Utc>>,
+    values: Vec<f64>,
+}
+
+/// Map a CloudWatch metric name to the metric it measures
+fn metric_for(name: &str) -> Option<UtilizationMetric> {
+    match name.to_ascii_lowercase().as_str() {
+        "cpuutilization" | "cpu" => Some(UtilizationMetric::Cpu),
+        "mem_used_percent" | "memoryutilization" | "memory" => Some(UtilizationMetric::Memory),
+        "networkin" | "network_in" => Some(UtilizationMetric::NetworkIn),
+        "networkout" | "network_out" => Some(UtilizationMetric::NetworkOut),
+        _ => None,
+    }
+}
+
+/// Parse a CloudWatch export into samples. Network metrics exported as
+/// byte totals per period are converted to bytes per second using
+/// `period_seconds`.
+pub fn parse_cloudwatch(input: &[u8], period_seconds: u32) -> Result<Vec<MetricSample>, String> {
+    if period_seconds == 0 {
+        return Err("period must be positive".into());
+    }
+    let period = f64::from(period_seconds);
+
+    if let Ok(export) = serde_json::from_slice::<StatisticsExport>(input) {
+        let metric = metric_for(&export.label)
+            .ok_or_else(|| format!("unsupported metric '{}'", export.label))?;
+        return export
+            .datapoints
+            .into_iter()
+            .map(|point| {
+                let value = if metric.is_percentage() {
+                    point.maximum.or(point.average)
+                } else if point.unit.as_deref() == Some("Bytes/Second") {
+                    point.average.or(point.maximum)
+                } else {
+                    point
+                        .sum
+                        .map(|sum| sum / period)
+                        .or(point.average.map(|avg| avg / period))
+                };
+                let value = value.ok_or_else(|| {
+                    format!(
+                        "datapoint at {} has no Maximum, Average or Sum",
+                        point.timestamp
+                    )
+                })?;
+                Ok(MetricSample {
+                    metric,
+                    timestamp: point.timestamp,
+                    value,
+                })
+            })
+            .collect();
+    }
+
+    let export: MetricDataExport = serde_json::from_slice(input)
+        .map_err(|_| "expected get-metric-statistics or get-metric-data JSON output".to_string())?;
+    let mut samples = Vec::new();
+    for result in export.metric_data_results {
+        let name = result.label.as_deref().unwrap_or(&result.id);
+        let metric = metric_for(name)
+            .or_else(|| metric_for(&result.id))
+            .ok_or_else(|| format!("unsupported metric '{}'", name))?;
+        if result.timestamps.len() != result.values.len() {
+            return Err(format!(
+                "metric '{}' has {} timestamps but {} values",
+                name,
+                result.timestamps.len(),
+                result.values.len()
+            ));
+        }
+        samples.extend(result.timestamps.into_iter().zip(result.values).map(
+            |(timestamp, value)| MetricSample {
+                metric,
+                timestamp,
+                value: if metric.is_percentage() {
+                    value
+                } else {
+                    value / period
+                },
+            },
+        ));
+    }
+    Ok(samples)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_both_export_formats() {
+        let statistics = br#"{
+            "Label": "CPUUtilization",
+            "Datapoints": [
+                {"Timestamp": "2026-10-01T00:00:00Z", "Average": 12.5, "Maximum": 40.0, "Unit": "Percent"},
+                {"Timestamp": "2026-10-01T00:05:00Z", "Average": 20.0, "Unit": "Percent"}
+            ]
+        }"#;
+        let samples = parse_cloudwatch(statistics, 300).unwrap();
+        assert_eq!(samples.len(), 2);
+        assert_eq!(samples[0].metric, UtilizationMetric::Cpu);
+        assert_eq!(samples[0].value, 40.0);
+        assert_eq!(samples[1].value, 20.0);
+
+        let network = br#"{
+            "Label": "NetworkIn",
+            "Datapoints": [{"Timestamp": "2026-10-01T00:00:00Z", "Sum": 3000.0, "Unit": "Bytes"}]
+        }"#;
+        assert_eq!(parse_cloudwatch(network, 300).unwrap()[0].value, 10.0);
+
+        let data = br#"{
+            "MetricDataResults": [
+                {"Id": "m1", "Label": "mem_used_percent",
+                 "Timestamps": ["2026-10-01T00:00:00Z"], "Values": [55.0]},
+                {"Id": "networkOut",
+                 "Timestamps": ["2026-10-01T00:00:00Z"], "Values": [600.0]}
+            ]
+        }"#;
+        let samples = parse_cloudwatch(data, 60).unwrap();
+        assert_eq!(samples[0].metric, UtilizationMetric::Memory);
+        assert_eq!(samples[0].value, 55.0);
+        assert_eq!(samples[1].metric, UtilizationMetric::NetworkOut);
+        assert_eq!(samples[1].value, 10.0);
+
+        assert!(parse_cloudwatch(br#"{"Label": "DiskReadOps", "Datapoints": []}"#, 300).is_err());
+        assert!(parse_cloudwatch(b"not json", 300).is_err());
+    }
+}
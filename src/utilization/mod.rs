@@ -0,0 +1,11 @@
+//! Resource utilization metrics
+//!
+//! CPU, memory and network utilization samples of discovered resources,
+//! pushed through the API or imported from CloudWatch JSON exports. Their
+//! distribution over a period feeds rightsizing recommendations.
+
+pub mod import;
+pub mod model;
+pub mod store;
+
+pub use model::{MetricSample, MetricStats, UtilizationMetric};
@@ -0,0 +1,127 @@
+//! Utilization sample and statistics types
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use utoipa::ToSchema;
+use uuid::Uuid;
+use validator::{Validate, ValidationError};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub enum UtilizationMetric {
+    /// CPU utilization, percent
+    Cpu,
+    /// Memory utilization, percent
+    Memory,
+    /// Bytes received per second
+    NetworkIn,
+    /// Bytes sent per second
+    NetworkOut,
+}
+
+impl UtilizationMetric {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            UtilizationMetric::Cpu => "cpu",
+            UtilizationMetric::Memory => "memory",
+            UtilizationMetric::NetworkIn => "network_in",
+            UtilizationMetric::NetworkOut => "network_out",
+        }
+    }
+
+    pub(super) fn parse(s: &str) -> Self {
+        match s {
+            "memory" => UtilizationMetric::Memory,
+            "network_in" => UtilizationMetric::NetworkIn,
+            "network_out" => UtilizationMetric::NetworkOut,
+            _ => UtilizationMetric::Cpu,
+        }
+    }
+
+    pub fn is_percentage(self) -> bool {
+        matches!(self, UtilizationMetric::Cpu | UtilizationMetric::Memory)
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize, Validate, ToSchema)]
+#[serde(rename_all = "camelCase")]
+#[validate(schema(function = "validate_sample"))]
+pub struct MetricSample {
+    pub metric: UtilizationMetric,
+    pub timestamp: DateTime<Utc>,
+    pub value: f64,
+}
+
+fn validate_sample(sample: &MetricSample) -> Result<(), ValidationError> {
+    let max = if sample.metric.is_percentage() {
+        100.0
+    } else {
+        f64::MAX
+    };
+    if !sample.value.is_finite() || sample.value < 0.0 || sample.value > max {
+        let mut err = ValidationError::new("value");
+        err.message =
+            Some("value must be a non-negative number, at most 100 for percentages".into());
+        return Err(err);
+    }
+    Ok(())
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct MetricSampleRow {
+    pub metric: String,
+    pub observed_at: DateTime<Utc>,
+    pub value: f64,
+}
+
+impl From<MetricSampleRow> for MetricSample {
+    fn from(row: MetricSampleRow) -> Self {
+        MetricSample {
+            metric: UtilizationMetric::parse(&row.metric),
+            timestamp: row.observed_at,
+            value: row.value,
+        }
+    }
+}
+
+/// Distribution of one metric of a resource over a period
+#[derive(Debug, Clone, PartialEq, Serialize, ToSchema)]
+#[serde(rename_all = "camelCase")]
+pub struct MetricStats {
+    pub metric: UtilizationMetric,
+    pub samples: i64,
+    pub average: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub maximum: f64,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+}
+
+#[derive(sqlx::FromRow)]
+pub(super) struct MetricStatsRow {
+    pub resource_id: Uuid,
+    pub metric: String,
+    pub samples: i64,
+    pub average: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub maximum: f64,
+    pub first: DateTime<Utc>,
+    pub last: DateTime<Utc>,
+}
+
+impl From<MetricStatsRow> for MetricStats {
+    fn from(row: MetricStatsRow) -> Self {
+        MetricStats {
+            metric: UtilizationMetric::parse(&row.metric),
+            samples: row.samples,
+            average: row.average,
+            p50: row.p50,
+            p95: row.p95,
+            maximum: row.maximum,
+            first: row.first,
+            last: row.last,
+        }
+    }
+}
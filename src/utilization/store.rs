@@ -0,0 +1,143 @@
+//! Storage and statistics of utilization samples
+
+use std::collections::{HashMap, HashSet};
+
+use chrono::{DateTime, Utc};
+use sqlx::{Postgres, QueryBuilder};
+use uuid::Uuid;
+
+use super::model::{MetricSample, MetricSampleRow, MetricStats, MetricStatsRow, UtilizationMetric};
+use crate::db::DbPool;
+
+/// The last of the samples given for each metric and time, in order.
+/// Postgres rejects an upsert that would write the same row twice.
+fn latest_samples(samples: &[MetricSample]) -> Vec<&MetricSample> {
+    let mut seen = HashSet::new();
+    let mut latest: Vec<&MetricSample> = samples
+        .iter()
+        .rev()
+        .filter(|sample| seen.insert((sample.metric, sample.timestamp)))
+        .collect();
+    latest.reverse();
+    latest
+}
+
+/// Store samples of a resource; samples for the same metric and time are
+/// replaced, by the last one when a batch repeats them
+pub async fn insert_samples(
+    db: &DbPool,
+    org_id: &str,
+    resource_id: Uuid,
+    samples: &[MetricSample],
+) -> Result<u64, sqlx::Error> {
+    const CHUNK_SIZE: usize = 1000;
+
+    let mut tx = db.begin().await?;
+    let mut written = 0;
+    for chunk in latest_samples(samples).chunks(CHUNK_SIZE) {
+        let mut builder: QueryBuilder<Postgres> = QueryBuilder::new(
+            "INSERT INTO resource_metrics (resource_id, metric, observed_at, value, organization_id) ",
+        );
+        builder.push_values(chunk, |mut row, sample| {
+            row.push_bind(resource_id)
+                .push_bind(sample.metric.as_str())
+                .push_bind(sample.timestamp)
+                .push_bind(sample.value)
+                .push_bind(org_id);
+        });
+        builder.push(
+            " ON CONFLICT (resource_id, metric, observed_at) DO UPDATE SET value = EXCLUDED.value",
+        );
+        written += builder.build().execute(&mut *tx).await?.rows_affected();
+    }
+    tx.commit().await?;
+
+    Ok(written)
+}
+
+pub async fn list_samples(
+    db: &DbPool,
+    resource_id: Uuid,
+    metric: Option<UtilizationMetric>,
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<Vec<MetricSample>, sqlx::Error> {
+    let rows: Vec<MetricSampleRow> = sqlx::query_as(
+        "SELECT metric, observed_at, value FROM resource_metrics
+         WHERE resource_id = $1 AND ($2::text IS NULL OR metric = $2)
+           AND observed_at >= $3 AND observed_at < $4
+         ORDER BY metric, observed_at
+         LIMIT 50000",
+    )
+    .bind(resource_id)
+    .bind(metric.map(UtilizationMetric::as_str))
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await?;
+
+    Ok(rows.into_iter().map(MetricSample::from).collect())
+}
+
+/// Per-metric statistics of each resource's samples in `[start, end)`
+pub async fn stats(
+    db: &DbPool,
+    resource_ids: &[Uuid],
+    start: DateTime<Utc>,
+    end: DateTime<Utc>,
+) -> Result<HashMap<Uuid, Vec<MetricStats>>, sqlx::Error> {
+    if resource_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let rows: Vec<MetricStatsRow> = sqlx::query_as(
+        "SELECT resource_id, metric, COUNT(*) AS samples, AVG(value) AS average,
+                percentile_cont(0.5) WITHIN GROUP (ORDER BY value) AS p50,
+                percentile_cont(0.95) WITHIN GROUP (ORDER BY value) AS p95,
+                MAX(value) AS maximum, MIN(observed_at) AS first, MAX(observed_at) AS last
+         FROM resource_metrics
+         WHERE resource_id = ANY($1) AND observed_at >= $2 AND observed_at < $3
+         GROUP BY resource_id, metric
+         ORDER BY resource_id, metric",
+    )
+    .bind(resource_ids)
+    .bind(start)
+    .bind(end)
+    .fetch_all(db)
+    .await?;
+
+    let mut stats: HashMap<Uuid, Vec<MetricStats>> = HashMap::new();
+    for row in rows {
+        stats
+            .entry(row.resource_id)
+            .or_default()
+            .push(MetricStats::from(row));
+    }
+    Ok(stats)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_latest_samples() {
+        let at = |minute| Utc.with_ymd_and_hms(2026, 10, 18, 12, minute, 0).unwrap();
+        let sample = |metric, minute, value| MetricSample {
+            metric,
+            timestamp: at(minute),
+            value,
+        };
+        let samples = [
+            sample(UtilizationMetric::Cpu, 0, 10.0),
+            sample(UtilizationMetric::Cpu, 5, 20.0),
+            sample(UtilizationMetric::Memory, 0, 30.0),
+            sample(UtilizationMetric::Cpu, 0, 40.0),
+        ];
+        let latest: Vec<f64> = latest_samples(&samples)
+            .iter()
+            .map(|sample| sample.value)
+            .collect();
+        assert_eq!(latest, vec![20.0, 30.0, 40.0]);
+    }
+}
@@ -1,7 +1,15 @@
 //! Request validation utilities using the `validator` crate.
 //!
-//! This module provides a `ValidatedJson` extractor that combines JSON deserialization
-//! with automatic validation of request payloads.
+//! This module provides extractors that combine deserialization with
+//! automatic validation of request payloads:
+//!
+//! - `ValidatedJson` for JSON bodies
+//! - `ValidatedQuery` for query strings
+//! - `ValidatedPath` for path parameters
+//! - `ValidatedForm` for `application/x-www-form-urlencoded` bodies
+//!
+//! All of them reject with the same `AppError`, listing each offending field
+//! by JSON pointer into the body or the decoded query or path parameters.
 //!
 //! # Usage
 //!
@@ -28,26 +36,41 @@
 //!     Ok(Json(payload))
 //! }
 //! ```
+//!
+//! Query strings may repeat a key to pass a list (`region=a&region=b`); a
+//! list field using [`comma_separated`] also accepts `region=a,b`.
+
+use std::collections::BTreeMap;
 
 use axum::{
     async_trait,
-    extract::{rejection::JsonRejection, FromRequest, Request},
-    Json,
+    body::Bytes,
+    extract::{
+        rejection::RawPathParamsRejection, FromRequest, FromRequestParts, RawPathParams, Request,
+    },
+    http::{header::CONTENT_TYPE, request::Parts, HeaderMap},
 };
-use serde::de::DeserializeOwned;
-use validator::Validate;
+use serde::de::{DeserializeOwned, IntoDeserializer};
+use serde::{Deserialize, Deserializer};
+use serde_json::{error::Category, Value};
+use serde_path_to_error::Segment;
+use validator::{Validate, ValidationError, ValidationErrors, ValidationErrorsKind};
 
-use crate::error::AppError;
+use crate::error::{AppError, FieldError};
 
 /// A JSON extractor that automatically validates the payload.
 ///
-/// This extractor combines `axum::Json` with `validator::Validate` to provide
-/// automatic validation of incoming JSON request bodies.
+/// This extractor combines JSON deserialization with `validator::Validate` to
+/// provide automatic validation of incoming JSON request bodies.
 ///
 /// # Error Handling
 ///
-/// - If JSON parsing fails, returns a `400 Bad Request` with parse error details
-/// - If validation fails, returns a `400 Bad Request` with validation error details
+/// Every error names the offending fields by JSON pointer, e.g.
+/// `/samples/3/value`:
+///
+/// - Malformed JSON returns a `400 Bad Request` (`request.invalid_json`)
+/// - A missing field or a value of the wrong type, and any failed
+///   validation, returns a `422 Unprocessable Entity` (`validation.failed`)
 #[derive(Debug, Clone, Copy, Default)]
 pub struct ValidatedJson<T>(pub T);
 
@@ -60,50 +83,353 @@ where
     type Rejection = AppError;
 
     async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
-        // First, try to extract JSON
-        let Json(value) =
-            Json::<T>::from_request(req, state)
-                .await
-                .map_err(|rejection: JsonRejection| {
-                    AppError::BadRequest(format!("Invalid JSON: {}", rejection))
-                })?;
+        if !content_type_is(req.headers(), "json") {
+            return Err(AppError::BadRequest(
+                "Expected request with `Content-Type: application/json`".to_string(),
+            ));
+        }
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| AppError::BadRequest(format!("Invalid request body: {}", e)))?;
+
+        Ok(ValidatedJson(validated(parse_json(&body)?)?))
+    }
+}
+
+/// A query string extractor that validates the parameters.
+///
+/// A key may be repeated to fill a list field. Malformed values, missing
+/// parameters and failed validation return a `422 Unprocessable Entity`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedQuery<T>(pub T);
 
-        // Then validate the payload
-        value.validate().map_err(|e| {
-            let errors = format_validation_errors(&e);
-            AppError::Validation(errors)
-        })?;
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ValidatedQuery<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
 
-        Ok(ValidatedJson(value))
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        let query = parts.uri.query().unwrap_or_default();
+        Ok(ValidatedQuery(validated(parse_form(query.as_bytes())?)?))
     }
 }
 
-/// Formats validation errors into a human-readable string.
-fn format_validation_errors(errors: &validator::ValidationErrors) -> String {
-    let field_errors: Vec<String> = errors
-        .field_errors()
-        .iter()
-        .map(|(field, errs)| {
-            let messages: Vec<String> = errs
-                .iter()
-                .map(|e| {
-                    e.message
-                        .as_ref()
-                        .map(|m| m.to_string())
-                        .unwrap_or_else(|| format!("invalid value for '{}'", e.code))
-                })
-                .collect();
-            format!("{}: {}", field, messages.join(", "))
+/// A path parameter extractor that validates the parameters.
+///
+/// The parameters are decoded like a query string into a struct, so errors
+/// point at the parameter, e.g. `/id`. Unparsable parameters and failed
+/// validation return a `422 Unprocessable Entity`.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedPath<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequestParts<S> for ValidatedPath<T>
+where
+    T: DeserializeOwned + Validate + Send,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let params = RawPathParams::from_request_parts(parts, state)
+            .await
+            .map_err(|rejection| match rejection {
+                RawPathParamsRejection::InvalidUtf8InPathParam(e) => {
+                    data_error(String::new(), e.body_text())
+                }
+                // The extractor is used on a route without parameters
+                rejection => AppError::Internal(rejection.body_text()),
+            })?;
+        let params = form_urlencoded::Serializer::new(String::new())
+            .extend_pairs(params.iter())
+            .finish();
+        Ok(ValidatedPath(validated(parse_form(params.as_bytes())?)?))
+    }
+}
+
+/// A URL-encoded form body extractor that validates the payload.
+///
+/// Like [`ValidatedQuery`], a key may be repeated to fill a list field.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ValidatedForm<T>(pub T);
+
+#[async_trait]
+impl<S, T> FromRequest<S> for ValidatedForm<T>
+where
+    T: DeserializeOwned + Validate,
+    S: Send + Sync,
+{
+    type Rejection = AppError;
+
+    async fn from_request(req: Request, state: &S) -> Result<Self, Self::Rejection> {
+        if !content_type_is(req.headers(), "x-www-form-urlencoded") {
+            return Err(AppError::BadRequest(
+                "Expected request with `Content-Type: application/x-www-form-urlencoded`"
+                    .to_string(),
+            ));
+        }
+        let body = Bytes::from_request(req, state)
+            .await
+            .map_err(|e| AppError::BadRequest(format!("Invalid request body: {}", e)))?;
+        Ok(ValidatedForm(validated(parse_form(&body)?)?))
+    }
+}
+
+/// Deserialize a list from repeated values that may each hold several
+/// comma-separated items, so `?region=a,b&region=c` gives three regions.
+/// Items are deserialized from strings, which suits strings, enums, ids and
+/// dates.
+///
+/// ```ignore
+/// #[serde(default, deserialize_with = "comma_separated")]
+/// pub region: Vec<String>,
+/// ```
+pub fn comma_separated<'de, D, T>(deserializer: D) -> Result<Vec<T>, D::Error>
+where
+    D: Deserializer<'de>,
+    T: DeserializeOwned,
+{
+    let mut items = Vec::new();
+    for value in Vec::<String>::deserialize(deserializer)? {
+        for item in value.split(',').map(str::trim).filter(|i| !i.is_empty()) {
+            items.push(T::deserialize(item.into_deserializer())?);
+        }
+    }
+    Ok(items)
+}
+
+/// Whether the request's media type is `application/<subtype>`, or a
+/// structured syntax suffixed `+<subtype>` one
+fn content_type_is(headers: &HeaderMap, subtype: &str) -> bool {
+    let Some(content_type) = headers.get(CONTENT_TYPE).and_then(|v| v.to_str().ok()) else {
+        return false;
+    };
+    let essence = content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase();
+    essence
+        .strip_prefix("application/")
+        .is_some_and(|s| s == subtype || s.ends_with(&format!("+{}", subtype)))
+}
+
+fn validated<T: Validate>(value: T) -> Result<T, AppError> {
+    value
+        .validate()
+        .map_err(|e| AppError::InvalidFields(format_validation_errors(&e), field_errors(&e)))?;
+    Ok(value)
+}
+
+/// Escape a key for use as a JSON pointer segment
+fn escape(key: &str) -> String {
+    key.replace('~', "~0").replace('/', "~1")
+}
+
+/// Deserialize a JSON body, pointing errors at the value they are about
+fn parse_json<T: DeserializeOwned>(body: &[u8]) -> Result<T, AppError> {
+    let mut deserializer = serde_json::Deserializer::from_slice(body);
+    let value = serde_path_to_error::deserialize(&mut deserializer)
+        .map_err(|e| json_error(path_pointer(e.path()), e.inner()))?;
+    deserializer
+        .end()
+        .map_err(|e| json_error(String::new(), &e))?;
+    Ok(value)
+}
+
+/// Deserialize a URL-encoded query string or form body
+fn parse_form<T: DeserializeOwned>(input: &[u8]) -> Result<T, AppError> {
+    let deserializer = serde_html_form::Deserializer::new(form_urlencoded::parse(input));
+    serde_path_to_error::deserialize(deserializer)
+        .map_err(|e| data_error(path_pointer(e.path()), e.inner().to_string()))
+}
+
+fn path_pointer(path: &serde_path_to_error::Path) -> String {
+    path.iter()
+        .filter_map(|segment| match segment {
+            Segment::Seq { index } => Some(format!("/{}", index)),
+            Segment::Map { key } => Some(format!("/{}", escape(key))),
+            Segment::Enum { .. } | Segment::Unknown => None,
         })
+        .collect()
+}
+
+fn json_error(pointer: String, error: &serde_json::Error) -> AppError {
+    if error.classify() == Category::Data {
+        // The pointer already says where the value is
+        let message = error.to_string();
+        let location = format!(" at line {} column {}", error.line(), error.column());
+        let message = message.strip_suffix(&location).unwrap_or(&message);
+        return data_error(pointer, message.to_string());
+    }
+    AppError::InvalidJson(FieldError {
+        pointer,
+        code: "syntax".to_string(),
+        message: error.to_string(),
+        params: BTreeMap::new(),
+    })
+}
+
+/// A value that is missing or of the wrong type
+fn data_error(mut pointer: String, message: String) -> AppError {
+    // serde reports a missing field at the object holding it
+    let missing = message
+        .strip_prefix("missing field `")
+        .and_then(|rest| rest.split('`').next());
+    let code = match missing {
+        Some(field) => {
+            pointer.push('/');
+            pointer.push_str(&escape(field));
+            "required"
+        }
+        None => "type",
+    };
+    let error = FieldError {
+        pointer,
+        code: code.to_string(),
+        message,
+        params: BTreeMap::new(),
+    };
+    AppError::InvalidFields(describe(std::slice::from_ref(&error)), vec![error])
+}
+
+/// JSON pointer segment of a payload field. Payloads are camelCase while
+/// the validator reports Rust field names.
+fn camel_case(field: &str) -> String {
+    let mut out = String::with_capacity(field.len());
+    let mut upper = false;
+    for c in field.chars() {
+        match c {
+            '_' => upper = true,
+            c if upper => {
+                out.extend(c.to_uppercase());
+                upper = false;
+            }
+            c => out.push(c),
+        }
+    }
+    out
+}
+
+/// Message for checks declared without one
+fn default_message(code: &str, params: &BTreeMap<String, Value>) -> String {
+    let bounds = |noun: &str| match (params.get("min"), params.get("max"), params.get("equal")) {
+        (_, _, Some(equal)) => format!("{} must be {}", noun, equal),
+        (Some(min), Some(max), _) => format!("{} must be between {} and {}", noun, min, max),
+        (Some(min), None, _) => format!("{} must be at least {}", noun, min),
+        (None, Some(max), _) => format!("{} must be at most {}", noun, max),
+        (None, None, _) => format!("{} is out of range", noun),
+    };
+    match code {
+        "length" => bounds("length"),
+        "range" => bounds("value"),
+        "email" => "must be an email address".to_string(),
+        "url" => "must be a URL".to_string(),
+        "required" => "is required".to_string(),
+        code => format!("invalid value for '{}'", code),
+    }
+}
+
+/// Struct-level checks may name the field an error is about in a `field`
+/// param; such errors point at the field rather than at the struct.
+pub const FIELD_PARAM: &str = "field";
+
+fn field_error(pointer: &str, error: &ValidationError) -> FieldError {
+    let pointer = match error.params.get(FIELD_PARAM).and_then(Value::as_str) {
+        Some(field) => format!("{}/{}", pointer, camel_case(field)),
+        None => pointer.to_string(),
+    };
+    // The offending value is left out as it may be a secret
+    let params: BTreeMap<String, Value> = error
+        .params
+        .iter()
+        .filter(|(name, _)| *name != "value" && *name != FIELD_PARAM)
+        .map(|(name, value)| (name.to_string(), value.clone()))
         .collect();
+    FieldError {
+        pointer,
+        code: error.code.to_string(),
+        message: error
+            .message
+            .as_ref()
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| default_message(&error.code, &params)),
+        params,
+    }
+}
+
+fn collect_field_errors(errors: &ValidationErrors, prefix: &str, out: &mut Vec<FieldError>) {
+    let mut fields: Vec<_> = errors.errors().iter().collect();
+    fields.sort_by_key(|(field, _)| **field);
+    for (field, kind) in fields {
+        // Struct-level checks point at the struct itself, or at the field
+        // they name
+        let pointer = match *field {
+            "__all__" => prefix.to_string(),
+            field => format!("{}/{}", prefix, camel_case(field)),
+        };
+        match kind {
+            ValidationErrorsKind::Field(errs) => {
+                out.extend(errs.iter().map(|e| field_error(&pointer, e)))
+            }
+            ValidationErrorsKind::Struct(nested) => collect_field_errors(nested, &pointer, out),
+            ValidationErrorsKind::List(items) => {
+                for (index, nested) in items {
+                    collect_field_errors(nested, &format!("{}/{}", pointer, index), out);
+                }
+            }
+        }
+    }
+}
 
-    field_errors.join("; ")
+/// One entry per failed check, including those of nested structs and list
+/// items
+fn field_errors(errors: &ValidationErrors) -> Vec<FieldError> {
+    let mut out = Vec::new();
+    collect_field_errors(errors, "", &mut out);
+    out
+}
+
+fn describe(errors: &[FieldError]) -> String {
+    errors
+        .iter()
+        .map(|e| match e.pointer.as_str() {
+            "" => e.message.clone(),
+            pointer => format!("{}: {}", pointer, e.message),
+        })
+        .collect::<Vec<_>>()
+        .join("; ")
+}
+
+/// Formats validation errors into a human-readable string.
+fn format_validation_errors(errors: &ValidationErrors) -> String {
+    describe(&field_errors(errors))
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use validator::ValidationErrors;
+    use serde::Deserialize;
+
+    #[derive(Debug, Deserialize, Validate)]
+    #[serde(rename_all = "camelCase")]
+    struct Sample {
+        #[validate(range(min = 0.0, max = 100.0))]
+        cpu_percent: f64,
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    #[serde(rename_all = "camelCase")]
+    struct Upload {
+        #[validate(length(min = 1, max = 8))]
+        source_name: String,
+        #[validate(nested)]
+        samples: Vec<Sample>,
+    }
 
     #[test]
     fn test_format_validation_errors_empty() {
@@ -111,4 +437,158 @@ mod tests {
         let result = format_validation_errors(&errors);
         assert_eq!(result, "");
     }
+
+    #[test]
+    fn test_field_errors() {
+        let upload: Upload = parse_json(
+            br#"{"sourceName": "", "samples": [{"cpuPercent": 5}, {"cpuPercent": 150}]}"#,
+        )
+        .unwrap();
+        let errors = field_errors(&upload.validate().unwrap_err());
+        assert_eq!(errors.len(), 2);
+        assert_eq!(errors[0].pointer, "/samples/1/cpuPercent");
+        assert_eq!(errors[0].code, "range");
+        assert_eq!(errors[0].params["max"], 100.0);
+        assert!(!errors[0].params.contains_key("value"));
+        assert_eq!(errors[1].pointer, "/sourceName");
+        assert_eq!(errors[1].message, "length must be between 1 and 8");
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    #[serde(rename_all = "camelCase")]
+    struct Filter {
+        #[serde(default, deserialize_with = "comma_separated")]
+        region: Vec<String>,
+        #[validate(range(min = 1, max = 1000))]
+        limit: Option<i64>,
+    }
+
+    #[test]
+    fn test_query_params() {
+        let filter: Filter = parse_form(b"region=us-east-1,eu-west-1&region=ap-south-1").unwrap();
+        assert_eq!(filter.region, ["us-east-1", "eu-west-1", "ap-south-1"]);
+        assert_eq!(filter.limit, None);
+
+        match parse_form::<Filter>(b"limit=lots").unwrap_err() {
+            AppError::InvalidFields(_, errors) => {
+                assert_eq!(
+                    (errors[0].pointer.as_str(), errors[0].code.as_str()),
+                    ("/limit", "type")
+                )
+            }
+            e => panic!("unexpected {:?}", e),
+        }
+        let filter: Filter = parse_form(b"limit=5000").unwrap();
+        assert!(validated(filter).is_err());
+    }
+
+    #[derive(Debug, Deserialize, Validate)]
+    struct ItemPath {
+        #[allow(dead_code)]
+        id: uuid::Uuid,
+        #[validate(range(min = 1, max = 100))]
+        limit: i64,
+    }
+
+    #[tokio::test]
+    async fn test_path_and_form_extractors() {
+        use axum::{
+            body::{to_bytes, Body},
+            http::{Method, StatusCode},
+            routing::{get, post},
+            Router,
+        };
+        use tower::Service;
+
+        let mut app = Router::new()
+            .route(
+                "/items/:id/:limit",
+                get(|ValidatedPath(path): ValidatedPath<ItemPath>| async move {
+                    path.limit.to_string()
+                }),
+            )
+            .route(
+                "/filters",
+                post(|ValidatedForm(filter): ValidatedForm<Filter>| async move {
+                    filter.region.join(",")
+                }),
+            );
+        let mut call = |method: Method, uri: &str, content_type: &str, body: &'static str| {
+            let request = Request::builder()
+                .method(method)
+                .uri(uri)
+                .header(CONTENT_TYPE, content_type)
+                .body(Body::from(body))
+                .unwrap();
+            let response = app.call(request);
+            async move {
+                let response = response.await.unwrap();
+                let status = response.status();
+                let body = to_bytes(response.into_body(), usize::MAX).await.unwrap();
+                (status, String::from_utf8(body.to_vec()).unwrap())
+            }
+        };
+        let first_error = |body: &str| {
+            let problem: Value = serde_json::from_str(body).unwrap();
+            (
+                problem["errors"][0]["pointer"]
+                    .as_str()
+                    .unwrap()
+                    .to_string(),
+                problem["errors"][0]["code"].as_str().unwrap().to_string(),
+            )
+        };
+        let id = "5f0c2b1e-8d6f-4d1b-9a53-0f2b8b4c7e21";
+
+        let (status, body) = call(Method::GET, &format!("/items/{}/5", id), "", "").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "5"));
+        let (status, body) = call(Method::GET, "/items/not-an-id/5", "", "").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(first_error(&body), ("/id".to_string(), "type".to_string()));
+        let (status, body) = call(Method::GET, &format!("/items/{}/500", id), "", "").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            first_error(&body),
+            ("/limit".to_string(), "range".to_string())
+        );
+
+        let form = "application/x-www-form-urlencoded";
+        let (status, body) = call(Method::POST, "/filters", form, "region=a,b&region=c").await;
+        assert_eq!((status, body.as_str()), (StatusCode::OK, "a,b,c"));
+        let (status, body) = call(Method::POST, "/filters", form, "limit=lots").await;
+        assert_eq!(status, StatusCode::UNPROCESSABLE_ENTITY);
+        assert_eq!(
+            first_error(&body),
+            ("/limit".to_string(), "type".to_string())
+        );
+        let (status, _) = call(Method::POST, "/filters", "application/json", "{}").await;
+        assert_eq!(status, StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn test_json_errors() {
+        let pointer_and_code = |body: &[u8]| match parse_json::<Upload>(body).unwrap_err() {
+            AppError::InvalidFields(_, errors) => {
+                (errors[0].pointer.clone(), errors[0].code.clone())
+            }
+            AppError::InvalidJson(error) => (error.pointer, error.code),
+            e => panic!("unexpected {:?}", e),
+        };
+        assert_eq!(
+            pointer_and_code(br#"{"sourceName": "a", "samples": [{"cpuPercent": "high"}]}"#),
+            ("/samples/0/cpuPercent".to_string(), "type".to_string())
+        );
+        assert_eq!(
+            pointer_and_code(br#"{"samples": []}"#),
+            ("/sourceName".to_string(), "required".to_string())
+        );
+        assert_eq!(
+            pointer_and_code(br#"{"sourceName": "a", "samples": [{"#),
+            ("/samples/0".to_string(), "syntax".to_string())
+        );
+        assert_eq!(
+            pointer_and_code(br#"{"sourceName": "a", "samples": []} x"#),
+            ("".to_string(), "syntax".to_string())
+        );
+    }
 }